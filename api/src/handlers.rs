@@ -14,8 +14,11 @@
 
 mod blocks_api;
 mod chain_api;
+mod foreign_rpc;
+mod owner_rpc;
 mod peers_api;
 mod pool_api;
+mod scan_api;
 mod server_api;
 mod transactions_api;
 mod utils;
@@ -24,32 +27,44 @@ mod version_api;
 use self::blocks_api::BlockHandler;
 use self::blocks_api::HeaderHandler;
 use self::chain_api::ChainCompactHandler;
+use self::chain_api::ChainDevFeeHandler;
 use self::chain_api::ChainHandler;
+use self::chain_api::ChainSnapshotHandler;
 use self::chain_api::ChainValidationHandler;
+use self::chain_api::KernelHandler;
 use self::chain_api::OutputHandler;
+use self::foreign_rpc::ForeignRpc;
+use self::owner_rpc::OwnerRpc;
 use self::peers_api::PeerHandler;
 use self::peers_api::PeersAllHandler;
 use self::peers_api::PeersConnectedHandler;
+use self::pool_api::FeeEstimateHandler;
 use self::pool_api::PoolInfoHandler;
 use self::pool_api::PoolPushHandler;
+use self::pool_api::PoolTxsHandler;
+use self::scan_api::OutputScanHandler;
 use self::server_api::IndexHandler;
 use self::server_api::KernelDownloadHandler;
 use self::server_api::StatusHandler;
+use self::server_api::SupplyHandler;
 use self::transactions_api::TxHashSetHandler;
 use self::version_api::VersionHandler;
 use crate::auth::{BasicAuthMiddleware, GRIN_BASIC_REALM};
 use crate::chain;
+use crate::json_rpc::JsonRpcHandler;
 use crate::p2p;
 use crate::pool;
 use crate::rest::*;
-use crate::router::{Router, RouterError};
+use crate::router::{HandlerObj, Router, RouterError};
+use crate::types::SnapshotStatus;
 use crate::util;
 use crate::util::RwLock;
 use std::net::SocketAddr;
 use std::sync::Arc;
 
 /// Start all server HTTP handlers. Register all of them with Router
-/// and runs the corresponding HTTP server.
+/// and runs the corresponding HTTP server. `extra_routes` are handlers
+/// served along the node's own ones, such as the stratum server's.
 ///
 /// Hyper currently has a bug that prevents clean shutdown. In order
 /// to avoid having references kept forever by handlers, we only pass
@@ -63,14 +78,17 @@ pub fn start_rest_apis(
 	peers: Arc<p2p::Peers>,
 	api_secret: Option<String>,
 	tls_config: Option<TLSConfig>,
+	extra_routes: Vec<(&'static str, HandlerObj)>,
 ) -> bool {
 	let mut apis = ApiServer::new();
 	let mut router = build_router(chain, tx_pool, peers).expect("unable to build API router");
+	for (route, handler) in extra_routes {
+		router
+			.add_route(route, handler)
+			.expect("unable to add API route");
+	}
 	if let Some(api_secret) = api_secret {
-		let api_basic_auth = format!("Basic {}", util::to_base64(&format!("bitgrin:{}", api_secret)));
-		let basic_auth_middleware =
-			Arc::new(BasicAuthMiddleware::new(api_basic_auth, &GRIN_BASIC_REALM));
-		router.add_middleware(basic_auth_middleware);
+		add_basic_auth(&mut router, &api_secret).expect("unable to add API auth");
 	}
 
 	info!("Starting HTTP API server at {}.", addr);
@@ -85,6 +103,20 @@ pub fn start_rest_apis(
 	}
 }
 
+/// Requires the API secret on every API, the json-rpc ones included.
+fn add_basic_auth(router: &mut Router, api_secret: &str) -> Result<(), RouterError> {
+	let api_basic_auth = format!(
+		"Basic {}",
+		util::to_base64(&format!("bitgrin:{}", api_secret))
+	);
+	let basic_auth_middleware =
+		Arc::new(BasicAuthMiddleware::new(api_basic_auth, &GRIN_BASIC_REALM));
+	for path in &["/v1", "/v2/foreign", "/v2/owner"] {
+		router.add_path_middleware(path, basic_auth_middleware.clone())?;
+	}
+	Ok(())
+}
+
 pub fn build_router(
 	chain: Arc<chain::Chain>,
 	tx_pool: Arc<RwLock<pool::TransactionPool>>,
@@ -95,10 +127,15 @@ pub fn build_router(
 		"get headers".to_string(),
 		"get chain".to_string(),
 		"post chain/compact".to_string(),
+		"get chain/devfee".to_string(),
+		"get chain/snapshot".to_string(),
+		"post chain/snapshot?height=1000&name=bg_chain_data".to_string(),
 		"get chain/validate".to_string(),
 		"get chain/outputs/byids?id=xxx,yyy,zzz".to_string(),
 		"get chain/outputs/byheight?start_height=101&end_height=200".to_string(),
+		"get chain/kernels/xxx?min_height=100&max_height=200".to_string(),
 		"get status".to_string(),
+		"get supply?height=1000000".to_string(),
 		"get txhashset/roots".to_string(),
 		"get txhashset/lastoutputs?n=10".to_string(),
 		"get txhashset/lastrangeproofs".to_string(),
@@ -106,6 +143,9 @@ pub fn build_router(
 		"get txhashset/outputs?start_index=1&max=100".to_string(),
 		"get txhashset/merkleproof?n=1".to_string(),
 		"get pool".to_string(),
+		"get pool/txs?start_index=0&max=100&stem=true".to_string(),
+		"get pool/txs/xxx?stem=true".to_string(),
+		"get pool/fee_estimate?blocks=3&history=60".to_string(),
 		"post pool/push_tx".to_string(),
 		"post peers/a.b.c.d:p/ban".to_string(),
 		"post peers/a.b.c.d:p/unban".to_string(),
@@ -120,6 +160,9 @@ pub fn build_router(
 		chain: Arc::downgrade(&chain),
 	};
 
+	let kernel_handler = KernelHandler {
+		chain: Arc::downgrade(&chain),
+	};
 	let block_handler = BlockHandler {
 		chain: Arc::downgrade(&chain),
 	};
@@ -132,6 +175,13 @@ pub fn build_router(
 	let chain_compact_handler = ChainCompactHandler {
 		chain: Arc::downgrade(&chain),
 	};
+	let chain_dev_fee_handler = ChainDevFeeHandler {
+		chain: Arc::downgrade(&chain),
+	};
+	let chain_snapshot_handler = ChainSnapshotHandler {
+		chain: Arc::downgrade(&chain),
+		status: Arc::new(RwLock::new(SnapshotStatus::Idle)),
+	};
 	let chain_validation_handler = ChainValidationHandler {
 		chain: Arc::downgrade(&chain),
 	};
@@ -139,6 +189,9 @@ pub fn build_router(
 		chain: Arc::downgrade(&chain),
 		peers: Arc::downgrade(&peers),
 	};
+	let supply_handler = SupplyHandler {
+		chain: Arc::downgrade(&chain),
+	};
 	let kernel_download_handler = KernelDownloadHandler {
 		peers: Arc::downgrade(&peers),
 	};
@@ -148,6 +201,12 @@ pub fn build_router(
 	let pool_info_handler = PoolInfoHandler {
 		tx_pool: Arc::downgrade(&tx_pool),
 	};
+	let pool_txs_handler = PoolTxsHandler {
+		tx_pool: Arc::downgrade(&tx_pool),
+	};
+	let fee_estimate_handler = FeeEstimateHandler {
+		tx_pool: Arc::downgrade(&tx_pool),
+	};
 	let pool_push_handler = PoolPushHandler {
 		tx_pool: Arc::downgrade(&tx_pool),
 	};
@@ -164,6 +223,25 @@ pub fn build_router(
 		chain: Arc::downgrade(&chain),
 	};
 
+	let output_scan_handler = OutputScanHandler {
+		chain: Arc::downgrade(&chain),
+		tx_pool: Arc::downgrade(&tx_pool),
+	};
+
+	let foreign_rpc_handler = JsonRpcHandler {
+		methods: Arc::new(ForeignRpc {
+			chain: Arc::downgrade(&chain),
+			tx_pool: Arc::downgrade(&tx_pool),
+		}),
+	};
+	let owner_rpc_handler = JsonRpcHandler {
+		methods: Arc::new(OwnerRpc {
+			chain: Arc::downgrade(&chain),
+			tx_pool: Arc::downgrade(&tx_pool),
+			peers: Arc::downgrade(&peers),
+		}),
+	};
+
 	let mut router = Router::new();
 
 	router.add_route("/v1/", Arc::new(index_handler))?;
@@ -171,16 +249,71 @@ pub fn build_router(
 	router.add_route("/v1/headers/*", Arc::new(header_handler))?;
 	router.add_route("/v1/chain", Arc::new(chain_tip_handler))?;
 	router.add_route("/v1/chain/outputs/*", Arc::new(output_handler))?;
+	router.add_route("/v1/chain/kernels/*", Arc::new(kernel_handler))?;
 	router.add_route("/v1/chain/compact", Arc::new(chain_compact_handler))?;
+	router.add_route("/v1/chain/devfee", Arc::new(chain_dev_fee_handler))?;
+	router.add_route("/v1/chain/snapshot", Arc::new(chain_snapshot_handler))?;
 	router.add_route("/v1/chain/validate", Arc::new(chain_validation_handler))?;
 	router.add_route("/v1/txhashset/*", Arc::new(txhashset_handler))?;
 	router.add_route("/v1/status", Arc::new(status_handler))?;
+	router.add_route("/v1/supply", Arc::new(supply_handler))?;
 	router.add_route("/v1/kerneldownload", Arc::new(kernel_download_handler))?;
 	router.add_route("/v1/pool", Arc::new(pool_info_handler))?;
+	let pool_txs_handler = Arc::new(pool_txs_handler);
+	router.add_route("/v1/pool/txs", pool_txs_handler.clone())?;
+	router.add_route("/v1/pool/txs/*", pool_txs_handler)?;
+	router.add_route("/v1/pool/fee_estimate", Arc::new(fee_estimate_handler))?;
 	router.add_route("/v1/pool/push_tx", Arc::new(pool_push_handler))?;
 	router.add_route("/v1/peers/all", Arc::new(peers_all_handler))?;
 	router.add_route("/v1/peers/connected", Arc::new(peers_connected_handler))?;
 	router.add_route("/v1/peers/**", Arc::new(peer_handler))?;
 	router.add_route("/v1/version", Arc::new(version_handler))?;
+	router.add_route("/v2/foreign", Arc::new(foreign_rpc_handler))?;
+	router.add_route("/v2/owner", Arc::new(owner_rpc_handler))?;
+	router.add_route("/v2/owner/scan", Arc::new(output_scan_handler))?;
 	Ok(router)
 }
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::router::{Handler, ResponseFuture};
+	use futures::{future, Future};
+	use hyper::service::Service;
+	use hyper::{Body, Request, Response, StatusCode};
+
+	struct Ok200;
+
+	impl Handler for Ok200 {
+		fn post(&self, _req: Request<Body>) -> ResponseFuture {
+			Box::new(future::ok(Response::new(Body::empty())))
+		}
+	}
+
+	#[test]
+	fn basic_auth_on_all_apis() {
+		let mut router = Router::new();
+		for path in &["/v1/status", "/v2/foreign", "/v2/owner", "/v2/owner/scan"] {
+			router.add_route(path, Arc::new(Ok200)).unwrap();
+		}
+		add_basic_auth(&mut router, "secret").unwrap();
+		let auth = format!("Basic {}", util::to_base64("bitgrin:secret"));
+
+		for path in &["/v1/status", "/v2/foreign", "/v2/owner", "/v2/owner/scan"] {
+			let mut post = |auth: Option<&str>| {
+				let mut req = Request::post(*path);
+				if let Some(auth) = auth {
+					req.header("authorization", auth);
+				}
+				let res = router.call(req.body(Body::empty()).unwrap());
+				Future::wait(res).unwrap().status()
+			};
+			assert_eq!(post(None), StatusCode::UNAUTHORIZED, "{}", path);
+			assert_eq!(
+				post(Some("Basic Yml0Z3Jpbjp3cm9uZw==")),
+				StatusCode::UNAUTHORIZED
+			);
+			assert_eq!(post(Some(&auth)), StatusCode::OK, "{}", path);
+		}
+	}
+}
@@ -35,7 +35,7 @@ pub struct HeaderHandler {
 }
 
 impl HeaderHandler {
-	fn get_header(&self, input: String) -> Result<BlockHeaderPrintable, Error> {
+	pub(crate) fn get_header(&self, input: String) -> Result<BlockHeaderPrintable, Error> {
 		// will fail quick if the provided isn't a commitment
 		if let Ok(h) = self.get_header_for_output(input.clone()) {
 			return Ok(h);
@@ -87,7 +87,11 @@ pub struct BlockHandler {
 }
 
 impl BlockHandler {
-	fn get_block(&self, h: &Hash, include_merkle_proof: bool) -> Result<BlockPrintable, Error> {
+	pub(crate) fn get_block(
+		&self,
+		h: &Hash,
+		include_merkle_proof: bool,
+	) -> Result<BlockPrintable, Error> {
 		let chain = w(&self.chain)?;
 		let block = chain.get_block(h).context(ErrorKind::NotFound)?;
 		BlockPrintable::from_block(&block, chain, false, include_merkle_proof)
@@ -102,7 +106,7 @@ impl BlockHandler {
 	}
 
 	// Try to decode the string as a height or a hash.
-	fn parse_input(&self, input: String) -> Result<Hash, Error> {
+	pub(crate) fn parse_input(&self, input: String) -> Result<Hash, Error> {
 		trace!("BlockHandler::parse_input");
 		if let Ok(height) = input.parse() {
 			match w(&self.chain)?.get_header_by_height(height) {
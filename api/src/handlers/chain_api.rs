@@ -14,16 +14,20 @@
 
 use super::utils::{get_output, w};
 use crate::chain;
+use crate::chain::hypersync;
 use crate::core::core::hash::Hashed;
 use crate::rest::*;
 use crate::router::{Handler, ResponseFuture};
 use crate::types::*;
 use crate::util;
+use crate::util::secp::constants::PEDERSEN_COMMITMENT_SIZE;
 use crate::util::secp::pedersen::Commitment;
+use crate::util::RwLock;
 use crate::web::*;
 use failure::ResultExt;
 use hyper::{Body, Request, StatusCode};
-use std::sync::Weak;
+use std::sync::{Arc, Weak};
+use std::thread;
 
 /// Chain handler. Get the head details.
 /// GET /v1/chain
@@ -32,7 +36,7 @@ pub struct ChainHandler {
 }
 
 impl ChainHandler {
-	fn get_tip(&self) -> Result<Tip, Error> {
+	pub(crate) fn get_tip(&self) -> Result<Tip, Error> {
 		let head = w(&self.chain)?
 			.head()
 			.map_err(|e| ErrorKind::Internal(format!("can't get head: {}", e)))?;
@@ -47,6 +51,95 @@ impl Handler for ChainHandler {
 	}
 }
 
+/// Kernel handler. Looks up a kernel by excess, optionally only in the
+/// blocks between the given heights.
+/// GET /v1/chain/kernels/<excess>?min_height=1000&max_height=2000
+pub struct KernelHandler {
+	pub chain: Weak<chain::Chain>,
+}
+
+impl KernelHandler {
+	fn get_kernel(&self, req: Request<Body>) -> Result<LocatedTxKernel, Error> {
+		let excess = req
+			.uri()
+			.path()
+			.trim_end_matches('/')
+			.rsplit('/')
+			.next()
+			.ok_or_else(|| ErrorKind::Argument("missing excess".to_owned()))?;
+
+		let params = QueryParams::from(req.uri().query());
+		let min_height = match params.get("min_height") {
+			Some(_) => Some(parse_param!(params, "min_height", 0)),
+			None => None,
+		};
+		let max_height = match params.get("max_height") {
+			Some(_) => Some(parse_param!(params, "max_height", 0)),
+			None => None,
+		};
+		self.find_kernel(excess, min_height, max_height)
+	}
+
+	pub(crate) fn find_kernel(
+		&self,
+		excess: &str,
+		min_height: Option<u64>,
+		max_height: Option<u64>,
+	) -> Result<LocatedTxKernel, Error> {
+		let excess = util::from_hex(excess.to_owned())
+			.map_err(|_| ErrorKind::Argument("invalid excess".to_owned()))?;
+		if excess.len() != PEDERSEN_COMMITMENT_SIZE {
+			return Err(ErrorKind::Argument("invalid excess".to_owned()))?;
+		}
+		let excess = Commitment::from_vec(excess);
+
+		let (kernel, header, mmr_index) = w(&self.chain)?
+			.find_kernel(&excess, min_height, max_height)
+			.map_err(|e| ErrorKind::Internal(format!("can't look up kernel: {}", e)))?
+			.ok_or(ErrorKind::NotFound)?;
+		Ok(LocatedTxKernel {
+			tx_kernel: TxKernelPrintable::from_txkernel(&kernel),
+			height: header.height,
+			block_hash: header.hash().to_hex(),
+			mmr_index,
+		})
+	}
+}
+
+impl Handler for KernelHandler {
+	fn get(&self, req: Request<Body>) -> ResponseFuture {
+		result_to_response(self.get_kernel(req))
+	}
+}
+
+/// Dev fee handler. Lists the dev fee payouts with their vesting status.
+/// GET /v1/chain/devfee
+pub struct ChainDevFeeHandler {
+	pub chain: Weak<chain::Chain>,
+}
+
+impl ChainDevFeeHandler {
+	pub(crate) fn get_dev_fee_outputs(&self) -> Result<Vec<DevFeeOutputPrintable>, Error> {
+		let chain = w(&self.chain)?;
+		let head = chain
+			.head()
+			.map_err(|e| ErrorKind::Internal(format!("can't get head: {}", e)))?;
+		let outputs = chain
+			.dev_fee_outputs()
+			.map_err(|e| ErrorKind::Internal(format!("can't get dev fee outputs: {}", e)))?;
+		Ok(outputs
+			.iter()
+			.map(|o| DevFeeOutputPrintable::from_dev_fee_output(o, head.height))
+			.collect())
+	}
+}
+
+impl Handler for ChainDevFeeHandler {
+	fn get(&self, _req: Request<Body>) -> ResponseFuture {
+		result_to_response(self.get_dev_fee_outputs())
+	}
+}
+
 /// Chain validation handler.
 /// GET /v1/chain/validate
 pub struct ChainValidationHandler {
@@ -84,6 +177,76 @@ impl Handler for ChainCompactHandler {
 	}
 }
 
+/// Chain snapshot handler. Packages, in the background, the chain state at
+/// the given height (defaults to the head) as a hypersync snapshot written
+/// next to the node db root. Progress is polled with GET.
+/// POST /v1/chain/snapshot?height=1000&name=bg_chain_data
+/// GET /v1/chain/snapshot
+pub struct ChainSnapshotHandler {
+	pub chain: Weak<chain::Chain>,
+	pub status: Arc<RwLock<SnapshotStatus>>,
+}
+
+impl ChainSnapshotHandler {
+	fn start_snapshot(&self, req: &Request<Body>) -> Result<SnapshotStatus, Error> {
+		let chain = w(&self.chain)?;
+		let params = QueryParams::from(req.uri().query());
+		let header = match params.get("height") {
+			Some(_) => {
+				let height = parse_param!(params, "height", 0);
+				chain
+					.get_header_by_height(height)
+					.context(ErrorKind::NotFound)?
+			}
+			None => chain
+				.head_header()
+				.map_err(|e| ErrorKind::Internal(format!("can't get head: {}", e)))?,
+		};
+		let name = params
+			.get("name")
+			.cloned()
+			.unwrap_or_else(|| hypersync::SNAPSHOT_NAME.to_owned());
+		hypersync::check_snapshot_name(&name).map_err(|e| ErrorKind::Argument(format!("{}", e)))?;
+
+		let started = {
+			let mut status = self.status.write();
+			if let SnapshotStatus::InProgress(_) = *status {
+				return Err(ErrorKind::RequestError(
+					"snapshot already in progress".to_owned(),
+				))?;
+			}
+			*status = SnapshotStatus::InProgress(header.height);
+			status.clone()
+		};
+
+		let status = self.status.clone();
+		thread::Builder::new()
+			.name("snapshot".to_string())
+			.spawn(move || {
+				let res = chain.snapshot(&header, &name);
+				*status.write() = match res {
+					Ok(manifest) => SnapshotStatus::Done(Box::new(manifest)),
+					Err(e) => {
+						error!("snapshot at {} failed: {}", header.height, e);
+						SnapshotStatus::Failed(format!("{}", e))
+					}
+				};
+			})
+			.map_err(|e| ErrorKind::Internal(format!("can't start snapshot: {}", e)))?;
+		Ok(started)
+	}
+}
+
+impl Handler for ChainSnapshotHandler {
+	fn get(&self, _req: Request<Body>) -> ResponseFuture {
+		json_response(&*self.status.read())
+	}
+
+	fn post(&self, req: Request<Body>) -> ResponseFuture {
+		result_to_response(self.start_snapshot(&req))
+	}
+}
+
 // Supports retrieval of multiple outputs in a single request -
 // GET /v1/chain/outputs/byids?id=xxx,yyy,zzz
 // GET /v1/chain/outputs/byids?id=xxx&id=yyy&id=zzz
@@ -0,0 +1,277 @@
+// Copyright 2018 The BitGrin Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use super::blocks_api::{BlockHandler, HeaderHandler};
+use super::chain_api::{ChainDevFeeHandler, KernelHandler};
+use super::pool_api::PoolTxsHandler;
+use super::transactions_api::TxHashSetHandler;
+use super::utils::{get_output, w};
+use super::version_api::VersionHandler;
+use crate::chain;
+use crate::core::core::hash::{Hash, Hashed};
+use crate::core::core::Transaction;
+use crate::core::ser;
+use crate::json_rpc::{to_result, Params, RpcError, RpcMethods};
+use crate::pool;
+use crate::rest::ErrorKind;
+use crate::types::*;
+use crate::util;
+use crate::util::RwLock;
+use serde_json::Value;
+use std::sync::Weak;
+
+/// Chain and pool operations anyone can be allowed to call, served over
+/// JSON-RPC.
+/// POST /v2/foreign
+pub struct ForeignRpc {
+	pub chain: Weak<chain::Chain>,
+	pub tx_pool: Weak<RwLock<pool::TransactionPool>>,
+}
+
+impl ForeignRpc {
+	pub fn get_version(&self) -> Result<Version, RpcError> {
+		let handler = VersionHandler {
+			chain: self.chain.clone(),
+		};
+		Ok(handler.get_version()?)
+	}
+
+	pub fn get_tip(&self) -> Result<Tip, RpcError> {
+		let head = w(&self.chain)?.head()?;
+		Ok(Tip::from_tip(head))
+	}
+
+	/// Header given either its height, its hash or an unspent output commit.
+	pub fn get_header(
+		&self,
+		height: Option<u64>,
+		hash: Option<String>,
+		commit: Option<String>,
+	) -> Result<BlockHeaderPrintable, RpcError> {
+		let handler = HeaderHandler {
+			chain: self.chain.clone(),
+		};
+		let input = block_input(height, hash, commit)?;
+		Ok(handler.get_header(input)?)
+	}
+
+	/// Block given either its height, its hash or an unspent output commit.
+	pub fn get_block(
+		&self,
+		height: Option<u64>,
+		hash: Option<String>,
+		commit: Option<String>,
+	) -> Result<BlockPrintable, RpcError> {
+		let handler = BlockHandler {
+			chain: self.chain.clone(),
+		};
+		let hash = match commit {
+			Some(commit) => self.block_hash_for_output(&commit)?,
+			None => handler.parse_input(block_input(height, hash, None)?)?,
+		};
+		Ok(handler.get_block(&hash, true)?)
+	}
+
+	fn block_hash_for_output(&self, commit: &str) -> Result<Hash, RpcError> {
+		let output_id = get_output(&self.chain, commit)?.1;
+		let header = w(&self.chain)?.get_header_for_output(&output_id)?;
+		Ok(header.hash())
+	}
+
+	/// Kernel given its excess, optionally only looked for in the blocks
+	/// between the given heights.
+	pub fn get_kernel(
+		&self,
+		excess: String,
+		min_height: Option<u64>,
+		max_height: Option<u64>,
+	) -> Result<LocatedTxKernel, RpcError> {
+		let handler = KernelHandler {
+			chain: self.chain.clone(),
+		};
+		Ok(handler.find_kernel(&excess, min_height, max_height)?)
+	}
+
+	/// Unspent outputs for the given commits, missing ones left out.
+	pub fn get_outputs(&self, commits: Vec<String>) -> Result<Vec<Output>, RpcError> {
+		let mut outputs = vec![];
+		for commit in commits {
+			match get_output(&self.chain, &commit) {
+				Ok((output, _)) => outputs.push(output),
+				Err(e) => match e.kind() {
+					ErrorKind::NotFound => {}
+					_ => return Err(e.into()),
+				},
+			}
+		}
+		Ok(outputs)
+	}
+
+	/// Unspent outputs by insertion index in the output MMR.
+	pub fn get_unspent_outputs(
+		&self,
+		start_index: u64,
+		max: u64,
+	) -> Result<OutputListing, RpcError> {
+		let handler = TxHashSetHandler {
+			chain: self.chain.clone(),
+		};
+		Ok(handler.outputs(start_index, max)?)
+	}
+
+	pub fn get_dev_fee_outputs(&self) -> Result<Vec<DevFeeOutputPrintable>, RpcError> {
+		let handler = ChainDevFeeHandler {
+			chain: self.chain.clone(),
+		};
+		Ok(handler.get_dev_fee_outputs()?)
+	}
+
+	/// Coin supply at the chain head, optionally projected to a height.
+	pub fn get_supply(&self, height: Option<u64>) -> Result<Supply, RpcError> {
+		let head = w(&self.chain)?.head()?;
+		Ok(Supply::at_height(head.height, height)?)
+	}
+
+	pub fn get_pool_size(&self) -> Result<usize, RpcError> {
+		Ok(w(&self.tx_pool)?.read().total_size())
+	}
+
+	/// Transactions of the txpool, stem transactions aren't public.
+	pub fn get_unconfirmed_transactions(
+		&self,
+		start_index: usize,
+		max: usize,
+	) -> Result<PoolTxListing, RpcError> {
+		let handler = PoolTxsHandler {
+			tx_pool: self.tx_pool.clone(),
+		};
+		Ok(handler.pool_txs(start_index, max, false)?)
+	}
+
+	/// Transaction of the txpool with a kernel of the given hash.
+	pub fn get_unconfirmed_transaction(
+		&self,
+		kernel_hash: String,
+	) -> Result<PoolTxPrintable, RpcError> {
+		let handler = PoolTxsHandler {
+			tx_pool: self.tx_pool.clone(),
+		};
+		Ok(handler.pool_tx(&kernel_hash, false)?)
+	}
+
+	/// Fee rate to be mined within `blocks` blocks, see `TransactionPool::estimate_fee`.
+	pub fn estimate_fee(&self, blocks: u64, history: u64) -> Result<pool::FeeEstimate, RpcError> {
+		if blocks == 0 {
+			return Err(RpcError::invalid_params(
+				"blocks must be at least 1".to_owned(),
+			));
+		}
+		let pool = w(&self.tx_pool)?;
+		// walk the chain without holding the pool lock
+		let blockchain = pool.read().blockchain.clone();
+		let recent = pool::fee_estimate::recent_blocks(&*blockchain, history.min(1440))?;
+		Ok(pool.read().estimate_fee(blocks, &recent))
+	}
+
+	/// Pushes a hex-encoded serialized transaction to the pool, stemming it
+	/// unless `fluff` is set.
+	pub fn push_transaction(&self, tx_hex: String, fluff: bool) -> Result<(), RpcError> {
+		let tx_bin = util::from_hex(tx_hex)
+			.map_err(|e| RpcError::invalid_params(format!("tx_hex: {}", e)))?;
+		let tx: Transaction = ser::deserialize(&mut &tx_bin[..])
+			.map_err(|e| RpcError::invalid_params(format!("tx_hex: {}", e)))?;
+		let source = pool::TxSource {
+			debug_name: "push-rpc".to_string(),
+			identifier: "?.?.?.?".to_string(),
+		};
+		info!(
+			"Pushing transaction {} to pool (inputs: {}, outputs: {}, kernels: {})",
+			tx.hash(),
+			tx.inputs().len(),
+			tx.outputs().len(),
+			tx.kernels().len(),
+		);
+
+		let pool_arc = w(&self.tx_pool)?;
+		let mut tx_pool = pool_arc.write();
+		let header = tx_pool.blockchain.chain_head()?;
+		tx_pool.add_to_pool(source, tx, !fluff, &header)?;
+		Ok(())
+	}
+}
+
+/// Block lookup input the v1 handlers understand: a height, a hash or a
+/// commit, exactly one of them.
+fn block_input(
+	height: Option<u64>,
+	hash: Option<String>,
+	commit: Option<String>,
+) -> Result<String, RpcError> {
+	match (height, hash, commit) {
+		(Some(height), None, None) => Ok(height.to_string()),
+		(None, Some(hash), None) => Ok(hash),
+		(None, None, Some(commit)) => Ok(commit),
+		_ => Err(RpcError::invalid_params(
+			"expected one of height, hash or commit".to_owned(),
+		)),
+	}
+}
+
+impl RpcMethods for ForeignRpc {
+	fn call(&self, method: &str, params: &Params) -> Result<Value, RpcError> {
+		match method {
+			"get_version" => to_result(self.get_version()),
+			"get_tip" => to_result(self.get_tip()),
+			"get_header" => to_result(self.get_header(
+				params.get(0, "height")?,
+				params.get(1, "hash")?,
+				params.get(2, "commit")?,
+			)),
+			"get_block" => to_result(self.get_block(
+				params.get(0, "height")?,
+				params.get(1, "hash")?,
+				params.get(2, "commit")?,
+			)),
+			"get_kernel" => to_result(self.get_kernel(
+				params.required(0, "excess")?,
+				params.get(1, "min_height")?,
+				params.get(2, "max_height")?,
+			)),
+			"get_outputs" => to_result(self.get_outputs(params.required(0, "commits")?)),
+			"get_unspent_outputs" => to_result(self.get_unspent_outputs(
+				params.get(0, "start_index")?.unwrap_or(1),
+				params.get(1, "max")?.unwrap_or(100),
+			)),
+			"get_dev_fee_outputs" => to_result(self.get_dev_fee_outputs()),
+			"get_supply" => to_result(self.get_supply(params.get(0, "height")?)),
+			"get_pool_size" => to_result(self.get_pool_size()),
+			"get_unconfirmed_transactions" => to_result(self.get_unconfirmed_transactions(
+				params.get(0, "start_index")?.unwrap_or(0),
+				params.get(1, "max")?.unwrap_or(100),
+			)),
+			"get_unconfirmed_transaction" => {
+				to_result(self.get_unconfirmed_transaction(params.required(0, "kernel_hash")?))
+			}
+			"estimate_fee" => to_result(self.estimate_fee(
+				params.get(0, "blocks")?.unwrap_or(3),
+				params.get(1, "history")?.unwrap_or(60),
+			)),
+			"push_transaction" => to_result(self.push_transaction(
+				params.required(0, "tx_hex")?,
+				params.get(1, "fluff")?.unwrap_or(false),
+			)),
+			_ => Err(RpcError::method_not_found(method)),
+		}
+	}
+}
@@ -0,0 +1,142 @@
+// Copyright 2018 The BitGrin Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use super::pool_api::PoolTxsHandler;
+use super::server_api::StatusHandler;
+use super::utils::w;
+use crate::chain;
+use crate::json_rpc::{to_result, Params, RpcError, RpcMethods};
+use crate::p2p;
+use crate::p2p::types::{PeerAddr, PeerInfoDisplay, ReasonForBan};
+use crate::pool;
+use crate::types::*;
+use crate::util::RwLock;
+use serde_json::Value;
+use std::sync::Weak;
+
+/// Node administration operations, served over JSON-RPC behind the API
+/// secret.
+/// POST /v2/owner
+pub struct OwnerRpc {
+	pub chain: Weak<chain::Chain>,
+	pub tx_pool: Weak<RwLock<pool::TransactionPool>>,
+	pub peers: Weak<p2p::Peers>,
+}
+
+impl OwnerRpc {
+	pub fn get_status(&self) -> Result<Status, RpcError> {
+		let handler = StatusHandler {
+			chain: self.chain.clone(),
+			peers: self.peers.clone(),
+		};
+		Ok(handler.get_status()?)
+	}
+
+	pub fn validate_chain(&self) -> Result<(), RpcError> {
+		w(&self.chain)?.validate(true, true)?;
+		Ok(())
+	}
+
+	pub fn compact_chain(&self) -> Result<(), RpcError> {
+		w(&self.chain)?.compact()?;
+		Ok(())
+	}
+
+	/// Known peers, or the one with the given address.
+	pub fn get_peers(&self, addr: Option<String>) -> Result<Vec<p2p::PeerData>, RpcError> {
+		let peers = w(&self.peers)?;
+		match addr {
+			Some(addr) => match peers.get_peer(parse_peer_addr(&addr)?) {
+				Ok(peer) => Ok(vec![peer]),
+				Err(_) => Err(RpcError::not_found()),
+			},
+			None => Ok(peers.all_peers()),
+		}
+	}
+
+	pub fn get_connected_peers(&self) -> Result<Vec<PeerInfoDisplay>, RpcError> {
+		Ok(w(&self.peers)?
+			.connected_peers()
+			.iter()
+			.map(|p| p.info.clone().into())
+			.collect())
+	}
+
+	pub fn ban_peer(&self, addr: String) -> Result<(), RpcError> {
+		w(&self.peers)?.ban_peer(parse_peer_addr(&addr)?, ReasonForBan::ManualBan);
+		Ok(())
+	}
+
+	pub fn unban_peer(&self, addr: String) -> Result<(), RpcError> {
+		w(&self.peers)?.unban_peer(parse_peer_addr(&addr)?);
+		Ok(())
+	}
+
+	/// Transactions of both the txpool and the stempool.
+	pub fn get_pool_transactions(
+		&self,
+		start_index: usize,
+		max: usize,
+	) -> Result<PoolTxListing, RpcError> {
+		let handler = PoolTxsHandler {
+			tx_pool: self.tx_pool.clone(),
+		};
+		Ok(handler.pool_txs(start_index, max, true)?)
+	}
+
+	/// Transaction of the txpool or stempool with a kernel of the given hash.
+	pub fn get_pool_transaction(&self, kernel_hash: String) -> Result<PoolTxPrintable, RpcError> {
+		let handler = PoolTxsHandler {
+			tx_pool: self.tx_pool.clone(),
+		};
+		Ok(handler.pool_tx(&kernel_hash, true)?)
+	}
+}
+
+/// Peer address as either "ip" or "ip:port", the latter only really useful
+/// for local testing on loopback.
+fn parse_peer_addr(addr: &str) -> Result<PeerAddr, RpcError> {
+	if let Ok(ip_addr) = addr.parse() {
+		Ok(PeerAddr::from_ip(ip_addr))
+	} else if let Ok(addr) = addr.parse() {
+		Ok(PeerAddr(addr))
+	} else {
+		Err(RpcError::invalid_params(format!(
+			"invalid peer address: {}",
+			addr
+		)))
+	}
+}
+
+impl RpcMethods for OwnerRpc {
+	fn call(&self, method: &str, params: &Params) -> Result<Value, RpcError> {
+		match method {
+			"get_status" => to_result(self.get_status()),
+			"validate_chain" => to_result(self.validate_chain()),
+			"compact_chain" => to_result(self.compact_chain()),
+			"get_peers" => to_result(self.get_peers(params.get(0, "addr")?)),
+			"get_connected_peers" => to_result(self.get_connected_peers()),
+			"ban_peer" => to_result(self.ban_peer(params.required(0, "addr")?)),
+			"unban_peer" => to_result(self.unban_peer(params.required(0, "addr")?)),
+			"get_pool_transactions" => to_result(self.get_pool_transactions(
+				params.get(0, "start_index")?.unwrap_or(0),
+				params.get(1, "max")?.unwrap_or(100),
+			)),
+			"get_pool_transaction" => {
+				to_result(self.get_pool_transaction(params.required(0, "kernel_hash")?))
+			}
+			_ => Err(RpcError::method_not_found(method)),
+		}
+	}
+}
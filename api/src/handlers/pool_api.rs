@@ -13,7 +13,7 @@
 // limitations under the License.
 
 use super::utils::w;
-use crate::core::core::hash::Hashed;
+use crate::core::core::hash::{Hash, Hashed};
 use crate::core::core::Transaction;
 use crate::core::ser;
 use crate::pool;
@@ -27,6 +27,7 @@ use failure::ResultExt;
 use futures::future::{err, ok};
 use futures::Future;
 use hyper::{Body, Request, StatusCode};
+use std::cmp;
 use std::sync::Weak;
 
 /// Get basic information about the transaction pool.
@@ -46,6 +47,126 @@ impl Handler for PoolInfoHandler {
 	}
 }
 
+/// Whether the stem pool is asked for, false unless `stem=true` is given.
+fn stem_param(params: &QueryParams) -> Result<bool, Error> {
+	match params.get("stem") {
+		None => Ok(false),
+		Some(stem) => stem.parse().map_err(|_| {
+			ErrorKind::Argument(format!("invalid value of parameter stem: {}", stem)).into()
+		}),
+	}
+}
+
+/// Lists the transactions in the pool, or looks one up by the hash of one
+/// of its kernels. Stem pool transactions are only included when asked for.
+/// GET /v1/pool/txs?start_index=0&max=100&stem=true
+/// GET /v1/pool/txs/<kernel_hash>?stem=true
+pub struct PoolTxsHandler {
+	pub tx_pool: Weak<RwLock<pool::TransactionPool>>,
+}
+
+impl PoolTxsHandler {
+	fn list_txs(&self, req: Request<Body>) -> Result<PoolTxListing, Error> {
+		let params = QueryParams::from(req.uri().query());
+		let stem = stem_param(&params)?;
+		let start_index = parse_param!(params, "start_index", 0);
+		let max = parse_param!(params, "max", 100);
+		self.pool_txs(start_index, max, stem)
+	}
+
+	pub(crate) fn pool_txs(
+		&self,
+		start_index: usize,
+		max: usize,
+		stem: bool,
+	) -> Result<PoolTxListing, Error> {
+		let max = cmp::min(max, 1000);
+		let pool_arc = w(&self.tx_pool)?;
+		let pool = pool_arc.read();
+		let mut entries: Vec<_> = pool.txpool.entries.iter().map(|x| (x, false)).collect();
+		if stem {
+			entries.extend(pool.stempool.entries.iter().map(|x| (x, true)));
+		}
+		Ok(PoolTxListing {
+			total: entries.len(),
+			start_index,
+			txs: entries
+				.into_iter()
+				.skip(start_index)
+				.take(max)
+				.map(|(x, stem)| PoolTxPrintable::from_pool_entry(x, stem))
+				.collect(),
+		})
+	}
+
+	fn get_tx(&self, req: Request<Body>, kernel_hash: &str) -> Result<PoolTxPrintable, Error> {
+		let params = QueryParams::from(req.uri().query());
+		let stem = stem_param(&params)?;
+		self.pool_tx(kernel_hash, stem)
+	}
+
+	pub(crate) fn pool_tx(&self, kernel_hash: &str, stem: bool) -> Result<PoolTxPrintable, Error> {
+		let hash = Hash::from_hex(kernel_hash)
+			.map_err(|_| ErrorKind::Argument("invalid kernel hash".to_owned()))?;
+
+		let pool_arc = w(&self.tx_pool)?;
+		let pool = pool_arc.read();
+		if let Some(entry) = pool.txpool.retrieve_entry_by_kernel_hash(hash) {
+			return Ok(PoolTxPrintable::from_pool_entry(entry, false));
+		}
+		if stem {
+			if let Some(entry) = pool.stempool.retrieve_entry_by_kernel_hash(hash) {
+				return Ok(PoolTxPrintable::from_pool_entry(entry, true));
+			}
+		}
+		Err(ErrorKind::NotFound)?
+	}
+}
+
+impl Handler for PoolTxsHandler {
+	fn get(&self, req: Request<Body>) -> ResponseFuture {
+		match right_path_element!(req) {
+			"txs" => result_to_response(self.list_txs(req)),
+			kernel_hash => {
+				let kernel_hash = kernel_hash.to_owned();
+				result_to_response(self.get_tx(req, &kernel_hash))
+			}
+		}
+	}
+}
+
+/// Estimates the fee rate (per thousand units of tx weight) for a
+/// transaction to be mined within the given number of blocks, from the
+/// current pool backlog and the fees paid in recent blocks.
+/// GET /v1/pool/fee_estimate?blocks=3&history=60
+pub struct FeeEstimateHandler {
+	pub tx_pool: Weak<RwLock<pool::TransactionPool>>,
+}
+
+impl FeeEstimateHandler {
+	fn estimate_fee(&self, req: Request<Body>) -> Result<pool::FeeEstimate, Error> {
+		let params = QueryParams::from(req.uri().query());
+		let blocks = parse_param!(params, "blocks", 3);
+		if blocks == 0 {
+			return Err(ErrorKind::Argument("blocks must be at least 1".to_owned()))?;
+		}
+		let history = cmp::min(parse_param!(params, "history", 60), 1440);
+
+		let pool_arc = w(&self.tx_pool)?;
+		// walk the chain without holding the pool lock
+		let blockchain = pool_arc.read().blockchain.clone();
+		let recent = pool::fee_estimate::recent_blocks(&*blockchain, history)
+			.map_err(|e| ErrorKind::Internal(format!("can't estimate fee: {}", e)))?;
+		Ok(pool_arc.read().estimate_fee(blocks, &recent))
+	}
+}
+
+impl Handler for FeeEstimateHandler {
+	fn get(&self, req: Request<Body>) -> ResponseFuture {
+		result_to_response(self.estimate_fee(req))
+	}
+}
+
 /// Dummy wrapper for the hex-encoded serialized transaction.
 #[derive(Serialize, Deserialize)]
 struct TxWrapper {
@@ -121,3 +242,107 @@ impl Handler for PoolPushHandler {
 		)
 	}
 }
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::core::core::verifier_cache::LruVerifierCache;
+	use crate::core::core::{Block, BlockHeader, BlockSums, TxKernel};
+	use chrono::Utc;
+	use std::sync::Arc;
+
+	struct NoChain;
+
+	impl pool::BlockChain for NoChain {
+		fn verify_coinbase_maturity(&self, _tx: &Transaction) -> Result<(), pool::PoolError> {
+			Ok(())
+		}
+		fn verify_tx_lock_height(&self, _tx: &Transaction) -> Result<(), pool::PoolError> {
+			Ok(())
+		}
+		fn validate_tx(&self, _tx: &Transaction) -> Result<(), pool::PoolError> {
+			Ok(())
+		}
+		fn chain_head(&self) -> Result<BlockHeader, pool::PoolError> {
+			Err(pool::PoolError::Other("no chain".to_owned()))
+		}
+		fn get_block_header(&self, _hash: &Hash) -> Result<BlockHeader, pool::PoolError> {
+			Err(pool::PoolError::Other("no chain".to_owned()))
+		}
+		fn get_block(&self, _hash: &Hash) -> Result<Block, pool::PoolError> {
+			Err(pool::PoolError::Other("no chain".to_owned()))
+		}
+		fn get_block_sums(&self, _hash: &Hash) -> Result<BlockSums, pool::PoolError> {
+			Err(pool::PoolError::Other("no chain".to_owned()))
+		}
+	}
+
+	fn entry(fee: u64) -> pool::PoolEntry {
+		pool::PoolEntry {
+			src: pool::TxSource {
+				debug_name: "test".to_owned(),
+				identifier: "?.?.?.?".to_owned(),
+			},
+			tx_at: Utc::now(),
+			tx: Transaction::empty().with_kernel(TxKernel::empty().with_fee(fee)),
+		}
+	}
+
+	/// A pool with one transaction in the txpool and one in the stempool,
+	/// and the kernel hashes of both.
+	fn setup() -> (Arc<RwLock<pool::TransactionPool>>, Hash, Hash) {
+		let mut tx_pool = pool::TransactionPool::new(
+			pool::PoolConfig::default(),
+			Arc::new(NoChain),
+			Arc::new(RwLock::new(LruVerifierCache::new())),
+			Arc::new(pool::types::NoopAdapter {}),
+		);
+		let (tx, stem_tx) = (entry(1), entry(2));
+		let hashes = (tx.tx.kernels()[0].hash(), stem_tx.tx.kernels()[0].hash());
+		tx_pool.txpool.entries.push(tx);
+		tx_pool.stempool.entries.push(stem_tx);
+		(Arc::new(RwLock::new(tx_pool)), hashes.0, hashes.1)
+	}
+
+	fn request(uri: &str) -> Request<Body> {
+		Request::builder().uri(uri).body(Body::empty()).unwrap()
+	}
+
+	#[test]
+	fn list_txs_stem() {
+		let (tx_pool, _, _) = setup();
+		let handler = PoolTxsHandler {
+			tx_pool: Arc::downgrade(&tx_pool),
+		};
+		let list = |uri| handler.list_txs(request(uri));
+
+		let txs = list("/v1/pool/txs").unwrap();
+		assert_eq!(txs.total, 1);
+		assert!(!txs.txs[0].stem);
+		assert_eq!(list("/v1/pool/txs?stem=false").unwrap().total, 1);
+		let txs = list("/v1/pool/txs?stem=true").unwrap();
+		assert_eq!(txs.total, 2);
+		assert!(txs.txs[1].stem);
+		assert!(list("/v1/pool/txs?stem=yes").is_err());
+	}
+
+	#[test]
+	fn get_tx_stem() {
+		let (tx_pool, tx_hash, stem_hash) = setup();
+		let handler = PoolTxsHandler {
+			tx_pool: Arc::downgrade(&tx_pool),
+		};
+		let get = |hash: Hash, query: &str| {
+			let uri = format!("/v1/pool/txs/{}{}", hash.to_hex(), query);
+			handler.get_tx(request(&uri), &hash.to_hex())
+		};
+
+		for query in &["", "?stem=false", "?stem=true"] {
+			assert!(!get(tx_hash, query).unwrap().stem);
+		}
+		assert!(get(stem_hash, "").is_err());
+		assert!(get(stem_hash, "?stem=false").is_err());
+		assert!(get(stem_hash, "?stem=true").unwrap().stem);
+		assert!(get(tx_hash, "?stem=1").is_err());
+	}
+}
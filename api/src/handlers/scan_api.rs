@@ -0,0 +1,251 @@
+// Copyright 2018 The BitGrin Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use super::utils::w;
+use crate::chain;
+use crate::core::core::pmmr;
+use crate::core::core::{OutputFeatures, OutputIdentifier};
+use crate::core::global;
+use crate::core::libtx::proof;
+use crate::keychain::ViewKey;
+use crate::pool;
+use crate::rest::*;
+use crate::router::{Handler, ResponseFuture};
+use crate::types::*;
+use crate::util;
+use crate::util::secp::pedersen::Commitment;
+use crate::util::secp::{ContextFlag, Secp256k1};
+use crate::util::RwLock;
+use crate::web::*;
+use failure::ResultExt;
+use futures::sink::Wait;
+use futures::sync::mpsc;
+use futures::{future, Future, Sink, Stream};
+use hyper::{Body, Chunk, Request, Response, StatusCode};
+use std::collections::HashSet;
+use std::io;
+use std::sync::{Arc, Weak};
+use std::thread;
+
+/// Number of outputs read from the MMRs at once
+const SCAN_BATCH_SIZE: u64 = 1000;
+
+/// Number of lines buffered before the scan waits on the client
+const SCAN_BUFFER: usize = 64;
+
+/// Scans the unspent outputs of a range of blocks for the ones a view key
+/// owns, rewinding their rangeproofs. Owned outputs are streamed back as
+/// JSON lines while the scan goes on, followed by a summary.
+/// Outputs using a regular switch commitment can't be recognized by a view
+/// key yet and are skipped.
+/// POST /v2/owner/scan
+pub struct OutputScanHandler {
+	pub chain: Weak<chain::Chain>,
+	pub tx_pool: Weak<RwLock<pool::TransactionPool>>,
+}
+
+impl OutputScanHandler {
+	fn prepare(&self, req: OutputScanRequest) -> Result<OutputScan, Error> {
+		let chain = w(&self.chain)?;
+		let secp = Secp256k1::with_caps(ContextFlag::Commit);
+		let view_key = ViewKey::from_base58(&secp, &req.view_key, global::is_floonet())
+			.context(ErrorKind::Argument("Not a valid view key".to_owned()))?;
+
+		let head = chain
+			.head()
+			.context(ErrorKind::Internal("Can't get the chain head".to_owned()))?;
+		let start_height = req.start_height.unwrap_or(0);
+		let end_height = req.end_height.unwrap_or(head.height).min(head.height);
+		if start_height > end_height {
+			return Err(ErrorKind::Argument(format!(
+				"Invalid block range {} to {}",
+				start_height, end_height
+			)))?;
+		}
+
+		let pool_spent = {
+			let tx_pool = w(&self.tx_pool)?;
+			let tx_pool = tx_pool.read();
+			tx_pool
+				.txpool
+				.all_transactions()
+				.iter()
+				.chain(tx_pool.stempool.all_transactions().iter())
+				.flat_map(|tx| tx.inputs().iter().map(|i| i.commitment()))
+				.collect()
+		};
+
+		Ok(OutputScan {
+			chain,
+			secp,
+			view_key,
+			start_height,
+			end_height,
+			pool_spent,
+		})
+	}
+}
+
+impl Handler for OutputScanHandler {
+	fn post(&self, req: Request<Body>) -> ResponseFuture {
+		let chain = self.chain.clone();
+		let tx_pool = self.tx_pool.clone();
+		Box::new(
+			parse_body(req).then(move |req: Result<OutputScanRequest, Error>| {
+				let handler = OutputScanHandler { chain, tx_pool };
+				let scan = match req.and_then(|req| handler.prepare(req)) {
+					Ok(scan) => scan,
+					Err(e) => return result_to_response::<()>(Err(e)),
+				};
+				let (sender, receiver) = mpsc::channel(SCAN_BUFFER);
+				let res = thread::Builder::new()
+					.name("output_scan".to_string())
+					.spawn(move || scan.run(sender.wait()));
+				if let Err(e) = res {
+					return response(StatusCode::INTERNAL_SERVER_ERROR, format!("{}", e));
+				}
+				let stream =
+					receiver.map_err(|_| io::Error::new(io::ErrorKind::Other, "scan stopped"));
+				let res = Response::builder()
+					.status(StatusCode::OK)
+					.header("content-type", "application/x-ndjson")
+					.body(Body::wrap_stream(stream));
+				match res {
+					Ok(res) => Box::new(future::ok(res)),
+					Err(e) => response(StatusCode::INTERNAL_SERVER_ERROR, format!("{}", e)),
+				}
+			}),
+		)
+	}
+}
+
+/// A validated output scan, run on its own thread
+struct OutputScan {
+	chain: Arc<chain::Chain>,
+	secp: Secp256k1,
+	view_key: ViewKey,
+	start_height: u64,
+	end_height: u64,
+	/// Commitments spent by the transactions of the pool
+	pool_spent: HashSet<Commitment>,
+}
+
+impl OutputScan {
+	fn run(self, mut sink: Wait<mpsc::Sender<Chunk>>) {
+		let mut emit = |item: OutputScanItem| -> Result<(), Error> {
+			let mut line = serde_json::to_string(&item)
+				.context(ErrorKind::Internal("Can't serialize output".to_owned()))?;
+			line.push('\n');
+			sink.send(line.into())
+				.map_err(|_| ErrorKind::ResponseError("Client went away".to_owned()).into())
+		};
+		let last = match self.scan(&mut emit) {
+			Ok(summary) => OutputScanItem::Done(summary),
+			Err(e) => match e.kind() {
+				ErrorKind::ResponseError(_) => return,
+				_ => OutputScanItem::Error {
+					message: format!("{}", e),
+				},
+			},
+		};
+		if emit(last).is_ok() {
+			let _ = sink.flush();
+		}
+	}
+
+	/// Leaf index of the last output of the block at the given height
+	fn last_index(&self, height: u64) -> Result<u64, Error> {
+		let header = self
+			.chain
+			.get_header_by_height(height)
+			.context(ErrorKind::Internal(format!("Can't get header {}", height)))?;
+		Ok(pmmr::n_leaves(header.output_mmr_size))
+	}
+
+	fn scan<F>(&self, emit: &mut F) -> Result<OutputScanSummary, Error>
+	where
+		F: FnMut(OutputScanItem) -> Result<(), Error>,
+	{
+		let end_index = self.last_index(self.end_height)?;
+		let mut index = match self.start_height {
+			0 => 1,
+			h => self.last_index(h - 1)? + 1,
+		};
+		// height of the block the next owned output is looked for in
+		let mut height = self.start_height;
+		let mut height_index = self.last_index(height)?;
+		let mut scanned = 0;
+		let mut found = 0;
+
+		while index <= end_index {
+			let max = SCAN_BATCH_SIZE.min(end_index - index + 1);
+			let (last_index, _, outputs) = self
+				.chain
+				.unspent_outputs_by_insertion_index(index, max)
+				.context(ErrorKind::Internal("Can't read outputs".to_owned()))?;
+			if outputs.is_empty() {
+				break;
+			}
+			for output in outputs {
+				scanned += 1;
+				// outputs with a regular switch commitment fail to rewind
+				let (amount, key_id, _) = match proof::rewind(
+					&self.secp,
+					&self.view_key,
+					output.commit,
+					None,
+					output.proof,
+				) {
+					Ok(Some(res)) => res,
+					_ => continue,
+				};
+				let mmr_index = self
+					.chain
+					.get_output_pos(&output.commit)
+					.context(ErrorKind::Internal("Can't get output position".to_owned()))?;
+				if pmmr::n_leaves(mmr_index) > end_index {
+					break;
+				}
+				while pmmr::n_leaves(mmr_index) > height_index {
+					height += 1;
+					height_index = self.last_index(height)?;
+				}
+				let spent = self.pool_spent.contains(&output.commit)
+					|| self
+						.chain
+						.is_unspent(&OutputIdentifier::from_output(&output))
+						.is_err();
+				found += 1;
+				emit(OutputScanItem::Output(ScannedOutput {
+					commit: util::to_hex(output.commit.0.to_vec()),
+					amount,
+					key_id: key_id.to_hex(),
+					path: key_id.to_bip_32_string(),
+					is_coinbase: output.features == OutputFeatures::Coinbase,
+					height,
+					mmr_index,
+					spent,
+				}))?;
+			}
+			index = last_index + 1;
+		}
+
+		Ok(OutputScanSummary {
+			start_height: self.start_height,
+			end_height: self.end_height,
+			scanned,
+			found,
+		})
+	}
+}
@@ -67,7 +67,7 @@ pub struct StatusHandler {
 }
 
 impl StatusHandler {
-	fn get_status(&self) -> Result<Status, Error> {
+	pub(crate) fn get_status(&self) -> Result<Status, Error> {
 		let head = w(&self.chain)?
 			.head()
 			.map_err(|e| ErrorKind::Internal(format!("can't get head: {}", e)))?;
@@ -83,3 +83,31 @@ impl Handler for StatusHandler {
 		result_to_response(self.get_status())
 	}
 }
+
+/// Supply handler. Coin supply at the chain head, optionally the emission
+/// projected to a given height.
+/// GET /v1/supply
+/// GET /v1/supply?height=1000000
+pub struct SupplyHandler {
+	pub chain: Weak<chain::Chain>,
+}
+
+impl SupplyHandler {
+	fn get_supply(&self, req: Request<Body>) -> Result<Supply, Error> {
+		let head = w(&self.chain)?
+			.head()
+			.map_err(|e| ErrorKind::Internal(format!("can't get head: {}", e)))?;
+		let params = QueryParams::from(req.uri().query());
+		let projected_height = match params.get("height") {
+			Some(_) => Some(parse_param!(params, "height", 0)),
+			None => None,
+		};
+		Supply::at_height(head.height, projected_height)
+	}
+}
+
+impl Handler for SupplyHandler {
+	fn get(&self, req: Request<Body>) -> ResponseFuture {
+		result_to_response(self.get_supply(req))
+	}
+}
@@ -0,0 +1,413 @@
+// Copyright 2018 The BitGrin Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! JSON-RPC 2.0 over HTTP POST. An `RpcMethods` implementation dispatches
+//! method calls, `JsonRpcHandler` takes care of the protocol: single and
+//! batch requests, notifications and the error objects.
+
+use crate::chain;
+use crate::pool::PoolError;
+use crate::rest::{Error, ErrorKind};
+use crate::router::{Handler, ResponseFuture};
+use crate::store;
+use crate::web::{read_body, response};
+use futures::future::ok;
+use futures::Future;
+use hyper::header::CONTENT_TYPE;
+use hyper::{Body, Request, Response, StatusCode};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::{self, json, Map, Value};
+use std::fmt;
+use std::sync::Arc;
+
+/// Invalid JSON was received.
+pub const PARSE_ERROR: i64 = -32700;
+/// The JSON sent is not a valid request object.
+pub const INVALID_REQUEST: i64 = -32600;
+/// The method does not exist.
+pub const METHOD_NOT_FOUND: i64 = -32601;
+/// Invalid method parameters.
+pub const INVALID_PARAMS: i64 = -32602;
+/// Internal error.
+pub const INTERNAL_ERROR: i64 = -32603;
+/// What was asked for doesn't exist.
+pub const NOT_FOUND: i64 = -32000;
+/// The chain rejected the operation, `data.kind` is the `chain::ErrorKind`.
+pub const CHAIN_ERROR: i64 = -32001;
+/// The pool rejected the operation, `data.kind` is the `PoolError`.
+pub const POOL_ERROR: i64 = -32002;
+
+/// Error object returned in a JSON-RPC response.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RpcError {
+	pub code: i64,
+	pub message: String,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub data: Option<Value>,
+}
+
+impl RpcError {
+	pub fn new(code: i64, message: String) -> RpcError {
+		RpcError {
+			code,
+			message,
+			data: None,
+		}
+	}
+
+	pub fn invalid_request(message: &str) -> RpcError {
+		RpcError::new(INVALID_REQUEST, format!("Invalid request: {}", message))
+	}
+
+	pub fn method_not_found(method: &str) -> RpcError {
+		RpcError::new(METHOD_NOT_FOUND, format!("Method not found: {}", method))
+	}
+
+	pub fn invalid_params(message: String) -> RpcError {
+		RpcError::new(INVALID_PARAMS, format!("Invalid params: {}", message))
+	}
+
+	pub fn internal(message: String) -> RpcError {
+		RpcError::new(INTERNAL_ERROR, format!("Internal error: {}", message))
+	}
+
+	pub fn not_found() -> RpcError {
+		RpcError::new(NOT_FOUND, "Not found".to_owned())
+	}
+
+	/// Error carrying the kind of the underlying error, for clients to match
+	/// on, as `data.kind`.
+	fn with_kind<K: fmt::Debug>(code: i64, message: String, kind: &K) -> RpcError {
+		RpcError {
+			code,
+			message,
+			data: Some(json!({ "kind": variant_name(kind) })),
+		}
+	}
+}
+
+impl fmt::Display for RpcError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "{} ({})", self.message, self.code)
+	}
+}
+
+/// Name of an enum variant, without its fields.
+fn variant_name<K: fmt::Debug>(kind: &K) -> String {
+	format!("{:?}", kind)
+		.chars()
+		.take_while(|c| c.is_alphanumeric() || *c == '_')
+		.collect()
+}
+
+impl From<Error> for RpcError {
+	fn from(e: Error) -> RpcError {
+		match e.kind() {
+			ErrorKind::Argument(msg) | ErrorKind::RequestError(msg) => {
+				RpcError::invalid_params(msg.clone())
+			}
+			ErrorKind::NotFound => RpcError::not_found(),
+			ErrorKind::Internal(msg) | ErrorKind::ResponseError(msg) => {
+				RpcError::internal(msg.clone())
+			}
+		}
+	}
+}
+
+impl From<chain::Error> for RpcError {
+	fn from(e: chain::Error) -> RpcError {
+		let kind = e.kind();
+		match kind {
+			chain::ErrorKind::OutputNotFound
+			| chain::ErrorKind::RangeproofNotFound
+			| chain::ErrorKind::TxKernelNotFound
+			| chain::ErrorKind::StoreErr(store::Error::NotFoundErr(_), _) => {
+				RpcError::with_kind(NOT_FOUND, format!("{}", kind), &kind)
+			}
+			_ => RpcError::with_kind(CHAIN_ERROR, format!("{}", kind), &kind),
+		}
+	}
+}
+
+impl From<PoolError> for RpcError {
+	fn from(e: PoolError) -> RpcError {
+		RpcError::with_kind(POOL_ERROR, format!("{}", e), &e)
+	}
+}
+
+/// Parameters of a call, either positional or by name.
+pub struct Params(Value);
+
+impl Params {
+	/// Optional parameter, at `index` when positional or named `name`.
+	pub fn get<T: DeserializeOwned>(
+		&self,
+		index: usize,
+		name: &str,
+	) -> Result<Option<T>, RpcError> {
+		let value = match self.0 {
+			Value::Array(ref params) => params.get(index),
+			Value::Object(ref params) => params.get(name),
+			_ => None,
+		};
+		match value {
+			None | Some(Value::Null) => Ok(None),
+			Some(value) => serde_json::from_value(value.clone())
+				.map(Some)
+				.map_err(|e| RpcError::invalid_params(format!("{}: {}", name, e))),
+		}
+	}
+
+	/// Mandatory parameter, at `index` when positional or named `name`.
+	pub fn required<T: DeserializeOwned>(&self, index: usize, name: &str) -> Result<T, RpcError> {
+		self.get(index, name)?
+			.ok_or_else(|| RpcError::invalid_params(format!("missing {}", name)))
+	}
+}
+
+/// Serializes the result of a call.
+pub fn to_result<T: Serialize>(res: Result<T, RpcError>) -> Result<Value, RpcError> {
+	res.and_then(|v| {
+		serde_json::to_value(v).map_err(|e| RpcError::internal(format!("serialization: {}", e)))
+	})
+}
+
+/// A set of methods callable through JSON-RPC.
+pub trait RpcMethods {
+	/// Calls `method`, failing with `RpcError::method_not_found` if there's
+	/// no such method.
+	fn call(&self, method: &str, params: &Params) -> Result<Value, RpcError>;
+}
+
+/// Serves JSON-RPC 2.0 requests for a set of methods.
+/// POST /v2/foreign
+/// POST /v2/owner
+pub struct JsonRpcHandler {
+	pub methods: Arc<dyn RpcMethods + Send + Sync>,
+}
+
+impl JsonRpcHandler {
+	/// Handles a single or batch request body, returning the response body,
+	/// if any.
+	pub fn handle(&self, body: &[u8]) -> Option<Value> {
+		let request: Value = match serde_json::from_slice(body) {
+			Ok(request) => request,
+			Err(e) => {
+				let error = RpcError::new(PARSE_ERROR, format!("Parse error: {}", e));
+				return Some(error_response(error, Value::Null));
+			}
+		};
+		match request {
+			Value::Array(requests) => {
+				if requests.is_empty() {
+					let error = RpcError::invalid_request("empty batch");
+					return Some(error_response(error, Value::Null));
+				}
+				let responses: Vec<Value> = requests
+					.into_iter()
+					.filter_map(|r| self.handle_request(r))
+					.collect();
+				if responses.is_empty() {
+					None
+				} else {
+					Some(Value::Array(responses))
+				}
+			}
+			request => self.handle_request(request),
+		}
+	}
+
+	/// Handles a single request, returning nothing for notifications.
+	fn handle_request(&self, request: Value) -> Option<Value> {
+		let mut request = match request {
+			Value::Object(request) => request,
+			_ => {
+				let error = RpcError::invalid_request("not an object");
+				return Some(error_response(error, Value::Null));
+			}
+		};
+		let id = request.remove("id");
+		let res = parse_request(&request).and_then(|(method, params)| {
+			debug!("json-rpc call {}", method);
+			self.methods.call(method, &params)
+		});
+		let id = id?;
+		Some(match res {
+			Ok(result) => json!({ "jsonrpc": "2.0", "result": result, "id": id }),
+			Err(e) => error_response(e, id),
+		})
+	}
+}
+
+fn parse_request(request: &Map<String, Value>) -> Result<(&str, Params), RpcError> {
+	if request.get("jsonrpc") != Some(&Value::from("2.0")) {
+		return Err(RpcError::invalid_request("jsonrpc must be \"2.0\""));
+	}
+	let method = match request.get("method") {
+		Some(Value::String(method)) => method,
+		_ => return Err(RpcError::invalid_request("missing method")),
+	};
+	let params = match request.get("params") {
+		None => Value::Null,
+		Some(params @ Value::Array(_)) | Some(params @ Value::Object(_)) => params.clone(),
+		Some(_) => return Err(RpcError::invalid_request("params must be structured")),
+	};
+	Ok((method, Params(params)))
+}
+
+fn error_response(error: RpcError, id: Value) -> Value {
+	json!({ "jsonrpc": "2.0", "error": error, "id": id })
+}
+
+impl Handler for JsonRpcHandler {
+	fn post(&self, req: Request<Body>) -> ResponseFuture {
+		let methods = self.methods.clone();
+		Box::new(read_body(req).then(move |body| {
+			let body = match body {
+				Ok(body) => body,
+				Err(e) => return response(StatusCode::BAD_REQUEST, format!("{}", e)),
+			};
+			let handler = JsonRpcHandler { methods };
+			let res = match handler.handle(&body) {
+				None => Response::new(Body::empty()),
+				Some(res) => match serde_json::to_string(&res) {
+					Ok(res) => Response::builder()
+						.status(StatusCode::OK)
+						.header(CONTENT_TYPE, "application/json")
+						.body(res.into())
+						.unwrap(),
+					Err(e) => {
+						let mut res = Response::new(format!("{}", e).into());
+						*res.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
+						res
+					}
+				},
+			};
+			Box::new(ok(res))
+		}))
+	}
+
+	fn get(&self, _req: Request<Body>) -> ResponseFuture {
+		response(StatusCode::METHOD_NOT_ALLOWED, "json-rpc requires POST")
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	struct Echo;
+
+	impl RpcMethods for Echo {
+		fn call(&self, method: &str, params: &Params) -> Result<Value, RpcError> {
+			match method {
+				"echo" => to_result(params.required::<String>(0, "msg")),
+				"add" => {
+					let a: u64 = params.required(0, "a")?;
+					let b: u64 = params.get(1, "b")?.unwrap_or(1);
+					to_result(Ok(a + b))
+				}
+				"fail" => Err(PoolError::LowFeeTransaction(8).into()),
+				_ => Err(RpcError::method_not_found(method)),
+			}
+		}
+	}
+
+	fn call(body: &str) -> Option<Value> {
+		let handler = JsonRpcHandler {
+			methods: Arc::new(Echo),
+		};
+		handler.handle(body.as_bytes())
+	}
+
+	fn error_code(res: &Value) -> i64 {
+		res["error"]["code"].as_i64().unwrap()
+	}
+
+	#[test]
+	fn json_rpc_calls() {
+		let res = call(r#"{"jsonrpc":"2.0","method":"echo","params":["hi"],"id":1}"#).unwrap();
+		assert_eq!(res, json!({"jsonrpc": "2.0", "result": "hi", "id": 1}));
+		let res = call(r#"{"jsonrpc":"2.0","method":"add","params":{"a":2},"id":"x"}"#).unwrap();
+		assert_eq!(res["result"], json!(3));
+		assert_eq!(res["id"], json!("x"));
+
+		let res = call(r#"{"jsonrpc":"2.0","method":"add","params":[2,"3"],"id":1}"#).unwrap();
+		assert_eq!(error_code(&res), INVALID_PARAMS);
+		let res = call(r#"{"jsonrpc":"2.0","method":"add","id":1}"#).unwrap();
+		assert_eq!(error_code(&res), INVALID_PARAMS);
+		let res = call(r#"{"jsonrpc":"2.0","method":"nope","id":1}"#).unwrap();
+		assert_eq!(error_code(&res), METHOD_NOT_FOUND);
+		let res = call(r#"{"jsonrpc":"1.0","method":"echo","id":1}"#).unwrap();
+		assert_eq!(error_code(&res), INVALID_REQUEST);
+		let res = call(r#"{"jsonrpc":"2.0","method":"echo","params":"hi","id":1}"#).unwrap();
+		assert_eq!(error_code(&res), INVALID_REQUEST);
+		let res = call(r#"{"jsonrpc":"2.0","method""#).unwrap();
+		assert_eq!(error_code(&res), PARSE_ERROR);
+		assert_eq!(res["id"], Value::Null);
+
+		let res = call(r#"{"jsonrpc":"2.0","method":"fail","id":1}"#).unwrap();
+		assert_eq!(error_code(&res), POOL_ERROR);
+		assert_eq!(res["error"]["data"]["kind"], json!("LowFeeTransaction"));
+	}
+
+	#[test]
+	fn json_rpc_batches() {
+		// notifications get no response
+		assert!(call(r#"{"jsonrpc":"2.0","method":"echo","params":["hi"]}"#).is_none());
+		assert!(call(r#"[{"jsonrpc":"2.0","method":"echo","params":["hi"]}]"#).is_none());
+
+		let res = call(
+			r#"[
+				{"jsonrpc":"2.0","method":"echo","params":["a"],"id":1},
+				{"jsonrpc":"2.0","method":"echo","params":["b"]},
+				{"jsonrpc":"2.0","method":"nope","id":2},
+				3
+			]"#,
+		)
+		.unwrap();
+		let res = res.as_array().unwrap();
+		assert_eq!(res.len(), 3);
+		assert_eq!(res[0]["result"], json!("a"));
+		assert_eq!(error_code(&res[1]), METHOD_NOT_FOUND);
+		assert_eq!(error_code(&res[2]), INVALID_REQUEST);
+
+		let res = call("[]").unwrap();
+		assert_eq!(error_code(&res), INVALID_REQUEST);
+	}
+
+	#[test]
+	fn json_rpc_body_limit() {
+		let handler = JsonRpcHandler {
+			methods: Arc::new(Echo),
+		};
+		let post = |body: String| {
+			let req = Request::post("/v2/foreign").body(body.into()).unwrap();
+			Future::wait(handler.post(req)).unwrap().status()
+		};
+		let msg = "x".repeat(1000);
+		let body = format!(
+			r#"{{"jsonrpc":"2.0","method":"echo","params":["{}"],"id":1}}"#,
+			msg
+		);
+		assert_eq!(post(body), StatusCode::OK);
+		let msg = "x".repeat(crate::web::MAX_BODY_SIZE);
+		let body = format!(
+			r#"{{"jsonrpc":"2.0","method":"echo","params":["{}"],"id":1}}"#,
+			msg
+		);
+		assert_eq!(post(body), StatusCode::BAD_REQUEST);
+	}
+}
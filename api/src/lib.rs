@@ -14,9 +14,10 @@
 
 use bitgrin_chain as chain;
 use bitgrin_core as core;
+use bitgrin_keychain as keychain;
 use bitgrin_p2p as p2p;
 use bitgrin_pool as pool;
-
+use bitgrin_store as store;
 use bitgrin_util as util;
 
 use failure;
@@ -35,6 +36,7 @@ mod web;
 pub mod auth;
 pub mod client;
 mod handlers;
+pub mod json_rpc;
 mod rest;
 mod router;
 mod types;
@@ -128,6 +128,16 @@ impl Router {
 		self.node_mut(NodeId(0)).add_middleware(mw);
 	}
 
+	/// Adds a middleware to all the routes under the given path.
+	pub fn add_path_middleware(&mut self, path: &str, mw: HandlerObj) -> Result<(), RouterError> {
+		let mut node_id = self.root();
+		for key in generate_path(path) {
+			node_id = self.find(node_id, key).ok_or(RouterError::RouteNotFound)?;
+		}
+		self.node_mut(node_id).add_middleware(mw);
+		Ok(())
+	}
+
 	fn root(&self) -> NodeId {
 		NodeId(0)
 	}
@@ -336,6 +346,8 @@ mod tests {
 		routes
 			.add_route("/v1/zzz/*/zzz", Arc::new(HandlerImpl(6)))
 			.unwrap();
+		routes.add_path_middleware("/v1/zzz", h1.clone()).unwrap();
+		assert!(routes.add_path_middleware("/v2", h1.clone()).is_err());
 	}
 
 	#[test]
@@ -15,15 +15,20 @@
 use std::sync::Arc;
 
 use crate::chain;
+use crate::chain::hypersync::HypersyncManifest;
 use crate::core::core::hash::Hashed;
 use crate::core::core::merkle_proof::MerkleProof;
-use crate::core::{core, ser};
+use crate::core::{consensus, core, ser};
 use crate::p2p;
+use crate::pool;
+use crate::rest::{Error, ErrorKind};
 use crate::util;
 use crate::util::secp::pedersen;
+use chrono::prelude::Utc;
 use serde;
 use serde::de::MapAccess;
 use serde::ser::SerializeStruct;
+use std::convert::TryFrom;
 use std::fmt;
 
 macro_rules! no_dup {
@@ -70,6 +75,19 @@ impl Tip {
 	}
 }
 
+/// Status of the hypersync snapshot packaged on request by the node
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum SnapshotStatus {
+	/// No snapshot requested since the node started
+	Idle,
+	/// Packaging the snapshot at the given height
+	InProgress(u64),
+	/// Last snapshot packaged, with its manifest
+	Done(Box<HypersyncManifest>),
+	/// Last snapshot failed, with the reason why
+	Failed(String),
+}
+
 /// Status page containing different server information
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Status {
@@ -94,6 +112,102 @@ impl Status {
 	}
 }
 
+/// Coin supply at the chain head, all amounts in nanobitgrins
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Supply {
+	/// Height of the chain head
+	pub height: u64,
+	/// Everything emitted up to the head
+	pub total: u64,
+	/// Emitted minus the dev fee still locked
+	pub circulating: u64,
+	/// Number of dev fee outputs still locked
+	pub locked_dev_fee_outputs: u64,
+	/// Amount in the dev fee outputs still locked
+	pub locked_dev_fee: u64,
+	/// Height the emission was projected to, when requested
+	pub projected_height: Option<u64>,
+	/// Everything emitted by that height
+	pub projected_total: Option<u64>,
+}
+
+impl Supply {
+	/// Supply at the given head height, with the emission projected to
+	/// another height if requested. Projections past
+	/// `consensus::MAX_PROJECTED_HEIGHT` are refused.
+	pub fn at_height(height: u64, projected_height: Option<u64>) -> Result<Supply, Error> {
+		if let Some(h) = projected_height {
+			if h > consensus::MAX_PROJECTED_HEIGHT {
+				return Err(ErrorKind::Argument(format!(
+					"height {} past the maximum projected height {}",
+					h,
+					consensus::MAX_PROJECTED_HEIGHT
+				)))?;
+			}
+		}
+		let total = overage_at_height(height)?;
+		let (locked_dev_fee_outputs, locked_dev_fee) = consensus::locked_dev_fee_at_height(height);
+		let projected_total = match projected_height {
+			Some(h) => Some(overage_at_height(h)?),
+			None => None,
+		};
+		Ok(Supply {
+			height,
+			total,
+			circulating: total.saturating_sub(locked_dev_fee),
+			locked_dev_fee_outputs,
+			locked_dev_fee,
+			projected_height,
+			projected_total,
+		})
+	}
+}
+
+/// Total overage at a height as an amount.
+fn overage_at_height(height: u64) -> Result<u64, Error> {
+	let overage = consensus::total_overage_at_height(height);
+	u64::try_from(overage).map_err(|_| {
+		ErrorKind::Internal(format!("invalid overage {} at height {}", overage, height)).into()
+	})
+}
+
+/// A dev fee payout and whether it vested yet
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct DevFeeOutputPrintable {
+	/// Height of the block paying it out
+	pub height: u64,
+	/// Amount paid out, in nanobitgrins
+	pub amount: u64,
+	/// Height from which it can be spent
+	pub lock_height: u64,
+	/// Whether it can't be spent yet at the chain head
+	pub locked: bool,
+	/// Whether it is still unspent
+	pub unspent: bool,
+	/// Coinbase output commitment (as hex string), unknown once spent and
+	/// its block removed
+	pub commit: Option<String>,
+	/// Coinbase kernel excess (as hex string)
+	pub kernel_excess: Option<String>,
+}
+
+impl DevFeeOutputPrintable {
+	pub fn from_dev_fee_output(
+		output: &chain::DevFeeOutput,
+		head_height: u64,
+	) -> DevFeeOutputPrintable {
+		DevFeeOutputPrintable {
+			height: output.height,
+			amount: output.amount,
+			lock_height: output.lock_height,
+			locked: head_height < output.lock_height,
+			unspent: output.unspent,
+			commit: output.commit.map(|c| util::to_hex(c.0.to_vec())),
+			kernel_excess: output.kernel_excess.map(|c| util::to_hex(c.0.to_vec())),
+		}
+	}
+}
+
 /// TxHashSet
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct TxHashSet {
@@ -501,6 +615,18 @@ impl TxKernelPrintable {
 	}
 }
 
+/// A kernel and where it was found on chain
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct LocatedTxKernel {
+	pub tx_kernel: TxKernelPrintable,
+	/// Height of the block including it
+	pub height: u64,
+	/// Hash of the block including it
+	pub block_hash: String,
+	/// Position in the kernel MMR
+	pub mmr_index: u64,
+}
+
 // Just the information required for wallet reconstruction
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct BlockHeaderInfo {
@@ -700,6 +826,144 @@ pub struct PoolInfo {
 	pub pool_size: usize,
 }
 
+/// A transaction waiting in the pool
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PoolTxPrintable {
+	/// Hash of the transaction
+	pub tx_hash: String,
+	/// Whether the transaction is still in the stem pool
+	pub stem: bool,
+	/// Total fee
+	pub fee: u64,
+	/// Weight used by the pool to prioritize transactions
+	pub weight: usize,
+	/// Fee per thousand units of weight
+	pub fee_rate: u64,
+	/// Where we heard about the transaction from
+	pub source: String,
+	/// Identifier of the source, usually the peer address
+	pub source_id: String,
+	/// When the transaction was added to the pool
+	pub received_at: String,
+	/// Seconds since the transaction was added to the pool
+	pub age: i64,
+	/// Commitments of the inputs spent
+	pub inputs: Vec<String>,
+	/// Commitments of the outputs created
+	pub outputs: Vec<String>,
+	/// The kernels, along with their hash
+	pub kernels: Vec<PoolTxKernel>,
+}
+
+/// A kernel of a pool transaction along with its hash, used to look
+/// the transaction up
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PoolTxKernel {
+	pub hash: String,
+	pub tx_kernel: TxKernelPrintable,
+}
+
+impl PoolTxPrintable {
+	pub fn from_pool_entry(entry: &pool::PoolEntry, stem: bool) -> PoolTxPrintable {
+		let tx = &entry.tx;
+		PoolTxPrintable {
+			tx_hash: tx.hash().to_hex(),
+			stem,
+			fee: tx.fee(),
+			weight: tx.tx_weight(),
+			fee_rate: tx.fee_to_weight(),
+			source: entry.src.debug_name.clone(),
+			source_id: entry.src.identifier.clone(),
+			received_at: entry.tx_at.to_rfc3339(),
+			age: Utc::now().signed_duration_since(entry.tx_at).num_seconds(),
+			inputs: tx
+				.inputs()
+				.iter()
+				.map(|i| util::to_hex(i.commitment().0.to_vec()))
+				.collect(),
+			outputs: tx
+				.outputs()
+				.iter()
+				.map(|o| util::to_hex(o.commitment().0.to_vec()))
+				.collect(),
+			kernels: tx
+				.kernels()
+				.iter()
+				.map(|k| PoolTxKernel {
+					hash: k.hash().to_hex(),
+					tx_kernel: TxKernelPrintable::from_txkernel(k),
+				})
+				.collect(),
+		}
+	}
+}
+
+/// A page of pool transactions
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PoolTxListing {
+	/// Number of transactions matching the query
+	pub total: usize,
+	/// Offset of the first transaction returned
+	pub start_index: usize,
+	/// The transactions
+	pub txs: Vec<PoolTxPrintable>,
+}
+
+/// Request of an output scan: the view key, in its base58check string
+/// format, and the heights of the blocks whose outputs to scan, genesis to
+/// the head by default
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct OutputScanRequest {
+	pub view_key: String,
+	#[serde(default)]
+	pub start_height: Option<u64>,
+	#[serde(default)]
+	pub end_height: Option<u64>,
+}
+
+/// An unspent output owned by the key of an output scan
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct ScannedOutput {
+	/// The output commitment (as hex string)
+	pub commit: String,
+	/// Amount recovered from the rangeproof
+	pub amount: u64,
+	/// Identifier of the key the output was built with (as hex string)
+	pub key_id: String,
+	/// Derivation path of the key
+	pub path: String,
+	/// Whether the output is a coinbase one
+	pub is_coinbase: bool,
+	/// Height of the block which contains the output
+	pub height: u64,
+	/// MMR Index of output
+	pub mmr_index: u64,
+	/// Whether a transaction of the pool spends the output, or a block did
+	/// while scanning
+	pub spent: bool,
+}
+
+/// Totals of an output scan
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct OutputScanSummary {
+	pub start_height: u64,
+	pub end_height: u64,
+	/// Number of unspent outputs whose rangeproof got rewound
+	pub scanned: u64,
+	/// Number of them owned by the key
+	pub found: u64,
+}
+
+/// A line of the output scan stream: an owned output, followed by the
+/// summary once done or the error that stopped the scan
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum OutputScanItem {
+	Output(ScannedOutput),
+	Done(OutputScanSummary),
+	Error { message: String },
+}
+
 #[cfg(test)]
 mod test {
 	use super::*;
@@ -735,4 +999,21 @@ mod test {
 		let serialized = serde_json::to_string(&deserialized).unwrap();
 		assert_eq!(serialized, hex_commit);
 	}
+
+	#[test]
+	fn serialize_output_scan_item() {
+		let done = OutputScanItem::Done(OutputScanSummary {
+			start_height: 0,
+			end_height: 10,
+			scanned: 12,
+			found: 1,
+		});
+		let serialized = serde_json::to_string(&done).unwrap();
+		assert_eq!(
+			serialized,
+			"{\"type\":\"done\",\"start_height\":0,\"end_height\":10,\"scanned\":12,\"found\":1}"
+		);
+		let deserialized: OutputScanItem = serde_json::from_str(&serialized).unwrap();
+		assert_eq!(deserialized, done);
+	}
 }
@@ -9,21 +9,38 @@ use std::collections::HashMap;
 use std::fmt::Debug;
 use url::form_urlencoded;
 
+/// Largest request body the handlers read, a bound on what a client can
+/// make the node buffer.
+pub const MAX_BODY_SIZE: usize = 4_000_000;
+
+/// Read the request body, up to `MAX_BODY_SIZE` bytes
+pub fn read_body(req: Request<Body>) -> Box<dyn Future<Item = Vec<u8>, Error = Error> + Send> {
+	Box::new(
+		req.into_body()
+			.map_err(|e| ErrorKind::RequestError(format!("Failed to read request: {}", e)).into())
+			.fold(vec![], |mut body, chunk| {
+				if body.len() + chunk.len() > MAX_BODY_SIZE {
+					return err(Error::from(ErrorKind::RequestError(format!(
+						"Request body over {} bytes",
+						MAX_BODY_SIZE
+					))));
+				}
+				body.extend_from_slice(&chunk);
+				ok(body)
+			}),
+	)
+}
+
 /// Parse request body
 pub fn parse_body<T>(req: Request<Body>) -> Box<dyn Future<Item = T, Error = Error> + Send>
 where
 	for<'de> T: Deserialize<'de> + Send + 'static,
 {
 	Box::new(
-		req.into_body()
-			.concat2()
-			.map_err(|e| ErrorKind::RequestError(format!("Failed to read request: {}", e)).into())
-			.and_then(|body| match serde_json::from_reader(&body.to_vec()[..]) {
-				Ok(obj) => ok(obj),
-				Err(e) => {
-					err(ErrorKind::RequestError(format!("Invalid request body: {}", e)).into())
-				}
-			}),
+		read_body(req).and_then(|body| match serde_json::from_slice(&body) {
+			Ok(obj) => ok(obj),
+			Err(e) => err(ErrorKind::RequestError(format!("Invalid request body: {}", e)).into()),
+		}),
 	)
 }
 
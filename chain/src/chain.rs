@@ -19,26 +19,31 @@ use crate::core::core::hash::{Hash, Hashed, ZERO_HASH};
 use crate::core::core::merkle_proof::MerkleProof;
 use crate::core::core::verifier_cache::VerifierCache;
 use crate::core::core::{
-	Block, BlockHeader, BlockSums, Committed, Output, OutputIdentifier, Transaction, TxKernelEntry,
+	Block, BlockHeader, BlockSums, Committed, Output, OutputIdentifier, Transaction, TxKernel,
+	TxKernelEntry,
 };
+use crate::core::consensus;
 use crate::core::global;
 use crate::core::pow;
 use crate::core::ser::{Readable, StreamingReader};
 use crate::error::{Error, ErrorKind};
+use crate::hypersync::{self, HypersyncManifest};
 use crate::pipe;
 use crate::store;
 use crate::txhashset;
 use crate::txhashset::TxHashSet;
 use crate::types::{
-	BlockStatus, ChainAdapter, NoStatus, Options, Tip, TxHashSetRoots, TxHashsetWriteStatus, BlockPrintable
+	BlockStatus, ChainAdapter, NoStatus, Options, Reorg, Tip, TxHashSetRoots, TxHashsetWriteStatus,
+	BlockPrintable, DevFeeOutput,
 };
 use crate::util::secp::pedersen::{Commitment, RangeProof};
 use crate::util::RwLock;
 use bitgrin_store::Error::NotFoundErr;
+use std::cmp;
 use std::collections::HashMap;
 use std::fs::{self, File};
 use std::io::Read;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 use std::time::{Duration, Instant};
@@ -178,6 +183,14 @@ impl Chain {
 		setup_head(&genesis, &store, &mut txhashset)?;
 		Chain::log_heads(&store)?;
 
+		// A new archive node indexes its blocks from the genesis on, an
+		// existing one needs a reindex to backfill them.
+		if archive_mode && store.head()?.height == 0 && store.get_hash_by_height(0).is_err() {
+			let batch = store.batch()?;
+			batch.index_block(&genesis)?;
+			batch.commit()?;
+		}
+
 		Ok(Chain {
 			db_root,
 			store,
@@ -305,6 +318,7 @@ impl Chain {
 		// We have more work if the chain head is updated.
 		let is_more_work = head.is_some();
 		let mut is_next_block = false;
+		let mut reorg = None;
 		let mut reorg_depth = None;
 		warn!("## Chain::determine_status");
 		if let Some(head) = head {
@@ -313,6 +327,12 @@ impl Chain {
 			} else {
 				warn!("## Chain::calculate reorg_depth");
 				reorg_depth = Some(prev_head.height.saturating_sub(head.height) + 1);
+				// the block is committed already, failing to look up the
+				// blocks of the reorg only leaves its details out
+				match Reorg::between(&prev_head, &head, |h| self.get_block_header(h)) {
+					Ok(details) => reorg = Some(details),
+					Err(e) => error!("Failed to look up the blocks of the reorg: {:?}", e),
+				}
 				match reorg_depth {
 					Some(depth) => { warn!("## Chain::reorg_depth {}", depth); }
 					None => { warn!("## Chain::no_reorg_depth_found");  }
@@ -375,7 +395,7 @@ impl Chain {
 
 		match (is_more_work, is_next_block) {
 			(true, true) => BlockStatus::Next,
-			(true, false) => BlockStatus::Reorg(reorg_depth.unwrap_or(0)),
+			(true, false) => BlockStatus::Reorg(reorg_depth.unwrap_or(0), reorg),
 			(false, _) => BlockStatus::Fork,
 		}
 	}
@@ -501,6 +521,7 @@ impl Chain {
 			txhashset,
 			batch,
 			orphans: self.orphans.clone(),
+			archive_mode: self.archive_mode,
 		})
 	}
 
@@ -620,32 +641,6 @@ impl Chain {
 		}
 	}
 
-	fn validate_hash_checkpoint(&self, hash_str: &str, checkpoint_height: u64) -> bool {
-		let h = Hash::from_hex(hash_str);
-		match h {
-			Ok(hash) => {
-				match self.get_block_header(&hash) {
-					Ok(header) => {
-						return header.height == checkpoint_height;
-					}
-					Err(_) => {
-						if self.head().unwrap().height < checkpoint_height {
-							// The block is not in our chain, but this
-							// is simply because the chain is not synchronized
-							// this far yet, is not a chain validation error
-							return true;
-						}
-						// This is an invalid chain
-						return false;
-					}
-				}
-			}
-			Err(_) => {
-				return false;
-			}
-		}
-	}
-
 	/// Validate the current chain state.
 	pub fn validate(&self, fast_validation: bool, integrity_checks: bool) -> Result<(), Error> {
 		let header = self.store.head_header()?;
@@ -656,32 +651,15 @@ impl Chain {
 		}
 
 		if integrity_checks {
-			// Verify chain is correct
-			let grin_hash = "00000798ee3d720f3983fd6fc170b71322922d5521a1ec599b1ab442f5fc8212";
-			let bitgrin_40k = "000227e48810ad9924bedf88c224e358ecc6411ed9a87587dd79f13feaf64448";
-			let xbg_hf_1y = "22005348dfb71c10b932b17e3d21f248ac79a7cdc1549d7e116855f1ca42c04b";
-			let xbg_hfe_1y = "5faa1bdb8e9a9fd5ac237699c71b881b64c8accd251e0ee98ba239361eff2ff6";
-			let xbg_hff_1y = "0012d56405ab1620bc2024851101ab130ba2f2a1e847634766b963372c2ef580";
-
-			if self.validate_hash_checkpoint(grin_hash, 5) {
-				error!("Consensus failure due to synchronization with Grin chain isntead of BitGrin chain.");
-				return Err(ErrorKind::TxHashSetErr(String::from("Consensus failure")).into());
-			}
-			if !self.validate_hash_checkpoint(bitgrin_40k, 40_000) {
-				error!("Consensus failure at checkpoint 40,000");
-				return Err(ErrorKind::TxHashSetErr(String::from("Consensus failure")).into());
-			}
-			if !self.validate_hash_checkpoint(xbg_hf_1y, 524_160) {
-				error!("Consensus failure at checkpoint 524,160");
-				return Err(ErrorKind::TxHashSetErr(String::from("Consensus failure")).into());
-			}
-			if !self.validate_hash_checkpoint(xbg_hfe_1y, 524_182) {
-				error!("Consensus failure at checkpoint 524,182");
-				return Err(ErrorKind::TxHashSetErr(String::from("Consensus failure")).into());
-			}
-			if !self.validate_hash_checkpoint(xbg_hff_1y, 524_183) {
-				error!("Consensus failure at checkpoint 524,183");
-				return Err(ErrorKind::TxHashSetErr(String::from("Consensus failure")).into());
+			// Verify our chain goes through every checkpoint up to the head
+			for (height, hash) in global::checkpoints().iter() {
+				if height > header.height {
+					break;
+				}
+				if self.get_header_by_height(height)?.hash() != hash {
+					error!("Consensus failure at checkpoint {}", height);
+					return Err(ErrorKind::CheckpointMismatch(height).into());
+				}
 			}
 		}
 
@@ -855,6 +833,68 @@ impl Chain {
 		))
 	}
 
+	/// Packages the chain state at the provided header as a hypersync
+	/// snapshot, written as `{name}.zip` and `{name}.manifest.json` to the
+	/// snapshot directory next to the db root. No block or header gets
+	/// processed while the chain data is being copied, the copy is then
+	/// rewound to the header and packaged.
+	pub fn snapshot(&self, header: &BlockHeader, name: &str) -> Result<HypersyncManifest, Error> {
+		hypersync::check_snapshot_name(name)?;
+		self.is_on_current_chain(header)?;
+
+		let db_root = Path::new(&self.db_root);
+		let out_dir = db_root
+			.parent()
+			.unwrap_or_else(|| Path::new("."))
+			.join(hypersync::SNAPSHOT_DIR);
+		let staging = out_dir.join(format!("{}.staging", name));
+		if staging.exists() {
+			fs::remove_dir_all(&staging)?;
+		}
+		{
+			let _txhashset = self.txhashset.write();
+			hypersync::copy_chain_data(db_root, &staging)?;
+		}
+
+		let genesis = self.get_block(&self.genesis.hash())?;
+		let res = hypersync::package_snapshot(&staging, genesis, header, &out_dir, name);
+		if res.is_err() {
+			let _ = fs::remove_dir_all(&staging);
+		}
+		res
+	}
+
+	/// Rewinds the whole chain state (txhashset, header and sync MMRs and
+	/// all heads) to the provided header, discarding everything above it.
+	/// Blocks and headers above it are kept in the db.
+	pub fn rewind_to(&self, header: &BlockHeader) -> Result<(), Error> {
+		self.is_on_current_chain(header)?;
+		if header.height > self.head()?.height {
+			return Err(ErrorKind::Other(format!("no block at height {}", header.height)).into());
+		}
+
+		let tip = Tip::from_header(header);
+		let mut txhashset = self.txhashset.write();
+		let mut batch = self.store.batch()?;
+		txhashset::extending(&mut txhashset, &mut batch, |extension| {
+			extension.rewind(header)?;
+			extension.validate_roots()?;
+			Ok(())
+		})?;
+		// the sync MMR may lag behind the header MMR, rebuild it when it does
+		txhashset::sync_extending(&mut txhashset, &mut batch, |extension| {
+			match extension.get_header_by_height(header.height) {
+				Ok(h) if h.hash() == header.hash() => extension.rewind(header),
+				_ => extension.rebuild(&tip, &self.genesis),
+			}
+		})?;
+
+		batch.save_head(&tip)?;
+		batch.save_sync_head(&tip)?;
+		batch.commit()?;
+		Ok(())
+	}
+
 	// Special handling to make sure the whole kernel set matches each of its
 	// roots in each block header, without truncation. We go back header by
 	// header, rewind and check each root. This fixes a potential weakness in
@@ -1313,6 +1353,93 @@ impl Chain {
 		Ok((outputs.0, max_index, output_vec))
 	}
 
+	/// Finds the kernel with the given excess in the blocks from `min_height`
+	/// (genesis by default) to `max_height` (the head by default), most
+	/// recent first. Returns the kernel, the header of its block and its
+	/// position in the kernel MMR.
+	pub fn find_kernel(
+		&self,
+		excess: &Commitment,
+		min_height: Option<u64>,
+		max_height: Option<u64>,
+	) -> Result<Option<(TxKernel, BlockHeader, u64)>, Error> {
+		let head = self.head()?;
+		let max_height = cmp::min(max_height.unwrap_or(head.height), head.height);
+		let min_height = min_height.unwrap_or(0);
+		if min_height > max_height {
+			return Ok(None);
+		}
+
+		let txhashset = self.txhashset.read();
+		let max_header = txhashset.get_header_by_height(max_height)?;
+		let min_size = match min_height {
+			0 => 0,
+			h => txhashset.get_header_by_height(h - 1)?.kernel_mmr_size,
+		};
+		let found = txhashset::rewindable_kernel_view(&txhashset, |view| {
+			view.rewind(&max_header)?;
+			Ok(view.find_kernel(excess, min_size))
+		})?;
+		let (kernel, pos) = match found {
+			Some(found) => found,
+			None => return Ok(None),
+		};
+
+		// the block is the first one whose kernel MMR covers the position
+		let (mut low, mut high) = (min_height, max_height);
+		while low < high {
+			let mid = low + (high - low) / 2;
+			if txhashset.get_header_by_height(mid)?.kernel_mmr_size >= pos {
+				high = mid;
+			} else {
+				low = mid + 1;
+			}
+		}
+		let header = txhashset.get_header_by_height(low)?;
+		Ok(Some((kernel, header, pos)))
+	}
+
+	/// Dev fee payouts created so far, found from the coinbase kernel and
+	/// output of each block paying one out.
+	pub fn dev_fee_outputs(&self) -> Result<Vec<DevFeeOutput>, Error> {
+		let head = self.head()?;
+		let txhashset = self.txhashset.read();
+		let mut payouts = vec![];
+		for height in 1..=cmp::min(head.height, consensus::QTY_DEV_FEE_PAYOUTS) {
+			let header = txhashset.get_header_by_height(height)?;
+			let prev = self.get_previous_header(&header)?;
+			let kernel_excess = txhashset
+				.kernels_between(prev.kernel_mmr_size, header.kernel_mmr_size)
+				.into_iter()
+				.find(|k| k.kernel.is_coinbase())
+				.map(|k| k.kernel.excess);
+			let unspent = txhashset
+				.unspent_outputs_between(prev.output_mmr_size, header.output_mmr_size)
+				.into_iter()
+				.find(|o| o.features.is_coinbase())
+				.map(|o| o.commit);
+			// a spent output is only left in its block, if we still have it
+			let commit = match unspent {
+				Some(commit) => Some(commit),
+				None => self.get_block(&header.hash()).ok().and_then(|b| {
+					b.outputs()
+						.iter()
+						.find(|o| o.is_coinbase())
+						.map(|o| o.commit)
+				}),
+			};
+			payouts.push(DevFeeOutput {
+				height,
+				amount: consensus::dev_fee_at_height(height),
+				lock_height: consensus::get_coinbase_maturity_for_block(0, height),
+				commit,
+				kernel_excess,
+				unspent: unspent.is_some(),
+			});
+		}
+		Ok(payouts)
+	}
+
 	/// Orphans pool size
 	pub fn orphans_len(&self) -> usize {
 		self.orphans.len()
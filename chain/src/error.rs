@@ -65,6 +65,9 @@ pub enum ErrorKind {
 	/// Block height is invalid (not previous + 1)
 	#[fail(display = "Invalid Block Height")]
 	InvalidBlockHeight,
+	/// Block is on a fork below a checkpoint, or does not match one
+	#[fail(display = "Checkpoint Mismatch at height {}", _0)]
+	CheckpointMismatch(u64),
 	/// One of the root hashes in the block is invalid
 	#[fail(display = "Invalid Root")]
 	InvalidRoot,
@@ -155,6 +158,9 @@ pub enum ErrorKind {
 	/// Generic seed hash retrieval error
 	#[fail(display = "Seed hash unreachable")]
 	SeedHashUnreachable,
+	/// Hypersync snapshot could not be fetched or failed verification
+	#[fail(display = "Snapshot Error: {}", _0)]
+	Snapshot(String),
 }
 
 impl Display for Error {
@@ -199,6 +205,7 @@ impl Error {
 			| ErrorKind::SerErr(_)
 			| ErrorKind::TxHashSetErr(_)
 			| ErrorKind::GenesisBlockRequired
+			| ErrorKind::Snapshot(_)
 			| ErrorKind::Other(_) => false,
 			_ => true,
 		}
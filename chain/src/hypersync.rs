@@ -0,0 +1,567 @@
+// Copyright 2018 The BitGrin Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Hypersync snapshots. A snapshot is a zip of a node's chain data directory
+//! described by a manifest, which pins the snapshot height, block hash,
+//! txhashset roots and the blake2b digest of every file in the archive.
+//! Snapshots are fetched from an ordered list of mirrors (failing over on
+//! error), partial downloads are resumed, and nothing is used until both the
+//! files and the resulting chain state match the manifest.
+//! Any node can also package its own chain state as a snapshot, to be
+//! published on a mirror of its operator's choosing.
+
+use std::collections::HashSet;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read};
+use std::path::Path;
+use std::sync::Arc;
+
+use reqwest::{header, Client, StatusCode};
+
+use crate::blake2::blake2b::Blake2b;
+use crate::chain::Chain;
+use crate::core::core::hash::{Hash, Hashed};
+use crate::core::core::verifier_cache::LruVerifierCache;
+use crate::core::core::{Block, BlockHeader};
+use crate::core::{global, pow};
+use crate::error::{Error, ErrorKind};
+use crate::types::NoopAdapter;
+use crate::util::{self, zip, RwLock};
+
+/// Version of the manifest format produced by this node.
+pub const MANIFEST_VERSION: u16 = 1;
+
+/// Files which only make sense for a running node and are never part of a
+/// snapshot (LMDB lock files).
+const SKIPPED_FILES: &[&str] = &["lock.mdb"];
+
+/// Directories of the data dir which are never part of a snapshot.
+const SKIPPED_DIRS: &[&str] = &["peer"];
+
+/// Default name of the snapshots packaged by a node, matching the default
+/// manifest name of the hypersync config so mirrors can serve them as is.
+pub const SNAPSHOT_NAME: &str = "bg_chain_data";
+
+/// Directory, next to the db root, the snapshots of a node are written to.
+pub const SNAPSHOT_DIR: &str = "snapshots";
+
+/// A single file of a snapshot, relative to the root it was taken from.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SnapshotFile {
+	/// Path relative to the snapshot root, '/' separated
+	pub path: String,
+	/// Size of the file in bytes
+	pub size: u64,
+	/// Hex encoded 32 bytes blake2b digest of the file content
+	pub blake2b: String,
+}
+
+impl SnapshotFile {
+	/// Describes the file at `root/rel_path`.
+	pub fn from_path(root: &Path, rel_path: &str) -> Result<SnapshotFile, Error> {
+		let file = File::open(root.join(rel_path))?;
+		let size = file.metadata()?.len();
+		Ok(SnapshotFile {
+			path: rel_path.to_owned(),
+			size,
+			blake2b: blake2b_digest(file)?,
+		})
+	}
+
+	/// Checks the file found under `root` matches this entry.
+	pub fn verify(&self, root: &Path) -> Result<(), Error> {
+		self.verify_file(&root.join(&self.path))
+	}
+
+	/// Checks the file at `path` matches the size and digest of this entry.
+	pub fn verify_file(&self, path: &Path) -> Result<(), Error> {
+		let file = File::open(path)
+			.map_err(|e| ErrorKind::Snapshot(format!("cannot open {}: {}", self.path, e)))?;
+		let size = file.metadata()?.len();
+		if size != self.size {
+			return Err(ErrorKind::Snapshot(format!(
+				"{}: size {}, expected {}",
+				self.path, size, self.size
+			))
+			.into());
+		}
+		if !blake2b_digest(file)?.eq_ignore_ascii_case(&self.blake2b) {
+			return Err(ErrorKind::Snapshot(format!("{}: digest mismatch", self.path)).into());
+		}
+		Ok(())
+	}
+}
+
+/// Txhashset roots of the snapshot head, hex encoded.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SnapshotRoots {
+	/// Output MMR root
+	pub output_root: String,
+	/// Range proof MMR root
+	pub rproof_root: String,
+	/// Kernel MMR root
+	pub kernel_root: String,
+}
+
+/// Describes a snapshot archive and the chain state it contains.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HypersyncManifest {
+	/// Manifest format version
+	pub version: u16,
+	/// Short name of the chain type the snapshot belongs to ("main", "floo")
+	pub chain_type: String,
+	/// Height of the snapshot head
+	pub height: u64,
+	/// Hash of the snapshot head, hex encoded
+	pub block_hash: String,
+	/// Output MMR size at the snapshot head
+	pub output_mmr_size: u64,
+	/// Kernel MMR size at the snapshot head
+	pub kernel_mmr_size: u64,
+	/// Txhashset roots at the snapshot head
+	pub roots: SnapshotRoots,
+	/// The archive itself, `path` being its name on the mirrors
+	pub archive: SnapshotFile,
+	/// Every file contained in the archive
+	pub files: Vec<SnapshotFile>,
+}
+
+impl HypersyncManifest {
+	/// Builds the manifest of the data dir `data_dir` whose head is `header`,
+	/// packaged as `archive`.
+	pub fn build(
+		data_dir: &Path,
+		header: &BlockHeader,
+		archive: &Path,
+	) -> Result<HypersyncManifest, Error> {
+		let mut paths = vec![];
+		list_files(data_dir, "", &mut paths)?;
+		paths.sort();
+		let files = paths
+			.iter()
+			.map(|p| SnapshotFile::from_path(data_dir, p))
+			.collect::<Result<Vec<_>, _>>()?;
+
+		let archive_dir = archive.parent().unwrap_or_else(|| Path::new(""));
+		let archive_name = archive
+			.file_name()
+			.and_then(|n| n.to_str())
+			.ok_or_else(|| ErrorKind::Snapshot(format!("invalid archive path {:?}", archive)))?;
+
+		Ok(HypersyncManifest {
+			version: MANIFEST_VERSION,
+			chain_type: global::chain_shortname(),
+			height: header.height,
+			block_hash: header.hash().to_hex(),
+			output_mmr_size: header.output_mmr_size,
+			kernel_mmr_size: header.kernel_mmr_size,
+			roots: SnapshotRoots {
+				output_root: header.output_root.to_hex(),
+				rproof_root: header.range_proof_root.to_hex(),
+				kernel_root: header.kernel_root.to_hex(),
+			},
+			archive: SnapshotFile::from_path(archive_dir, archive_name)?,
+			files,
+		})
+	}
+
+	/// Parses a manifest, rejecting versions newer than ours and manifests
+	/// for another chain type.
+	pub fn from_json(data: &[u8]) -> Result<HypersyncManifest, Error> {
+		let manifest: HypersyncManifest = serde_json::from_slice(data)
+			.map_err(|e| ErrorKind::Snapshot(format!("invalid manifest: {}", e)))?;
+		if manifest.version > MANIFEST_VERSION {
+			return Err(ErrorKind::Snapshot(format!(
+				"unsupported manifest version {}",
+				manifest.version
+			))
+			.into());
+		}
+		if manifest.chain_type != global::chain_shortname() {
+			return Err(ErrorKind::Snapshot(format!(
+				"manifest is for chain {}, expected {}",
+				manifest.chain_type,
+				global::chain_shortname()
+			))
+			.into());
+		}
+		Ok(manifest)
+	}
+
+	/// Serializes the manifest.
+	pub fn to_json(&self) -> Result<String, Error> {
+		serde_json::to_string_pretty(self)
+			.map_err(|e| ErrorKind::Snapshot(format!("manifest serialization: {}", e)).into())
+	}
+
+	/// Whether the archive entry `path` is listed in the manifest.
+	pub fn is_listed(&self, path: &str) -> bool {
+		self.files.iter().any(|f| f.path == path)
+	}
+
+	/// Checks the downloaded archive matches the manifest.
+	pub fn verify_archive(&self, archive: &Path) -> Result<(), Error> {
+		self.archive.verify_file(archive)
+	}
+
+	/// Checks every file listed in the manifest is present under `root` with
+	/// the expected size and digest.
+	pub fn verify_files(&self, root: &Path) -> Result<(), Error> {
+		for file in &self.files {
+			file.verify(root)?;
+		}
+		Ok(())
+	}
+
+	/// Extracts the listed files of `archive` into `dest` and verifies them.
+	/// Entries of the archive not listed in the manifest are ignored.
+	pub fn extract_archive(&self, archive: &Path, dest: &Path) -> Result<(), Error> {
+		self.verify_archive(archive)?;
+		let listed: HashSet<String> = self.files.iter().map(|f| f.path.clone()).collect();
+		fs::create_dir_all(dest)?;
+		zip::decompress(File::open(archive)?, dest, move |p: &Path| {
+			listed.contains(&rel_path_str(p))
+		})
+		.map_err(|e| ErrorKind::Snapshot(format!("extraction failed: {}", e)))?;
+		self.verify_files(dest)
+	}
+
+	/// Checks the head, header roots and txhashset of `chain` match the
+	/// manifest, then fully validates the chain state.
+	pub fn verify_chain(&self, chain: &Chain) -> Result<(), Error> {
+		let head = chain.head()?;
+		let header = chain.head_header()?;
+		if head.height != self.height
+			|| !head
+				.last_block_h
+				.to_hex()
+				.eq_ignore_ascii_case(&self.block_hash)
+		{
+			return Err(ErrorKind::Snapshot(format!(
+				"head {} at {}, manifest expects {} at {}",
+				head.last_block_h, head.height, self.block_hash, self.height
+			))
+			.into());
+		}
+		if header.output_mmr_size != self.output_mmr_size
+			|| header.kernel_mmr_size != self.kernel_mmr_size
+		{
+			return Err(ErrorKind::Snapshot("MMR sizes do not match manifest".to_owned()).into());
+		}
+
+		let expected = (
+			parse_hash(&self.roots.output_root)?,
+			parse_hash(&self.roots.rproof_root)?,
+			parse_hash(&self.roots.kernel_root)?,
+		);
+		if (
+			header.output_root,
+			header.range_proof_root,
+			header.kernel_root,
+		) != expected
+		{
+			return Err(
+				ErrorKind::Snapshot("header roots do not match manifest".to_owned()).into(),
+			);
+		}
+		let roots = chain.get_txhashset_roots();
+		if (roots.output_root, roots.rproof_root, roots.kernel_root) != expected {
+			return Err(
+				ErrorKind::Snapshot("txhashset roots do not match manifest".to_owned()).into(),
+			);
+		}
+
+		chain.validate(false, true)
+	}
+
+	/// Opens the chain data extracted in `db_root` and verifies it against the
+	/// manifest (see `verify_chain`).
+	pub fn verify_chain_data(&self, db_root: &Path, genesis: Block) -> Result<(), Error> {
+		let chain = open_chain(db_root, genesis)?;
+		self.verify_chain(&chain)
+	}
+}
+
+/// Checks a snapshot name can be used as is to name files in the snapshot
+/// directory.
+pub fn check_snapshot_name(name: &str) -> Result<(), Error> {
+	let valid = !name.is_empty()
+		&& !name.starts_with('.')
+		&& name
+			.chars()
+			.all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.');
+	if valid {
+		Ok(())
+	} else {
+		Err(ErrorKind::Snapshot(format!("invalid snapshot name {:?}", name)).into())
+	}
+}
+
+/// Copies the chain data of `db_root` to `dest`, leaving out everything
+/// which is never part of a snapshot.
+pub fn copy_chain_data(db_root: &Path, dest: &Path) -> Result<(), Error> {
+	let mut paths = vec![];
+	list_files(db_root, "", &mut paths)?;
+	for path in paths {
+		let target = dest.join(&path);
+		if let Some(parent) = target.parent() {
+			fs::create_dir_all(parent)?;
+		}
+		fs::copy(db_root.join(&path), target)?;
+	}
+	Ok(())
+}
+
+/// Packages the chain data copied to `staging` as a snapshot at `header`.
+/// The copy is rewound to the header, zipped to `{name}.zip` in `out_dir` and
+/// described by `{name}.manifest.json`, written last. The staging copy is
+/// removed once packaged.
+pub fn package_snapshot(
+	staging: &Path,
+	genesis: Block,
+	header: &BlockHeader,
+	out_dir: &Path,
+	name: &str,
+) -> Result<HypersyncManifest, Error> {
+	open_chain(staging, genesis)?.rewind_to(header)?;
+	for file in SKIPPED_FILES {
+		let _ = fs::remove_file(staging.join("lmdb").join(file));
+	}
+
+	let archive = out_dir.join(format!("{}.zip", name));
+	zip::compress(staging, &File::create(&archive)?)
+		.map_err(|e| ErrorKind::Snapshot(format!("compression failed: {}", e)))?;
+	let manifest = HypersyncManifest::build(staging, header, &archive)?;
+	fs::write(
+		out_dir.join(format!("{}.manifest.json", name)),
+		manifest.to_json()?,
+	)?;
+	fs::remove_dir_all(staging)?;
+	Ok(manifest)
+}
+
+/// Hex encoded 32 bytes blake2b digest of everything readable from `reader`.
+pub fn blake2b_digest<R: Read>(mut reader: R) -> Result<String, Error> {
+	let mut hasher = Blake2b::new(32);
+	let mut buf = vec![0; 64 * 1024];
+	loop {
+		let n = reader.read(&mut buf)?;
+		if n == 0 {
+			break;
+		}
+		hasher.update(&buf[..n]);
+	}
+	Ok(util::to_hex(hasher.finalize().as_bytes().to_vec()))
+}
+
+/// Fetches and parses the manifest `name` from the first mirror able to
+/// serve it. When `pinned_digest` is provided, the blake2b digest of the
+/// manifest bytes must match it.
+pub fn fetch_manifest(
+	mirrors: &[String],
+	name: &str,
+	pinned_digest: Option<&str>,
+) -> Result<HypersyncManifest, Error> {
+	let client = Client::new();
+	for mirror in mirrors {
+		let url = mirror_url(mirror, name);
+		match fetch_manifest_from(&client, &url, pinned_digest) {
+			Ok(manifest) => {
+				info!(
+					"hypersync: manifest for height {} from {}",
+					manifest.height, url
+				);
+				return Ok(manifest);
+			}
+			Err(e) => warn!("hypersync: manifest from {} rejected: {}", url, e),
+		}
+	}
+	Err(ErrorKind::Snapshot(format!("no mirror served a valid manifest {}", name)).into())
+}
+
+fn fetch_manifest_from(
+	client: &Client,
+	url: &str,
+	pinned_digest: Option<&str>,
+) -> Result<HypersyncManifest, Error> {
+	let mut res = client
+		.get(url)
+		.send()
+		.map_err(|e| ErrorKind::Snapshot(e.to_string()))?;
+	if !res.status().is_success() {
+		return Err(ErrorKind::Snapshot(format!("status {}", res.status())).into());
+	}
+	let mut data = vec![];
+	res.read_to_end(&mut data)?;
+	if let Some(pinned) = pinned_digest {
+		let digest = blake2b_digest(&data[..])?;
+		if !digest.eq_ignore_ascii_case(pinned) {
+			return Err(ErrorKind::Snapshot(format!(
+				"manifest digest {} does not match pinned digest {}",
+				digest, pinned
+			))
+			.into());
+		}
+	}
+	HypersyncManifest::from_json(&data)
+}
+
+/// Downloads the archive described by `manifest` to `dest`, trying each
+/// mirror in turn. An existing partial download is resumed. The archive is
+/// only kept once its size and digest match the manifest.
+pub fn download_archive(
+	mirrors: &[String],
+	manifest: &HypersyncManifest,
+	dest: &Path,
+) -> Result<(), Error> {
+	if dest.exists() && manifest.verify_archive(dest).is_ok() {
+		return Ok(());
+	}
+	let client = Client::new();
+	for mirror in mirrors {
+		let url = mirror_url(mirror, &manifest.archive.path);
+		if let Err(e) = fetch_into(&client, &url, dest, manifest.archive.size) {
+			// keep what we have, the next mirror resumes from there
+			warn!("hypersync: download from {} failed: {}", url, e);
+			continue;
+		}
+		match manifest.verify_archive(dest) {
+			Ok(_) => {
+				info!(
+					"hypersync: downloaded {} from {}",
+					manifest.archive.path, url
+				);
+				return Ok(());
+			}
+			Err(e) => {
+				warn!("hypersync: archive from {} rejected: {}", url, e);
+				fs::remove_file(dest)?;
+			}
+		}
+	}
+	Err(ErrorKind::Snapshot(format!(
+		"no mirror served a valid archive {}",
+		manifest.archive.path
+	))
+	.into())
+}
+
+fn fetch_into(client: &Client, url: &str, dest: &Path, size: u64) -> Result<(), Error> {
+	let mut offset = dest.metadata().map(|m| m.len()).unwrap_or(0);
+	if offset > size {
+		fs::remove_file(dest)?;
+		offset = 0;
+	}
+	if offset == size {
+		return Ok(());
+	}
+
+	let mut req = client.get(url);
+	if offset > 0 {
+		req = req.header(header::RANGE, format!("bytes={}-", offset));
+	}
+	let res = req.send().map_err(|e| ErrorKind::Snapshot(e.to_string()))?;
+	let mut file = match res.status() {
+		StatusCode::PARTIAL_CONTENT if offset > 0 => {
+			debug!("hypersync: resuming {} at {} bytes", url, offset);
+			OpenOptions::new().append(true).open(dest)?
+		}
+		status if status.is_success() => {
+			offset = 0;
+			File::create(dest)?
+		}
+		status => return Err(ErrorKind::Snapshot(format!("status {}", status)).into()),
+	};
+
+	let mut progress = DownloadProgress {
+		inner: res,
+		downloaded: offset,
+		total: size,
+		logged: 0,
+	};
+	io::copy(&mut progress, &mut file)?;
+	file.sync_all()?;
+	Ok(())
+}
+
+/// Logs download progress every 10%.
+struct DownloadProgress<R> {
+	inner: R,
+	downloaded: u64,
+	total: u64,
+	logged: u64,
+}
+
+impl<R: Read> Read for DownloadProgress<R> {
+	fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+		let n = self.inner.read(buf)?;
+		self.downloaded += n as u64;
+		if let Some(percent) = (self.downloaded * 100).checked_div(self.total) {
+			if percent >= self.logged + 10 {
+				info!("hypersync: downloading chain state {}%", percent);
+				self.logged = percent - percent % 10;
+			}
+		}
+		Ok(n)
+	}
+}
+
+fn open_chain(db_root: &Path, genesis: Block) -> Result<Chain, Error> {
+	let db_root = db_root
+		.to_str()
+		.ok_or_else(|| ErrorKind::Snapshot(format!("invalid db root {:?}", db_root)))?;
+	Chain::init(
+		db_root.to_owned(),
+		Arc::new(NoopAdapter {}),
+		genesis,
+		pow::verify_size,
+		Arc::new(RwLock::new(LruVerifierCache::new())),
+		false,
+	)
+}
+
+fn mirror_url(mirror: &str, name: &str) -> String {
+	format!("{}/{}", mirror.trim_end_matches('/'), name)
+}
+
+fn parse_hash(hex: &str) -> Result<Hash, Error> {
+	Hash::from_hex(hex).map_err(|_| ErrorKind::Snapshot(format!("invalid hash {}", hex)).into())
+}
+
+fn rel_path_str(path: &Path) -> String {
+	path.components()
+		.map(|c| c.as_os_str().to_string_lossy().into_owned())
+		.collect::<Vec<_>>()
+		.join("/")
+}
+
+fn list_files(root: &Path, rel: &str, paths: &mut Vec<String>) -> Result<(), Error> {
+	for entry in fs::read_dir(root.join(rel))? {
+		let entry = entry?;
+		let name = entry.file_name().to_string_lossy().into_owned();
+		let rel_path = if rel.is_empty() {
+			name.clone()
+		} else {
+			format!("{}/{}", rel, name)
+		};
+		if entry.file_type()?.is_dir() {
+			if !(rel.is_empty() && SKIPPED_DIRS.contains(&name.as_str())) {
+				list_files(root, &rel_path, paths)?;
+			}
+		} else if !SKIPPED_FILES.contains(&name.as_str()) {
+			paths.push(rel_path);
+		}
+	}
+	Ok(())
+}
@@ -28,6 +28,7 @@ extern crate serde_derive;
 #[macro_use]
 extern crate log;
 
+use blake2_rfc as blake2;
 use failure;
 use bitgrin_core as core;
 use bitgrin_keychain as keychain;
@@ -35,7 +36,9 @@ use bitgrin_util as util;
 
 mod chain;
 mod error;
+pub mod hypersync;
 pub mod pipe;
+pub mod repair;
 pub mod store;
 pub mod txhashset;
 pub mod types;
@@ -44,5 +47,8 @@ pub mod types;
 
 pub use crate::chain::{Chain, MAX_ORPHAN_SIZE};
 pub use crate::error::{Error, ErrorKind};
+pub use crate::repair::RepairReport;
 pub use crate::store::ChainStore;
-pub use crate::types::{BlockStatus, ChainAdapter, Options, Tip, TxHashsetWriteStatus};
+pub use crate::types::{
+	BlockStatus, ChainAdapter, DevFeeOutput, Options, Reorg, ReorgBlock, Tip, TxHashsetWriteStatus,
+};
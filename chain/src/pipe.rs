@@ -47,6 +47,8 @@ pub struct BlockContext<'a> {
 	pub verifier_cache: Arc<RwLock<dyn VerifierCache>>,
 	/// Recent orphan blocks to avoid double-processing
 	pub orphans: Arc<OrphanBlockPool>,
+	/// Whether to maintain the archive indexes of the store
+	pub archive_mode: bool,
 }
 
 /// Process a block header as part of processing a full block.
@@ -56,6 +58,8 @@ fn process_header_for_block(
 	is_fork: bool,
 	ctx: &mut BlockContext<'_>,
 ) -> Result<(), Error> {
+	validate_checkpoints(header, &global::checkpoints(), ctx)?;
+
 	txhashset::header_extending(&mut ctx.txhashset, &mut ctx.batch, |extension| {
 		extension.force_rollback();
 		if is_fork {
@@ -174,6 +178,9 @@ pub fn process_block(b: &Block, ctx: &mut BlockContext<'_>) -> Result<Option<Tip
 
 	// Update the chain head if total work is increased.
 	let res = update_head(b, ctx)?;
+	if res.is_some() && ctx.archive_mode {
+		update_archive_indexes(b, &head, &ctx.batch)?;
+	}
 	Ok(res)
 }
 
@@ -206,6 +213,12 @@ pub fn sync_block_headers(
 	};
 
 	if !all_known {
+		// Reject any fork below a checkpoint before storing anything.
+		let checkpoints = global::checkpoints();
+		for header in headers {
+			validate_checkpoints(header, &checkpoints, ctx)?;
+		}
+
 		let prev_header = ctx.batch.get_previous_header(&first_header)?;
 		txhashset::sync_extending(&mut ctx.txhashset, &mut ctx.batch, |extension| {
 			extension.rewind(&prev_header)?;
@@ -261,6 +274,7 @@ pub fn process_block_header(header: &BlockHeader, ctx: &mut BlockContext<'_>) ->
 	); // keep this
 
 	check_header_known(header, ctx)?;
+	validate_checkpoints(header, &global::checkpoints(), ctx)?;
 	validate_header(header, ctx)?;
 	Ok(())
 }
@@ -334,6 +348,40 @@ fn prev_header_store(
 	Ok(prev)
 }
 
+/// Checks the header agrees with the checkpoints: it must match the
+/// checkpoint at its height, and a header at or below the last checkpoint
+/// our header chain went through must be on our header chain.
+fn validate_checkpoints(
+	header: &BlockHeader,
+	checkpoints: &global::Checkpoints,
+	ctx: &BlockContext<'_>,
+) -> Result<(), Error> {
+	if !checkpoints.matches(header.height, &header.hash()) {
+		error!(
+			"pipe: header {} at {} does not match checkpoint",
+			header.hash(),
+			header.height
+		);
+		return Err(ErrorKind::CheckpointMismatch(header.height).into());
+	}
+
+	let header_head = ctx.batch.header_head()?;
+	if let Some((height, _)) = checkpoints.last_at(header_head.height) {
+		if header.height <= height
+			&& ctx.txhashset.get_header_hash_by_height(header.height)? != header.hash()
+		{
+			debug!(
+				"pipe: header {} at {} forks below checkpoint at {}",
+				header.hash(),
+				header.height,
+				height
+			);
+			return Err(ErrorKind::CheckpointMismatch(height).into());
+		}
+	}
+	Ok(())
+}
+
 /// First level of block validation that only needs to act on the block header
 /// to make it as cheap as possible. The different validations are also
 /// arranged by order of cost to have as little DoS surface as possible.
@@ -543,6 +591,52 @@ fn update_head(b: &Block, ctx: &BlockContext<'_>) -> Result<Option<Tip>, Error>
 	}
 }
 
+/// Brings the archive indexes in line with the chain now ending at the given
+/// block: the blocks of the previous chain above the fork point get
+/// unindexed, the ones of the new chain indexed. Blocks below the first one
+/// ever indexed are left to the reindex.
+fn update_archive_indexes(
+	b: &Block,
+	prev_head: &Tip,
+	batch: &store::Batch<'_>,
+) -> Result<(), Error> {
+	let store_err = |e| ErrorKind::StoreErr(e, "pipe archive indexes".to_owned());
+
+	// Walk the new chain down to the fork point, the first block indexed at
+	// its height or the first height not indexed at all.
+	let mut fork_blocks = vec![];
+	let mut header = b.header.clone();
+	loop {
+		match batch.get_hash_by_height(header.height) {
+			Ok(h) if h == header.hash() => break,
+			Ok(_) => fork_blocks.push(header.hash()),
+			Err(_) if header.hash() == b.hash() => fork_blocks.push(header.hash()),
+			Err(_) => break,
+		}
+		if header.height == 0 {
+			break;
+		}
+		header = batch.get_previous_header(&header).map_err(store_err)?;
+	}
+
+	let first_height = b.header.height + 1 - fork_blocks.len() as u64;
+	for height in (first_height..=prev_head.height).rev() {
+		if let Ok(h) = batch.get_hash_by_height(height) {
+			let old = batch.get_block(&h).map_err(store_err)?;
+			batch.unindex_block(&old).map_err(store_err)?;
+		}
+	}
+	for h in fork_blocks.iter().rev() {
+		if *h == b.hash() {
+			batch.index_block(b).map_err(store_err)?;
+		} else {
+			let block = batch.get_block(h).map_err(store_err)?;
+			batch.index_block(&block).map_err(store_err)?;
+		}
+	}
+	Ok(())
+}
+
 // Whether the provided block totals more work than the chain tip
 fn has_more_work(header: &BlockHeader, head: &Tip) -> bool {
 	header.total_difficulty() > head.total_difficulty
@@ -0,0 +1,322 @@
+// Copyright 2018 The BitGrin Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Offline repair of the chain data of a stopped node, for when the chain
+//! store and the txhashset files went out of sync (crash during a
+//! txhashset write, partial disk writes...).
+
+use std::sync::Arc;
+
+use crate::core::core::committed::Committed;
+use crate::core::core::hash::{Hash, Hashed};
+use crate::core::core::{Block, BlockHeader, BlockSums};
+use crate::error::{Error, ErrorKind};
+use crate::store::{Batch, ChainStore};
+use crate::txhashset::{self, Extension, TxHashSet};
+use crate::types::Tip;
+
+/// What a repair found and fixed.
+#[derive(Debug, Clone)]
+pub struct RepairReport {
+	/// Chain head before the repair
+	pub old_head: Tip,
+	/// Chain head after the repair, the last block consistent with the
+	/// txhashset
+	pub head: Tip,
+	/// Blocks removed from the store while rewinding to the new head
+	pub blocks_removed: u64,
+	/// Output positions that were missing or wrong in the chain store
+	pub output_positions: u64,
+	/// Block sums that were missing or wrong in the chain store
+	pub block_sums: u64,
+	/// Description of each fix, in the order they were made
+	pub fixes: Vec<String>,
+}
+
+impl RepairReport {
+	/// Whether the chain data was consistent already.
+	pub fn is_clean(&self) -> bool {
+		self.fixes.is_empty()
+	}
+}
+
+/// Checks the chain store at the provided root against the header MMR and
+/// the txhashset files and repairs what it can:
+///
+/// * the header MMR is rebuilt from the stored headers if it doesn't match
+///   the head,
+/// * the head is rewound, block by block, to the last block the txhashset
+///   roots and sizes are valid for, removing the blocks above it,
+/// * the header head is reset to the new head if the header MMR doesn't
+///   match it,
+/// * the output positions are rebuilt from the output MMR,
+/// * the block sums are recomputed from the stored blocks.
+///
+/// Nothing is written if any of these fails. The node must be stopped, the
+/// chain data is opened directly.
+pub fn repair(db_root: String, genesis: &Block) -> Result<RepairReport, Error> {
+	let store = Arc::new(ChainStore::new(&db_root)?);
+	let mut txhashset = TxHashSet::open(db_root, store.clone(), None)?;
+	let mut batch = store.batch()?;
+
+	let old_head = batch.head()?;
+	let mut report = RepairReport {
+		old_head: old_head.clone(),
+		head: old_head.clone(),
+		blocks_removed: 0,
+		output_positions: 0,
+		block_sums: 0,
+		fixes: vec![],
+	};
+
+	// a head without header can't be rewound from, the header chain is lost
+	let mut head = old_head;
+	let mut header = batch.get_block_header(&head.last_block_h).map_err(|_| {
+		ErrorKind::Other(format!(
+			"no header for the head {} at {}, the chain data has to be resynced",
+			head.last_block_h, head.height
+		))
+	})?;
+
+	let head_sums = loop {
+		let rebuilt = txhashset::header_extending(&mut txhashset, &mut batch, |ext| {
+			let needs_rebuild = match ext.get_header_by_height(head.height) {
+				Ok(h) => h.hash() != head.last_block_h,
+				Err(_) => true,
+			};
+			if needs_rebuild {
+				ext.rebuild(&head, &genesis.header)?;
+			}
+			Ok(needs_rebuild)
+		});
+		match rebuilt {
+			Ok(true) => report.fixes.push(format!(
+				"rebuilt the header MMR up to {} at {}",
+				head.last_block_h, head.height
+			)),
+			Ok(false) => {}
+			Err(e) => {
+				report.fixes.push(format!(
+					"header {} at {} is inconsistent with the header MMR: {}",
+					head.last_block_h, head.height, e
+				));
+				header = rewind_head(&batch, &header, &mut report)?;
+				head = Tip::from_header(&header);
+				continue;
+			}
+		}
+
+		// only checks the files, they are rewound once the head is found
+		let res = txhashset::extending(&mut txhashset, &mut batch, |ext| {
+			ext.force_rollback();
+			// files behind the block can't be rewound to it, only ahead
+			let (_, output_size, _, kernel_size) = ext.sizes();
+			if header.output_mmr_size > output_size || header.kernel_mmr_size > kernel_size {
+				return Err(ErrorKind::TxHashSetErr(format!(
+					"MMR sizes {} and {} are behind the block",
+					output_size, kernel_size
+				))
+				.into());
+			}
+			ext.rewind(&header)?;
+			ext.validate_roots()?;
+			ext.validate_sizes()?;
+			// the roots don't cover the UTXO leaf set, outputs still marked
+			// spent by a lost block only show in the kernel sums
+			if header.height == 0 {
+				return Ok(BlockSums::default());
+			}
+			let (utxo_sum, kernel_sum) = ext.validate_kernel_sums()?;
+			Ok(BlockSums {
+				utxo_sum,
+				kernel_sum,
+			})
+		});
+		match res {
+			Ok(sums) => break sums,
+			Err(e) => {
+				report.fixes.push(format!(
+					"txhashset is inconsistent with block {} at {}: {}",
+					head.last_block_h, head.height, e
+				));
+				header = rewind_head(&batch, &header, &mut report)?;
+				head = Tip::from_header(&header);
+			}
+		}
+	};
+
+	// the header head is only kept if the header MMR agrees with it once
+	// rewound to the head, saving a new head resets it anyway
+	let header_head = if head != report.old_head {
+		head.clone()
+	} else {
+		batch.header_head()?
+	};
+	let header_head_ok = header_head.height <= head.height
+		&& txhashset::header_extending(&mut txhashset, &mut batch, |ext| {
+			ext.force_rollback();
+			Ok(match ext.get_header_by_height(header_head.height) {
+				Ok(h) => h.hash() == header_head.last_block_h,
+				Err(_) => false,
+			})
+		})?;
+
+	// the files are rewound and the store fixed in a single extension, if
+	// anything fails both are left as they were
+	txhashset::extending(&mut txhashset, &mut batch, |ext| {
+		ext.rewind(&header)?;
+
+		if head != report.old_head {
+			ext.batch.save_head(&head)?;
+			report.fixes.push(format!(
+				"rewound the head from {} to {}",
+				report.old_head.height, head.height
+			));
+		}
+		report.head = head.clone();
+
+		if !header_head_ok {
+			ext.batch.reset_header_head()?;
+			report.fixes.push(format!(
+				"reset the header head from {} to {}",
+				header_head.height, head.height
+			));
+		}
+		ext.batch.reset_sync_head()?;
+
+		// the output MMR is now valid for the head, index the positions from it
+		let output_positions = ext.check_index()?;
+		if output_positions > 0 {
+			ext.rebuild_index()?;
+			report.output_positions = output_positions;
+			report.fixes.push(format!(
+				"rebuilt the output positions, {} were missing or wrong",
+				output_positions
+			));
+		}
+
+		repair_block_sums(ext, &header, &head_sums, &mut report)
+	})?;
+
+	batch.commit()?;
+	Ok(report)
+}
+
+/// Removes the block of the provided header from the store and returns the
+/// previous header, which becomes the head candidate.
+fn rewind_head(
+	batch: &Batch<'_>,
+	header: &BlockHeader,
+	report: &mut RepairReport,
+) -> Result<BlockHeader, Error> {
+	if header.height == 0 {
+		return Err(ErrorKind::Other(
+			"no consistent block down to genesis, the chain data has to be resynced".to_owned(),
+		)
+		.into());
+	}
+	let prev = batch.get_previous_header(header)?;
+	// rewinding needs the input bitmaps of the blocks, we can't go below
+	// the blocks we still have
+	if prev.height > 0 && !batch.block_exists(&prev.hash())? {
+		return Err(ErrorKind::Other(format!(
+			"no consistent block down to {} at {}, the chain data has to be resynced",
+			prev.hash(),
+			prev.height
+		))
+		.into());
+	}
+	if let Ok(b) = batch.get_block(&header.hash()) {
+		batch.unindex_block(&b)?;
+		batch.delete_block(&b.hash())?;
+		report.blocks_removed += 1;
+	}
+	Ok(prev)
+}
+
+/// Recomputes the block sums of the stored blocks up to the head, starting
+/// from the sums below the oldest one, and checks they add up to the sums of
+/// the txhashset at the head. Without stored blocks, the sums of the head are
+/// set to those of the txhashset.
+fn repair_block_sums(
+	ext: &Extension<'_>,
+	head: &BlockHeader,
+	head_sums: &BlockSums,
+	report: &mut RepairReport,
+) -> Result<(), Error> {
+	let batch = ext.batch;
+	let mut hashes: Vec<Hash> = vec![];
+	let mut current = head.clone();
+	while current.height > 0 && batch.block_exists(&current.hash())? {
+		hashes.push(current.hash());
+		current = batch.get_previous_header(&current)?;
+	}
+	// the sums of the block below the oldest one we have
+	let mut prev_sums = match batch.get_block_sums(&current.hash()) {
+		Ok(sums) if !hashes.is_empty() => sums,
+		_ => {
+			let head_ok = match batch.get_block_sums(&head.hash()) {
+				Ok(ref s) => same_sums(s, head_sums),
+				Err(_) => false,
+			};
+			if head.height > 0 && !head_ok {
+				batch.save_block_sums(&head.hash(), head_sums)?;
+				report.block_sums += 1;
+				report.fixes.push(format!(
+					"computed the block sums of the head {} from the txhashset",
+					head.height
+				));
+			}
+			return Ok(());
+		}
+	};
+
+	let mut fixed = 0;
+	for hash in hashes.iter().rev() {
+		let b = batch.get_block(hash)?;
+		let (utxo_sum, kernel_sum) = (prev_sums, &b as &dyn Committed)
+			.verify_kernel_sums(b.header.overage(), b.header.total_kernel_offset())?;
+		let sums = BlockSums {
+			utxo_sum,
+			kernel_sum,
+		};
+		match batch.get_block_sums(hash) {
+			Ok(ref s) if same_sums(s, &sums) => {}
+			_ => {
+				batch.save_block_sums(hash, &sums)?;
+				fixed += 1;
+			}
+		}
+		prev_sums = sums;
+	}
+	if !same_sums(&prev_sums, head_sums) {
+		return Err(ErrorKind::Other(format!(
+			"the stored blocks don't add up to the txhashset at {}, the chain data has to be resynced",
+			head.height
+		))
+		.into());
+	}
+	if fixed > 0 {
+		report.block_sums = fixed;
+		report.fixes.push(format!(
+			"recomputed {} block sums from the stored blocks",
+			fixed
+		));
+	}
+	Ok(())
+}
+
+fn same_sums(a: &BlockSums, b: &BlockSums) -> bool {
+	a.utxo_sum == b.utxo_sum && a.kernel_sum == b.kernel_sum
+}
@@ -22,7 +22,7 @@ use crate::types::Tip;
 use crate::util::secp::pedersen::Commitment;
 use croaring::Bitmap;
 use bitgrin_store as store;
-use bitgrin_store::{option_to_not_found, to_key, Error, SerIterator};
+use bitgrin_store::{option_to_not_found, to_key, u64_to_key, Error, SerIterator};
 use std::sync::Arc;
 
 const STORE_SUBPATH: &'static str = "chain";
@@ -37,6 +37,15 @@ const COMMIT_POS_PREFIX: u8 = 'c' as u8;
 const BLOCK_INPUT_BITMAP_PREFIX: u8 = 'B' as u8;
 const BLOCK_SUMS_PREFIX: u8 = 'M' as u8;
 
+// Archive indexes, only maintained in archive mode
+const OUTPUT_BLOCK_PREFIX: u8 = 'O' as u8;
+const SPENDING_BLOCK_PREFIX: u8 = 'S' as u8;
+const KERNEL_BLOCK_PREFIX: u8 = 'K' as u8;
+const HEIGHT_HASH_PREFIX: u8 = 'N' as u8;
+
+/// Number of blocks indexed per batch when rebuilding the archive indexes
+const REINDEX_BATCH_SIZE: usize = 1000;
+
 /// All chain-related database operations
 pub struct ChainStore {
 	db: store::Store,
@@ -121,6 +130,81 @@ impl ChainStore {
 		)
 	}
 
+	/// Hash of the block of the chain which created the output with the
+	/// given commitment (archive index).
+	pub fn get_output_block(&self, commit: &Commitment) -> Result<Hash, Error> {
+		option_to_not_found(
+			self.db
+				.get_ser(&to_key(OUTPUT_BLOCK_PREFIX, &mut commit.as_ref().to_vec())),
+			&format!("Block creating output: {:?}", commit),
+		)
+	}
+
+	/// Hash of the block of the chain which spent the output with the given
+	/// commitment (archive index).
+	pub fn get_spending_block(&self, commit: &Commitment) -> Result<Hash, Error> {
+		option_to_not_found(
+			self.db.get_ser(&to_key(
+				SPENDING_BLOCK_PREFIX,
+				&mut commit.as_ref().to_vec(),
+			)),
+			&format!("Block spending output: {:?}", commit),
+		)
+	}
+
+	/// Hash of the block of the chain which contains the kernel with the
+	/// given excess (archive index).
+	pub fn get_kernel_block(&self, excess: &Commitment) -> Result<Hash, Error> {
+		option_to_not_found(
+			self.db
+				.get_ser(&to_key(KERNEL_BLOCK_PREFIX, &mut excess.as_ref().to_vec())),
+			&format!("Block with kernel: {:?}", excess),
+		)
+	}
+
+	/// Hash of the block of the chain at the given height (archive index).
+	pub fn get_hash_by_height(&self, height: u64) -> Result<Hash, Error> {
+		option_to_not_found(
+			self.db.get_ser(&u64_to_key(HEIGHT_HASH_PREFIX, height)),
+			&format!("Block at height: {}", height),
+		)
+	}
+
+	/// Rebuilds the archive indexes from the blocks of the chain, from the
+	/// genesis to the head, all of which need to be in the store. Returns
+	/// the number of blocks indexed.
+	pub fn rebuild_archive_indexes(&self) -> Result<u64, Error> {
+		let mut hashes = vec![];
+		let mut header = self.head_header()?;
+		loop {
+			hashes.push(header.hash());
+			if header.height == 0 {
+				break;
+			}
+			header = self.get_previous_header(&header)?;
+		}
+
+		let batch = self.batch()?;
+		batch.clear_archive_indexes()?;
+		batch.commit()?;
+
+		let mut indexed = 0;
+		for chunk in hashes.rchunks(REINDEX_BATCH_SIZE) {
+			let batch = self.batch()?;
+			for h in chunk.iter().rev() {
+				batch.index_block(&batch.get_block(h)?)?;
+			}
+			batch.commit()?;
+			indexed += chunk.len();
+			debug!(
+				"rebuild_archive_indexes: {} of {} blocks",
+				indexed,
+				hashes.len()
+			);
+		}
+		Ok(hashes.len() as u64)
+	}
+
 	/// Builds a new batch to be used with this store.
 	pub fn batch(&self) -> Result<Batch<'_>, Error> {
 		Ok(Batch {
@@ -367,6 +451,87 @@ impl<'a> Batch<'a> {
 		}
 	}
 
+	/// Hash of the block of the chain at the given height (archive index).
+	pub fn get_hash_by_height(&self, height: u64) -> Result<Hash, Error> {
+		option_to_not_found(
+			self.db.get_ser(&u64_to_key(HEIGHT_HASH_PREFIX, height)),
+			&format!("Block at height: {}", height),
+		)
+	}
+
+	/// Adds a block of the chain to the archive indexes: its height, the
+	/// outputs it creates, the ones it spends and its kernels. A commitment
+	/// created again is unspent until spent again.
+	pub fn index_block(&self, b: &Block) -> Result<(), Error> {
+		let hash = b.hash();
+		self.db
+			.put_ser(&u64_to_key(HEIGHT_HASH_PREFIX, b.header.height), &hash)?;
+		for output in b.outputs() {
+			let mut commit = output.commitment().as_ref().to_vec();
+			let spending_key = to_key(SPENDING_BLOCK_PREFIX, &mut commit.clone());
+			if self.db.get_ser::<Hash>(&spending_key)?.is_some() {
+				self.db.delete(&spending_key)?;
+			}
+			self.db
+				.put_ser(&to_key(OUTPUT_BLOCK_PREFIX, &mut commit), &hash)?;
+		}
+		for input in b.inputs() {
+			let mut commit = input.commitment().as_ref().to_vec();
+			self.db
+				.put_ser(&to_key(SPENDING_BLOCK_PREFIX, &mut commit), &hash)?;
+		}
+		for kernel in b.kernels() {
+			let mut excess = kernel.excess.as_ref().to_vec();
+			self.db
+				.put_ser(&to_key(KERNEL_BLOCK_PREFIX, &mut excess), &hash)?;
+		}
+		Ok(())
+	}
+
+	/// Removes a block leaving the chain from the archive indexes. Entries
+	/// since pointed to another block, such as a commitment created again,
+	/// are left alone.
+	pub fn unindex_block(&self, b: &Block) -> Result<(), Error> {
+		let hash = b.hash();
+		self.delete_if_hash(u64_to_key(HEIGHT_HASH_PREFIX, b.header.height), &hash)?;
+		for output in b.outputs() {
+			let mut commit = output.commitment().as_ref().to_vec();
+			self.delete_if_hash(to_key(OUTPUT_BLOCK_PREFIX, &mut commit), &hash)?;
+		}
+		for input in b.inputs() {
+			let mut commit = input.commitment().as_ref().to_vec();
+			self.delete_if_hash(to_key(SPENDING_BLOCK_PREFIX, &mut commit), &hash)?;
+		}
+		for kernel in b.kernels() {
+			let mut excess = kernel.excess.as_ref().to_vec();
+			self.delete_if_hash(to_key(KERNEL_BLOCK_PREFIX, &mut excess), &hash)?;
+		}
+		Ok(())
+	}
+
+	fn delete_if_hash(&self, key: Vec<u8>, hash: &Hash) -> Result<(), Error> {
+		if self.db.get_ser::<Hash>(&key)?.as_ref() == Some(hash) {
+			self.db.delete(&key)?;
+		}
+		Ok(())
+	}
+
+	/// Clear all entries from the archive indexes (must be rebuilt after).
+	pub fn clear_archive_indexes(&self) -> Result<(), Error> {
+		for prefix in &[
+			OUTPUT_BLOCK_PREFIX,
+			SPENDING_BLOCK_PREFIX,
+			KERNEL_BLOCK_PREFIX,
+			HEIGHT_HASH_PREFIX,
+		] {
+			let key = to_key(*prefix, &mut "".to_string().into_bytes());
+			for (k, _) in self.db.iter::<Hash>(&key)? {
+				self.db.delete(&k)?;
+			}
+		}
+		Ok(())
+	}
+
 	/// Commits this batch. If it's a child batch, it will be merged with the
 	/// parent, otherwise the batch is written to db.
 	pub fn commit(self) -> Result<(), Error> {
@@ -20,6 +20,7 @@ use crate::core::core::pmmr::RewindablePMMR;
 use crate::core::core::{BlockHeader, TxKernel};
 use crate::error::{Error, ErrorKind};
 use crate::store::Batch;
+use crate::util::secp::pedersen::Commitment;
 use bitgrin_store::pmmr::PMMRBackend;
 
 /// Rewindable (but readonly) view of the kernel set (based on kernel MMR).
@@ -81,6 +82,22 @@ impl<'a> RewindableKernelView<'a> {
 		Ok(())
 	}
 
+	/// Searches the kernels of this view, back to the provided MMR size, for
+	/// the one with the given excess, most recent first. Returns the kernel
+	/// and its position in the kernel MMR.
+	pub fn find_kernel(&self, excess: &Commitment, min_size: u64) -> Option<(TxKernel, u64)> {
+		let mut pos = self.header.kernel_mmr_size;
+		while pos > min_size {
+			if let Some(entry) = self.pmmr.get_data(pos) {
+				if entry.kernel.excess == *excess {
+					return Some((entry.kernel, pos));
+				}
+			}
+			pos -= 1;
+		}
+		None
+	}
+
 	/// Read the "raw" kernel backend data file (via temp file for consistent view on data).
 	pub fn kernel_data_read(&self) -> Result<File, Error> {
 		let file = self
@@ -246,6 +246,26 @@ impl TxHashSet {
 			.elements_from_insertion_index(start_index, max_count)
 	}
 
+	/// Unspent outputs added to the output MMR when it grew from the first to
+	/// the second size, i.e. the ones of a block given the output MMR sizes of
+	/// its previous header and its own.
+	pub fn unspent_outputs_between(&self, from_size: u64, to_size: u64) -> Vec<OutputIdentifier> {
+		let output_pmmr =
+			ReadonlyPMMR::at(&self.output_pmmr_h.backend, self.output_pmmr_h.last_pos);
+		(pmmr::n_leaves(from_size) + 1..=pmmr::n_leaves(to_size))
+			.filter_map(|i| output_pmmr.get_data(pmmr::insertion_to_pmmr_index(i)))
+			.collect()
+	}
+
+	/// As above, for kernels
+	pub fn kernels_between(&self, from_size: u64, to_size: u64) -> Vec<TxKernelEntry> {
+		let kernel_pmmr =
+			ReadonlyPMMR::at(&self.kernel_pmmr_h.backend, self.kernel_pmmr_h.last_pos);
+		(pmmr::n_leaves(from_size) + 1..=pmmr::n_leaves(to_size))
+			.filter_map(|i| kernel_pmmr.get_data(pmmr::insertion_to_pmmr_index(i)))
+			.collect()
+	}
+
 	/// highest output insertion index available
 	pub fn highest_output_insertion_index(&self) -> u64 {
 		pmmr::n_leaves(self.output_pmmr_h.last_pos)
@@ -1257,6 +1277,20 @@ impl<'a> Extension<'a> {
 		Ok((output_sum, kernel_sum))
 	}
 
+	/// Counts the UTXOs whose indexed MMR position is missing or wrong.
+	pub fn check_index(&self) -> Result<u64, Error> {
+		let mut count = 0;
+		for pos in self.output_pmmr.leaf_pos_iter() {
+			if let Some(out) = self.output_pmmr.get_data(pos) {
+				match self.batch.get_output_pos(&out.commit) {
+					Ok(p) if p == pos => {}
+					_ => count += 1,
+				}
+			}
+		}
+		Ok(count)
+	}
+
 	/// Rebuild the index of MMR positions to the corresponding UTXOs.
 	/// This is a costly operation performed only when we receive a full new chain state.
 	pub fn rebuild_index(&self) -> Result<(), Error> {
@@ -16,7 +16,7 @@
 
 use crate::core::core::hash::{Hash, Hashed, ZERO_HASH};
 //use crate::core::core::{Block, BlockHeader};
-use crate::core::core::{Block};
+use crate::core::core::{Block, BlockHeader};
 use crate::core::{core, ser};
 use crate::core::core::merkle_proof::MerkleProof;
 use crate::core::pow::Difficulty;
@@ -65,6 +65,25 @@ pub struct TxHashSetRoots {
 	pub kernel_root: Hash,
 }
 
+/// A dev fee payout, created as the coinbase of one of the first blocks and
+/// locked for a longer time than regular coinbases.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DevFeeOutput {
+	/// Height of the block paying it out
+	pub height: u64,
+	/// Amount paid out, excluding any fee of that block
+	pub amount: u64,
+	/// Height from which it can be spent
+	pub lock_height: u64,
+	/// Coinbase output commitment, unknown when spent and its block was
+	/// removed
+	pub commit: Option<pedersen::Commitment>,
+	/// Excess of the coinbase kernel
+	pub kernel_excess: Option<pedersen::Commitment>,
+	/// Whether the output is still in the UTXO set
+	pub unspent: bool,
+}
+
 /// The tip of a fork. A handle to the fork ancestry from its leaf in the
 /// blockchain tree. References the max height and the latest and previous
 /// blocks
@@ -186,12 +205,78 @@ pub enum BlockStatus {
 	/// Block does not update the chain head and is a fork.
 	Fork,
 	/// Block updates the chain head via a (potentially disruptive) "reorg".
-	/// Previous block was not our previous chain head.
-	Reorg(u64),
+	/// Previous block was not our previous chain head. The reorg depth, from
+	/// the heights of both heads, and the blocks rolled back and applied,
+	/// unless they couldn't be looked up.
+	Reorg(u64, Option<Reorg>),
 	/// Chain hash unexpected
 	ChainIntegrityFailure,
 }
 
+/// Hash and height of a block on either side of a reorg.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ReorgBlock {
+	/// Hash of the block
+	pub hash: Hash,
+	/// Height of the block
+	pub height: u64,
+}
+
+impl ReorgBlock {
+	fn from_header(header: &BlockHeader) -> ReorgBlock {
+		ReorgBlock {
+			hash: header.hash(),
+			height: header.height,
+		}
+	}
+}
+
+/// Blocks rolled back and applied when the chain head moved to another fork.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Reorg {
+	/// Last block both forks have in common
+	pub fork_point: ReorgBlock,
+	/// Blocks rolled back, from the former head down to the fork point
+	pub disconnected: Vec<ReorgBlock>,
+	/// Blocks applied, from the fork point up to the new head
+	pub connected: Vec<ReorgBlock>,
+}
+
+impl Reorg {
+	/// Walks back from both heads to the block they have in common, `header`
+	/// giving the header of a hash.
+	pub fn between<F>(prev_head: &Tip, head: &Tip, header: F) -> Result<Reorg, Error>
+	where
+		F: Fn(&Hash) -> Result<BlockHeader, Error>,
+	{
+		let mut old = header(&prev_head.last_block_h)?;
+		let mut new = header(&head.last_block_h)?;
+		let mut disconnected = vec![];
+		let mut connected = vec![];
+		while old.hash() != new.hash() {
+			if old.height >= new.height {
+				disconnected.push(ReorgBlock::from_header(&old));
+				old = header(&old.prev_hash)?;
+			}
+			if new.height > old.height {
+				connected.push(ReorgBlock::from_header(&new));
+				new = header(&new.prev_hash)?;
+			}
+		}
+		connected.reverse();
+		Ok(Reorg {
+			fork_point: ReorgBlock::from_header(&old),
+			disconnected,
+			connected,
+		})
+	}
+
+	/// Number of blocks rolled back.
+	pub fn depth(&self) -> u64 {
+		self.disconnected.len() as u64
+	}
+}
+
 
 
 
@@ -0,0 +1,250 @@
+// Copyright 2018 The BitGrin Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use bitgrin_chain as chain;
+use bitgrin_core as core;
+use bitgrin_keychain as keychain;
+use bitgrin_util as util;
+
+use std::fs;
+use std::sync::Arc;
+
+use chrono::Duration;
+
+use crate::chain::types::NoopAdapter;
+use crate::chain::{Chain, ChainStore, Options};
+use crate::core::consensus;
+use crate::core::core::hash::Hashed;
+use crate::core::core::verifier_cache::LruVerifierCache;
+use crate::core::core::{Block, BlockHeader, Transaction};
+use crate::core::global::{self, ChainTypes};
+use crate::core::libtx::{build, reward, ProofBuilder};
+use crate::core::pow::{self, Difficulty};
+use crate::keychain::{ExtKeychain, ExtKeychainPath, Identifier, Keychain};
+use crate::util::RwLock;
+
+fn key_id(n: u64) -> Identifier {
+	ExtKeychainPath::new(1, n as u32, 0, 0, 0).to_identifier()
+}
+
+fn prepare_block<K: Keychain>(
+	kc: &K,
+	prev: &BlockHeader,
+	chain: &Chain,
+	txs: Vec<Transaction>,
+	n: u64,
+) -> Block {
+	let height = prev.height + 1;
+	let fees = txs.iter().map(|tx| tx.fee()).sum();
+	let builder = ProofBuilder::new(kc);
+	let reward = reward::output(kc, &builder, &key_id(n), fees, height, false).unwrap();
+	let mut b = Block::new(prev, txs, Difficulty::min(), reward).unwrap();
+	b.header.timestamp = prev.timestamp + Duration::seconds(60);
+	b.header.pow.total_difficulty = prev.total_difficulty() + Difficulty::min();
+	b.header.pow.proof = pow::Proof::random(global::proofsize());
+	if chain.head().unwrap().last_block_h == prev.hash() {
+		chain.set_txhashset_roots(&mut b).unwrap();
+	} else {
+		chain.set_txhashset_roots_forked(&mut b, prev).unwrap();
+	}
+	b
+}
+
+/// Checks the height index against the chain headers up to the head.
+fn check_heights(chain: &Chain) {
+	let store = chain.store();
+	for h in 0..=chain.head().unwrap().height {
+		let header = chain.get_header_by_height(h).unwrap();
+		assert_eq!(store.get_hash_by_height(h).unwrap(), header.hash());
+	}
+	assert!(store
+		.get_hash_by_height(chain.head().unwrap().height + 1)
+		.is_err());
+}
+
+#[test]
+fn archive_indexes() {
+	let dir = ".bitgrin_archive_indexes";
+	let _ = fs::remove_dir_all(dir);
+	global::set_mining_mode(ChainTypes::AutomatedTesting);
+	util::init_test_logger();
+
+	let chain = Chain::init(
+		dir.to_string(),
+		Arc::new(NoopAdapter {}),
+		pow::mine_genesis_block().unwrap(),
+		pow::verify_size,
+		Arc::new(RwLock::new(LruVerifierCache::new())),
+		true,
+	)
+	.unwrap();
+	let store = chain.store();
+	let kc = ExtKeychain::from_random_seed(false).unwrap();
+	let mine = |prev: &BlockHeader, txs: Vec<Transaction>, n: u64| {
+		let b = prepare_block(&kc, prev, &chain, txs, n);
+		chain.process_block(b.clone(), Options::SKIP_POW).unwrap();
+		b
+	};
+
+	let mut blocks = vec![];
+	for n in 1..=global::coinbase_maturity() + 2 {
+		let prev = chain.head_header().unwrap();
+		blocks.push(mine(&prev, vec![], n));
+	}
+	check_heights(&chain);
+	for b in &blocks {
+		let commit = b.outputs()[0].commitment();
+		assert_eq!(store.get_output_block(&commit).unwrap(), b.hash());
+		assert!(store.get_spending_block(&commit).is_err());
+		assert_eq!(
+			store.get_kernel_block(&b.kernels()[0].excess).unwrap(),
+			b.hash()
+		);
+	}
+
+	// spend the first coinbase
+	let amount = consensus::reward_at_height(1).0;
+	let builder = ProofBuilder::new(&kc);
+	let tx = build::transaction(
+		vec![
+			build::coinbase_input(amount, key_id(1)),
+			build::output(amount - 2, key_id(100)),
+			build::with_fee(2),
+		],
+		&kc,
+		&builder,
+	)
+	.unwrap();
+	let spent = tx.inputs()[0].commitment();
+	let created = tx.outputs()[0].commitment();
+	let excess = tx.kernels()[0].excess;
+	let fork_point = chain.head_header().unwrap();
+	let block = mine(&fork_point, vec![tx], 1000);
+
+	check_heights(&chain);
+	assert_eq!(store.get_output_block(&spent).unwrap(), blocks[0].hash());
+	assert_eq!(store.get_spending_block(&spent).unwrap(), block.hash());
+	assert_eq!(store.get_output_block(&created).unwrap(), block.hash());
+	assert_eq!(store.get_kernel_block(&excess).unwrap(), block.hash());
+
+	// a longer fork without the transaction takes over
+	let fork1 = mine(&fork_point, vec![], 2000);
+	assert_eq!(chain.head().unwrap().last_block_h, block.hash());
+	let fork2 = mine(&fork1.header, vec![], 2001);
+	assert_eq!(chain.head().unwrap().last_block_h, fork2.hash());
+
+	let check_fork = || {
+		check_heights(&chain);
+		assert_eq!(store.get_output_block(&spent).unwrap(), blocks[0].hash());
+		assert!(store.get_spending_block(&spent).is_err());
+		assert!(store.get_output_block(&created).is_err());
+		assert!(store.get_kernel_block(&excess).is_err());
+		for b in &[&fork1, &fork2] {
+			let commit = b.outputs()[0].commitment();
+			assert_eq!(store.get_output_block(&commit).unwrap(), b.hash());
+		}
+		let commit = block.outputs()[0].commitment();
+		assert!(store.get_output_block(&commit).is_err());
+	};
+	check_fork();
+
+	// the reindex comes to the same indexes
+	let count = store.rebuild_archive_indexes().unwrap();
+	assert_eq!(count, chain.head().unwrap().height + 1);
+	check_fork();
+
+	let _ = fs::remove_dir_all(dir);
+}
+
+#[test]
+fn archive_indexes_recreated_output() {
+	let dir = ".bitgrin_archive_indexes_recreated";
+	let _ = fs::remove_dir_all(dir);
+	global::set_mining_mode(ChainTypes::AutomatedTesting);
+
+	let store = ChainStore::new(dir).unwrap();
+	let genesis = pow::mine_genesis_block().unwrap();
+	let kc = ExtKeychain::from_random_seed(false).unwrap();
+	let builder = ProofBuilder::new(&kc);
+
+	// the same commitment gets created, spent and created again
+	let txs = vec![
+		build::transaction(
+			vec![
+				build::input(12, key_id(1)),
+				build::output(10, key_id(2)),
+				build::with_fee(2),
+			],
+			&kc,
+			&builder,
+		)
+		.unwrap(),
+		build::transaction(
+			vec![
+				build::input(10, key_id(2)),
+				build::output(8, key_id(3)),
+				build::with_fee(2),
+			],
+			&kc,
+			&builder,
+		)
+		.unwrap(),
+		build::transaction(
+			vec![
+				build::input(12, key_id(4)),
+				build::output(10, key_id(2)),
+				build::with_fee(2),
+			],
+			&kc,
+			&builder,
+		)
+		.unwrap(),
+	];
+	let commit = txs[0].outputs()[0].commitment();
+	assert_eq!(txs[2].outputs()[0].commitment(), commit);
+
+	let mut prev = genesis.header.clone();
+	let mut blocks = vec![];
+	for (n, tx) in txs.into_iter().enumerate() {
+		let height = prev.height + 1;
+		let reward = reward::output(
+			&kc,
+			&builder,
+			&key_id(100 + n as u64),
+			tx.fee(),
+			height,
+			false,
+		)
+		.unwrap();
+		let b = Block::new(&prev, vec![tx], Difficulty::min(), reward).unwrap();
+		prev = b.header.clone();
+		blocks.push(b);
+	}
+	let index = |b: &Block| {
+		let batch = store.batch().unwrap();
+		batch.index_block(b).unwrap();
+		batch.commit().unwrap();
+	};
+
+	index(&blocks[0]);
+	index(&blocks[1]);
+	assert_eq!(store.get_output_block(&commit).unwrap(), blocks[0].hash());
+	assert_eq!(store.get_spending_block(&commit).unwrap(), blocks[1].hash());
+
+	index(&blocks[2]);
+	assert_eq!(store.get_output_block(&commit).unwrap(), blocks[2].hash());
+	assert!(store.get_spending_block(&commit).is_err());
+
+	let _ = fs::remove_dir_all(dir);
+}
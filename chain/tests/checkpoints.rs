@@ -0,0 +1,183 @@
+// Copyright 2018 The BitGrin Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use bitgrin_chain as chain;
+use bitgrin_core as core;
+use bitgrin_keychain as keychain;
+use bitgrin_util as util;
+
+use std::fs;
+use std::sync::Arc;
+
+use chrono::Duration;
+
+use crate::chain::types::NoopAdapter;
+use crate::chain::{Chain, ErrorKind, Options};
+use crate::core::core::hash::Hashed;
+use crate::core::core::verifier_cache::LruVerifierCache;
+use crate::core::core::{Block, BlockHeader};
+use crate::core::global::{self, ChainTypes, Checkpoints};
+use crate::core::libtx::{reward, ProofBuilder};
+use crate::core::pow::{self, Difficulty};
+use crate::keychain::{ExtKeychain, ExtKeychainPath, Keychain};
+use crate::util::RwLock;
+
+fn clean_output_dir(dir_name: &str) {
+	let _ = fs::remove_dir_all(dir_name);
+}
+
+fn setup(dir_name: &str, genesis: Block) -> Chain {
+	clean_output_dir(dir_name);
+	Chain::init(
+		dir_name.to_string(),
+		Arc::new(NoopAdapter {}),
+		genesis,
+		pow::verify_size,
+		Arc::new(RwLock::new(LruVerifierCache::new())),
+		false,
+	)
+	.unwrap()
+}
+
+fn prepare_block<K: Keychain>(kc: &K, prev: &BlockHeader, chain: &Chain) -> Block {
+	let height = prev.height + 1;
+	let key_id = ExtKeychainPath::new(1, height as u32, 0, 0, 0).to_identifier();
+	let reward = reward::output(kc, &ProofBuilder::new(kc), &key_id, 0, height, false).unwrap();
+	let mut b = Block::new(prev, vec![], Difficulty::min(), reward).unwrap();
+	b.header.timestamp = prev.timestamp + Duration::seconds(60);
+	b.header.pow.total_difficulty = prev.total_difficulty() + Difficulty::min();
+	b.header.pow.proof = pow::Proof::random(global::proofsize());
+	chain.set_txhashset_roots(&mut b).unwrap();
+	b
+}
+
+fn mine<K: Keychain>(kc: &K, chain: &Chain, count: usize) {
+	for _ in 0..count {
+		let prev = chain.head_header().unwrap();
+		let b = prepare_block(kc, &prev, chain);
+		chain.process_block(b, Options::SKIP_POW).unwrap();
+	}
+}
+
+fn headers(chain: &Chain, from: u64, to: u64) -> Vec<BlockHeader> {
+	(from..=to)
+		.map(|h| chain.get_header_by_height(h).unwrap())
+		.collect()
+}
+
+fn is_checkpoint_mismatch(res: Result<(), chain::Error>, height: u64) -> bool {
+	match res {
+		Err(e) => e.kind() == ErrorKind::CheckpointMismatch(height),
+		Ok(_) => false,
+	}
+}
+
+#[test]
+fn checkpoints_table() {
+	let mainnet = Checkpoints::for_chain(&ChainTypes::Mainnet);
+	assert_eq!(
+		mainnet.get(40_000).unwrap().to_hex(),
+		"000227e48810ad9924bedf88c224e358ecc6411ed9a87587dd79f13feaf64448"
+	);
+	assert_eq!(mainnet.last_at(524_181).unwrap().0, 524_160);
+	assert!(mainnet.last_at(1).is_none());
+	assert!(mainnet.matches(3, &mainnet.get(2).unwrap()));
+	assert!(!mainnet.matches(40_000, &mainnet.get(2).unwrap()));
+	assert_eq!(
+		Checkpoints::for_chain(&ChainTypes::Floonet),
+		Checkpoints::default()
+	);
+
+	// operator checkpoints can be added but never override shipped ones
+	let mut checkpoints = mainnet.clone();
+	assert!(checkpoints.add(2, mainnet.get(40_000).unwrap()).is_err());
+	assert!(checkpoints.add(2, mainnet.get(2).unwrap()).is_ok());
+	assert!(checkpoints.add(600_000, mainnet.get(2).unwrap()).is_ok());
+	assert_eq!(checkpoints.last_at(700_000).unwrap().0, 600_000);
+}
+
+#[test]
+fn reject_forks_below_checkpoints() {
+	let dir = ".bitgrin_checkpoints";
+	global::set_mining_mode(ChainTypes::AutomatedTesting);
+	util::init_test_logger();
+	let genesis = pow::mine_genesis_block().unwrap();
+
+	// our chain, and a fork of it from height 2
+	let chain = setup(&format!("{}/main", dir), genesis.clone());
+	let kc = ExtKeychain::from_random_seed(false).unwrap();
+	mine(&kc, &chain, 8);
+	let fork = setup(&format!("{}/fork", dir), genesis.clone());
+	for header in headers(&chain, 1, 2) {
+		let b = chain.get_block(&header.hash()).unwrap();
+		fork.process_block(b, Options::SKIP_POW).unwrap();
+	}
+	let fork_kc = ExtKeychain::from_random_seed(false).unwrap();
+	mine(&fork_kc, &fork, 7);
+
+	let mut checkpoints = Checkpoints::default();
+	checkpoints
+		.add(4, chain.get_header_by_height(4).unwrap().hash())
+		.unwrap();
+	global::set_operator_checkpoints(checkpoints).unwrap();
+	chain.validate(false, true).unwrap();
+
+	// fork headers crossing the checkpoint are rejected and never stored
+	let fork_headers = headers(&fork, 3, 9);
+	assert!(is_checkpoint_mismatch(
+		chain.sync_block_headers(&fork_headers, Options::SKIP_POW),
+		4
+	));
+	for header in &fork_headers {
+		assert!(chain.get_block_header(&header.hash()).is_err());
+	}
+
+	// so are fork headers below the checkpoint we already went through
+	assert!(is_checkpoint_mismatch(
+		chain.sync_block_headers(&fork_headers[..1], Options::SKIP_POW),
+		4
+	));
+	assert!(is_checkpoint_mismatch(
+		chain.process_block_header(&fork_headers[0], Options::SKIP_POW),
+		4
+	));
+	let fork_block = fork.get_block(&fork_headers[0].hash()).unwrap();
+	assert!(chain.process_block(fork_block, Options::SKIP_POW).is_err());
+	assert_eq!(chain.head().unwrap().height, 8);
+
+	// a node syncing from scratch rejects the fork at the checkpoint
+	let fresh = setup(&format!("{}/fresh", dir), genesis.clone());
+	fresh
+		.rebuild_sync_mmr(&fresh.header_head().unwrap())
+		.unwrap();
+	fresh
+		.sync_block_headers(&headers(&fork, 1, 3), Options::SKIP_POW)
+		.unwrap();
+	assert!(is_checkpoint_mismatch(
+		fresh.sync_block_headers(&headers(&fork, 4, 9), Options::SKIP_POW),
+		4
+	));
+	assert_eq!(fresh.header_head().unwrap().height, 3);
+
+	// and validation fails when our own chain misses a checkpoint
+	let mut checkpoints = Checkpoints::default();
+	checkpoints
+		.add(5, fork.get_header_by_height(5).unwrap().hash())
+		.unwrap();
+	global::set_operator_checkpoints(checkpoints).unwrap();
+	assert!(is_checkpoint_mismatch(chain.validate(false, true), 5));
+
+	global::set_operator_checkpoints(Checkpoints::default()).unwrap();
+	clean_output_dir(dir);
+}
@@ -0,0 +1,119 @@
+// Copyright 2018 The BitGrin Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use bitgrin_chain as chain;
+use bitgrin_core as core;
+use bitgrin_keychain as keychain;
+use bitgrin_util as util;
+
+use std::fs;
+use std::sync::Arc;
+
+use chrono::Duration;
+
+use crate::chain::types::NoopAdapter;
+use crate::chain::{Chain, Options};
+use crate::core::consensus;
+use crate::core::core::hash::Hashed;
+use crate::core::core::verifier_cache::LruVerifierCache;
+use crate::core::core::{Block, BlockHeader, Transaction};
+use crate::core::global::{self, ChainTypes};
+use crate::core::libtx::{build, reward, ProofBuilder};
+use crate::core::pow::{self, Difficulty};
+use crate::keychain::{ExtKeychain, ExtKeychainPath, Identifier, Keychain};
+use crate::util::RwLock;
+
+fn key_id(height: u64) -> Identifier {
+	ExtKeychainPath::new(1, height as u32, 0, 0, 0).to_identifier()
+}
+
+fn prepare_block<K: Keychain>(
+	kc: &K,
+	prev: &BlockHeader,
+	chain: &Chain,
+	txs: Vec<Transaction>,
+) -> Block {
+	let height = prev.height + 1;
+	let fees = txs.iter().map(|tx| tx.fee()).sum();
+	let builder = ProofBuilder::new(kc);
+	let reward = reward::output(kc, &builder, &key_id(height), fees, height, false).unwrap();
+	let mut b = Block::new(prev, txs, Difficulty::min(), reward).unwrap();
+	b.header.timestamp = prev.timestamp + Duration::seconds(60);
+	b.header.pow.total_difficulty = prev.total_difficulty() + Difficulty::min();
+	b.header.pow.proof = pow::Proof::random(global::proofsize());
+	chain.set_txhashset_roots(&mut b).unwrap();
+	b
+}
+
+#[test]
+fn dev_fee_outputs() {
+	let dir = ".bitgrin_dev_fee";
+	let _ = fs::remove_dir_all(dir);
+	global::set_mining_mode(ChainTypes::AutomatedTesting);
+	util::init_test_logger();
+
+	let chain = Chain::init(
+		dir.to_string(),
+		Arc::new(NoopAdapter {}),
+		pow::mine_genesis_block().unwrap(),
+		pow::verify_size,
+		Arc::new(RwLock::new(LruVerifierCache::new())),
+		false,
+	)
+	.unwrap();
+	let kc = ExtKeychain::from_random_seed(false).unwrap();
+	for _ in 0..global::coinbase_maturity() + 2 {
+		let prev = chain.head_header().unwrap();
+		let b = prepare_block(&kc, &prev, &chain, vec![]);
+		chain.process_block(b, Options::SKIP_POW).unwrap();
+	}
+
+	// spend the first payout
+	let amount = consensus::reward_at_height(1).0;
+	let builder = ProofBuilder::new(&kc);
+	let tx = build::transaction(
+		vec![
+			build::coinbase_input(amount, key_id(1)),
+			build::output(amount - 2, key_id(100)),
+			build::with_fee(2),
+		],
+		&kc,
+		&builder,
+	)
+	.unwrap();
+	let prev = chain.head_header().unwrap();
+	let b = prepare_block(&kc, &prev, &chain, vec![tx]);
+	chain.process_block(b, Options::SKIP_POW).unwrap();
+
+	let head = chain.head().unwrap();
+	let payouts = chain.dev_fee_outputs().unwrap();
+	assert_eq!(payouts.len() as u64, head.height);
+	for payout in &payouts {
+		let block = chain
+			.get_block(&chain.get_header_by_height(payout.height).unwrap().hash())
+			.unwrap();
+		let output = block.outputs().iter().find(|o| o.is_coinbase()).unwrap();
+		let kernel = block.kernels().iter().find(|k| k.is_coinbase()).unwrap();
+		assert_eq!(payout.commit, Some(output.commit));
+		assert_eq!(payout.kernel_excess, Some(kernel.excess));
+		assert_eq!(payout.amount, consensus::dev_fee_at_height(payout.height));
+		assert_eq!(
+			payout.lock_height,
+			payout.height * (consensus::DEV_FEE_PAYOUT_INTERVAL + 1)
+		);
+		assert_eq!(payout.unspent, payout.height != 1);
+	}
+
+	let _ = fs::remove_dir_all(dir);
+}
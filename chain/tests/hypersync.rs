@@ -0,0 +1,343 @@
+// Copyright 2018 The BitGrin Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use bitgrin_chain as chain;
+use bitgrin_core as core;
+use bitgrin_keychain as keychain;
+use bitgrin_util as util;
+
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::net::TcpListener;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use chrono::Duration;
+
+use crate::chain::hypersync::{self, HypersyncManifest};
+use crate::chain::types::NoopAdapter;
+use crate::chain::{Chain, Options};
+use crate::core::core::hash::Hashed;
+use crate::core::core::verifier_cache::LruVerifierCache;
+use crate::core::core::{Block, BlockHeader};
+use crate::core::global::{self, ChainTypes};
+use crate::core::libtx::{reward, ProofBuilder};
+use crate::core::pow::{self, Difficulty};
+use crate::keychain::{ExtKeychain, ExtKeychainPath, Keychain};
+use crate::util::{zip, RwLock};
+
+fn clean_output_dir(dir_name: &str) {
+	let _ = fs::remove_dir_all(dir_name);
+}
+
+fn prepare_block<K: Keychain>(kc: &K, prev: &BlockHeader, chain: &Chain) -> Block {
+	let height = prev.height + 1;
+	let key_id = ExtKeychainPath::new(1, height as u32, 0, 0, 0).to_identifier();
+	let reward = reward::output(kc, &ProofBuilder::new(kc), &key_id, 0, height, false).unwrap();
+	let mut b = Block::new(prev, vec![], Difficulty::min(), reward).unwrap();
+	b.header.timestamp = prev.timestamp + Duration::seconds(60);
+	b.header.pow.total_difficulty = prev.total_difficulty() + Difficulty::min();
+	b.header.pow.proof = pow::Proof::random(global::proofsize());
+	chain.set_txhashset_roots(&mut b).unwrap();
+	b
+}
+
+/// A snapshot of a small chain, as it would be published on the mirrors.
+struct Fixture {
+	genesis: Block,
+	manifest: HypersyncManifest,
+	manifest_bytes: Vec<u8>,
+	archive_bytes: Vec<u8>,
+}
+
+fn build_fixture(dir: &str) -> Fixture {
+	global::set_mining_mode(ChainTypes::AutomatedTesting);
+	util::init_test_logger();
+	clean_output_dir(dir);
+	let chain_dir = format!("{}/chain_data", dir);
+	let genesis = pow::mine_genesis_block().unwrap();
+	let header = {
+		let chain = Chain::init(
+			chain_dir.clone(),
+			Arc::new(NoopAdapter {}),
+			genesis.clone(),
+			pow::verify_size,
+			Arc::new(RwLock::new(LruVerifierCache::new())),
+			false,
+		)
+		.unwrap();
+		let kc = ExtKeychain::from_random_seed(false).unwrap();
+		for _ in 0..6 {
+			let prev = chain.head_header().unwrap();
+			let b = prepare_block(&kc, &prev, &chain);
+			chain.process_block(b, Options::SKIP_POW).unwrap();
+		}
+		chain.validate(false, true).unwrap();
+		chain.head_header().unwrap()
+	};
+
+	let archive = Path::new(dir).join("snapshot.zip");
+	zip::compress(Path::new(&chain_dir), &File::create(&archive).unwrap()).unwrap();
+	let manifest = HypersyncManifest::build(Path::new(&chain_dir), &header, &archive).unwrap();
+	assert_eq!(manifest.height, 6);
+	assert_eq!(manifest.archive.path, "snapshot.zip");
+	assert!(manifest.is_listed("header/header_head/pmmr_data.bin"));
+	assert!(manifest.is_listed("lmdb/data.mdb"));
+	assert!(!manifest.is_listed("lmdb/lock.mdb"));
+
+	let manifest_bytes = manifest.to_json().unwrap().into_bytes();
+	assert_eq!(
+		HypersyncManifest::from_json(&manifest_bytes).unwrap(),
+		manifest
+	);
+	Fixture {
+		genesis,
+		manifest,
+		manifest_bytes,
+		archive_bytes: fs::read(&archive).unwrap(),
+	}
+}
+
+/// Minimal local stand-in for a mirror, serving static content with support
+/// for range requests. Returns the mirror url and the log of requests
+/// received as (path, range header).
+fn serve(files: HashMap<String, Vec<u8>>) -> (String, Arc<Mutex<Vec<(String, Option<String>)>>>) {
+	let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+	let url = format!("http://{}", listener.local_addr().unwrap());
+	let log = Arc::new(Mutex::new(vec![]));
+	let server_log = log.clone();
+	thread::spawn(move || {
+		for stream in listener.incoming() {
+			let mut stream = match stream {
+				Ok(s) => s,
+				Err(_) => continue,
+			};
+			let mut req = vec![];
+			let mut buf = [0; 1024];
+			while !String::from_utf8_lossy(&req).contains("\r\n\r\n") {
+				match stream.read(&mut buf) {
+					Ok(0) | Err(_) => break,
+					Ok(n) => req.extend_from_slice(&buf[..n]),
+				}
+			}
+			let req = String::from_utf8_lossy(&req).into_owned();
+			let path = req.split_whitespace().nth(1).unwrap_or("").to_owned();
+			let range = req
+				.lines()
+				.find(|l| l.to_lowercase().starts_with("range:"))
+				.map(|l| l[6..].trim().to_owned());
+			server_log
+				.lock()
+				.unwrap()
+				.push((path.clone(), range.clone()));
+
+			let (status, body) = match files.get(&path) {
+				None => ("404 Not Found", vec![]),
+				Some(data) => match range {
+					Some(r) => {
+						let start: usize = r
+							.trim_start_matches("bytes=")
+							.trim_end_matches('-')
+							.parse()
+							.unwrap();
+						("206 Partial Content", data[start..].to_vec())
+					}
+					None => ("200 OK", data.clone()),
+				},
+			};
+			let head = format!(
+				"HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+				status,
+				body.len()
+			);
+			let _ = stream.write_all(head.as_bytes());
+			let _ = stream.write_all(&body);
+		}
+	});
+	(url, log)
+}
+
+fn mirror_files(fixture: &Fixture, archive: Vec<u8>) -> HashMap<String, Vec<u8>> {
+	let mut files = HashMap::new();
+	files.insert("/manifest.json".to_owned(), fixture.manifest_bytes.clone());
+	files.insert("/snapshot.zip".to_owned(), archive);
+	files
+}
+
+#[test]
+fn hypersync_mirror_failover() {
+	let dir = ".bitgrin_hypersync_failover";
+	let fixture = build_fixture(dir);
+
+	let mut corrupt = fixture.archive_bytes.clone();
+	let last = corrupt.len() - 1;
+	corrupt[last] ^= 0xff;
+	let (bad_mirror, _) = serve(mirror_files(&fixture, corrupt));
+	let (good_mirror, _) = serve(mirror_files(&fixture, fixture.archive_bytes.clone()));
+	let mirrors = vec![
+		"http://127.0.0.1:1".to_owned(),
+		bad_mirror,
+		format!("{}/", good_mirror),
+	];
+
+	let pinned = hypersync::blake2b_digest(&fixture.manifest_bytes[..]).unwrap();
+	let manifest = hypersync::fetch_manifest(&mirrors, "manifest.json", Some(&pinned)).unwrap();
+	assert_eq!(manifest, fixture.manifest);
+
+	let archive = Path::new(dir).join("download.zip");
+	hypersync::download_archive(&mirrors, &manifest, &archive).unwrap();
+	assert_eq!(fs::read(&archive).unwrap(), fixture.archive_bytes);
+
+	let staging = Path::new(dir).join("staging");
+	manifest.extract_archive(&archive, &staging).unwrap();
+	assert!(!staging.join("lmdb/lock.mdb").exists());
+	manifest
+		.verify_chain_data(&staging, fixture.genesis.clone())
+		.unwrap();
+
+	clean_output_dir(dir);
+}
+
+#[test]
+fn hypersync_resume_download() {
+	let dir = ".bitgrin_hypersync_resume";
+	let fixture = build_fixture(dir);
+	let (mirror, log) = serve(mirror_files(&fixture, fixture.archive_bytes.clone()));
+	let mirrors = vec![mirror];
+
+	let archive = Path::new(dir).join("download.zip");
+	let half = fixture.archive_bytes.len() / 2;
+	fs::write(&archive, &fixture.archive_bytes[..half]).unwrap();
+	hypersync::download_archive(&mirrors, &fixture.manifest, &archive).unwrap();
+	assert_eq!(fs::read(&archive).unwrap(), fixture.archive_bytes);
+	assert_eq!(
+		log.lock().unwrap().clone(),
+		vec![("/snapshot.zip".to_owned(), Some(format!("bytes={}-", half)))]
+	);
+
+	// a complete archive is not downloaded again
+	hypersync::download_archive(&mirrors, &fixture.manifest, &archive).unwrap();
+	assert_eq!(log.lock().unwrap().len(), 1);
+
+	clean_output_dir(dir);
+}
+
+#[test]
+fn hypersync_rejects_bad_snapshots() {
+	let dir = ".bitgrin_hypersync_reject";
+	let fixture = build_fixture(dir);
+	let (mirror, _) = serve(mirror_files(&fixture, fixture.archive_bytes.clone()));
+	let mirrors = vec![mirror];
+
+	// pinned manifest digest mismatch, or missing manifest
+	let pinned = hypersync::blake2b_digest(&b"another manifest"[..]).unwrap();
+	assert!(hypersync::fetch_manifest(&mirrors, "manifest.json", Some(&pinned)).is_err());
+	assert!(hypersync::fetch_manifest(&mirrors, "missing.json", None).is_err());
+
+	// manifest for another chain type
+	let mut other = fixture.manifest.clone();
+	other.chain_type = "main".to_owned();
+	let other_bytes = other.to_json().unwrap().into_bytes();
+	assert!(HypersyncManifest::from_json(&other_bytes).is_err());
+
+	let archive = Path::new(dir).join("download.zip");
+	hypersync::download_archive(&mirrors, &fixture.manifest, &archive).unwrap();
+
+	// tampered file after extraction
+	let staging = Path::new(dir).join("staging");
+	fixture
+		.manifest
+		.extract_archive(&archive, &staging)
+		.unwrap();
+	let tampered = staging.join(&fixture.manifest.files[0].path);
+	let mut data = fs::read(&tampered).unwrap();
+	data.push(0);
+	fs::write(&tampered, data).unwrap();
+	assert!(fixture.manifest.verify_files(&staging).is_err());
+
+	// chain state not matching the manifest head
+	clean_output_dir(staging.to_str().unwrap());
+	fixture
+		.manifest
+		.extract_archive(&archive, &staging)
+		.unwrap();
+	let mut wrong_head = fixture.manifest.clone();
+	wrong_head.height += 1;
+	assert!(wrong_head
+		.verify_chain_data(&staging, fixture.genesis.clone())
+		.is_err());
+	let mut wrong_roots = fixture.manifest.clone();
+	wrong_roots.roots.kernel_root = wrong_roots.roots.output_root.clone();
+	assert!(wrong_roots
+		.verify_chain_data(&staging, fixture.genesis.clone())
+		.is_err());
+
+	clean_output_dir(dir);
+}
+
+#[test]
+fn hypersync_snapshot_from_node() {
+	let dir = ".bitgrin_hypersync_snapshot";
+	global::set_mining_mode(ChainTypes::AutomatedTesting);
+	util::init_test_logger();
+	clean_output_dir(dir);
+	let chain_dir = format!("{}/chain_data", dir);
+	let genesis = pow::mine_genesis_block().unwrap();
+	let chain = Chain::init(
+		chain_dir.clone(),
+		Arc::new(NoopAdapter {}),
+		genesis.clone(),
+		pow::verify_size,
+		Arc::new(RwLock::new(LruVerifierCache::new())),
+		false,
+	)
+	.unwrap();
+	let kc = ExtKeychain::from_random_seed(false).unwrap();
+	for _ in 0..8 {
+		let prev = chain.head_header().unwrap();
+		let b = prepare_block(&kc, &prev, &chain);
+		chain.process_block(b, Options::SKIP_POW).unwrap();
+	}
+
+	// invalid names never reach the file system
+	let head = chain.head_header().unwrap();
+	assert!(chain.snapshot(&head, "../escape").is_err());
+	assert!(chain.snapshot(&head, "").is_err());
+
+	// snapshot below the head, the node itself is left untouched
+	let header = chain.get_header_by_height(6).unwrap();
+	let manifest = chain.snapshot(&header, "test_snapshot").unwrap();
+	assert_eq!(chain.head_header().unwrap(), head);
+	assert_eq!(manifest.height, 6);
+	assert_eq!(manifest.block_hash, header.hash().to_hex());
+	assert!(!manifest.is_listed("lmdb/lock.mdb"));
+
+	let out_dir = Path::new(dir).join(hypersync::SNAPSHOT_DIR);
+	assert!(!out_dir.join("test_snapshot.staging").exists());
+	let manifest_bytes = fs::read(out_dir.join("test_snapshot.manifest.json")).unwrap();
+	assert_eq!(
+		HypersyncManifest::from_json(&manifest_bytes).unwrap(),
+		manifest
+	);
+
+	// the snapshot is what a hypersyncing node gets and verifies
+	let archive = out_dir.join("test_snapshot.zip");
+	let staging = Path::new(dir).join("staging");
+	manifest.extract_archive(&archive, &staging).unwrap();
+	manifest.verify_chain_data(&staging, genesis).unwrap();
+
+	clean_output_dir(dir);
+}
@@ -270,10 +270,18 @@ fn mine_reorg() {
 		chain.process_block(b, chain::Options::SKIP_POW).unwrap();
 
 		// Check that reorg is correctly reported in block status
-		assert_eq!(
-			*adapter.last_status.read(),
-			Some(BlockStatus::Reorg(REORG_DEPTH))
-		);
+		match *adapter.last_status.read() {
+			Some(BlockStatus::Reorg(depth, Some(ref reorg))) => {
+				assert_eq!(depth, REORG_DEPTH);
+				assert_eq!(reorg.depth(), REORG_DEPTH);
+				assert_eq!(reorg.fork_point.hash, fork_head.hash());
+				assert_eq!(reorg.disconnected[0].hash, head.hash());
+				assert_eq!(reorg.disconnected[0].height, NUM_BLOCKS_MAIN);
+				assert_eq!(reorg.connected.len(), 1);
+				assert_eq!(reorg.connected[0].hash, reorg_head.hash());
+			}
+			ref status => panic!("unexpected block status {:?}", status),
+		}
 
 		// Chain should be switched to the reorganized chain
 		let head = chain.head_header().unwrap();
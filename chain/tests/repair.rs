@@ -0,0 +1,290 @@
+// Copyright 2018 The BitGrin Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use bitgrin_chain as chain;
+use bitgrin_core as core;
+use bitgrin_keychain as keychain;
+use bitgrin_util as util;
+
+use std::fs;
+use std::sync::Arc;
+
+use chrono::Duration;
+
+use crate::chain::txhashset::TxHashSet;
+use crate::chain::types::NoopAdapter;
+use crate::chain::{repair, txhashset, Chain, ChainStore, Options, Tip};
+use crate::core::consensus;
+use crate::core::core::hash::Hashed;
+use crate::core::core::verifier_cache::LruVerifierCache;
+use crate::core::core::{Block, BlockHeader, BlockSums, Transaction};
+use crate::core::global::{self, ChainTypes};
+use crate::core::libtx::{build, reward, ProofBuilder};
+use crate::core::pow::{self, Difficulty};
+use crate::keychain::{ExtKeychain, ExtKeychainPath, Identifier, Keychain};
+use crate::util::RwLock;
+
+fn key_id(n: u64) -> Identifier {
+	ExtKeychainPath::new(1, n as u32, 0, 0, 0).to_identifier()
+}
+
+fn prepare_block<K: Keychain>(
+	kc: &K,
+	prev: &BlockHeader,
+	chain: &Chain,
+	txs: Vec<Transaction>,
+) -> Block {
+	let height = prev.height + 1;
+	let fees = txs.iter().map(|tx| tx.fee()).sum();
+	let builder = ProofBuilder::new(kc);
+	let reward = reward::output(kc, &builder, &key_id(height), fees, height, false).unwrap();
+	let mut b = Block::new(prev, txs, Difficulty::min(), reward).unwrap();
+	b.header.timestamp = prev.timestamp + Duration::seconds(60);
+	b.header.pow.total_difficulty = prev.total_difficulty() + Difficulty::min();
+	b.header.pow.proof = pow::Proof::random(global::proofsize());
+	chain.set_txhashset_roots(&mut b).unwrap();
+	b
+}
+
+fn init_chain(dir: &str, genesis: Block) -> Chain {
+	Chain::init(
+		dir.to_string(),
+		Arc::new(NoopAdapter {}),
+		genesis,
+		pow::verify_size,
+		Arc::new(RwLock::new(LruVerifierCache::new())),
+		false,
+	)
+	.unwrap()
+}
+
+#[test]
+fn repair_chain_data() {
+	let dir = ".bitgrin_repair";
+	let _ = fs::remove_dir_all(dir);
+	global::set_mining_mode(ChainTypes::AutomatedTesting);
+	util::init_test_logger();
+
+	let genesis = pow::mine_genesis_block().unwrap();
+	let kc = ExtKeychain::from_random_seed(false).unwrap();
+	let chain = init_chain(dir, genesis.clone());
+	let mine = |chain: &Chain, txs: Vec<Transaction>| {
+		let prev = chain.head_header().unwrap();
+		let b = prepare_block(&kc, &prev, chain, txs);
+		chain.process_block(b.clone(), Options::SKIP_POW).unwrap();
+		b
+	};
+
+	for _ in 0..global::coinbase_maturity() + 1 {
+		mine(&chain, vec![]);
+	}
+	let amount = consensus::reward_at_height(1).0;
+	let builder = ProofBuilder::new(&kc);
+	let tx = build::transaction(
+		vec![
+			build::coinbase_input(amount, key_id(1)),
+			build::output(amount - 2, key_id(100)),
+			build::with_fee(2),
+		],
+		&kc,
+		&builder,
+	)
+	.unwrap();
+	let spent = tx.inputs()[0].commitment();
+	let created = tx.outputs()[0].commitment();
+	mine(&chain, vec![tx]);
+	let consistent = chain.head_header().unwrap();
+	let created_pos = chain.get_output_pos(&created).unwrap();
+	mine(&chain, vec![]);
+	let lost = mine(&chain, vec![]);
+
+	// nothing to fix in a healthy chain
+	drop(chain);
+	let report = repair::repair(dir.to_string(), &genesis).unwrap();
+	assert!(report.is_clean(), "{:?}", report.fixes);
+	assert_eq!(report.head.height, lost.header.height);
+
+	// the txhashset files fall back two blocks behind the store, as after a
+	// crash, and some block sums and output positions get corrupted
+	let chain = init_chain(dir, genesis.clone());
+	{
+		let store = chain.store();
+		let mut batch = store.batch().unwrap();
+		let txhashset = chain.txhashset();
+		let mut txhashset = txhashset.write();
+		txhashset::extending(&mut txhashset, &mut batch, |ext| ext.rewind(&consistent)).unwrap();
+		batch.clear_output_pos().unwrap();
+		batch.save_output_pos(&spent, created_pos).unwrap();
+		let mut header = consistent.clone();
+		for _ in 0..3 {
+			batch
+				.save_block_sums(&header.hash(), &BlockSums::default())
+				.unwrap();
+			header = batch.get_previous_header(&header).unwrap();
+		}
+		batch.commit().unwrap();
+	}
+	drop(chain);
+
+	let report = repair::repair(dir.to_string(), &genesis).unwrap();
+	assert!(!report.is_clean());
+	assert_eq!(report.old_head.height, lost.header.height);
+	assert_eq!(report.head.last_block_h, consistent.hash());
+	assert_eq!(report.blocks_removed, 2);
+	assert_eq!(report.block_sums, 3);
+	assert!(report.output_positions > 0);
+
+	// the node starts from the repaired head and keeps going
+	let chain = init_chain(dir, genesis.clone());
+	assert_eq!(chain.head().unwrap().last_block_h, consistent.hash());
+	assert_eq!(chain.header_head().unwrap().last_block_h, consistent.hash());
+	assert_eq!(chain.get_output_pos(&created).unwrap(), created_pos);
+	assert!(chain.get_output_pos(&spent).is_err());
+	let mut header = consistent.clone();
+	for _ in 0..3 {
+		assert!(chain.get_block_sums(&header.hash()).is_ok());
+		header = chain.get_previous_header(&header).unwrap();
+	}
+	chain.validate(false, true).unwrap();
+	let b = mine(&chain, vec![]);
+	assert_eq!(chain.head().unwrap().last_block_h, b.hash());
+	drop(chain);
+
+	let report = repair::repair(dir.to_string(), &genesis).unwrap();
+	assert!(report.is_clean(), "{:?}", report.fixes);
+
+	let _ = fs::remove_dir_all(dir);
+}
+
+#[test]
+fn repair_failure_leaves_chain_data_untouched() {
+	let dir = ".bitgrin_repair_failure";
+	let _ = fs::remove_dir_all(dir);
+	global::set_mining_mode(ChainTypes::AutomatedTesting);
+	util::init_test_logger();
+
+	let genesis = pow::mine_genesis_block().unwrap();
+	let kc = ExtKeychain::from_random_seed(false).unwrap();
+	let chain = init_chain(dir, genesis.clone());
+	let mut blocks = vec![];
+	for _ in 0..5 {
+		let prev = chain.head_header().unwrap();
+		let b = prepare_block(&kc, &prev, &chain, vec![]);
+		chain.process_block(b.clone(), Options::SKIP_POW).unwrap();
+		blocks.push(b);
+	}
+	let consistent = blocks[2].header.clone();
+	let lost = blocks[4].header.clone();
+
+	// the head falls back behind the txhashset files and the genesis block
+	// sums get corrupted, so the block sums can't be recomputed
+	let genesis_sums = chain.get_block_sums(&genesis.hash()).unwrap();
+	{
+		let store = chain.store();
+		let batch = store.batch().unwrap();
+		batch.save_head(&Tip::from_header(&consistent)).unwrap();
+		let sums = batch.get_block_sums(&blocks[0].hash()).unwrap();
+		batch
+			.save_block_sums(
+				&genesis.hash(),
+				&BlockSums {
+					utxo_sum: sums.utxo_sum,
+					kernel_sum: genesis_sums.kernel_sum,
+				},
+			)
+			.unwrap();
+		batch.commit().unwrap();
+	}
+	drop(chain);
+
+	// the failed repair neither rewinds the files nor moves the head
+	assert!(repair::repair(dir.to_string(), &genesis).is_err());
+	{
+		let store = Arc::new(ChainStore::new(dir).unwrap());
+		assert_eq!(store.head().unwrap().last_block_h, consistent.hash());
+		let txhashset = TxHashSet::open(dir.to_string(), store.clone(), None).unwrap();
+		assert_eq!(txhashset.roots().output_root, lost.output_root);
+		let batch = store.batch().unwrap();
+		batch
+			.save_block_sums(&genesis.hash(), &genesis_sums)
+			.unwrap();
+		batch.commit().unwrap();
+	}
+
+	let report = repair::repair(dir.to_string(), &genesis).unwrap();
+	assert_eq!(report.head.last_block_h, consistent.hash());
+	{
+		let store = Arc::new(ChainStore::new(dir).unwrap());
+		let txhashset = TxHashSet::open(dir.to_string(), store, None).unwrap();
+		assert_eq!(txhashset.roots().output_root, consistent.output_root);
+	}
+
+	let _ = fs::remove_dir_all(dir);
+}
+
+#[test]
+fn repair_detects_lost_spends() {
+	let dir = ".bitgrin_repair_lost_spends";
+	let _ = fs::remove_dir_all(dir);
+	global::set_mining_mode(ChainTypes::AutomatedTesting);
+	util::init_test_logger();
+
+	let genesis = pow::mine_genesis_block().unwrap();
+	let kc = ExtKeychain::from_random_seed(false).unwrap();
+	let chain = init_chain(dir, genesis.clone());
+	let mine = |chain: &Chain, txs: Vec<Transaction>| {
+		let prev = chain.head_header().unwrap();
+		let b = prepare_block(&kc, &prev, chain, txs);
+		chain.process_block(b.clone(), Options::SKIP_POW).unwrap();
+		b
+	};
+
+	for _ in 0..global::coinbase_maturity() + 1 {
+		mine(&chain, vec![]);
+	}
+	let amount = consensus::reward_at_height(1).0;
+	let builder = ProofBuilder::new(&kc);
+	let tx = build::transaction(
+		vec![
+			build::coinbase_input(amount, key_id(1)),
+			build::output(amount - 2, key_id(100)),
+			build::with_fee(2),
+		],
+		&kc,
+		&builder,
+	)
+	.unwrap();
+	let prev = chain.head_header().unwrap();
+	mine(&chain, vec![tx]);
+
+	// the files have the block spending the coinbase but the store head is
+	// still the block before, as after a crash between flushing the files
+	// and committing the store, the roots and sizes match the head but the
+	// coinbase is still spent in the UTXO set
+	{
+		let store = chain.store();
+		let batch = store.batch().unwrap();
+		batch.save_head(&Tip::from_header(&prev)).unwrap();
+		batch.commit().unwrap();
+	}
+	drop(chain);
+
+	// only below the block that created the coinbase does the UTXO set add
+	// up again
+	let report = repair::repair(dir.to_string(), &genesis).unwrap();
+	assert!(!report.is_clean());
+	assert_eq!(report.head.height, 0);
+
+	let _ = fs::remove_dir_all(dir);
+}
@@ -99,6 +99,19 @@ fn comments() -> HashMap<String, String> {
 		"archive_mode".to_string(),
 		"
 #run the node in \"full archive\" mode (default is fast-sync, pruned node)
+#archive nodes also index which block created and spent each output, holds
+#each kernel and is at each height, run \"bitgrin server reindex\" to backfill
+#the indexes of a node that ran in archive mode before they existed
+"
+		.to_string(),
+	);
+
+	retval.insert(
+		"checkpoints".to_string(),
+		"
+#block hashes the chain must go through, on top of the ones shipped with
+#the node. Any fork below a checkpoint is rejected, e.g.
+#checkpoints = [{ height = 600000, hash = \"...\" }]
 "
 		.to_string(),
 	);
@@ -168,6 +181,78 @@ fn comments() -> HashMap<String, String> {
 		.to_string(),
 	);
 
+	retval.insert(
+		"max_retry_interval".to_string(),
+		"
+#Requests are queued on disk until the url responds with a success status,
+#in order for each url, and carry a \"sequence\" field increasing by one for
+#each request to the same url.
+#When a secret is set, each request carries the HMAC-SHA256 of its body with
+#the secret in the X-BitGrin-Signature header, as \"sha256=<hex>\".
+#secret = \"change me\"
+
+#The longest time in seconds between two attempts of a failed request, the
+#wait doubling after each failure.
+"
+		.to_string(),
+	);
+
+	retval.insert(
+		"max_attempts".to_string(),
+		"
+#The number of attempts after which a request is dropped, 0 to retry forever.
+"
+		.to_string(),
+	);
+
+	retval.insert(
+		"max_pending".to_string(),
+		"
+#The number of requests kept on disk for each url while it doesn't respond,
+#the oldest ones being dropped past it, 0 for no limit.
+"
+		.to_string(),
+	);
+
+	retval.insert(
+		"[server.hypersync_config]".to_string(),
+		"
+#########################################
+### HYPERSYNC CONFIGURATION           ###
+#########################################
+"
+		.to_string(),
+	);
+
+	retval.insert(
+		"enable_hypersync".to_string(),
+		"
+#Whether an empty node bootstraps its chain state from a snapshot before
+#syncing with peers. The snapshot is verified against its manifest and fully
+#validated before being used.
+"
+		.to_string(),
+	);
+
+	retval.insert(
+		"mirrors".to_string(),
+		"
+#Base urls serving the snapshot manifest and archive, tried in order.
+"
+		.to_string(),
+	);
+
+	retval.insert(
+		"manifest_name".to_string(),
+		"
+#Name of the snapshot manifest on the mirrors.
+#The blake2b digest of the manifest can be pinned, in which case a manifest
+#with a different digest is rejected.
+#manifest_digest = \"\"
+"
+		.to_string(),
+	);
+
 	retval.insert(
 		"[server.dandelion_config]".to_string(),
 		"
@@ -273,6 +358,15 @@ peers_preferred = [\"18.212.122.156:8514\", \"3.93.64.52:8514\", \"35.205.149.77
 #until we get to at least this number
 #peer_min_preferred_count = 24
 
+#minimum number of distinct address groups (/16 for ipv4, /32 for ipv6) among
+#the peers we connect to, so that a single network can't fill all our slots
+#peer_min_outbound_groups = 4
+
+#misbehavior score at which a peer gets banned. Peers get points for rate
+#abuse (25), unsolicited data (20) and stale headers (10), the score halving
+#every hour. Invalid blocks or headers still get a peer banned right away
+#misbehavior_ban_threshold = 100
+
 # 15 = Bit flags for FULL_NODE
 #This structure needs to be changed internally, to make it more configurable
 
@@ -354,6 +448,14 @@ peers_preferred = [\"18.212.122.156:8514\", \"3.93.64.52:8514\", \"35.205.149.77
 	retval.insert(
 		"attempt_time_per_block".to_string(),
 		"
+#what port and address to also listen on with the encrypted binary
+#protocol, miners should pin the public key the server logs on start
+#binary_stratum_server_addr = \"127.0.0.1:8517\"
+
+#file holding the secret key of the binary protocol server, generated
+#when missing, stratum_server.key in the chain data directory by default
+#binary_stratum_key_file = \"stratum_server.key\"
+
 #the amount of time, in seconds, to attempt to mine on a particular
 #header before stopping and re-collecting transactions from the pool
 "
@@ -379,11 +481,100 @@ peers_preferred = [\"18.212.122.156:8514\", \"3.93.64.52:8514\", \"35.205.149.77
 	retval.insert(
 		"burn_reward".to_string(),
 		"
+#file listing the workers allowed to log in, with their password and the
+#wallet receiver their blocks pay, e.g.
+#  [workers.bob]
+#  password = \"secret\"
+#  wallet_listener_url = \"http://10.0.0.2:8515\"
+#  [groups.alice] # any \"alice.<rig>\" login
+#  password = \"secret\"
+#any login is accepted when not set
+#worker_credentials_file = \"stratum_workers.toml\"
+
 #whether to ignore the reward (mostly for testing)
 "
 		.to_string(),
 	);
 
+	retval.insert(
+		"pplns_window".to_string(),
+		"
+#blocks found by the stratum workers are paid to the last shares worth
+#this many times the block difficulty (PPLNS), once matured
+"
+		.to_string(),
+	);
+
+	retval.insert(
+		"[server.stratum_mining_config.vardiff_config]".to_string(),
+		"
+#variable share difficulty, adjusting the share difficulty of each worker
+#to its hashrate
+"
+		.to_string(),
+	);
+
+	retval.insert(
+		"enable_vardiff".to_string(),
+		"
+#whether to adjust worker share difficulties, all workers mining at
+#minimum_share_difficulty otherwise (the default)
+"
+		.to_string(),
+	);
+
+	retval.insert(
+		"target_share_time".to_string(),
+		"
+#the number of seconds we'd like between two shares of a worker
+"
+		.to_string(),
+	);
+
+	retval.insert(
+		"retarget_time".to_string(),
+		"
+#the number of seconds the share rate of a worker is measured over
+#before adjusting its share difficulty
+"
+		.to_string(),
+	);
+
+	retval.insert(
+		"[server.stratum_mining_config.template_policy_config]".to_string(),
+		"
+#which transactions the blocks handed out to the workers include
+"
+		.to_string(),
+	);
+
+	retval.insert(
+		"template_policy".to_string(),
+		"
+#MaxFees: as many transactions as fit, best fees first
+#EmptyAfterTip: no transaction for empty_block_seconds after a new tip, so
+#workers switch to it right away, then as many as fit
+"
+		.to_string(),
+	);
+
+	retval.insert(
+		"empty_block_seconds".to_string(),
+		"
+#the number of seconds blocks stay empty after a new tip with EmptyAfterTip
+"
+		.to_string(),
+	);
+
+	retval.insert(
+		"excluded_kernels".to_string(),
+		"
+#kernel excesses (hex) whose transactions are never included, along with
+#the ones spending their outputs
+"
+		.to_string(),
+	);
+
 	retval.insert(
 		"[wallet]".to_string(),
 		"
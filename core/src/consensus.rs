@@ -105,15 +105,90 @@ pub fn get_coinbase_maturity_for_block(fee: u64, height: u64) -> u64 {
 	return height + COINBASE_MATURITY;
 }
 
-/// The total overage at a given height. Variable due to changing rewards
-/// TODOBG: Make this more efficient by hardcoding reward schedule times
+/// Number of reward eras, after the first, whose cumulative overage is
+/// tabulated: 4096 years of blocks.
+const TABULATED_ERAS: u64 = 1024;
+
+/// Highest height the total overage should be computed at on request, a few
+/// eras past the tabulated ones: the eras after the table are summed up one
+/// by one, and their rewards barely add anything to the total anyway.
+pub const MAX_PROJECTED_HEIGHT: u64 = (TABULATED_ERAS + 4) * HALVENING_FREQUENCY;
+
+lazy_static! {
+	/// Total overage at the last block of each reward era, starting with the
+	/// first one. Era `n` ends at height `n * HALVENING_FREQUENCY`.
+	static ref ERA_OVERAGE: Vec<i64> = {
+		let mut eras = Vec::with_capacity(TABULATED_ERAS as usize + 1);
+		let mut sum = first_era_overage(HALVENING_FREQUENCY - 1) + era_reward(1) as i64;
+		eras.push(sum);
+		for era in 2..=TABULATED_ERAS + 1 {
+			sum += (HALVENING_FREQUENCY * era_reward(era)) as i64;
+			eras.push(sum);
+		}
+		eras
+	};
+}
+
+/// Block reward of the era `n`, from height `(n - 1) * HALVENING_FREQUENCY`
+/// (excluded) to `n * HALVENING_FREQUENCY`: the base reward divided by `n`.
+fn era_reward(era: u64) -> u64 {
+	BLOCK_REWARD * BITGRIN_BASE / era
+}
+
+/// Closed form of the total overage up to a height before the end of the
+/// first era: genesis, the dev fee payouts, then the regular reward with
+/// the one year block.
+fn first_era_overage(height: u64) -> i64 {
+	let mut sum = reward_at_height(0).0 as i64;
+	sum += (min(height, QTY_DEV_FEE_PAYOUTS) * dev_fee_at_height(1)) as i64;
+	if height > QTY_DEV_FEE_PAYOUTS {
+		let regular = adjusted_block_reward(QTY_DEV_FEE_PAYOUTS + 1);
+		sum += ((height - QTY_DEV_FEE_PAYOUTS) * regular) as i64;
+		if height >= YEAR_HEIGHT {
+			sum += adjusted_block_reward(YEAR_HEIGHT) as i64 - regular as i64;
+		}
+	}
+	sum
+}
+
+/// Total overage at the last block of the given era.
+fn era_overage(era: u64) -> i64 {
+	match ERA_OVERAGE.get(era as usize - 1) {
+		Some(sum) => *sum,
+		// past the table, thousands of years out, the eras are summed up
+		None => (ERA_OVERAGE.len() as u64 + 1..=era)
+			.fold(*ERA_OVERAGE.last().unwrap(), |sum, n| {
+				sum + (HALVENING_FREQUENCY * era_reward(n)) as i64
+			}),
+	}
+}
+
+/// The total overage at a given height, the sum of the rewards of all blocks
+/// up to it. The first era has a closed form. After it, the reward follows
+/// a harmonic schedule, the base reward divided by the era number, which
+/// integer division keeps from having an exact closed form: the overage at
+/// the end of each era is tabulated once, leaving a multiplication for the
+/// blocks of the current era.
 pub fn total_overage_at_height(height: u64) -> i64 {
-	let mut sum: i64 = 0;
-	for i in 0..=height {
-		let reward = reward_at_height(i as u64).0 as i64;
-		sum += reward;
+	if height < HALVENING_FREQUENCY {
+		return first_era_overage(height);
+	}
+	let era = (height - 1) / HALVENING_FREQUENCY + 1;
+	if era == 1 {
+		return era_overage(1);
 	}
-	return sum;
+	let blocks = height - (era - 1) * HALVENING_FREQUENCY;
+	era_overage(era - 1) + (blocks * era_reward(era)) as i64
+}
+
+/// Number and total amount of the dev fee payouts created by the given
+/// height that are still locked at that height.
+pub fn locked_dev_fee_at_height(height: u64) -> (u64, u64) {
+	(1..=min(height, QTY_DEV_FEE_PAYOUTS))
+		.filter(|h| get_coinbase_maturity_for_block(0, *h) > height)
+		.fold((0, 0), |(count, amount), h| {
+			(count + 1, amount + dev_fee_at_height(h))
+		})
 }
 
 /// Returns (reward, height_offset) at given height
@@ -720,41 +720,8 @@ impl Block {
 		)?;
 
 		// Check for chain consistency, avoids connection to invalid chains/forks
-		if self.header.height == 2 {
-			let expected_hash = "4c37bb19c526509cc502828115542cb2f0b120efbabc0af2e84c24d00b6e6133";
-			if self.header.hash().to_hex() != expected_hash {
-				return Err(Error::IntegrityCheck);
-			}
-		}
-		if self.header.height == 40_000 {
-			let expected_hash = "000227e48810ad9924bedf88c224e358ecc6411ed9a87587dd79f13feaf64448";
-			if self.header.hash().to_hex() != expected_hash {
-				return Err(Error::IntegrityCheck);
-			}
-		}
-		if self.header.height == 150_000 {
-			let expected_hash = "00010a98dcd2a822af5ee55db000b797f1e8b320f6cca4a5e423cc2f8c894520";
-			if self.header.hash().to_hex() != expected_hash {
-				return Err(Error::IntegrityCheck);
-			}
-		}
-		if self.header.height == 524_160 {
-			let expected_hash = "22005348dfb71c10b932b17e3d21f248ac79a7cdc1549d7e116855f1ca42c04b";
-			if self.header.hash().to_hex() != expected_hash {
-				return Err(Error::IntegrityCheck);
-			}
-		}
-		if self.header.height == 524_182 {
-			let expected_hash = "5faa1bdb8e9a9fd5ac237699c71b881b64c8accd251e0ee98ba239361eff2ff6";
-			if self.header.hash().to_hex() != expected_hash {
-				return Err(Error::IntegrityCheck);
-			}
-		}
-		if self.header.height == 524_183 {
-			let expected_hash = "0012d56405ab1620bc2024851101ab130ba2f2a1e847634766b963372c2ef580";
-			if self.header.hash().to_hex() != expected_hash {
-				return Err(Error::IntegrityCheck);
-			}
+		if !global::checkpoints().matches(self.header.height, &self.hash()) {
+			return Err(Error::IntegrityCheck);
 		}
 		/*let fork_block_height = 190105;
 		if self.header.height > fork_block_height {
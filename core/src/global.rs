@@ -31,6 +31,10 @@ use crate::pow::{
 /// e.g. CI, User testing, production values
 use crate::util::RwLock;
 
+use crate::core::hash::Hash;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
 /// Define these here, as they should be developer-set, not really tweakable
 /// by users
 
@@ -135,12 +139,25 @@ lazy_static! {
 	/// PoW context type to instantiate
 	pub static ref POW_CONTEXT_TYPE: RwLock<PoWContextTypes> =
 			RwLock::new(PoWContextTypes::Cuckoo);
+
+	/// Checkpoints supplied by the node operator, on top of the ones shipped
+	/// for the chain type
+	pub static ref OPERATOR_CHECKPOINTS: RwLock<Checkpoints> =
+			RwLock::new(Checkpoints::default());
+
+	/// All the checkpoints enforced, built again only when the chain type or
+	/// the operator checkpoints change
+	static ref CHECKPOINTS: RwLock<Arc<Checkpoints>> =
+			RwLock::new(Arc::new(Checkpoints::for_chain(&ChainTypes::Mainnet)));
 }
 
 /// Set the mining mode
 pub fn set_mining_mode(mode: ChainTypes) {
-	let mut param_ref = CHAIN_TYPE.write();
-	*param_ref = mode;
+	{
+		let mut param_ref = CHAIN_TYPE.write();
+		*param_ref = mode;
+	}
+	build_checkpoints();
 }
 
 /// Return either a cuckoo context or a cuckatoo context
@@ -282,6 +299,131 @@ pub fn is_production_mode() -> bool {
 	ChainTypes::Floonet == *param_ref || ChainTypes::Mainnet == *param_ref
 }
 
+/// Block hashes the chain is required to go through, by height. Any chain
+/// forking below a checkpoint is invalid.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Checkpoints(BTreeMap<u64, Hash>);
+
+impl Checkpoints {
+	/// Checkpoints shipped with the node for the provided chain type.
+	pub fn for_chain(chain_type: &ChainTypes) -> Checkpoints {
+		let table: &[(u64, &str)] = match *chain_type {
+			// Checkpoint at height 2 also keeps us off the Grin chain
+			ChainTypes::Mainnet => &[
+				(
+					2,
+					"4c37bb19c526509cc502828115542cb2f0b120efbabc0af2e84c24d00b6e6133",
+				),
+				(
+					40_000,
+					"000227e48810ad9924bedf88c224e358ecc6411ed9a87587dd79f13feaf64448",
+				),
+				(
+					150_000,
+					"00010a98dcd2a822af5ee55db000b797f1e8b320f6cca4a5e423cc2f8c894520",
+				),
+				(
+					524_160,
+					"22005348dfb71c10b932b17e3d21f248ac79a7cdc1549d7e116855f1ca42c04b",
+				),
+				(
+					524_182,
+					"5faa1bdb8e9a9fd5ac237699c71b881b64c8accd251e0ee98ba239361eff2ff6",
+				),
+				(
+					524_183,
+					"0012d56405ab1620bc2024851101ab130ba2f2a1e847634766b963372c2ef580",
+				),
+			],
+			_ => &[],
+		};
+		let mut checkpoints = Checkpoints::default();
+		for (height, hash) in table {
+			checkpoints.0.insert(
+				*height,
+				Hash::from_hex(hash).expect("valid checkpoint hash"),
+			);
+		}
+		checkpoints
+	}
+
+	/// Adds a checkpoint, refusing to replace an existing checkpoint at the
+	/// same height with another hash.
+	pub fn add(&mut self, height: u64, hash: Hash) -> Result<(), String> {
+		match self.0.get(&height) {
+			Some(h) if *h != hash => Err(format!(
+				"conflicting checkpoints at height {}: {} and {}",
+				height, h, hash
+			)),
+			_ => {
+				self.0.insert(height, hash);
+				Ok(())
+			}
+		}
+	}
+
+	/// Adds all the checkpoints of `other`, see `add`.
+	pub fn merge(&mut self, other: &Checkpoints) -> Result<(), String> {
+		for (height, hash) in other.iter() {
+			self.add(height, hash)?;
+		}
+		Ok(())
+	}
+
+	/// Hash the chain is required to have at the provided height, if any.
+	pub fn get(&self, height: u64) -> Option<Hash> {
+		self.0.get(&height).cloned()
+	}
+
+	/// Whether a block hash at the provided height agrees with the
+	/// checkpoints.
+	pub fn matches(&self, height: u64, hash: &Hash) -> bool {
+		match self.0.get(&height) {
+			Some(h) => h == hash,
+			None => true,
+		}
+	}
+
+	/// The highest checkpoint at or below the provided height.
+	pub fn last_at(&self, height: u64) -> Option<(u64, Hash)> {
+		self.0
+			.range(..=height)
+			.next_back()
+			.map(|(h, hash)| (*h, *hash))
+	}
+
+	/// All checkpoints, by increasing height.
+	pub fn iter(&self) -> impl Iterator<Item = (u64, Hash)> + '_ {
+		self.0.iter().map(|(h, hash)| (*h, *hash))
+	}
+}
+
+/// Sets the checkpoints supplied by the node operator. They must agree with
+/// the checkpoints shipped for the current chain type.
+pub fn set_operator_checkpoints(checkpoints: Checkpoints) -> Result<(), String> {
+	checkpoints
+		.clone()
+		.merge(&Checkpoints::for_chain(&CHAIN_TYPE.read()))?;
+	*OPERATOR_CHECKPOINTS.write() = checkpoints;
+	build_checkpoints();
+	Ok(())
+}
+
+/// All the checkpoints enforced for the current chain type, including the
+/// ones supplied by the node operator.
+pub fn checkpoints() -> Arc<Checkpoints> {
+	CHECKPOINTS.read().clone()
+}
+
+// Builds the checkpoints enforced from the shipped and operator ones.
+fn build_checkpoints() {
+	let mut checkpoints = Checkpoints::for_chain(&CHAIN_TYPE.read());
+	for (height, hash) in OPERATOR_CHECKPOINTS.read().iter() {
+		let _ = checkpoints.add(height, hash);
+	}
+	*CHECKPOINTS.write() = Arc::new(checkpoints);
+}
+
 /// Are we in floonet?
 /// Note: We do not have a corresponding is_mainnet() as we want any tests to be as close
 /// as possible to "mainnet" configuration as possible.
@@ -419,15 +419,12 @@ impl ProofBuild for ViewKey {
 			return Ok(None);
 		}
 
-		let mut key = self.clone();
-		let mut hasher = BIP32BitGrinHasher::new(self.is_floo);
-		for i in self.depth..path.depth {
-			let child_number = path.path[i as usize];
-			if child_number.is_hardened() {
-				return Ok(None);
-			}
-			key = key.ckd_pub(&secp, &mut hasher, child_number)?;
+		let cnums = &path.path[self.depth as usize..path.depth as usize];
+		if cnums.iter().any(|c| c.is_hardened()) {
+			return Ok(None);
 		}
+		let mut hasher = BIP32BitGrinHasher::new(self.is_floo);
+		let key = self.derive_pub(secp, &mut hasher, cnums)?;
 		let pub_key = key.commit(secp, amount, &switch)?;
 		if commit.to_pubkey(&secp)? == pub_key {
 			Ok(Some((id, switch)))
@@ -756,4 +753,170 @@ mod tests {
 			assert!(rewind.is_none());
 		}
 	}
+
+	#[test]
+	fn view_key_serialize() {
+		let rng = &mut thread_rng();
+		let keychain = ExtKeychain::from_random_seed(false).unwrap();
+
+		let builder = ProofBuilder::new(&keychain);
+		let mut hasher = keychain.hasher();
+		let view_key =
+			ViewKey::create(&keychain, keychain.master.clone(), &mut hasher, false).unwrap();
+
+		let data = view_key.serialize(keychain.secp());
+		assert_eq!(data.len(), bitgrin_keychain::view_key::VIEW_KEY_SIZE);
+		assert!(ViewKey::deserialize(keychain.secp(), &data[1..], false).is_err());
+		let deserialized =
+			ViewKey::from_base58(keychain.secp(), &view_key.to_string(), false).unwrap();
+		assert_eq!(deserialized, view_key);
+
+		// The deserialized key still recognizes outputs
+		let amount = rng.gen();
+		let id = ExtKeychain::derive_key_id(3, rng.gen::<u16>() as u32, 0, 0, 0);
+		let switch = SwitchCommitmentType::None;
+		let commit = keychain.commit(amount, &id, &switch).unwrap();
+		let proof = create(
+			&keychain,
+			&builder,
+			amount,
+			&id,
+			&switch,
+			commit.clone(),
+			None,
+		)
+		.unwrap();
+		let rewind = rewind(keychain.secp(), &deserialized, commit, None, proof).unwrap();
+		assert_eq!(rewind, Some((amount, id, switch)));
+	}
+
+	/// Commitment of a new output of the keychain, and its proof
+	fn output_proof<K>(
+		keychain: &K,
+		builder: &ProofBuilder<'_, K>,
+		amount: u64,
+		id: &Identifier,
+	) -> (Commitment, RangeProof)
+	where
+		K: Keychain,
+	{
+		let switch = SwitchCommitmentType::None;
+		let commit = keychain.commit(amount, id, &switch).unwrap();
+		let proof = create(keychain, builder, amount, id, &switch, commit, None).unwrap();
+		(commit, proof)
+	}
+
+	#[test]
+	fn view_key_account() {
+		let rng = &mut thread_rng();
+		let keychain = ExtKeychain::from_random_seed(false).unwrap();
+		let secp = keychain.secp();
+
+		let builder = ProofBuilder::new(&keychain);
+		let mut hasher = keychain.hasher();
+		let view_key =
+			ViewKey::create(&keychain, keychain.master.clone(), &mut hasher, false).unwrap();
+
+		// View key of account m/1, derived publicly..
+		let account = ChildNumber::from_normal_idx(1);
+		let account_view_key = view_key.derive_pub(secp, &mut hasher, &[account]).unwrap();
+		assert_eq!(account_view_key.depth, 1);
+
+		// ..is the one built from the private key of the account
+		let account_key = keychain
+			.master
+			.ckd_priv(secp, &mut hasher, account)
+			.unwrap();
+		let private_view_key = ViewKey::create(&keychain, account_key, &mut hasher, false).unwrap();
+		assert_eq!(account_view_key, private_view_key);
+
+		for depth in 1..=4 {
+			let amount = rng.gen();
+			let id = ExtKeychain::derive_key_id(
+				depth,
+				1,
+				rng.gen::<u16>() as u32,
+				rng.gen::<u16>() as u32,
+				rng.gen::<u16>() as u32,
+			);
+			let (commit, proof) = output_proof(&keychain, &builder, amount, &id);
+			let expected = Some((amount, id, SwitchCommitmentType::None));
+			let res = rewind(secp, &view_key, commit, None, proof).unwrap();
+			assert_eq!(res, expected);
+			let res = rewind(secp, &account_view_key, commit, None, proof).unwrap();
+			assert_eq!(res, expected);
+
+			// Outputs of account m/2 are only recognized by the root key
+			let id = ExtKeychain::derive_key_id(
+				depth,
+				2,
+				rng.gen::<u16>() as u32,
+				rng.gen::<u16>() as u32,
+				rng.gen::<u16>() as u32,
+			);
+			let (commit, proof) = output_proof(&keychain, &builder, amount, &id);
+			assert!(rewind(secp, &view_key, commit, None, proof)
+				.unwrap()
+				.is_some());
+			assert!(rewind(secp, &account_view_key, commit, None, proof)
+				.unwrap()
+				.is_none());
+		}
+
+		// Outputs of the root key aren't recognized by the account key
+		let id = ExtKeychain::derive_key_id(0, 0, 0, 0, 0);
+		let (commit, proof) = output_proof(&keychain, &builder, rng.gen(), &id);
+		assert!(rewind(secp, &account_view_key, commit, None, proof)
+			.unwrap()
+			.is_none());
+	}
+
+	#[test]
+	fn view_key_depths() {
+		let rng = &mut thread_rng();
+		let keychain = ExtKeychain::from_random_seed(false).unwrap();
+		let secp = keychain.secp();
+
+		let builder = ProofBuilder::new(&keychain);
+		let mut hasher = keychain.hasher();
+		let view_key =
+			ViewKey::create(&keychain, keychain.master.clone(), &mut hasher, false).unwrap();
+
+		// View keys at depths 2 and 3 under m/1/5/7
+		let path = [
+			ChildNumber::from_normal_idx(1),
+			ChildNumber::from_normal_idx(5),
+			ChildNumber::from_normal_idx(7),
+		];
+		for depth in 2..=3 {
+			let child_view_key = view_key
+				.derive_pub(secp, &mut hasher, &path[..depth])
+				.unwrap();
+			assert_eq!(child_view_key.depth as usize, depth);
+
+			// Survives a round trip through its string format
+			let s = child_view_key.to_string();
+			let child_view_key = ViewKey::from_base58(secp, &s, false).unwrap();
+
+			let amount = rng.gen();
+			let id = ExtKeychain::derive_key_id(4, 1, 5, 7, rng.gen::<u16>() as u32);
+			let (commit, proof) = output_proof(&keychain, &builder, amount, &id);
+			let res = rewind(secp, &child_view_key, commit, None, proof).unwrap();
+			assert_eq!(res, Some((amount, id, SwitchCommitmentType::None)));
+
+			// Siblings at the depth of the key, or above it
+			for other in &[(1, 6, 7), (2, 5, 7)] {
+				let id = ExtKeychain::derive_key_id(4, other.0, other.1, other.2, 0);
+				let (commit, proof) = output_proof(&keychain, &builder, amount, &id);
+				let res = rewind(secp, &child_view_key, commit, None, proof).unwrap();
+				assert!(res.is_none());
+			}
+
+			// Hardened children can't be derived publicly
+			let id = ExtKeychain::derive_key_id(4, 1, 5, 7, u32::max_value() - 2);
+			let (commit, proof) = output_proof(&keychain, &builder, amount, &id);
+			let res = rewind(secp, &child_view_key, commit, None, proof).unwrap();
+			assert!(res.is_none());
+		}
+	}
 }
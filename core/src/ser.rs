@@ -275,6 +275,12 @@ where
 	Ok(res)
 }
 
+/// Reads a length prefixed UTF-8 string.
+pub fn read_string(reader: &mut dyn Reader) -> Result<String, Error> {
+	let bytes = reader.read_bytes_len_prefix()?;
+	String::from_utf8(bytes).map_err(|_| Error::CorruptedData)
+}
+
 /// Trait that every type that can be deserialized from binary must implement.
 /// Reads directly to a Reader, a utility type thinly wrapping an
 /// underlying Read implementation.
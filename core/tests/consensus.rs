@@ -19,6 +19,7 @@ use self::core::core::block::HeaderVersion;
 use self::core::global;
 use self::core::pow::Difficulty;
 use chrono::prelude::Utc;
+use rand::{thread_rng, Rng};
 use std::fmt::{self, Display};
 
 /// Last n blocks for difficulty calculation purposes
@@ -690,3 +691,74 @@ fn hard_forks() {
 		));
 	}
 }
+
+#[test]
+fn total_overage_matches_block_rewards() {
+	// every height through the first eras, against the running sum of rewards
+	let mut sum = 0;
+	for height in 0..=3 * HALVENING_FREQUENCY + 1 {
+		sum += reward_at_height(height).0 as i64;
+		assert_eq!(total_overage_at_height(height), sum, "height {}", height);
+	}
+
+	// random heights further out add exactly the reward of their block
+	let mut rng = thread_rng();
+	for _ in 0..10_000 {
+		let height = rng.gen_range(1, 1_000 * HALVENING_FREQUENCY);
+		assert_eq!(
+			total_overage_at_height(height) - total_overage_at_height(height - 1),
+			reward_at_height(height).0 as i64,
+			"height {}",
+			height
+		);
+	}
+}
+
+#[test]
+fn total_overage_at_era_boundaries() {
+	// the first era block by block, then a block count times the reward of
+	// each era, past the tabulated ones
+	let mut sum: i64 = (0..=HALVENING_FREQUENCY)
+		.map(|h| reward_at_height(h).0 as i64)
+		.sum();
+	assert_eq!(total_overage_at_height(HALVENING_FREQUENCY), sum);
+	for era in 2..1_100 {
+		let start = (era - 1) * HALVENING_FREQUENCY;
+		let end = era * HALVENING_FREQUENCY;
+		let reward = reward_at_height(end).0;
+		assert_eq!(reward_at_height(start + 1).0, reward, "era {}", era);
+		assert_eq!(
+			total_overage_at_height(start + 1),
+			sum + reward as i64,
+			"era {}",
+			era
+		);
+		sum += (HALVENING_FREQUENCY * reward) as i64;
+		assert_eq!(
+			total_overage_at_height(end - 1),
+			sum - reward as i64,
+			"era {}",
+			era
+		);
+		assert_eq!(total_overage_at_height(end), sum, "era {}", era);
+	}
+}
+
+#[test]
+fn locked_dev_fee() {
+	let amount = DEV_FEE_AMT * BITGRIN_BASE;
+	assert_eq!(locked_dev_fee_at_height(0), (0, 0));
+	assert_eq!(locked_dev_fee_at_height(10), (10, 10 * amount));
+	assert_eq!(
+		locked_dev_fee_at_height(QTY_DEV_FEE_PAYOUTS),
+		(QTY_DEV_FEE_PAYOUTS, QTY_DEV_FEE_PAYOUTS * amount)
+	);
+
+	// each payout unlocks in turn
+	let unlock = get_coinbase_maturity_for_block(0, 1);
+	assert_eq!(locked_dev_fee_at_height(unlock - 1).0, QTY_DEV_FEE_PAYOUTS);
+	assert_eq!(locked_dev_fee_at_height(unlock).0, QTY_DEV_FEE_PAYOUTS - 1);
+	let last_unlock = get_coinbase_maturity_for_block(0, QTY_DEV_FEE_PAYOUTS);
+	assert_eq!(locked_dev_fee_at_height(last_unlock - 1), (1, amount));
+	assert_eq!(locked_dev_fee_at_height(last_unlock), (0, 0));
+}
@@ -47,6 +47,7 @@ pub enum Error {
 	Transaction(String),
 	RangeProof(String),
 	SwitchCommitment,
+	ViewKey(String),
 }
 
 impl From<secp::Error> for Error {
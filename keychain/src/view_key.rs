@@ -6,20 +6,29 @@ use super::extkey_bip32::{
 	Fingerprint,
 };
 use super::types::{Error, Keychain};
+use crate::base58;
 use crate::util::secp::constants::GENERATOR_PUB_J_RAW;
 use crate::util::secp::ffi;
 use crate::util::secp::key::{PublicKey, SecretKey};
 use crate::util::secp::Secp256k1;
 use crate::SwitchCommitmentType;
+use std::fmt;
 
-/*const VERSION_FLOO_NS: [u8;4] = [0x03, 0x27, 0x3E, 0x4B];
-const VERSION_FLOO: [u8;4]    = [0x03, 0x27, 0x3E, 0x4B];
-const VERSION_MAIN_NS: [u8;4] = [0x03, 0x3C, 0x08, 0xDF];
-const VERSION_MAIN: [u8;4]    = [0x03, 0x3C, 0x08, 0xDF];*/
+/// Version bytes of floonet view keys
+const VERSION_FLOO: [u8; 4] = [0x03, 0x27, 0x3E, 0x4B];
+/// Version bytes of mainnet view keys
+const VERSION_MAIN: [u8; 4] = [0x03, 0x3C, 0x08, 0xDF];
+
+/// Size of a serialized view key: version, depth, parent fingerprint, child
+/// number, public key, switch public key, chain code and rewind hash
+pub const VIEW_KEY_SIZE: usize = 4 + 1 + 4 + 4 + 33 + 33 + 32 + 32;
 
 /// Key that can be used to scan the chain for owned outputs
 /// This is a public key, meaning it cannot be used to spend those outputs
-/// At the moment only depth 0 keys can be used
+/// A key derived at some depth, such as the one of account m/1, only
+/// recognizes the outputs whose identifiers are under its path. The rewind
+/// hash is shared by all the keys of a wallet though, so their holders can
+/// still read the amounts of the wallet's other outputs
 #[derive(Clone, PartialEq, Eq, Debug)]
 pub struct ViewKey {
 	/// Whether this view key is meant for floonet or not
@@ -80,6 +89,70 @@ impl ViewKey {
 		})
 	}
 
+	/// Serializes the key, prefixed with the version bytes of its network. A
+	/// missing switch public key is serialized as zeros.
+	pub fn serialize(&self, secp: &Secp256k1) -> Vec<u8> {
+		let mut data = Vec::with_capacity(VIEW_KEY_SIZE);
+		data.extend_from_slice(if self.is_floo {
+			&VERSION_FLOO
+		} else {
+			&VERSION_MAIN
+		});
+		data.push(self.depth);
+		data.extend_from_slice(&self.parent_fingerprint[..]);
+		let mut child_number = [0; 4];
+		BigEndian::write_u32(&mut child_number, u32::from(self.child_number));
+		data.extend_from_slice(&child_number);
+		data.extend_from_slice(&self.public_key.serialize_vec(secp, true)[..]);
+		match self.switch_public_key {
+			Some(ref key) => data.extend_from_slice(&key.serialize_vec(secp, true)[..]),
+			None => data.extend_from_slice(&[0; 33]),
+		}
+		data.extend_from_slice(&self.chain_code[..]);
+		data.extend_from_slice(&self.rewind_hash);
+		data
+	}
+
+	/// Deserializes a key serialized with `serialize`, refusing the ones of
+	/// another network than the given one.
+	pub fn deserialize(secp: &Secp256k1, data: &[u8], is_floo: bool) -> Result<Self, Error> {
+		if data.len() != VIEW_KEY_SIZE {
+			return Err(Error::ViewKey(format!("invalid length {}", data.len())));
+		}
+		let (version, other_version) = match is_floo {
+			true => (VERSION_FLOO, VERSION_MAIN),
+			false => (VERSION_MAIN, VERSION_FLOO),
+		};
+		if data[0..4] == other_version {
+			return Err(Error::ViewKey("key of another network".to_owned()));
+		}
+		if data[0..4] != version {
+			return Err(Error::ViewKey(format!("unknown version {:?}", &data[0..4])));
+		}
+		let switch_public_key = if data[46..79].iter().all(|b| *b == 0) {
+			None
+		} else {
+			Some(PublicKey::from_slice(secp, &data[46..79])?)
+		};
+		Ok(Self {
+			is_floo,
+			depth: data[4],
+			parent_fingerprint: Fingerprint::from(&data[5..9]),
+			child_number: ChildNumber::from(BigEndian::read_u32(&data[9..13])),
+			public_key: PublicKey::from_slice(secp, &data[13..46])?,
+			switch_public_key,
+			chain_code: ChainCode::from(&data[79..111]),
+			rewind_hash: data[111..].to_vec(),
+		})
+	}
+
+	/// Parses a key in its base58check string format, refusing the ones of
+	/// another network than the given one.
+	pub fn from_base58(secp: &Secp256k1, s: &str, is_floo: bool) -> Result<Self, Error> {
+		let data = base58::from_check(s).map_err(|e| Error::ViewKey(e.to_string()))?;
+		Self::deserialize(secp, &data, is_floo)
+	}
+
 	fn rewind_hash(secp: &Secp256k1, public_root_key: PublicKey) -> Vec<u8> {
 		let ser = public_root_key.serialize_vec(secp, true);
 		blake2b(32, &[], &ser[..]).as_bytes().to_vec()
@@ -147,6 +220,24 @@ impl ViewKey {
 		})
 	}
 
+	/// Derives a child key from a path, which can't include hardened child
+	/// numbers.
+	pub fn derive_pub<H>(
+		&self,
+		secp: &Secp256k1,
+		hasher: &mut H,
+		cnums: &[ChildNumber],
+	) -> Result<Self, Error>
+	where
+		H: BIP32Hasher,
+	{
+		let mut key = self.clone();
+		for cnum in cnums {
+			key = key.ckd_pub(secp, hasher, *cnum)?;
+		}
+		Ok(key)
+	}
+
 	pub fn commit(
 		&self,
 		secp: &Secp256k1,
@@ -193,3 +284,64 @@ impl ViewKey {
 		Fingerprint::from(&self.identifier(secp, hasher)[0..4])
 	}
 }
+
+impl fmt::Display for ViewKey {
+	fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+		let secp = Secp256k1::without_caps();
+		fmt.write_str(&base58::check_encode_slice(&self.serialize(&secp)))
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::keychain::ExtKeychain;
+
+	fn new_view_key(is_floo: bool) -> (ExtKeychain, ViewKey) {
+		let keychain = ExtKeychain::from_random_seed(is_floo).unwrap();
+		let mut hasher = keychain.hasher();
+		let view_key =
+			ViewKey::create(&keychain, keychain.master.clone(), &mut hasher, is_floo).unwrap();
+		(keychain, view_key)
+	}
+
+	#[test]
+	fn base58_round_trip() {
+		for is_floo in &[false, true] {
+			let (keychain, view_key) = new_view_key(*is_floo);
+			let s = view_key.to_string();
+			let parsed = ViewKey::from_base58(keychain.secp(), &s, *is_floo).unwrap();
+			assert_eq!(parsed, view_key);
+			assert_eq!(parsed.to_string(), s);
+		}
+	}
+
+	#[test]
+	fn base58_round_trip_without_switch_key() {
+		let (keychain, mut view_key) = new_view_key(false);
+		view_key.switch_public_key = None;
+		let parsed = ViewKey::from_base58(keychain.secp(), &view_key.to_string(), false).unwrap();
+		assert_eq!(parsed, view_key);
+	}
+
+	#[test]
+	fn wrong_network() {
+		let (keychain, view_key) = new_view_key(false);
+		let s = view_key.to_string();
+		assert!(ViewKey::from_base58(keychain.secp(), &s, true).is_err());
+
+		let (keychain, view_key) = new_view_key(true);
+		let s = view_key.to_string();
+		assert!(ViewKey::from_base58(keychain.secp(), &s, false).is_err());
+	}
+
+	#[test]
+	fn bad_checksum() {
+		let (keychain, view_key) = new_view_key(false);
+		let mut s = view_key.to_string();
+		let last = s.pop().unwrap();
+		s.push(if last == '1' { '2' } else { '1' });
+		assert!(ViewKey::from_base58(keychain.secp(), &s, false).is_err());
+		assert!(ViewKey::from_base58(keychain.secp(), "", false).is_err());
+	}
+}
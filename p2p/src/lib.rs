@@ -48,10 +48,10 @@ pub mod types;
 
 pub use crate::conn::SEND_CHANNEL_CAP;
 pub use crate::peer::Peer;
-pub use crate::peers::Peers;
+pub use crate::peers::{select_by_group, Peers};
 pub use crate::serv::{DummyAdapter, Server};
 pub use crate::store::{PeerData, State};
 pub use crate::types::{
-	Capabilities, ChainAdapter, Direction, Error, P2PConfig, PeerAddr, PeerInfo, ReasonForBan,
-	Seeding, TxHashSetRead, MAX_BLOCK_HEADERS, MAX_LOCATORS, MAX_PEER_ADDRS,
+	Capabilities, ChainAdapter, Direction, Error, Misbehavior, P2PConfig, PeerAddr, PeerInfo,
+	ReasonForBan, Seeding, TxHashSetRead, MAX_BLOCK_HEADERS, MAX_LOCATORS, MAX_PEER_ADDRS,
 };
@@ -32,8 +32,8 @@ use crate::msg::{
 };
 use crate::protocol::Protocol;
 use crate::types::{
-	Capabilities, ChainAdapter, Error, NetAdapter, P2PConfig, PeerAddr, PeerInfo, ReasonForBan,
-	TxHashSetRead,
+	Capabilities, ChainAdapter, Error, Misbehavior, NetAdapter, P2PConfig, PeerAddr, PeerInfo,
+	ReasonForBan, TxHashSetRead,
 };
 use chrono::prelude::{DateTime, Utc};
 
@@ -241,7 +241,9 @@ impl Peer {
 
 	/// Send the ban reason before banning
 	pub fn send_ban_reason(&self, ban_reason: ReasonForBan) -> Result<(), Error> {
-		let ban_reason_msg = BanReason { ban_reason };
+		let ban_reason_msg = BanReason {
+			ban_reason: ban_reason.on_wire(),
+		};
 		self.send(ban_reason_msg, msg::Type::BanReason).map(|_| ())
 	}
 
@@ -610,4 +612,8 @@ impl NetAdapter for TrackingAdapter {
 	fn is_banned(&self, addr: PeerAddr) -> bool {
 		self.adapter.is_banned(addr)
 	}
+
+	fn peer_misbehaved(&self, addr: PeerAddr, misbehavior: Misbehavior) {
+		self.adapter.peer_misbehaved(addr, misbehavior)
+	}
 }
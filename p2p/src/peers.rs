@@ -13,6 +13,7 @@
 // limitations under the License.
 
 use crate::util::RwLock;
+use std::cmp::Reverse;
 use std::collections::HashMap;
 use std::fs::File;
 use std::io::Read;
@@ -30,18 +31,23 @@ use crate::core::pow::Difficulty;
 use crate::peer::Peer;
 use crate::store::{PeerData, PeerStore, State};
 use crate::types::{
-	Capabilities, ChainAdapter, Error, NetAdapter, P2PConfig, PeerAddr, PeerInfo, ReasonForBan,
-	TxHashSetRead, MAX_PEER_ADDRS,
+	Capabilities, ChainAdapter, Error, Misbehavior, MisbehaviorScore, NetAdapter, P2PConfig,
+	PeerAddr, PeerInfo, ReasonForBan, TxHashSetRead, MAX_PEER_ADDRS,
 };
 use chrono::prelude::*;
 use chrono::Duration;
 
 const LOCK_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(2);
 
+/// Headers announced this far behind our head are stale
+const STALE_HEADER_DEPTH: u64 = 60;
+
 pub struct Peers {
 	pub adapter: Arc<dyn ChainAdapter>,
 	store: PeerStore,
 	peers: RwLock<HashMap<PeerAddr, Arc<Peer>>>,
+	// misbehavior scores, kept across reconnections
+	misbehavior: RwLock<HashMap<PeerAddr, MisbehaviorScore>>,
 	config: P2PConfig,
 }
 
@@ -52,6 +58,7 @@ impl Peers {
 			store,
 			config,
 			peers: RwLock::new(HashMap::new()),
+			misbehavior: RwLock::new(HashMap::new()),
 		}
 	}
 
@@ -65,6 +72,8 @@ impl Peers {
 				return Err(Error::Timeout);
 			}
 		};
+		// keep the history of a peer we already know to score it
+		let known = self.store.get_peer(peer.info.addr).ok();
 		let peer_data = PeerData {
 			addr: peer.info.addr,
 			capabilities: peer.info.capabilities,
@@ -73,9 +82,15 @@ impl Peers {
 			last_banned: 0,
 			ban_reason: ReasonForBan::None,
 			last_connected: Utc::now().timestamp(),
+			uptime: known.as_ref().map_or(0, |p| p.uptime),
+			blocks_delivered: known.as_ref().map_or(0, |p| p.blocks_delivered),
+			ban_count: known.as_ref().map_or(0, |p| p.ban_count),
 		};
 		debug!("Saving newly connected peer {}.", peer_data.addr);
 		self.save_peer(&peer_data)?;
+		if let Some(score) = self.misbehavior.read().get(&peer_data.addr) {
+			peer.info.live_info.write().misbehavior = *score;
+		}
 		peers.insert(peer_data.addr, peer.clone());
 
 		Ok(())
@@ -84,6 +99,7 @@ impl Peers {
 	/// Add a peer as banned to block future connections, usually due to failed
 	/// handshake
 	pub fn add_banned(&self, addr: PeerAddr, ban_reason: ReasonForBan) -> Result<(), Error> {
+		let known = self.store.get_peer(addr).ok();
 		let peer_data = PeerData {
 			addr,
 			capabilities: Capabilities::UNKNOWN,
@@ -92,6 +108,9 @@ impl Peers {
 			last_banned: Utc::now().timestamp(),
 			ban_reason,
 			last_connected: Utc::now().timestamp(),
+			uptime: known.as_ref().map_or(0, |p| p.uptime),
+			blocks_delivered: known.as_ref().map_or(0, |p| p.blocks_delivered),
+			ban_count: known.as_ref().map_or(0, |p| p.ban_count) + 1,
 		};
 		debug!("Banning peer {}.", addr);
 		self.save_peer(&peer_data)
@@ -155,6 +174,15 @@ impl Peers {
 		self.outgoing_connected_peers().len() as u32
 	}
 
+	/// Number of outbound peers currently connected to in each address group.
+	pub fn outbound_groups(&self) -> HashMap<Vec<u8>, usize> {
+		let mut groups = HashMap::new();
+		for p in self.outgoing_connected_peers() {
+			*groups.entry(p.info.addr.group()).or_insert(0) += 1;
+		}
+		groups
+	}
+
 	// Return vec of connected peers that currently advertise more work
 	// (total_difficulty) than we do.
 	pub fn more_work_peers(&self) -> Result<Vec<Arc<Peer>>, chain::Error> {
@@ -252,6 +280,7 @@ impl Peers {
 			};
 			peer.set_banned();
 			peer.stop();
+			self.record_uptime(&peer);
 
 			let mut peers = match self.peers.try_write_for(LOCK_TIMEOUT) {
 				Some(peers) => peers,
@@ -264,6 +293,30 @@ impl Peers {
 		}
 	}
 
+	/// Penalize a peer for misbehaving, banning it once its misbehavior score
+	/// crosses the threshold. The score decays over time.
+	pub fn misbehaved(&self, peer_addr: PeerAddr, misbehavior: Misbehavior) {
+		let peer = self.get_connected_peer(peer_addr);
+		let score = {
+			let mut scores = self.misbehavior.write();
+			let entry = scores.entry(peer_addr).or_default();
+			let penalty = misbehavior.penalty(self.config.misbehavior_ban_threshold());
+			let score = entry.add(penalty, Utc::now().timestamp());
+			if let Some(peer) = peer {
+				peer.info.live_info.write().misbehavior = *entry;
+			}
+			score
+		};
+		debug!(
+			"Peer {} misbehaved ({:?}), misbehavior score {:.0}",
+			peer_addr, misbehavior, score
+		);
+		if score >= self.config.misbehavior_ban_threshold() as f64 {
+			self.misbehavior.write().remove(&peer_addr);
+			self.ban_peer(peer_addr, misbehavior.ban_reason());
+		}
+	}
+
 	/// Unban a peer, checks if it exists and banned then unban
 	pub fn unban_peer(&self, peer_addr: PeerAddr) {
 		debug!("unban_peer: peer {}", peer_addr);
@@ -381,11 +434,35 @@ impl Peers {
 					}
 				};
 				p.stop();
+				self.record_uptime(p);
 				peers.remove(&p.info.addr);
 			}
 		}
 	}
 
+	/// Adds the time we've been connected to a peer we're disconnecting from
+	/// to its uptime.
+	fn record_uptime(&self, peer: &Peer) {
+		let connected = (Utc::now() - peer.info.first_seen()).num_seconds().max(0) as u64;
+		if let Err(e) = self
+			.store
+			.update_peer(peer.info.addr, |p| p.uptime += connected)
+		{
+			debug!("Couldn't record uptime of {}: {:?}", peer.info.addr, e);
+		}
+	}
+
+	// Credits the peer with a block at `height` if it extended our chain,
+	// which was at `prev_height` before.
+	fn block_delivered(&self, peer_info: &PeerInfo, height: u64, prev_height: u64) {
+		let extended = height > prev_height && self.adapter.total_height().unwrap_or(0) >= height;
+		if extended {
+			let _ = self
+				.store
+				.update_peer(peer_info.addr, |p| p.blocks_delivered += 1);
+		}
+	}
+
 	/// All peer information we have in storage
 	pub fn all_peers(&self) -> Vec<PeerData> {
 		match self.store.all_peers() {
@@ -397,10 +474,12 @@ impl Peers {
 		}
 	}
 
-	/// Find peers in store (not necessarily connected) and return their data
+	/// Find peers in store (not necessarily connected) and return their data,
+	/// spread over as many address groups as possible and favoring the groups
+	/// we have the fewest outbound peers in, best scored peers first.
 	pub fn find_peers(&self, state: State, cap: Capabilities, count: usize) -> Vec<PeerData> {
-		match self.store.find_peers(state, cap, count) {
-			Ok(peers) => peers,
+		match self.store.find_peers(state, cap, usize::MAX) {
+			Ok(peers) => select_by_group(peers, &self.outbound_groups(), count),
 			Err(e) => {
 				error!("failed to find peers: {:?}", e);
 				vec![]
@@ -435,6 +514,7 @@ impl Peers {
 	/// Also avoid connected peer count getting too high.
 	pub fn clean_peers(&self, max_count: usize) {
 		let mut rm = vec![];
+		let mut abusive = vec![];
 
 		// build a list of peers to be cleaned up
 		{
@@ -459,8 +539,7 @@ impl Peers {
 							peer.info.addr, counts.0, counts.1,
 						);
 					}
-					let _ = self.update_state(peer.info.addr, State::Banned);
-					rm.push(peer.info.addr.clone());
+					abusive.push(peer.info.addr);
 				} else {
 					let (stuck, diff) = peer.is_stuck();
 					match self.adapter.total_difficulty() {
@@ -477,6 +556,15 @@ impl Peers {
 			}
 		}
 
+		// penalize abusive peers, banning (and removing) them if they keep at it
+		for addr in abusive {
+			self.misbehaved(addr, Misbehavior::RateAbuse);
+		}
+		let now = Utc::now().timestamp();
+		self.misbehavior
+			.write()
+			.retain(|_, score| score.at(now) >= 1.0);
+
 		// ensure we do not still have too many connected peers
 		let excess_count = (self.peer_count() as usize)
 			.saturating_sub(rm.len())
@@ -502,7 +590,10 @@ impl Peers {
 				}
 			};
 			for addr in rm {
-				let _ = peers.get(&addr).map(|peer| peer.stop());
+				if let Some(peer) = peers.get(&addr) {
+					peer.stop();
+					self.record_uptime(peer);
+				}
 				peers.remove(&addr);
 			}
 		}
@@ -515,6 +606,7 @@ impl Peers {
 		}
 		for (_, peer) in peers.drain() {
 			peer.wait();
+			self.record_uptime(&peer);
 		}
 	}
 
@@ -522,10 +614,12 @@ impl Peers {
 		self.peer_count() >= self.config.peer_min_preferred_count()
 	}
 
-	/// We have enough peers, both total connected and outbound connected
+	/// We have enough peers, both total connected and outbound connected, the
+	/// latter in enough distinct address groups
 	pub fn healthy_peers_mix(&self) -> bool {
 		self.enough_peers()
 			&& self.peer_outbound_count() >= self.config.peer_min_preferred_count() / 2
+			&& self.outbound_groups().len() >= self.config.peer_min_outbound_groups() as usize
 	}
 
 	/// Removes those peers that seem to have expired
@@ -554,6 +648,56 @@ impl Peers {
 	}
 }
 
+/// Picks up to `count` of the provided peers, one per address group in turn,
+/// so that no group can fill all the slots. Groups with the fewest of our
+/// outbound peers, as counted in `outbound_groups`, come first, then those
+/// with the best scored peers. Within a group, best scored peers come first,
+/// ties keeping the order they were provided in.
+pub fn select_by_group(
+	peers: Vec<PeerData>,
+	outbound_groups: &HashMap<Vec<u8>, usize>,
+	count: usize,
+) -> Vec<PeerData> {
+	let mut buckets: HashMap<Vec<u8>, Vec<PeerData>> = HashMap::new();
+	for p in peers {
+		buckets.entry(p.addr.group()).or_insert(vec![]).push(p);
+	}
+	let mut buckets = buckets
+		.into_iter()
+		.map(|(group, mut peers)| {
+			peers.sort_by_key(|p| Reverse(p.score()));
+			(group, peers)
+		})
+		.collect::<Vec<_>>();
+	buckets.sort_by_key(|(group, peers)| {
+		(
+			outbound_groups.get(group).cloned().unwrap_or(0),
+			Reverse(peers[0].score()),
+		)
+	});
+
+	let mut buckets = buckets
+		.into_iter()
+		.map(|(_, peers)| peers.into_iter())
+		.collect::<Vec<_>>();
+	let mut res = vec![];
+	while res.len() < count {
+		let before = res.len();
+		for peers in buckets.iter_mut() {
+			if res.len() >= count {
+				break;
+			}
+			if let Some(p) = peers.next() {
+				res.push(p);
+			}
+		}
+		if res.len() == before {
+			break;
+		}
+	}
+	res
+}
+
 impl ChainAdapter for Peers {
 	fn total_difficulty(&self) -> Result<Difficulty, chain::Error> {
 		self.adapter.total_difficulty()
@@ -590,16 +734,16 @@ impl ChainAdapter for Peers {
 		was_requested: bool,
 	) -> Result<bool, chain::Error> {
 		let hash = b.hash();
+		let height = b.header.height;
+		let prev_height = self.adapter.total_height()?;
 		if !self.adapter.block_received(b, peer_info, was_requested)? {
 			// if the peer sent us a block that's intrinsically bad
-			// they are either mistaken or malevolent, both of which require a ban
-			debug!(
-				"Received a bad block {} from  {}, the peer will be banned",
-				hash, peer_info.addr,
-			);
-			self.ban_peer(peer_info.addr, ReasonForBan::BadBlock);
+			// they are either mistaken or malevolent, both of which get penalized
+			debug!("Received a bad block {} from  {}", hash, peer_info.addr);
+			self.misbehaved(peer_info.addr, Misbehavior::Invalid(ReasonForBan::BadBlock));
 			Ok(false)
 		} else {
+			self.block_delivered(peer_info, height, prev_height);
 			Ok(true)
 		}
 	}
@@ -610,16 +754,22 @@ impl ChainAdapter for Peers {
 		peer_info: &PeerInfo,
 	) -> Result<bool, chain::Error> {
 		let hash = cb.hash();
+		let height = cb.header.height;
+		let prev_height = self.adapter.total_height()?;
 		if !self.adapter.compact_block_received(cb, peer_info)? {
 			// if the peer sent us a block that's intrinsically bad
-			// they are either mistaken or malevolent, both of which require a ban
+			// they are either mistaken or malevolent, both of which get penalized
 			debug!(
-				"Received a bad compact block {} from  {}, the peer will be banned",
+				"Received a bad compact block {} from  {}",
 				hash, peer_info.addr
 			);
-			self.ban_peer(peer_info.addr, ReasonForBan::BadCompactBlock);
+			self.misbehaved(
+				peer_info.addr,
+				Misbehavior::Invalid(ReasonForBan::BadCompactBlock),
+			);
 			Ok(false)
 		} else {
+			self.block_delivered(peer_info, height, prev_height);
 			Ok(true)
 		}
 	}
@@ -629,10 +779,16 @@ impl ChainAdapter for Peers {
 		bh: core::BlockHeader,
 		peer_info: &PeerInfo,
 	) -> Result<bool, chain::Error> {
+		if bh.height + STALE_HEADER_DEPTH < self.adapter.total_height()? {
+			self.misbehaved(peer_info.addr, Misbehavior::StaleHeaders);
+		}
 		if !self.adapter.header_received(bh, peer_info)? {
 			// if the peer sent us a block header that's intrinsically bad
-			// they are either mistaken or malevolent, both of which require a ban
-			self.ban_peer(peer_info.addr, ReasonForBan::BadBlockHeader);
+			// they are either mistaken or malevolent, both of which get penalized
+			self.misbehaved(
+				peer_info.addr,
+				Misbehavior::Invalid(ReasonForBan::BadBlockHeader),
+			);
 			Ok(false)
 		} else {
 			Ok(true)
@@ -646,8 +802,11 @@ impl ChainAdapter for Peers {
 	) -> Result<bool, chain::Error> {
 		if !self.adapter.headers_received(headers, peer_info)? {
 			// if the peer sent us a block header that's intrinsically bad
-			// they are either mistaken or malevolent, both of which require a ban
-			self.ban_peer(peer_info.addr, ReasonForBan::BadBlockHeader);
+			// they are either mistaken or malevolent, both of which get penalized
+			self.misbehaved(
+				peer_info.addr,
+				Misbehavior::Invalid(ReasonForBan::BadBlockHeader),
+			);
 			Ok(false)
 		} else {
 			Ok(true)
@@ -685,11 +844,11 @@ impl ChainAdapter for Peers {
 		peer_info: &PeerInfo,
 	) -> Result<bool, chain::Error> {
 		if !self.adapter.txhashset_write(h, txhashset_data, peer_info)? {
-			debug!(
-				"Received a bad txhashset data from {}, the peer will be banned",
-				peer_info.addr
+			debug!("Received a bad txhashset data from {}", peer_info.addr);
+			self.misbehaved(
+				peer_info.addr,
+				Misbehavior::Invalid(ReasonForBan::BadTxHashSet),
 			);
-			self.ban_peer(peer_info.addr, ReasonForBan::BadTxHashSet);
 			Ok(false)
 		} else {
 			Ok(true)
@@ -741,6 +900,9 @@ impl NetAdapter for Peers {
 				last_banned: 0,
 				ban_reason: ReasonForBan::None,
 				last_connected: Utc::now().timestamp(),
+				uptime: 0,
+				blocks_delivered: 0,
+				ban_count: 0,
 			};
 			if let Err(e) = self.save_peer(&peer) {
 				error!("Could not save received peer address: {:?}", e);
@@ -761,4 +923,8 @@ impl NetAdapter for Peers {
 			false
 		}
 	}
+
+	fn peer_misbehaved(&self, addr: PeerAddr, misbehavior: Misbehavior) {
+		self.misbehaved(addr, misbehavior)
+	}
 }
@@ -19,7 +19,7 @@ use crate::msg::{
 	BanReason, GetPeerAddrs, Headers, KernelDataResponse, Locator, PeerAddrs, Ping, Pong,
 	TxHashSetArchive, TxHashSetRequest, Type,
 };
-use crate::types::{Error, NetAdapter, PeerInfo};
+use crate::types::{Error, Misbehavior, NetAdapter, PeerInfo};
 use chrono::prelude::Utc;
 use rand::{thread_rng, Rng};
 use std::cmp;
@@ -328,6 +328,8 @@ impl MessageHandler for Protocol {
 					error!(
 						"handle_payload: txhashset archive received but SyncStatus not on TxHashsetDownload",
 					);
+					self.adapter
+						.peer_misbehaved(self.peer_info.addr, Misbehavior::UnsolicitedData);
 					return Err(Error::BadMessage);
 				}
 
@@ -30,8 +30,8 @@ use crate::peer::Peer;
 use crate::peers::Peers;
 use crate::store::PeerStore;
 use crate::types::{
-	Capabilities, ChainAdapter, Error, NetAdapter, P2PConfig, PeerAddr, PeerInfo, ReasonForBan,
-	TxHashSetRead,
+	Capabilities, ChainAdapter, Error, Misbehavior, NetAdapter, P2PConfig, PeerAddr, PeerInfo,
+	ReasonForBan, TxHashSetRead,
 };
 use crate::util::StopState;
 use chrono::prelude::{DateTime, Utc};
@@ -343,4 +343,5 @@ impl NetAdapter for DummyAdapter {
 	fn is_banned(&self, _: PeerAddr) -> bool {
 		false
 	}
+	fn peer_misbehaved(&self, _: PeerAddr, _: Misbehavior) {}
 }
@@ -18,6 +18,7 @@ use chrono::Utc;
 use num::FromPrimitive;
 use rand::seq::SliceRandom;
 use rand::thread_rng;
+use std::cmp;
 
 use crate::core::ser::{self, Readable, Reader, Writeable, Writer};
 use crate::types::{Capabilities, PeerAddr, ReasonForBan};
@@ -28,6 +29,13 @@ const STORE_SUBPATH: &'static str = "peers";
 
 const PEER_PREFIX: u8 = 'P' as u8;
 
+/// Points a peer gets per hour connected, up to a week
+const MAX_UPTIME_SCORE: u64 = 24 * 7;
+/// Points a peer gets per useful block, up to this many blocks
+const MAX_BLOCKS_SCORE: u64 = 1000;
+/// Points a peer loses each time it's banned
+const BAN_PENALTY: i64 = 100;
+
 // Types of messages
 enum_from_primitive! {
 	#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
@@ -56,6 +64,25 @@ pub struct PeerData {
 	pub ban_reason: ReasonForBan,
 	/// Time when we last connected to this peer.
 	pub last_connected: i64,
+	/// Total number of seconds we've been connected to this peer.
+	#[serde(default)]
+	pub uptime: u64,
+	/// Number of blocks received from this peer that extended our chain.
+	#[serde(default)]
+	pub blocks_delivered: u64,
+	/// Number of times the peer got banned.
+	#[serde(default)]
+	pub ban_count: u32,
+}
+
+impl PeerData {
+	/// Score of the peer from its past behavior: a point per hour connected
+	/// and per useful block delivered, both capped, less a penalty per ban.
+	pub fn score(&self) -> i64 {
+		let uptime = cmp::min(self.uptime / 3600, MAX_UPTIME_SCORE);
+		let blocks = cmp::min(self.blocks_delivered, MAX_BLOCKS_SCORE);
+		(uptime + blocks) as i64 - BAN_PENALTY * self.ban_count as i64
+	}
 }
 
 impl Writeable for PeerData {
@@ -68,7 +95,10 @@ impl Writeable for PeerData {
 			[write_u8, self.flags as u8],
 			[write_i64, self.last_banned],
 			[write_i32, self.ban_reason as i32],
-			[write_i64, self.last_connected]
+			[write_i64, self.last_connected],
+			[write_u64, self.uptime],
+			[write_u64, self.blocks_delivered],
+			[write_u32, self.ban_count]
 		);
 		Ok(())
 	}
@@ -88,6 +118,8 @@ impl Readable for PeerData {
 			Err(_) => Utc::now().timestamp(),
 			Ok(lc) => lc,
 		};
+		// same for the scoring data, added later still
+		let (uptime, blocks_delivered, ban_count) = read_scoring(reader).unwrap_or((0, 0, 0));
 
 		let user_agent = String::from_utf8(ua).map_err(|_| ser::Error::CorruptedData)?;
 		let capabilities = Capabilities::from_bits_truncate(capab);
@@ -102,12 +134,19 @@ impl Readable for PeerData {
 				last_banned: lb,
 				ban_reason,
 				last_connected,
+				uptime,
+				blocks_delivered,
+				ban_count,
 			}),
 			None => Err(ser::Error::CorruptedData),
 		}
 	}
 }
 
+fn read_scoring(reader: &mut dyn Reader) -> Result<(u64, u64, u32), ser::Error> {
+	Ok((reader.read_u64()?, reader.read_u64()?, reader.read_u32()?))
+}
+
 /// Storage facility for peer data.
 pub struct PeerStore {
 	db: bitgrin_store::Store,
@@ -175,7 +214,8 @@ impl PeerStore {
 	}
 
 	/// Convenience method to load a peer data, update its status and save it
-	/// back. If new state is Banned its last banned time will be updated too.
+	/// back. If new state is Banned its last banned time and ban count will be
+	/// updated too.
 	pub fn update_state(&self, peer_addr: PeerAddr, new_state: State) -> Result<(), Error> {
 		let batch = self.db.batch()?;
 
@@ -183,10 +223,30 @@ impl PeerStore {
 			batch.get_ser::<PeerData>(&peer_key(peer_addr)[..]),
 			&format!("Peer at address: {}", peer_addr),
 		)?;
-		peer.flags = new_state;
 		if new_state == State::Banned {
 			peer.last_banned = Utc::now().timestamp();
+			if peer.flags != State::Banned {
+				peer.ban_count += 1;
+			}
 		}
+		peer.flags = new_state;
+
+		batch.put_ser(&peer_key(peer_addr)[..], &peer)?;
+		batch.commit()
+	}
+
+	/// Loads a peer data, applies the provided update and saves it back.
+	pub fn update_peer<F>(&self, peer_addr: PeerAddr, update: F) -> Result<(), Error>
+	where
+		F: FnOnce(&mut PeerData),
+	{
+		let batch = self.db.batch()?;
+
+		let mut peer = option_to_not_found(
+			batch.get_ser::<PeerData>(&peer_key(peer_addr)[..]),
+			&format!("Peer at address: {}", peer_addr),
+		)?;
+		update(&mut peer);
 
 		batch.put_ser(&peer_key(peer_addr)[..], &peer)?;
 		batch.commit()
@@ -55,6 +55,15 @@ const PEER_MAX_COUNT: u32 = 150;
 /// min preferred peer count
 const PEER_MIN_PREFERRED_COUNT: u32 = 24;
 
+/// min number of distinct address groups among outbound peers
+const PEER_MIN_OUTBOUND_GROUPS: u32 = 4;
+
+/// Misbehavior score at which a peer gets banned
+const MISBEHAVIOR_BAN_THRESHOLD: u32 = 100;
+
+/// Seconds for a misbehavior score to decay by half
+const MISBEHAVIOR_HALF_LIFE: i64 = 3600;
+
 #[derive(Debug)]
 pub enum Error {
 	Serialization(ser::Error),
@@ -202,6 +211,33 @@ impl PeerAddr {
 			format!("{}", self.0.ip())
 		}
 	}
+
+	/// Address group of the peer, peers in the same group being likely run
+	/// by the same operator: the /16 for ipv4 (and ipv4 mapped ipv6), the /32
+	/// for ipv6. Loopback addresses each have their own group, like they each
+	/// have their own key.
+	pub fn group(&self) -> Vec<u8> {
+		let ip = match self.0.ip() {
+			IpAddr::V6(ip) => match ip.to_ipv4() {
+				Some(ip4) if ip.segments()[5] == 0xffff => IpAddr::V4(ip4),
+				_ => IpAddr::V6(ip),
+			},
+			ip => ip,
+		};
+		if ip.is_loopback() {
+			let mut group = vec![0];
+			group.extend(self.as_key().into_bytes());
+			return group;
+		}
+		match ip {
+			IpAddr::V4(ip) => vec![4, ip.octets()[0], ip.octets()[1]],
+			IpAddr::V6(ip) => {
+				let mut group = vec![6];
+				group.extend(&ip.octets()[..4]);
+				group
+			}
+		}
+	}
 }
 
 /// Configuration for the peer-to-peer server.
@@ -234,6 +270,13 @@ pub struct P2PConfig {
 
 	pub peer_min_preferred_count: Option<u32>,
 
+	/// Minimum number of distinct address groups among the peers we connect
+	/// to, see PeerAddr::group
+	pub peer_min_outbound_groups: Option<u32>,
+
+	/// Misbehavior score at which a peer gets banned
+	pub misbehavior_ban_threshold: Option<u32>,
+
 	pub dandelion_peer: Option<PeerAddr>,
 }
 
@@ -253,6 +296,8 @@ impl Default for P2PConfig {
 			ban_window: None,
 			peer_max_count: None,
 			peer_min_preferred_count: None,
+			peer_min_outbound_groups: None,
+			misbehavior_ban_threshold: None,
 			dandelion_peer: None,
 		}
 	}
@@ -284,6 +329,22 @@ impl P2PConfig {
 			None => PEER_MIN_PREFERRED_COUNT,
 		}
 	}
+
+	/// return peer_min_outbound_groups
+	pub fn peer_min_outbound_groups(&self) -> u32 {
+		match self.peer_min_outbound_groups {
+			Some(n) => n,
+			None => PEER_MIN_OUTBOUND_GROUPS,
+		}
+	}
+
+	/// return misbehavior_ban_threshold
+	pub fn misbehavior_ban_threshold(&self) -> u32 {
+		match self.misbehavior_ban_threshold {
+			Some(n) => n,
+			None => MISBEHAVIOR_BAN_THRESHOLD,
+		}
+	}
 }
 
 /// Type of seeding the server will use to find other peers on the network.
@@ -354,6 +415,77 @@ enum_from_primitive! {
 		ManualBan = 5,
 		FraudHeight = 6,
 		BadHandshake = 7,
+		Misbehavior = 8,
+	}
+}
+
+impl ReasonForBan {
+	/// Reason sent to the banned peer. Misbehavior is only known locally,
+	/// older peers can't read it: it goes out as a manual ban.
+	pub fn on_wire(&self) -> ReasonForBan {
+		match *self {
+			ReasonForBan::Misbehavior => ReasonForBan::ManualBan,
+			reason => reason,
+		}
+	}
+}
+
+/// Misbehaviors a peer is penalized for, adding up to its misbehavior score
+/// until it gets banned.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Misbehavior {
+	/// Invalid block, header or txhashset, the reason being used as the ban
+	/// reason
+	Invalid(ReasonForBan),
+	/// Data we didn't ask for
+	UnsolicitedData,
+	/// Headers way behind our head
+	StaleHeaders,
+	/// Too many messages
+	RateAbuse,
+}
+
+impl Misbehavior {
+	/// Points added to the misbehavior score of the peer, given the score at
+	/// which it gets banned. Invalid data is never tolerated, its penalty
+	/// bans right away.
+	pub fn penalty(&self, ban_threshold: u32) -> u32 {
+		match *self {
+			Misbehavior::Invalid(_) => ban_threshold,
+			Misbehavior::RateAbuse => 25,
+			Misbehavior::UnsolicitedData => 20,
+			Misbehavior::StaleHeaders => 10,
+		}
+	}
+
+	/// Reason given when banning the peer for it
+	pub fn ban_reason(&self) -> ReasonForBan {
+		match *self {
+			Misbehavior::Invalid(reason) => reason,
+			_ => ReasonForBan::Misbehavior,
+		}
+	}
+}
+
+/// Misbehavior score of a peer, decaying by half every hour.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct MisbehaviorScore {
+	score: f64,
+	updated: i64,
+}
+
+impl MisbehaviorScore {
+	/// The score at time `now`.
+	pub fn at(&self, now: i64) -> f64 {
+		let elapsed = (now - self.updated).max(0) as f64;
+		self.score * 0.5f64.powf(elapsed / MISBEHAVIOR_HALF_LIFE as f64)
+	}
+
+	/// Adds a penalty at time `now` and returns the new score.
+	pub fn add(&mut self, penalty: u32, now: i64) -> f64 {
+		self.score = self.at(now) + penalty as f64;
+		self.updated = now;
+		self.score
 	}
 }
 
@@ -364,6 +496,7 @@ pub struct PeerLiveInfo {
 	pub last_seen: DateTime<Utc>,
 	pub stuck_detector: DateTime<Utc>,
 	pub first_seen: DateTime<Utc>,
+	pub misbehavior: MisbehaviorScore,
 }
 
 /// General information about a connected peer that's useful to other modules.
@@ -385,6 +518,7 @@ impl PeerLiveInfo {
 			first_seen: Utc::now(),
 			last_seen: Utc::now(),
 			stuck_detector: Utc::now(),
+			misbehavior: MisbehaviorScore::default(),
 		}
 	}
 }
@@ -414,6 +548,11 @@ impl PeerInfo {
 		self.live_info.read().first_seen
 	}
 
+	/// The current misbehavior score of the peer, rounded down.
+	pub fn misbehavior(&self) -> u32 {
+		self.live_info.read().misbehavior.at(Utc::now().timestamp()) as u32
+	}
+
 	/// Update the total_difficulty, height and last_seen of the peer.
 	/// Takes a write lock on the live_info.
 	pub fn update(&self, height: u64, total_difficulty: Difficulty) {
@@ -438,6 +577,8 @@ pub struct PeerInfoDisplay {
 	pub direction: Direction,
 	pub total_difficulty: Difficulty,
 	pub height: u64,
+	#[serde(default)]
+	pub misbehavior: u32,
 }
 
 impl From<PeerInfo> for PeerInfoDisplay {
@@ -450,6 +591,7 @@ impl From<PeerInfo> for PeerInfoDisplay {
 			direction: info.direction.clone(),
 			total_difficulty: info.total_difficulty(),
 			height: info.height(),
+			misbehavior: info.misbehavior(),
 		}
 	}
 }
@@ -584,4 +726,7 @@ pub trait NetAdapter: ChainAdapter {
 
 	/// Is this peer currently banned?
 	fn is_banned(&self, addr: PeerAddr) -> bool;
+
+	/// A peer misbehaved, penalize it and ban it once it did too much.
+	fn peer_misbehaved(&self, addr: PeerAddr, misbehavior: Misbehavior);
 }
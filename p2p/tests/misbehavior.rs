@@ -0,0 +1,59 @@
+// Copyright 2018 The BitGrin Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use bitgrin_p2p as p2p;
+
+use crate::p2p::types::{Misbehavior, MisbehaviorScore, ReasonForBan};
+
+#[test]
+fn misbehavior_score_decay() {
+	let mut score = MisbehaviorScore::default();
+	assert_eq!(score.at(1000), 0.0);
+
+	let rate = Misbehavior::RateAbuse;
+	assert_eq!(score.add(rate.penalty(100), 1000), 25.0);
+	assert_eq!(score.at(1000 + 3600), 12.5);
+	assert_eq!(score.at(1000 + 2 * 3600), 6.25);
+	// the clock going backward doesn't grow the score
+	assert_eq!(score.at(0), 25.0);
+
+	// penalties add up to the decayed score
+	let stale = Misbehavior::StaleHeaders;
+	assert_eq!(score.add(stale.penalty(100), 1000 + 3600), 22.5);
+	assert_eq!(score.at(1000 + 3600), 22.5);
+}
+
+#[test]
+fn misbehavior_invalid_bans_at_once() {
+	let invalid = Misbehavior::Invalid(ReasonForBan::BadBlock);
+	for threshold in &[1, 100, 1000] {
+		let mut score = MisbehaviorScore::default();
+		assert!(score.add(invalid.penalty(*threshold), 1000) >= *threshold as f64);
+	}
+}
+
+#[test]
+fn misbehavior_ban_reasons() {
+	let invalid = Misbehavior::Invalid(ReasonForBan::BadTxHashSet);
+	assert_eq!(invalid.ban_reason(), ReasonForBan::BadTxHashSet);
+	assert_eq!(
+		Misbehavior::RateAbuse.ban_reason(),
+		ReasonForBan::Misbehavior
+	);
+	assert!(Misbehavior::RateAbuse.penalty(100) > Misbehavior::StaleHeaders.penalty(100));
+
+	// misbehavior is sent to older peers as a reason they know about
+	assert_eq!(ReasonForBan::Misbehavior.on_wire(), ReasonForBan::ManualBan);
+	assert_eq!(ReasonForBan::BadBlock.on_wire(), ReasonForBan::BadBlock);
+}
@@ -0,0 +1,109 @@
+// Copyright 2018 The BitGrin Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use bitgrin_core as core;
+use bitgrin_p2p as p2p;
+
+use std::collections::HashMap;
+
+use crate::core::ser;
+use crate::p2p::types::{PeerAddr, ReasonForBan};
+use crate::p2p::{Capabilities, PeerData, State};
+
+fn peer(addr: &str, blocks_delivered: u64) -> PeerData {
+	PeerData {
+		addr: PeerAddr(addr.parse().unwrap()),
+		capabilities: Capabilities::FULL_NODE,
+		user_agent: "test".to_string(),
+		flags: State::Healthy,
+		last_banned: 0,
+		ban_reason: ReasonForBan::None,
+		last_connected: 0,
+		uptime: 0,
+		blocks_delivered,
+		ban_count: 0,
+	}
+}
+
+#[test]
+fn address_groups() {
+	let group = |addr: &str| PeerAddr(addr.parse().unwrap()).group();
+	assert_eq!(group("10.1.2.3:8514"), group("10.1.200.1:8514"));
+	assert_ne!(group("10.1.2.3:8514"), group("10.2.2.3:8514"));
+	assert_eq!(group("[::ffff:10.1.9.9]:8514"), group("10.1.2.3:8514"));
+	assert_eq!(group("[2001:db8::1]:8514"), group("[2001:db8:ff::2]:8514"));
+	assert_ne!(group("[2001:db8::1]:8514"), group("[2001:db9::1]:8514"));
+	assert_ne!(group("127.0.0.1:8514"), group("127.0.0.1:8515"));
+}
+
+#[test]
+fn peer_data_scoring() {
+	let mut p = peer("10.1.2.3:8514", 10);
+	p.uptime = 7200;
+	p.ban_count = 1;
+	assert_eq!(p.score(), 2 + 10 - 100);
+
+	let bytes = ser::ser_vec(&p).unwrap();
+	let read: PeerData = ser::deserialize(&mut &bytes[..]).unwrap();
+	assert_eq!(read.score(), p.score());
+
+	// peers stored before scoring still read, with a blank history
+	let old = &bytes[..bytes.len() - 20];
+	let read: PeerData = ser::deserialize(&mut &old[..]).unwrap();
+	assert_eq!(
+		(read.uptime, read.blocks_delivered, read.ban_count),
+		(0, 0, 0)
+	);
+}
+
+#[test]
+fn select_peers_by_group() {
+	let peers = vec![
+		peer("10.1.0.1:8514", 0),
+		peer("10.1.0.2:8514", 5),
+		peer("10.1.0.3:8514", 1),
+		peer("10.2.0.1:8514", 0),
+		peer("10.3.0.1:8514", 3),
+	];
+	let addrs = |peers: Vec<PeerData>| {
+		peers
+			.into_iter()
+			.map(|p| p.addr.to_string())
+			.collect::<Vec<_>>()
+	};
+
+	// one peer per group first, the group with the best peer leading
+	let res = p2p::select_by_group(peers.clone(), &HashMap::new(), 4);
+	assert_eq!(
+		addrs(res),
+		vec![
+			"10.1.0.2:8514",
+			"10.3.0.1:8514",
+			"10.2.0.1:8514",
+			"10.1.0.3:8514"
+		]
+	);
+
+	// groups we're already connected to come last
+	let mut outbound = HashMap::new();
+	outbound.insert(PeerAddr("10.1.9.9:8514".parse().unwrap()).group(), 1);
+	outbound.insert(PeerAddr("10.3.9.9:8514".parse().unwrap()).group(), 2);
+	let res = p2p::select_by_group(peers.clone(), &outbound, 3);
+	assert_eq!(
+		addrs(res),
+		vec!["10.2.0.1:8514", "10.1.0.2:8514", "10.3.0.1:8514"]
+	);
+
+	assert_eq!(p2p::select_by_group(peers, &outbound, 10).len(), 5);
+}
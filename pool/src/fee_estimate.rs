@@ -0,0 +1,194 @@
+// Copyright 2018 The BitGrin Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Fee estimation, looking at the fees paid by recently mined blocks and at
+//! the backlog of transactions waiting in the pool to figure out the fee
+//! rate a transaction needs to be mined within a number of blocks.
+//!
+//! Fee rates are expressed the same way as `Transaction::fee_to_weight`,
+//! in fee per thousand units of transaction weight.
+
+use self::core::consensus;
+use self::core::core::hash::Hashed;
+use self::core::core::{Block, Transaction, TransactionBody};
+use crate::types::{BlockChain, PoolError};
+use bitgrin_core as core;
+use std::cmp;
+
+/// Fee and weight of the transactions of a mined block, its coinbase left
+/// out.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BlockFeeStats {
+	/// Height of the block
+	pub height: u64,
+	/// Total fees paid by the block transactions
+	pub fees: u64,
+	/// Transaction weight of the block, without its coinbase
+	pub tx_weight: usize,
+	/// Consensus weight of the whole block
+	pub block_weight: usize,
+}
+
+impl BlockFeeStats {
+	/// Fee and weight figures of the provided block.
+	pub fn from_block(block: &Block) -> BlockFeeStats {
+		let outputs = block.outputs().iter().filter(|o| !o.is_coinbase()).count();
+		let kernels = block.kernels().iter().filter(|k| !k.is_coinbase()).count();
+		let tx_weight = if outputs + kernels > 0 {
+			TransactionBody::weight(block.inputs().len(), outputs, kernels)
+		} else {
+			0
+		};
+		BlockFeeStats {
+			height: block.header.height,
+			fees: block.total_fees(),
+			tx_weight,
+			block_weight: TransactionBody::weight_as_block(
+				block.inputs().len(),
+				block.outputs().len(),
+				block.kernels().len(),
+			),
+		}
+	}
+
+	/// Fee rate paid on average by the block transactions.
+	pub fn fee_rate(&self) -> u64 {
+		if self.tx_weight == 0 {
+			return 0;
+		}
+		self.fees.saturating_mul(1_000) / self.tx_weight as u64
+	}
+
+	/// Whether the block was (almost) full, meaning some transactions may
+	/// have been left out for paying too little.
+	pub fn is_full(&self, max_block_weight: usize) -> bool {
+		self.block_weight.saturating_mul(10) >= max_block_weight.saturating_mul(9)
+	}
+}
+
+/// Fee figures of the last `history` blocks of the chain, newest first.
+/// Blocks we don't have in full anymore are left out. Doesn't need the
+/// pool, so it can be called without holding the pool lock.
+pub fn recent_blocks(
+	chain: &dyn BlockChain,
+	history: u64,
+) -> Result<Vec<BlockFeeStats>, PoolError> {
+	let mut recent = vec![];
+	let mut hash = chain.chain_head()?.hash();
+	while (recent.len() as u64) < history {
+		let block = match chain.get_block(&hash) {
+			Ok(block) => block,
+			Err(_) => break,
+		};
+		if block.header.height == 0 {
+			break;
+		}
+		recent.push(BlockFeeStats::from_block(&block));
+		hash = block.header.prev_hash;
+	}
+	Ok(recent)
+}
+
+/// Fee rate estimated for a transaction to be mined within a number of
+/// blocks, along with the figures it was derived from.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FeeEstimate {
+	/// Number of blocks the transaction should be mined within
+	pub target_blocks: u64,
+	/// Estimated fee rate, per thousand units of transaction weight
+	pub fee_rate: u64,
+	/// Estimated fee per unit of transaction weight, rounded up
+	pub fee_base: u64,
+	/// Lowest fee rate accepted by our pool
+	pub min_fee_rate: u64,
+	/// Fee rate needed to outbid the pool backlog
+	pub pool_fee_rate: u64,
+	/// Fee rate paid in recent full blocks
+	pub recent_fee_rate: u64,
+	/// Consensus weight of the transactions waiting in the pool
+	pub pool_weight: usize,
+	/// Number of blocks needed to mine everything in the pool
+	pub pool_blocks: u64,
+	/// Number of recent blocks looked at
+	pub recent_blocks: usize,
+	/// How many of these were full
+	pub full_blocks: usize,
+}
+
+/// Estimates the fee rate for a transaction to be mined within
+/// `target_blocks` blocks. The result is the highest of:
+///
+/// * the lowest fee rate accepted by the pool,
+/// * the fee rate needed to get ahead of pool transactions that wouldn't
+///   fit in the next `target_blocks` blocks, with `pending` in the order
+///   miners would pick them,
+/// * the median fee rate of recent full blocks, if on average blocks were
+///   full more than `target_blocks` times in a row.
+pub fn estimate_fee(
+	target_blocks: u64,
+	accept_fee_base: u64,
+	max_block_weight: usize,
+	pending: &[Transaction],
+	recent: &[BlockFeeStats],
+) -> FeeEstimate {
+	let target_blocks = cmp::max(target_blocks, 1);
+	let min_fee_rate = accept_fee_base.saturating_mul(1_000);
+
+	// fill blocks with the pending txs, leaving room for the coinbase
+	let coinbase_weight = consensus::BLOCK_OUTPUT_WEIGHT + consensus::BLOCK_KERNEL_WEIGHT;
+	let capacity = max_block_weight.saturating_sub(coinbase_weight);
+	let mut pool_fee_rate = 0;
+	let mut pool_weight = 0;
+	let mut pool_blocks = 0;
+	let mut block_weight = capacity;
+	for tx in pending {
+		let weight = tx.tx_weight_as_block();
+		pool_weight += weight;
+		if block_weight + weight > capacity {
+			pool_blocks += 1;
+			block_weight = 0;
+		}
+		block_weight += weight;
+		if pool_blocks > target_blocks && pool_fee_rate == 0 {
+			pool_fee_rate = tx.fee_to_weight() + 1;
+		}
+	}
+
+	let mut full: Vec<u64> = recent
+		.iter()
+		.filter(|s| s.is_full(max_block_weight))
+		.map(|s| s.fee_rate())
+		.collect();
+	let not_full = (recent.len() - full.len()) as u64;
+	let recent_fee_rate = if !full.is_empty() && not_full * target_blocks < recent.len() as u64 {
+		full.sort_unstable();
+		full[full.len() / 2]
+	} else {
+		0
+	};
+
+	let fee_rate = cmp::max(min_fee_rate, cmp::max(pool_fee_rate, recent_fee_rate));
+	FeeEstimate {
+		target_blocks,
+		fee_rate,
+		fee_base: (fee_rate + 999) / 1_000,
+		min_fee_rate,
+		pool_fee_rate,
+		recent_fee_rate,
+		pool_weight,
+		pool_blocks,
+		recent_blocks: recent.len(),
+		full_blocks: full.len(),
+	}
+}
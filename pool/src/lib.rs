@@ -30,10 +30,12 @@ extern crate serde_derive;
 #[macro_use]
 extern crate log;
 
+pub mod fee_estimate;
 mod pool;
 pub mod transaction_pool;
 pub mod types;
 
+pub use crate::fee_estimate::{BlockFeeStats, FeeEstimate};
 pub use crate::pool::Pool;
 pub use crate::transaction_pool::TransactionPool;
 pub use crate::types::{
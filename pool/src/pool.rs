@@ -67,14 +67,16 @@ impl Pool {
 
 	/// Query the tx pool for an individual tx matching the given kernel hash.
 	pub fn retrieve_tx_by_kernel_hash(&self, hash: Hash) -> Option<Transaction> {
-		for x in &self.entries {
-			for k in x.tx.kernels() {
-				if k.hash() == hash {
-					return Some(x.tx.clone());
-				}
-			}
-		}
-		None
+		self.retrieve_entry_by_kernel_hash(hash)
+			.map(|x| x.tx.clone())
+	}
+
+	/// Query the tx pool for the entry (tx along with its source and
+	/// arrival time) matching the given kernel hash.
+	pub fn retrieve_entry_by_kernel_hash(&self, hash: Hash) -> Option<&PoolEntry> {
+		self.entries
+			.iter()
+			.find(|x| x.tx.kernels().iter().any(|k| k.hash() == hash))
 	}
 
 	/// Query the tx pool for all known txs based on kernel short_ids
@@ -21,12 +21,15 @@ use self::core::core::hash::{Hash, Hashed};
 use self::core::core::id::ShortId;
 use self::core::core::verifier_cache::VerifierCache;
 use self::core::core::{transaction, Block, BlockHeader, Transaction, Weighting};
+use self::core::global;
 use self::util::RwLock;
+use crate::fee_estimate::{self, BlockFeeStats, FeeEstimate};
 use crate::pool::Pool;
 use crate::types::{BlockChain, PoolAdapter, PoolConfig, PoolEntry, PoolError, TxSource};
 use chrono::prelude::*;
 use bitgrin_core as core;
 use bitgrin_util as util;
+use std::cmp;
 use std::collections::VecDeque;
 use std::sync::Arc;
 
@@ -202,6 +205,7 @@ impl TransactionPool {
 					.filter(|x| x.tx != *evictable_transaction)
 					.map(|x| x.clone())
 					.collect::<Vec<_>>();
+				self.adapter.tx_evicted(evictable_transaction);
 			}
 			None => (),
 		}
@@ -238,10 +242,20 @@ impl TransactionPool {
 	/// Reconcile the transaction pool (both txpool and stempool) against the
 	/// provided block.
 	pub fn reconcile_block(&mut self, block: &Block) -> Result<(), PoolError> {
+		let existing_txs = self.txpool.all_transactions();
+
 		// First reconcile the txpool.
 		self.txpool.reconcile_block(block);
 		self.txpool.reconcile(None, &block.header)?;
 
+		// Let the adapter know about the txs dropped other than by being mined.
+		for tx in existing_txs {
+			let mined = tx.kernels().iter().all(|k| block.kernels().contains(k));
+			if !mined && !self.txpool.contains_tx(tx.hash()) {
+				self.adapter.tx_evicted(&tx);
+			}
+		}
+
 		// Now reconcile our stempool, accounting for the updated txpool txs.
 		self.stempool.reconcile_block(block);
 		{
@@ -307,4 +321,22 @@ impl TransactionPool {
 		self.txpool
 			.prepare_mineable_transactions(self.config.mineable_max_weight)
 	}
+
+	/// Estimate the fee rate for a transaction to be mined within
+	/// `target_blocks` blocks, given the txs in our txpool and the fees paid
+	/// in recent blocks. The recent blocks are collected from the chain with
+	/// `fee_estimate::recent_blocks` beforehand, walking the chain while
+	/// holding the pool lock would hold up tx acceptance.
+	pub fn estimate_fee(&self, target_blocks: u64, recent: &[BlockFeeStats]) -> FeeEstimate {
+		let max_block_weight =
+			cmp::min(global::max_block_weight(), self.config.mineable_max_weight);
+		let pending = self.txpool.bucket_transactions(Weighting::NoLimit);
+		fee_estimate::estimate_fee(
+			target_blocks,
+			self.config.accept_fee_base,
+			max_block_weight,
+			&pending,
+			recent,
+		)
+	}
 }
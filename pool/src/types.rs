@@ -21,7 +21,7 @@ use self::core::core::block;
 use self::core::core::committed;
 use self::core::core::hash::Hash;
 use self::core::core::transaction::{self, Transaction};
-use self::core::core::{BlockHeader, BlockSums};
+use self::core::core::{Block, BlockHeader, BlockSums};
 use self::core::{consensus, global};
 use failure::Fail;
 use bitgrin_core as core;
@@ -242,6 +242,7 @@ pub trait BlockChain: Sync + Send {
 	fn chain_head(&self) -> Result<BlockHeader, PoolError>;
 
 	fn get_block_header(&self, hash: &Hash) -> Result<BlockHeader, PoolError>;
+	fn get_block(&self, hash: &Hash) -> Result<Block, PoolError>;
 	fn get_block_sums(&self, hash: &Hash) -> Result<BlockSums, PoolError>;
 }
 
@@ -254,6 +255,10 @@ pub trait PoolAdapter: Send + Sync {
 
 	/// The stem transaction pool has accepted this transactions as valid.
 	fn stem_tx_accepted(&self, tx: &transaction::Transaction) -> Result<(), PoolError>;
+
+	/// The transaction pool dropped this transaction without it being mined,
+	/// to make room for others or as it conflicts with the chain.
+	fn tx_evicted(&self, tx: &transaction::Transaction);
 }
 
 /// Dummy adapter used as a placeholder for real implementations
@@ -265,4 +270,5 @@ impl PoolAdapter for NoopAdapter {
 	fn stem_tx_accepted(&self, _tx: &transaction::Transaction) -> Result<(), PoolError> {
 		Ok(())
 	}
+	fn tx_evicted(&self, _tx: &transaction::Transaction) {}
 }
@@ -16,7 +16,7 @@ pub mod common;
 
 use self::core::core::hash::Hash;
 use self::core::core::verifier_cache::LruVerifierCache;
-use self::core::core::{BlockHeader, BlockSums, Transaction};
+use self::core::core::{Block, BlockHeader, BlockSums, Transaction};
 use self::keychain::{ExtKeychain, Keychain};
 use self::pool::types::{BlockChain, PoolError};
 use self::util::RwLock;
@@ -45,6 +45,10 @@ impl BlockChain for CoinbaseMaturityErrorChainAdapter {
 		unimplemented!();
 	}
 
+	fn get_block(&self, _hash: &Hash) -> Result<Block, PoolError> {
+		unimplemented!();
+	}
+
 	fn get_block_sums(&self, _hash: &Hash) -> Result<BlockSums, PoolError> {
 		unimplemented!();
 	}
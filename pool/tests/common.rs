@@ -58,6 +58,7 @@ impl ChainAdapter {
 		let batch = s.batch().unwrap();
 
 		batch.save_block_header(header).unwrap();
+		batch.save_block(block).unwrap();
 		batch.save_head(&tip).unwrap();
 
 		// Retrieve previous block_sums from the db.
@@ -112,6 +113,12 @@ impl BlockChain for ChainAdapter {
 			.map_err(|_| PoolError::Other(format!("failed to get block header")))
 	}
 
+	fn get_block(&self, hash: &Hash) -> Result<Block, PoolError> {
+		let s = self.store.read();
+		s.get_block(hash)
+			.map_err(|_| PoolError::Other(format!("failed to get block")))
+	}
+
 	fn get_block_sums(&self, hash: &Hash) -> Result<BlockSums, PoolError> {
 		let s = self.store.read();
 		s.get_block_sums(hash)
@@ -0,0 +1,173 @@
+// Copyright 2018 The BitGrin Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+pub mod common;
+
+use self::core::consensus;
+use self::core::core::verifier_cache::LruVerifierCache;
+use self::core::core::{Block, BlockHeader, Transaction};
+use self::core::global::{self, ChainTypes};
+use self::core::libtx;
+use self::core::pow::Difficulty;
+use self::keychain::{ExtKeychain, Keychain};
+use self::pool::fee_estimate::{estimate_fee, recent_blocks, BlockFeeStats};
+use self::util::RwLock;
+use crate::common::*;
+use bitgrin_core as core;
+use bitgrin_keychain as keychain;
+use bitgrin_pool as pool;
+use bitgrin_util as util;
+use std::sync::Arc;
+
+fn block_stats(height: u64, fees: u64, block_weight: usize) -> BlockFeeStats {
+	BlockFeeStats {
+		height,
+		fees,
+		tx_weight: 10,
+		block_weight,
+	}
+}
+
+/// Estimates from the pool backlog and from recent blocks.
+#[test]
+fn test_fee_estimate() {
+	global::set_mining_mode(ChainTypes::AutomatedTesting);
+	let keychain: ExtKeychain = Keychain::from_random_seed(false).unwrap();
+
+	// 1 input, 1 output and 1 kernel, 5 of them fit in a test block
+	let pending: Vec<Transaction> = (0..12)
+		.map(|i| test_transaction(&keychain, vec![1_000], vec![1_000 - (12 - i) * 10]))
+		.collect();
+	let max_weight = global::max_block_weight();
+
+	let estimate = estimate_fee(1, 0, max_weight, &pending, &[]);
+	assert_eq!(estimate.pool_blocks, 3);
+	assert_eq!(estimate.pool_weight, 12 * 25);
+	assert_eq!(estimate.pool_fee_rate, pending[5].fee_to_weight() + 1);
+	assert_eq!(estimate.fee_rate, estimate.pool_fee_rate);
+	assert_eq!(estimate.fee_base, (estimate.fee_rate + 999) / 1_000);
+
+	let estimate = estimate_fee(2, 0, max_weight, &pending, &[]);
+	assert_eq!(estimate.fee_rate, pending[10].fee_to_weight() + 1);
+
+	// the whole backlog fits, so the pool minimum is enough
+	let estimate = estimate_fee(3, 2, max_weight, &pending, &[]);
+	assert_eq!(estimate.pool_fee_rate, 0);
+	assert_eq!(estimate.min_fee_rate, 2_000);
+	assert_eq!(estimate.fee_rate, 2_000);
+	assert_eq!(estimate.fee_base, 2);
+
+	// 3 full blocks out of 4
+	let recent = vec![
+		block_stats(4, 50, max_weight),
+		block_stats(3, 30, max_weight - 5),
+		block_stats(2, 40, max_weight),
+		block_stats(1, 0, 26),
+	];
+	let estimate = estimate_fee(1, 0, max_weight, &[], &recent);
+	assert_eq!(estimate.recent_blocks, 4);
+	assert_eq!(estimate.full_blocks, 3);
+	assert_eq!(estimate.recent_fee_rate, 4_000);
+	assert_eq!(estimate.fee_rate, 4_000);
+
+	// waiting 4 blocks we can expect one with room
+	let estimate = estimate_fee(4, 0, max_weight, &[], &recent);
+	assert_eq!(estimate.recent_fee_rate, 0);
+	assert_eq!(estimate.fee_rate, 0);
+}
+
+/// Estimates from a pool looking at the blocks of its chain.
+#[test]
+fn test_pool_fee_estimate() {
+	util::init_test_logger();
+	global::set_mining_mode(ChainTypes::AutomatedTesting);
+	let keychain: ExtKeychain = Keychain::from_random_seed(false).unwrap();
+
+	let db_root = ".bitgrin_fee_estimate".to_string();
+	clean_output_dir(db_root.clone());
+
+	let chain = Arc::new(ChainAdapter::init(db_root.clone()).unwrap());
+	let verifier_cache = Arc::new(RwLock::new(LruVerifierCache::new()));
+	let pool = RwLock::new(test_setup(chain.clone(), verifier_cache.clone()));
+
+	let add_block = |prev: &BlockHeader, txs: Vec<Transaction>| {
+		let height = prev.height + 1;
+		let key_id = ExtKeychain::derive_key_id(1, height as u32, 0, 0, 0);
+		let fees = txs.iter().map(|tx| tx.fee()).sum();
+		let reward = libtx::reward::output(
+			&keychain,
+			&libtx::ProofBuilder::new(&keychain),
+			&key_id,
+			fees,
+			height,
+			false,
+		)
+		.unwrap();
+		let block = Block::new(prev, txs, Difficulty::min(), reward).unwrap();
+		chain.update_db_for_block(&block);
+		block
+	};
+
+	let block = add_block(&BlockHeader::default(), vec![]);
+	assert_eq!(BlockFeeStats::from_block(&block).fee_rate(), 0);
+
+	// spend the coinbase into outputs we can use in the pool
+	let reward = consensus::reward_at_height(1).0;
+	let tx = libtx::build::transaction(
+		vec![
+			libtx::build::coinbase_input(reward, ExtKeychain::derive_key_id(1, 1, 0, 0, 0)),
+			libtx::build::output(1_000, ExtKeychain::derive_key_id(1, 1_000, 0, 0, 0)),
+			libtx::build::output(2_000, ExtKeychain::derive_key_id(1, 2_000, 0, 0, 0)),
+			libtx::build::output(reward - 3_100, ExtKeychain::derive_key_id(1, 3, 0, 0, 0)),
+			libtx::build::with_fee(100),
+		],
+		&keychain,
+		&libtx::ProofBuilder::new(&keychain),
+	)
+	.unwrap();
+	let block = add_block(&block.header, vec![tx]);
+	let stats = BlockFeeStats::from_block(&block);
+	assert_eq!(stats.fees, 100);
+	assert_eq!(stats.tx_weight, 3 * 4 + 1 - 1);
+	assert_eq!(stats.block_weight, 1 + 4 * 21 + 2 * 3);
+	assert_eq!(stats.fee_rate(), 100 * 1_000 / 12);
+	assert!(!stats.is_full(global::max_block_weight()));
+	assert!(stats.is_full(100));
+
+	{
+		let mut write_pool = pool.write();
+		for (input, output) in vec![(1_000, 900), (2_000, 1_800)] {
+			let tx = test_transaction(&keychain, vec![input], vec![output]);
+			write_pool
+				.add_to_pool(test_source(), tx, false, &block.header)
+				.unwrap();
+		}
+	}
+
+	let blockchain = pool.read().blockchain.clone();
+	let recent = recent_blocks(&*blockchain, 10).unwrap();
+	let estimate = pool.read().estimate_fee(1, &recent);
+	assert_eq!(estimate.recent_blocks, 2);
+	assert_eq!(estimate.full_blocks, 0);
+	assert_eq!(estimate.recent_fee_rate, 0);
+	assert_eq!(estimate.pool_weight, 2 * 25);
+	assert_eq!(estimate.pool_blocks, 1);
+	assert_eq!(estimate.pool_fee_rate, 0);
+	assert_eq!(estimate.fee_rate, 0);
+
+	let recent = recent_blocks(&*blockchain, 1).unwrap();
+	assert_eq!(recent.len(), 1);
+
+	clean_output_dir(db_root.clone());
+}
@@ -14,6 +14,7 @@
 
 //! BitGrin P2P / API server
 
+pub mod checkpoint;
 pub mod dandelion_monitor;
 pub mod seed;
 pub mod server;
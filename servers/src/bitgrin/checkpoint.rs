@@ -0,0 +1,413 @@
+// Copyright 2018 The BitGrin Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Cross-checks the header at a given height with several peers before
+//! trusting it. The header chain is walked from genesis with one peer
+//! through the regular GetHeaders/Headers exchange, every other peer is then
+//! asked for the header following ours at that height. A single peer (or
+//! anyone sitting between us and it) can't make us accept its fork unless a
+//! quorum of the peers we talked to agrees with it.
+
+use chrono::prelude::{DateTime, Utc};
+use std::fs::File;
+use std::io::Read;
+use std::net::{SocketAddr, TcpStream};
+use std::path::PathBuf;
+use std::sync::mpsc;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::bitgrin::sync::get_locator_heights;
+use crate::chain;
+use crate::common::types::Error;
+use crate::core::core::hash::{Hash, Hashed};
+use crate::core::core::{BlockHeader, CompactBlock, Transaction};
+use crate::core::pow::Difficulty;
+use crate::p2p;
+use crate::p2p::handshake::Handshake;
+use crate::p2p::types::{Misbehavior, NetAdapter, PeerAddr, PeerInfo, TxHashSetRead};
+use crate::p2p::{Capabilities, ChainAdapter, P2PConfig, Peer};
+use crate::util::Mutex;
+
+/// How long we wait on a peer for each batch of headers.
+const HEADERS_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// What the peers we asked think of the header at a given height.
+#[derive(Debug, Clone)]
+pub struct HeaderConsensus {
+	/// Height of the header
+	pub height: u64,
+	/// Hash of the header the agreeing peers have at that height
+	pub hash: Hash,
+	/// Peers having that same header at that height
+	pub agreeing: Vec<PeerAddr>,
+	/// Peers following another chain, with the hash they have at that
+	/// height when they told us
+	pub disagreeing: Vec<(PeerAddr, Option<Hash>)>,
+	/// Peers we couldn't reach or that don't have the header yet
+	pub unavailable: Vec<PeerAddr>,
+}
+
+impl HeaderConsensus {
+	/// Whether enough peers agreed on the header, and more of them than
+	/// peers disagreeing.
+	pub fn has_quorum(&self, quorum: usize) -> bool {
+		self.agreeing.len() >= quorum && self.agreeing.len() > self.disagreeing.len()
+	}
+}
+
+/// Answer of a peer to our locator, compared to the header chain we got.
+enum Answer {
+	Agree,
+	Disagree(Option<Hash>),
+	Unavailable,
+}
+
+/// Connects to the provided peers and asks them for the header at the
+/// given height. Each peer that can provide the full header chain up to
+/// that height is tried in turn as the source of the candidate header,
+/// until a candidate gets at least `quorum` peers agreeing on it. Returns
+/// the best candidate found, the caller should check it has a quorum.
+pub fn header_consensus(
+	addrs: &[PeerAddr],
+	height: u64,
+	quorum: usize,
+	genesis: Hash,
+	config: P2PConfig,
+) -> Result<HeaderConsensus, Error> {
+	if height == 0 || quorum == 0 {
+		return Err(Error::ArgumentError(
+			"height and quorum must be positive".to_owned(),
+		));
+	}
+	let (tx, rx) = mpsc::channel();
+	let adapter = Arc::new(HeadersCollector {
+		sender: Mutex::new(tx),
+	});
+	let hs = Handshake::new(genesis, config.clone());
+	let self_addr = PeerAddr(SocketAddr::new(config.host, config.port));
+
+	let mut peers = vec![];
+	let mut unreachable = vec![];
+	for addr in addrs {
+		match connect(*addr, self_addr, &hs, adapter.clone()) {
+			Ok(peer) => peers.push(peer),
+			Err(e) => {
+				debug!("header_consensus: could not connect to {}: {:?}", addr, e);
+				unreachable.push(*addr);
+			}
+		}
+	}
+
+	let mut best: Option<HeaderConsensus> = None;
+	let mut bad_leads = vec![];
+	for lead in &peers {
+		// no point building the same candidate again, or asking a peer not
+		// there yet
+		if let Some(ref b) = best {
+			if b.agreeing.contains(&lead.info.addr) || b.unavailable.contains(&lead.info.addr) {
+				continue;
+			}
+		}
+		let hashes = match header_hashes(lead, &rx, height, genesis) {
+			Ok(hashes) => hashes,
+			Err(e) => {
+				debug!(
+					"header_consensus: no header chain from {}: {:?}",
+					lead.info.addr, e
+				);
+				if let p2p::Error::BadMessage = e {
+					bad_leads.push(lead.info.addr);
+				}
+				continue;
+			}
+		};
+
+		let mut consensus = HeaderConsensus {
+			height,
+			hash: hashes[height as usize],
+			agreeing: vec![lead.info.addr],
+			disagreeing: vec![],
+			unavailable: unreachable.clone(),
+		};
+		for peer in peers.iter().filter(|p| p.info.addr != lead.info.addr) {
+			match ask_peer(peer, &rx, &hashes) {
+				Answer::Agree => consensus.agreeing.push(peer.info.addr),
+				Answer::Disagree(hash) => {
+					warn!(
+						"header_consensus: peer {} disagrees on header at {} ({}, got {:?})",
+						peer.info.addr, height, consensus.hash, hash
+					);
+					consensus.disagreeing.push((peer.info.addr, hash));
+				}
+				Answer::Unavailable => consensus.unavailable.push(peer.info.addr),
+			}
+		}
+
+		let done = consensus.has_quorum(quorum);
+		let better = match best {
+			Some(ref b) => consensus.agreeing.len() > b.agreeing.len(),
+			None => true,
+		};
+		if better {
+			best = Some(consensus);
+		}
+		if done {
+			break;
+		}
+	}
+
+	for peer in &peers {
+		peer.stop();
+	}
+
+	match best {
+		Some(mut consensus) => {
+			// a peer whose chain didn't even link up isn't unavailable
+			for addr in bad_leads {
+				consensus.unavailable.retain(|a| *a != addr);
+				if !consensus.disagreeing.iter().any(|(a, _)| *a == addr) {
+					consensus.disagreeing.push((addr, None));
+				}
+			}
+			Ok(consensus)
+		}
+		None => Err(Error::General(format!(
+			"no peer could provide the header chain up to {}",
+			height
+		))),
+	}
+}
+
+fn connect(
+	addr: PeerAddr,
+	self_addr: PeerAddr,
+	hs: &Handshake,
+	adapter: Arc<HeadersCollector>,
+) -> Result<Peer, p2p::Error> {
+	let stream = TcpStream::connect_timeout(&addr.0, Duration::from_secs(10))?;
+	Peer::connect(
+		stream,
+		Capabilities::UNKNOWN,
+		Difficulty::min(),
+		self_addr,
+		hs,
+		adapter,
+	)
+}
+
+/// Waits for the next batch of headers sent by the provided peer, dropping
+/// anything left over from the other peers.
+fn next_headers(
+	rx: &mpsc::Receiver<(PeerAddr, Vec<BlockHeader>)>,
+	addr: PeerAddr,
+) -> Result<Vec<BlockHeader>, p2p::Error> {
+	let deadline = Instant::now() + HEADERS_TIMEOUT;
+	loop {
+		let now = Instant::now();
+		if now >= deadline {
+			return Err(p2p::Error::Timeout);
+		}
+		match rx.recv_timeout(deadline - now) {
+			Ok((from, headers)) => {
+				if from == addr {
+					return Ok(headers);
+				}
+			}
+			Err(_) => return Err(p2p::Error::Timeout),
+		}
+	}
+}
+
+/// Downloads the header chain from genesis up to the provided height,
+/// checking every header builds on the previous one. Returns the header
+/// hashes, indexed by height.
+fn header_hashes(
+	peer: &Peer,
+	rx: &mpsc::Receiver<(PeerAddr, Vec<BlockHeader>)>,
+	height: u64,
+	genesis: Hash,
+) -> Result<Vec<Hash>, p2p::Error> {
+	let mut hashes = vec![genesis];
+	while (hashes.len() as u64) <= height {
+		peer.send_header_request(vec![hashes[hashes.len() - 1]])?;
+		let mut received = 0;
+		while received < p2p::MAX_BLOCK_HEADERS as usize && (hashes.len() as u64) <= height {
+			let headers = next_headers(rx, peer.info.addr)?;
+			for header in &headers {
+				if header.height != hashes.len() as u64
+					|| header.prev_hash != hashes[hashes.len() - 1]
+				{
+					return Err(p2p::Error::BadMessage);
+				}
+				hashes.push(header.hash());
+			}
+			received += headers.len();
+		}
+	}
+	hashes.truncate(height as usize + 1);
+	Ok(hashes)
+}
+
+/// Sends a locator built from our header chain, the peer replies with the
+/// headers following the most recent one we have in common. A peer on our
+/// chain starts right at the height we're checking, otherwise we follow its
+/// headers until they either leave our chain or get there.
+fn ask_peer(
+	peer: &Peer,
+	rx: &mpsc::Receiver<(PeerAddr, Vec<BlockHeader>)>,
+	hashes: &[Hash],
+) -> Answer {
+	let height = hashes.len() as u64 - 1;
+	let locator = get_locator_heights(height - 1)
+		.into_iter()
+		.map(|h| hashes[h as usize])
+		.collect();
+	if peer.send_header_request(locator).is_err() {
+		return Answer::Unavailable;
+	}
+	let mut received = 0;
+	let mut diverged = false;
+	while received < p2p::MAX_BLOCK_HEADERS as usize {
+		let headers = match next_headers(rx, peer.info.addr) {
+			Ok(headers) => headers,
+			Err(_) => break,
+		};
+		received += headers.len();
+		for header in headers {
+			if header.height == 0 || header.height > height {
+				return Answer::Disagree(None);
+			}
+			let hash = header.hash();
+			diverged = diverged || hash != hashes[header.height as usize];
+			if header.height == height {
+				return match diverged {
+					true => Answer::Disagree(Some(hash)),
+					false => Answer::Agree,
+				};
+			}
+		}
+	}
+	match diverged {
+		true => Answer::Disagree(None),
+		// still on our chain, just not there yet
+		false => Answer::Unavailable,
+	}
+}
+
+/// Network adapter forwarding the headers we receive, everything else is
+/// ignored as we're not running a chain.
+struct HeadersCollector {
+	sender: Mutex<mpsc::Sender<(PeerAddr, Vec<BlockHeader>)>>,
+}
+
+impl ChainAdapter for HeadersCollector {
+	fn total_difficulty(&self) -> Result<Difficulty, chain::Error> {
+		Ok(Difficulty::min())
+	}
+	fn total_height(&self) -> Result<u64, chain::Error> {
+		Ok(0)
+	}
+	fn get_transaction(&self, _h: Hash) -> Option<Transaction> {
+		None
+	}
+	fn tx_kernel_received(&self, _h: Hash, _peer_info: &PeerInfo) -> Result<bool, chain::Error> {
+		Ok(true)
+	}
+	fn transaction_received(&self, _tx: Transaction, _stem: bool) -> Result<bool, chain::Error> {
+		Ok(true)
+	}
+	fn compact_block_received(
+		&self,
+		_cb: CompactBlock,
+		_peer_info: &PeerInfo,
+	) -> Result<bool, chain::Error> {
+		Ok(true)
+	}
+	fn header_received(
+		&self,
+		_bh: BlockHeader,
+		_peer_info: &PeerInfo,
+	) -> Result<bool, chain::Error> {
+		Ok(true)
+	}
+	fn block_received(
+		&self,
+		_b: crate::core::core::Block,
+		_peer_info: &PeerInfo,
+		_was_requested: bool,
+	) -> Result<bool, chain::Error> {
+		Ok(true)
+	}
+	fn headers_received(
+		&self,
+		bh: &[BlockHeader],
+		peer_info: &PeerInfo,
+	) -> Result<bool, chain::Error> {
+		let _ = self.sender.lock().send((peer_info.addr, bh.to_vec()));
+		Ok(true)
+	}
+	fn locate_headers(&self, _locator: &[Hash]) -> Result<Vec<BlockHeader>, chain::Error> {
+		Ok(vec![])
+	}
+	fn get_block(&self, _h: Hash) -> Option<crate::core::core::Block> {
+		None
+	}
+	fn kernel_data_read(&self) -> Result<File, chain::Error> {
+		Err(chain::ErrorKind::Other("no chain".to_owned()).into())
+	}
+	fn kernel_data_write(&self, _reader: &mut dyn Read) -> Result<bool, chain::Error> {
+		Ok(false)
+	}
+	fn txhashset_read(&self, _h: Hash) -> Option<TxHashSetRead> {
+		None
+	}
+	fn txhashset_receive_ready(&self) -> bool {
+		false
+	}
+	fn txhashset_write(
+		&self,
+		_h: Hash,
+		_txhashset_data: File,
+		_peer_info: &PeerInfo,
+	) -> Result<bool, chain::Error> {
+		Ok(false)
+	}
+	fn txhashset_download_update(
+		&self,
+		_start_time: DateTime<Utc>,
+		_downloaded_size: u64,
+		_total_size: u64,
+	) -> bool {
+		false
+	}
+	fn get_tmp_dir(&self) -> PathBuf {
+		std::env::temp_dir()
+	}
+	fn get_tmpfile_pathname(&self, tmpfile_name: String) -> PathBuf {
+		self.get_tmp_dir().join(tmpfile_name)
+	}
+}
+
+impl NetAdapter for HeadersCollector {
+	fn find_peer_addrs(&self, _capab: Capabilities) -> Vec<PeerAddr> {
+		vec![]
+	}
+	fn peer_addrs_received(&self, _addrs: Vec<PeerAddr>) {}
+	fn peer_difficulty(&self, _addr: PeerAddr, _diff: Difficulty, _height: u64) {}
+	fn is_banned(&self, _addr: PeerAddr) -> bool {
+		false
+	}
+	fn peer_misbehaved(&self, _addr: PeerAddr, _misbehavior: Misbehavior) {}
+}
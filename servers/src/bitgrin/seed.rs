@@ -225,7 +225,8 @@ fn monitor_peers(
 	}
 
 	// find some peers from our db
-	// and queue them up for a connection attempt
+	// and queue them up for a connection attempt, peers in address groups
+	// we have no outbound peer in first, then the groups we already have
 	let new_peers = peers.find_peers(
 		p2p::State::Healthy,
 		p2p::Capabilities::UNKNOWN,
@@ -34,15 +34,22 @@ use crate::chain;
 use crate::common::adapters::{
 	ChainToPoolAndNetAdapter, NetToChainAdapter, PoolToChainAdapter, PoolToNetAdapter,
 };
-use crate::common::hooks::{init_chain_hooks, init_net_hooks};
+use crate::common::hooks::{
+	init_chain_hooks, init_net_hooks, init_pool_hooks, init_webhook_outbox,
+};
 use crate::common::stats::{DiffBlock, DiffStats, PeerStats, ServerStateInfo, ServerStats};
-use crate::common::types::{Error, ServerConfig, StratumServerConfig, SyncState, SyncStatus};
-use crate::core::core::hash::{Hashed, ZERO_HASH};
+use crate::common::subscriptions::{self, EventHub};
+use crate::common::types::{
+	Error, ServerConfig, StratumServerConfig, SyncState, SyncStatus, TemplatePolicyConfig,
+	VardiffConfig,
+};
+use crate::core::core::hash::{Hash, Hashed, ZERO_HASH};
 use crate::core::core::verifier_cache::{LruVerifierCache, VerifierCache};
 use crate::core::{consensus, genesis, global, pow};
 use crate::bitgrin::{dandelion_monitor, seed, sync};
-use crate::mining::stratumserver;
+use crate::mining::share_ledger::ShareLedger;
 use crate::mining::test_miner::Miner;
+use crate::mining::{stratum_api, stratumserver};
 use crate::p2p;
 use crate::p2p::types::PeerAddr;
 use crate::pool;
@@ -73,6 +80,8 @@ pub struct Server {
 	connect_thread: Option<JoinHandle<()>>,
 	sync_thread: JoinHandle<()>,
 	dandelion_thread: JoinHandle<()>,
+	/// Shares and blocks of the stratum workers, when stratum is enabled
+	share_ledger: Option<Arc<ShareLedger>>,
 }
 
 impl Server {
@@ -155,7 +164,11 @@ impl Server {
 		let verifier_cache = Arc::new(RwLock::new(LruVerifierCache::new()));
 
 		let pool_adapter = Arc::new(PoolToChainAdapter::new());
-		let pool_net_adapter = Arc::new(PoolToNetAdapter::new(config.dandelion_config.clone()));
+		let event_hub = EventHub::new();
+		let pool_net_adapter = Arc::new(PoolToNetAdapter::new(
+			config.dandelion_config.clone(),
+			init_pool_hooks(&event_hub),
+		));
 		let tx_pool = Arc::new(RwLock::new(pool::TransactionPool::new(
 			config.pool_config.clone(),
 			pool_adapter.clone(),
@@ -165,11 +178,23 @@ impl Server {
 
 		let sync_state = Arc::new(SyncState::new());
 
+		let webhook_outbox = init_webhook_outbox(&config, stop_state.clone())?;
+
 		let chain_adapter = Arc::new(ChainToPoolAndNetAdapter::new(
 			tx_pool.clone(),
-			init_chain_hooks(&config),
+			init_chain_hooks(&config, &webhook_outbox, &event_hub),
 		));
 
+		let mut checkpoints = global::Checkpoints::default();
+		for c in &config.checkpoints {
+			let hash = Hash::from_hex(&c.hash)
+				.map_err(|_| Error::Configuration(format!("invalid checkpoint hash {}", c.hash)))?;
+			checkpoints
+				.add(c.height, hash)
+				.map_err(Error::Configuration)?;
+		}
+		global::set_operator_checkpoints(checkpoints).map_err(Error::Configuration)?;
+
 		let genesis = match config.chain_type {
 			global::ChainTypes::AutomatedTesting => genesis::genesis_dev(),
 			global::ChainTypes::UserTesting => genesis::genesis_dev(),
@@ -196,7 +221,7 @@ impl Server {
 			tx_pool.clone(),
 			verifier_cache.clone(),
 			config.clone(),
-			init_net_hooks(&config),
+			init_net_hooks(&config, &webhook_outbox, &event_hub),
 		));
 
 		let p2p_server = Arc::new(p2p::Server::new(
@@ -280,6 +305,17 @@ impl Server {
 			}
 		};
 
+		let share_ledger = match config.stratum_mining_config {
+			Some(ref c) if c.enable_stratum_server == Some(true) => {
+				Some(Arc::new(ShareLedger::new(&config.db_root, c.pplns_window)?))
+			}
+			_ => None,
+		};
+		let mut extra_routes = subscriptions::routes(&event_hub);
+		if let Some(ref ledger) = share_ledger {
+			extra_routes.extend(stratum_api::routes(ledger));
+		}
+
 		// TODO fix API shutdown and join this thread
 		api::start_rest_apis(
 			config.api_http_addr.clone(),
@@ -288,6 +324,7 @@ impl Server {
 			p2p_server.peers.clone(),
 			api_secret,
 			tls_conf,
+			extra_routes,
 		);
 
 		info!("Starting dandelion monitor: {}", &config.api_http_addr);
@@ -315,6 +352,7 @@ impl Server {
 			connect_thread,
 			sync_thread,
 			dandelion_thread,
+			share_ledger,
 		})
 	}
 
@@ -338,7 +376,11 @@ impl Server {
 	}
 
 	/// Start a minimal "stratum" mining service on a separate thread
-	pub fn start_stratum_server(&self, config: StratumServerConfig) {
+	pub fn start_stratum_server(&self, mut config: StratumServerConfig) {
+		if config.binary_stratum_key_file.is_none() {
+			let key_file = Path::new(&self.config.db_root).join("stratum_server.key");
+			config.binary_stratum_key_file = Some(key_file.to_string_lossy().into_owned());
+		}
 		let edge_bits = global::min_edge_bits();
 		let proof_size = global::proofsize();
 		let sync_state = self.sync_state.clone();
@@ -347,8 +389,8 @@ impl Server {
 			config.clone(),
 			self.chain.clone(),
 			self.tx_pool.clone(),
-			self.verifier_cache.clone(),
 			self.state_info.stratum_stats.clone(),
+			self.share_ledger.clone(),
 		);
 		let _ = thread::Builder::new()
 			.name("stratum_server".to_string())
@@ -377,8 +419,14 @@ impl Server {
 			burn_reward: false,
 			enable_stratum_server: None,
 			stratum_server_addr: None,
+			binary_stratum_server_addr: None,
+			binary_stratum_key_file: None,
 			wallet_listener_url: config_wallet_url,
 			minimum_share_difficulty: 1,
+			worker_credentials_file: None,
+			pplns_window: 2,
+			vardiff_config: VardiffConfig::default(),
+			template_policy_config: TemplatePolicyConfig::default(),
 		};
 
 		let mut miner = Miner::new(
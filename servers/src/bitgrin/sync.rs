@@ -19,4 +19,5 @@ mod header_sync;
 mod state_sync;
 mod syncer;
 
+pub(crate) use self::header_sync::get_locator_heights;
 pub use self::syncer::run_sync;
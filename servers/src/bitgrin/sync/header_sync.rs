@@ -270,7 +270,7 @@ fn close_enough(locator: &Vec<(u64, Hash)>, height: u64) -> Option<(u64, Hash)>
 }
 
 // current height back to 0 decreasing in powers of 2
-fn get_locator_heights(height: u64) -> Vec<u64> {
+pub(crate) fn get_locator_heights(height: u64) -> Vec<u64> {
 	let mut current = height;
 	let mut heights = vec![];
 	while current > 0 {
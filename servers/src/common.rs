@@ -18,3 +18,5 @@ pub mod adapters;
 pub mod stats;
 pub mod types;
 pub mod hooks;
+pub mod outbox;
+pub mod subscriptions;
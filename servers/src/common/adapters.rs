@@ -24,7 +24,7 @@ use std::thread;
 use std::time::Instant;
 
 use crate::chain::{self, BlockStatus, ChainAdapter, Options};
-use crate::common::hooks::{ChainEvents, NetEvents};
+use crate::common::hooks::{ChainEvents, NetEvents, PoolEvents};
 use crate::common::types::{
 	self, ChainValidationMode, DandelionEpoch, ServerConfig, SyncState, SyncStatus,
 };
@@ -722,7 +722,7 @@ impl ChainAdapter for ChainToPoolAndNetAdapter {
 		// Reconcile the txpool against the new block *after* we have broadcast it too our peers.
 		// This may be slow and we do not want to delay block propagation.
 		// We only want to reconcile the txpool against the new block *if* total work has increased.
-		let is_reorg = if let BlockStatus::Reorg(_) = status {
+		let is_reorg = if let BlockStatus::Reorg(..) = status {
 			true
 		} else {
 			false
@@ -775,6 +775,7 @@ impl ChainToPoolAndNetAdapter {
 pub struct PoolToNetAdapter {
 	peers: OneTime<Weak<p2p::Peers>>,
 	dandelion_epoch: Arc<RwLock<DandelionEpoch>>,
+	hooks: Vec<Box<dyn PoolEvents + Send + Sync>>,
 }
 
 /// Adapter between the Dandelion monitor and the current Dandelion "epoch".
@@ -806,6 +807,15 @@ impl DandelionAdapter for PoolToNetAdapter {
 impl pool::PoolAdapter for PoolToNetAdapter {
 	fn tx_accepted(&self, tx: &core::Transaction) {
 		self.peers().broadcast_transaction(tx);
+		for hook in &self.hooks {
+			hook.on_transaction_accepted(tx);
+		}
+	}
+
+	fn tx_evicted(&self, tx: &core::Transaction) {
+		for hook in &self.hooks {
+			hook.on_transaction_evicted(tx);
+		}
 	}
 
 	fn stem_tx_accepted(&self, tx: &core::Transaction) -> Result<(), pool::PoolError> {
@@ -841,10 +851,14 @@ impl pool::PoolAdapter for PoolToNetAdapter {
 
 impl PoolToNetAdapter {
 	/// Create a new pool to net adapter
-	pub fn new(config: DandelionConfig) -> PoolToNetAdapter {
+	pub fn new(
+		config: DandelionConfig,
+		hooks: Vec<Box<dyn PoolEvents + Send + Sync>>,
+	) -> PoolToNetAdapter {
 		PoolToNetAdapter {
 			peers: OneTime::new(),
 			dandelion_epoch: Arc::new(RwLock::new(DandelionEpoch::new(config))),
+			hooks,
 		}
 	}
 
@@ -903,6 +917,12 @@ impl pool::BlockChain for PoolToChainAdapter {
 			.map_err(|_| pool::PoolError::Other(format!("failed to get block_header")))
 	}
 
+	fn get_block(&self, hash: &Hash) -> Result<core::Block, pool::PoolError> {
+		self.chain()
+			.get_block(hash)
+			.map_err(|_| pool::PoolError::Other(format!("failed to get block")))
+	}
+
 	fn get_block_sums(&self, hash: &Hash) -> Result<BlockSums, pool::PoolError> {
 		self.chain()
 			.get_block_sums(hash)
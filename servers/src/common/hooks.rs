@@ -19,45 +19,119 @@ extern crate hyper;
 extern crate hyper_rustls;
 extern crate tokio;
 
-use crate::chain::BlockStatus;
-use crate::common::types::{ServerConfig, WebHooksConfig};
+use crate::chain::{BlockStatus, Reorg, ReorgBlock};
+use crate::common::outbox::{self, Delivery, OutboxWriter, WebhookOutbox};
+use crate::common::subscriptions::EventHub;
+use crate::common::types::{Error, ServerConfig, WebHooksConfig};
 use crate::core::core;
 use crate::core::core::hash::Hashed;
 use crate::p2p::types::PeerAddr;
-use futures::future::Future;
+use crate::util::StopState;
+use chrono::prelude::Utc;
+use futures::sync::oneshot;
+use futures::Future;
 use hyper::client::HttpConnector;
-use hyper::header::HeaderValue;
+use hyper::header::{HeaderName, HeaderValue};
 use hyper::Client;
 use hyper::{Body, Method, Request};
 use hyper_rustls::HttpsConnector;
 use serde::Serialize;
-use serde_json::{json, to_string};
+use serde_json::{json, Value};
+use std::sync::Arc;
+use std::thread;
 use std::time::Duration;
+use tokio::prelude::FutureExt;
 use tokio::runtime::Runtime;
 
 /// Returns the list of event hooks that will be initialized for network events
-pub fn init_net_hooks(config: &ServerConfig) -> Vec<Box<dyn NetEvents + Send + Sync>> {
+pub fn init_net_hooks(
+	config: &ServerConfig,
+	outbox: &Option<OutboxWriter>,
+	event_hub: &EventHub,
+) -> Vec<Box<dyn NetEvents + Send + Sync>> {
 	let mut list: Vec<Box<dyn NetEvents + Send + Sync>> = Vec::new();
 	list.push(Box::new(EventLogger));
-	if config.webhook_config.block_received_url.is_some()
-		|| config.webhook_config.tx_received_url.is_some()
-		|| config.webhook_config.header_received_url.is_some()
-	{
-		list.push(Box::new(WebHook::from_config(&config.webhook_config)));
+	list.push(Box::new(event_hub.clone()));
+	if let Some(outbox) = outbox {
+		if config.webhook_config.block_received_url.is_some()
+			|| config.webhook_config.tx_received_url.is_some()
+			|| config.webhook_config.header_received_url.is_some()
+		{
+			list.push(Box::new(WebHook::from_config(
+				&config.webhook_config,
+				outbox.clone(),
+			)));
+		}
 	}
 	list
 }
 
 /// Returns the list of event hooks that will be initialized for chain events
-pub fn init_chain_hooks(config: &ServerConfig) -> Vec<Box<dyn ChainEvents + Send + Sync>> {
+pub fn init_chain_hooks(
+	config: &ServerConfig,
+	outbox: &Option<OutboxWriter>,
+	event_hub: &EventHub,
+) -> Vec<Box<dyn ChainEvents + Send + Sync>> {
 	let mut list: Vec<Box<dyn ChainEvents + Send + Sync>> = Vec::new();
 	list.push(Box::new(EventLogger));
-	if config.webhook_config.block_accepted_url.is_some() {
-		list.push(Box::new(WebHook::from_config(&config.webhook_config)));
+	list.push(Box::new(event_hub.clone()));
+	if let Some(outbox) = outbox {
+		if config.webhook_config.block_accepted_url.is_some() {
+			list.push(Box::new(WebHook::from_config(
+				&config.webhook_config,
+				outbox.clone(),
+			)));
+		}
 	}
 	list
 }
 
+/// Returns the list of event hooks that will be initialized for transaction
+/// pool events
+pub fn init_pool_hooks(event_hub: &EventHub) -> Vec<Box<dyn PoolEvents + Send + Sync>> {
+	vec![Box::new(EventLogger), Box::new(event_hub.clone())]
+}
+
+/// Opens the webhook outbox and starts delivering its requests, a thread for
+/// each url so that an unresponsive one doesn't hold the others back, when
+/// any webhook is configured.
+pub fn init_webhook_outbox(
+	config: &ServerConfig,
+	stop_state: Arc<StopState>,
+) -> Result<Option<OutboxWriter>, Error> {
+	let webhook_config = &config.webhook_config;
+	if !webhook_config.is_enabled() {
+		return Ok(None);
+	}
+	let outbox = Arc::new(WebhookOutbox::open(&config.db_root)?);
+	let sender = Arc::new(WebHookSender::new(webhook_config)?);
+
+	// the configured urls, and the ones requests are still queued for since
+	// before a configuration change
+	let mut urls = outbox.urls()?;
+	for url in &[
+		&webhook_config.tx_received_url,
+		&webhook_config.header_received_url,
+		&webhook_config.block_received_url,
+		&webhook_config.block_accepted_url,
+	] {
+		if let Some(uri) = parse_url(url) {
+			let url = uri.to_string();
+			if !urls.contains(&url) {
+				urls.push(url);
+			}
+		}
+	}
+	for url in urls {
+		let (sender, outbox, stop_state) = (sender.clone(), outbox.clone(), stop_state.clone());
+		let _ = thread::Builder::new()
+			.name("webhooks".to_string())
+			.spawn(move || sender.run(&outbox, &url, stop_state));
+	}
+	let writer = OutboxWriter::start(outbox, webhook_config.max_pending);
+	Ok(Some(writer))
+}
+
 #[allow(unused_variables)]
 /// Trait to be implemented by Network Event Hooks
 pub trait NetEvents {
@@ -78,6 +152,16 @@ pub trait ChainEvents {
 	fn on_block_accepted(&self, block: &core::Block, status: &BlockStatus) {}
 }
 
+#[allow(unused_variables)]
+/// Trait to be implemented by Transaction Pool Event Hooks
+pub trait PoolEvents {
+	/// Triggers when a transaction is accepted in the transaction pool
+	fn on_transaction_accepted(&self, tx: &core::Transaction) {}
+
+	/// Triggers when a transaction leaves the transaction pool without being mined
+	fn on_transaction_evicted(&self, tx: &core::Transaction) {}
+}
+
 /// Basic Logger
 struct EventLogger;
 
@@ -114,10 +198,27 @@ impl NetEvents for EventLogger {
 	}
 }
 
+impl PoolEvents for EventLogger {
+	fn on_transaction_evicted(&self, tx: &core::Transaction) {
+		debug!("Evicted tx {} from the transaction pool.", tx.hash());
+	}
+}
+
 impl ChainEvents for EventLogger {
 	fn on_block_accepted(&self, block: &core::Block, status: &BlockStatus) {
 		match status {
-			BlockStatus::Reorg(depth) => {
+			BlockStatus::Reorg(_, Some(reorg)) => {
+				warn!(
+					"block_accepted (REORG!): {:?} at {} (depth: {}, fork point: {:?} at {}, diff: {})",
+					block.hash(),
+					block.header.height,
+					reorg.depth(),
+					reorg.fork_point.hash,
+					reorg.fork_point.height,
+					block.header.total_difficulty(),
+				);
+			}
+			BlockStatus::Reorg(depth, None) => {
 				warn!(
 					"block_accepted (REORG!): {:?} at {} (depth: {}, diff: {})",
 					block.hash(),
@@ -154,6 +255,26 @@ impl ChainEvents for EventLogger {
 	}
 }
 
+/// Adds the `depth` of a reorg to an event JSON object, and the
+/// `fork_point`, `disconnected` and `connected` fields when its blocks could
+/// be looked up, the depth then being the number of blocks rolled back.
+/// Disconnected blocks go from the former head down, connected ones up to
+/// the new head.
+pub fn add_reorg_fields(payload: &mut Value, depth: u64, reorg: &Option<Reorg>) {
+	let reorg = match reorg {
+		Some(reorg) => reorg,
+		None => {
+			payload["depth"] = depth.into();
+			return;
+		}
+	};
+	let block_json = |b: &ReorgBlock| json!({"hash": b.hash.to_hex(), "height": b.height});
+	payload["depth"] = reorg.depth().into();
+	payload["fork_point"] = block_json(&reorg.fork_point);
+	payload["disconnected"] = reorg.disconnected.iter().map(block_json).collect();
+	payload["connected"] = reorg.connected.iter().map(block_json).collect();
+}
+
 fn parse_url(value: &Option<String>) -> Option<hyper::Uri> {
 	match value {
 		Some(url) => {
@@ -174,7 +295,7 @@ fn parse_url(value: &Option<String>) -> Option<hyper::Uri> {
 	}
 }
 
-/// A struct that holds the hyper/tokio runtime.
+/// Queues the event data to POST to the configured urls in the outbox.
 struct WebHook {
 	/// url to POST transaction data when a new transaction arrives from a peer
 	tx_received_url: Option<hyper::Uri>,
@@ -184,115 +305,182 @@ struct WebHook {
 	block_received_url: Option<hyper::Uri>,
 	/// url to POST block data when a new block is accepted by our node (might be a reorg or a fork)
 	block_accepted_url: Option<hyper::Uri>,
+	/// Where the requests are queued to be delivered
+	outbox: OutboxWriter,
+}
+
+impl WebHook {
+	/// Instantiates a Webhook struct from a configuration file
+	fn from_config(config: &WebHooksConfig, outbox: OutboxWriter) -> WebHook {
+		WebHook {
+			tx_received_url: parse_url(&config.tx_received_url),
+			header_received_url: parse_url(&config.header_received_url),
+			block_received_url: parse_url(&config.block_received_url),
+			block_accepted_url: parse_url(&config.block_accepted_url),
+			outbox,
+		}
+	}
+
+	fn make_request<T: Serialize>(&self, payload: &T, uri: &Option<hyper::Uri>) -> bool {
+		if let Some(url) = uri {
+			let payload = match serde_json::to_value(payload) {
+				Ok(value) => value,
+				Err(_) => {
+					return false; // print error message
+				}
+			};
+			self.outbox
+				.push(&url.to_string(), payload, Utc::now().timestamp());
+		}
+		true
+	}
+}
+
+/// Delivers the requests queued in the outbox, holds the hyper/tokio runtime.
+struct WebHookSender {
 	/// The hyper client to be used for all requests
 	client: Client<HttpsConnector<HttpConnector>>,
 	/// The tokio event loop
 	runtime: Runtime,
+	/// timeout of a request
+	timeout: Duration,
+	/// secret the requests are signed with
+	secret: Option<String>,
+	/// longest time in seconds between two attempts of a request
+	max_retry_interval: u64,
+	/// attempts after which a request is dropped, 0 for never
+	max_attempts: u32,
 }
 
-impl WebHook {
-	/// Instantiates a Webhook struct
-	fn new(
-		tx_received_url: Option<hyper::Uri>,
-		header_received_url: Option<hyper::Uri>,
-		block_received_url: Option<hyper::Uri>,
-		block_accepted_url: Option<hyper::Uri>,
-		nthreads: u16,
-		timeout: u16,
-	) -> WebHook {
-		let keep_alive = Duration::from_secs(timeout as u64);
+impl WebHookSender {
+	fn new(config: &WebHooksConfig) -> Result<WebHookSender, Error> {
+		let timeout = Duration::from_secs(config.timeout as u64);
 
 		info!(
 			"Spawning {} threads for webhooks (timeout set to {} secs)",
-			nthreads, timeout
+			config.nthreads, config.timeout
 		);
 
-		let https = HttpsConnector::new(nthreads as usize);
+		let https = HttpsConnector::new(config.nthreads as usize);
 		let client = Client::builder()
-			.keep_alive_timeout(keep_alive)
+			.keep_alive_timeout(timeout)
 			.build::<_, hyper::Body>(https);
 
-		WebHook {
-			tx_received_url,
-			block_received_url,
-			header_received_url,
-			block_accepted_url,
+		Ok(WebHookSender {
 			client,
-			runtime: Runtime::new().unwrap(),
-		}
+			runtime: Runtime::new()?,
+			timeout,
+			secret: config.secret.clone(),
+			max_retry_interval: config.max_retry_interval,
+			max_attempts: config.max_attempts,
+		})
 	}
 
-	/// Instantiates a Webhook struct from a configuration file
-	fn from_config(config: &WebHooksConfig) -> WebHook {
-		WebHook::new(
-			parse_url(&config.tx_received_url),
-			parse_url(&config.header_received_url),
-			parse_url(&config.block_received_url),
-			parse_url(&config.block_accepted_url),
-			config.nthreads,
-			config.timeout,
-		)
+	/// Delivers the requests queued for `url` in order, oldest first.
+	fn run(&self, outbox: &WebhookOutbox, url: &str, stop_state: Arc<StopState>) {
+		while !stop_state.is_stopped() {
+			let now = Utc::now().timestamp();
+			let delivery = match outbox.next(url) {
+				Ok(Some(delivery)) if delivery.next_attempt <= now => delivery,
+				Ok(_) => {
+					thread::sleep(Duration::from_secs(1));
+					continue;
+				}
+				Err(e) => {
+					error!("Failed to read webhook outbox: {:?}", e);
+					thread::sleep(Duration::from_secs(1));
+					continue;
+				}
+			};
+			let res = match self.post(&delivery) {
+				Ok(()) => outbox.remove(&delivery),
+				Err(e) => {
+					warn!(
+						"Error sending POST request {} to {}: {}",
+						delivery.sequence, delivery.url, e
+					);
+					if self.max_attempts > 0 && delivery.attempts + 1 >= self.max_attempts {
+						error!(
+							"Giving up on POST request {} to {} after {} attempts",
+							delivery.sequence,
+							delivery.url,
+							delivery.attempts + 1
+						);
+						outbox.remove(&delivery)
+					} else {
+						outbox
+							.retry_later(&delivery, now, self.max_retry_interval)
+							.map(|_| ())
+					}
+				}
+			};
+			if let Err(e) = res {
+				error!("Failed to update webhook outbox: {:?}", e);
+				thread::sleep(Duration::from_secs(1));
+			}
+		}
 	}
 
-	fn post(&self, url: hyper::Uri, data: String) {
-		let mut req = Request::new(Body::from(data));
+	fn post(&self, delivery: &Delivery) -> Result<(), String> {
+		let url: hyper::Uri = delivery.url.parse().map_err(|e| format!("{}", e))?;
+		let mut req = Request::new(Body::from(delivery.body.clone()));
 		*req.method_mut() = Method::POST;
-		*req.uri_mut() = url.clone();
-		req.headers_mut().insert(
+		*req.uri_mut() = url;
+		let headers = req.headers_mut();
+		headers.insert(
 			hyper::header::CONTENT_TYPE,
 			HeaderValue::from_static("application/json"),
 		);
+		headers.insert(
+			HeaderName::from_static("x-bitgrin-sequence"),
+			HeaderValue::from(delivery.sequence),
+		);
+		if let Some(ref secret) = self.secret {
+			let signature = outbox::signature(secret.as_bytes(), delivery.body.as_bytes());
+			let value = HeaderValue::from_str(&format!("sha256={}", signature))
+				.map_err(|e| format!("{}", e))?;
+			headers.insert(HeaderName::from_static("x-bitgrin-signature"), value);
+		}
 
+		// the request runs on the shared runtime, each url's thread waiting
+		// for its own
+		let (tx, rx) = oneshot::channel();
 		let future = self
 			.client
 			.request(req)
-			.map(|_res| {})
-			.map_err(move |_res| {
-				warn!("Error sending POST request to {}", url);
+			.timeout(self.timeout)
+			.then(move |res| {
+				let _ = tx.send(res.map(|res| res.status()).map_err(|e| format!("{}", e)));
+				Ok(())
 			});
-
-		let handle = self.runtime.executor();
-		handle.spawn(future);
-	}
-	fn make_request<T: Serialize>(&self, payload: &T, uri: &Option<hyper::Uri>) -> bool {
-		if let Some(url) = uri {
-			let payload = match to_string(payload) {
-				Ok(serialized) => serialized,
-				Err(_) => {
-					return false; // print error message
-				}
-			};
-			self.post(url.clone(), payload);
+		self.runtime.executor().spawn(future);
+		let status = rx.wait().map_err(|_| "request dropped".to_owned())??;
+		if status.is_success() {
+			Ok(())
+		} else {
+			Err(format!("status {}", status))
 		}
-		true
 	}
 }
 
 impl ChainEvents for WebHook {
 	fn on_block_accepted(&self, block: &core::Block, status: &BlockStatus) {
 		let status_str = match status {
-			BlockStatus::Reorg(_) => "reorg",
+			BlockStatus::Reorg(..) => "reorg",
 			BlockStatus::Fork => "fork",
 			BlockStatus::Next => "head",
             BlockStatus::ChainIntegrityFailure => "integrityfailure",
 		};
 
-		// Add additional `depth` field to the JSON in case of reorg
-		let payload = if let BlockStatus::Reorg(depth) = status {
-			json!({
-				"hash": block.header.hash().to_hex(),
-				"status": status_str,
-				"data": block,
-
-				"depth": depth
-			})
-		} else {
-			json!({
-				"hash": block.header.hash().to_hex(),
-				"status": status_str,
-				"data": block
-			})
-		};
+		let mut payload = json!({
+			"hash": block.header.hash().to_hex(),
+			"status": status_str,
+			"data": block
+		});
+		// Add the blocks rolled back and applied to the JSON in case of reorg
+		if let BlockStatus::Reorg(depth, reorg) = status {
+			add_reorg_fields(&mut payload, *depth, reorg);
+		}
 
 		if !self.make_request(&payload, &self.block_accepted_url) {
 			error!(
@@ -0,0 +1,329 @@
+// Copyright 2018 The BitGrin Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! On-disk queue of the webhook deliveries not acknowledged yet. Deliveries
+//! survive restarts and are retried with an exponential backoff, in order
+//! for each url, each url having its own queue. Each one carries a sequence
+//! number, increasing by one for every delivery to the same url, so consumers
+//! can tell when they missed some.
+
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::sync::Arc;
+use std::thread;
+
+use ring::{digest, hmac};
+use serde_json::Value;
+
+use crate::core::ser::{self, read_string, Readable, Reader, Writeable, Writer};
+use crate::store::{self, to_key, to_key_u64, Error};
+use crate::util;
+
+const DB_NAME: &str = "webhooks";
+const STORE_SUBPATH: &str = "webhooks";
+
+const DELIVERY_PREFIX: u8 = 'D' as u8;
+const SEQUENCE_PREFIX: u8 = 'S' as u8;
+
+/// Requests from the event hooks waiting to be written to the outbox, past
+/// which new ones are dropped.
+const WRITER_QUEUE_SIZE: usize = 1024;
+
+/// A webhook POST request waiting to be acknowledged.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Delivery {
+	/// Url to POST to
+	pub url: String,
+	/// Sequence number of the delivery for its url, starting at 1
+	pub sequence: u64,
+	/// JSON body, sequence number included
+	pub body: String,
+	/// Failed attempts so far
+	pub attempts: u32,
+	/// Timestamp of the next attempt
+	pub next_attempt: i64,
+}
+
+impl Writeable for Delivery {
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), ser::Error> {
+		writer.write_bytes(&self.url)?;
+		writer.write_u64(self.sequence)?;
+		writer.write_bytes(&self.body)?;
+		writer.write_u32(self.attempts)?;
+		writer.write_i64(self.next_attempt)
+	}
+}
+
+impl Readable for Delivery {
+	fn read(reader: &mut dyn Reader) -> Result<Delivery, ser::Error> {
+		let url = read_string(reader)?;
+		let sequence = reader.read_u64()?;
+		let body = read_string(reader)?;
+		let attempts = reader.read_u32()?;
+		let next_attempt = reader.read_i64()?;
+		Ok(Delivery {
+			url,
+			sequence,
+			body,
+			attempts,
+			next_attempt,
+		})
+	}
+}
+
+// Deliveries are keyed by url then sequence number, the url being ended by
+// a zero byte so that no url is the prefix of another one's keys.
+fn url_prefix(url: &str) -> Vec<u8> {
+	let mut url = url.as_bytes().to_vec();
+	url.push(0);
+	to_key(DELIVERY_PREFIX, &mut url)
+}
+
+fn delivery_key(url: &str, sequence: u64) -> Vec<u8> {
+	let mut url = url.as_bytes().to_vec();
+	url.push(0);
+	to_key_u64(DELIVERY_PREFIX, &mut url, sequence)
+}
+
+/// Storage of the pending webhook deliveries.
+pub struct WebhookOutbox {
+	db: store::Store,
+}
+
+impl WebhookOutbox {
+	/// Opens the outbox under the provided root path.
+	pub fn open(db_root: &str) -> Result<WebhookOutbox, Error> {
+		let db = store::Store::new(db_root, Some(DB_NAME), Some(STORE_SUBPATH), None)?;
+		Ok(WebhookOutbox { db })
+	}
+
+	/// Queues a POST of the provided JSON object to `url`, adding the next
+	/// sequence number of the url to it as the `sequence` field. Keeps at
+	/// most `max_pending` deliveries for the url, dropping the oldest one,
+	/// 0 for no limit.
+	pub fn push(
+		&self,
+		url: &str,
+		mut payload: Value,
+		now: i64,
+		max_pending: u64,
+	) -> Result<Delivery, Error> {
+		let batch = self.db.batch()?;
+		let sequence_key = to_key(SEQUENCE_PREFIX, &mut url.as_bytes().to_vec());
+		let sequence = batch.get_ser::<u64>(&sequence_key)?.unwrap_or(0) + 1;
+
+		if let Value::Object(ref mut fields) = payload {
+			fields.insert("sequence".to_owned(), sequence.into());
+		}
+		let delivery = Delivery {
+			url: url.to_owned(),
+			sequence,
+			body: payload.to_string(),
+			attempts: 0,
+			next_attempt: now,
+		};
+		batch.put_ser(&delivery_key(url, sequence), &delivery)?;
+		batch.put_ser(&sequence_key, &sequence)?;
+		// the sequence numbers of the pending deliveries of a url follow each
+		// other, the one falling out of the limit is known
+		if max_pending > 0 && sequence > max_pending {
+			let dropped = delivery_key(url, sequence - max_pending);
+			if batch.get_ser::<Delivery>(&dropped)?.is_some() {
+				warn!(
+					"Webhook outbox full for {}, dropping POST request {}",
+					url,
+					sequence - max_pending
+				);
+				batch.delete(&dropped)?;
+			}
+		}
+		batch.commit()?;
+		Ok(delivery)
+	}
+
+	/// Oldest delivery to `url` not acknowledged yet, the next one to attempt.
+	pub fn next(&self, url: &str) -> Result<Option<Delivery>, Error> {
+		Ok(self
+			.db
+			.iter::<Delivery>(&url_prefix(url))?
+			.next()
+			.map(|(_, d)| d))
+	}
+
+	/// Urls anything was ever queued for, that may still have deliveries
+	/// pending.
+	pub fn urls(&self) -> Result<Vec<String>, Error> {
+		let prefix = to_key(SEQUENCE_PREFIX, &mut vec![]);
+		Ok(self
+			.db
+			.iter::<u64>(&prefix)?
+			.filter_map(|(key, _)| String::from_utf8(key[prefix.len()..].to_vec()).ok())
+			.collect())
+	}
+
+	/// Removes an acknowledged, or abandoned, delivery.
+	pub fn remove(&self, delivery: &Delivery) -> Result<(), Error> {
+		let batch = self.db.batch()?;
+		batch.delete(&delivery_key(&delivery.url, delivery.sequence))?;
+		batch.commit()
+	}
+
+	/// Records a failed attempt, pushing the next one back by twice as long
+	/// as the previous time, up to `max_interval` seconds.
+	pub fn retry_later(
+		&self,
+		delivery: &Delivery,
+		now: i64,
+		max_interval: u64,
+	) -> Result<Delivery, Error> {
+		let attempts = delivery.attempts + 1;
+		let delivery = Delivery {
+			attempts,
+			next_attempt: now + backoff(attempts, max_interval) as i64,
+			..delivery.clone()
+		};
+		let batch = self.db.batch()?;
+		batch.put_ser(&delivery_key(&delivery.url, delivery.sequence), &delivery)?;
+		batch.commit()?;
+		Ok(delivery)
+	}
+}
+
+/// Hands the requests of the event hooks over to a thread writing them to
+/// the outbox, so that hooks never wait on the disk.
+#[derive(Clone)]
+pub struct OutboxWriter {
+	sender: SyncSender<(String, Value, i64)>,
+}
+
+impl OutboxWriter {
+	/// Starts the thread writing to the outbox, keeping at most
+	/// `max_pending` deliveries for each url. It stops once every writer
+	/// is dropped.
+	pub fn start(outbox: Arc<WebhookOutbox>, max_pending: u64) -> OutboxWriter {
+		let (sender, receiver) = mpsc::sync_channel(WRITER_QUEUE_SIZE);
+		let _ = thread::Builder::new()
+			.name("webhooks_outbox".to_string())
+			.spawn(move || write_requests(&outbox, receiver, max_pending));
+		OutboxWriter { sender }
+	}
+
+	/// Queues a POST of the provided JSON object to `url`, dropped when the
+	/// outbox can't keep up.
+	pub fn push(&self, url: &str, payload: Value, now: i64) {
+		match self.sender.try_send((url.to_owned(), payload, now)) {
+			Ok(()) => {}
+			Err(TrySendError::Full(_)) => {
+				warn!("Webhook outbox busy, dropping POST request to {}", url)
+			}
+			Err(TrySendError::Disconnected(_)) => {
+				error!("Webhook outbox stopped, dropping POST request to {}", url)
+			}
+		}
+	}
+}
+
+fn write_requests(
+	outbox: &WebhookOutbox,
+	receiver: Receiver<(String, Value, i64)>,
+	max_pending: u64,
+) {
+	for (url, payload, now) in receiver {
+		if let Err(e) = outbox.push(&url, payload, now, max_pending) {
+			error!("Failed to queue POST request to {}: {:?}", url, e);
+		}
+	}
+}
+
+/// Seconds to wait after the given number of failed attempts: 1, 2, 4...
+/// up to `max_interval`.
+pub fn backoff(attempts: u32, max_interval: u64) -> u64 {
+	let interval = 1u64
+		.checked_shl(attempts.saturating_sub(1))
+		.unwrap_or(u64::MAX);
+	interval.min(max_interval.max(1))
+}
+
+/// Hex encoded HMAC-SHA256 of a delivery body with the shared secret.
+pub fn signature(secret: &[u8], body: &[u8]) -> String {
+	let key = hmac::SigningKey::new(&digest::SHA256, secret);
+	util::to_hex(hmac::sign(&key, body).as_ref().to_vec())
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use serde_json::json;
+
+	#[test]
+	fn outbox_deliveries() {
+		let db_root = "test_output/webhook_outbox";
+		let _ = std::fs::remove_dir_all(db_root);
+		{
+			let outbox = WebhookOutbox::open(db_root).unwrap();
+			let a1 = outbox
+				.push("http://a", json!({"hash": "01"}), 100, 0)
+				.unwrap();
+			let b1 = outbox
+				.push("http://b", json!({"hash": "02"}), 100, 0)
+				.unwrap();
+			let a2 = outbox
+				.push("http://a", json!({"hash": "03"}), 100, 0)
+				.unwrap();
+			assert_eq!((a1.sequence, b1.sequence, a2.sequence), (1, 1, 2));
+			assert_eq!(a2.body, r#"{"hash":"03","sequence":2}"#);
+
+			// a2 waits for a1, b1 doesn't
+			assert_eq!(outbox.next("http://a").unwrap(), Some(a1.clone()));
+			assert_eq!(outbox.next("http://b").unwrap(), Some(b1));
+			assert_eq!(outbox.next("http://c").unwrap(), None);
+			// no url's deliveries show up in another's queue
+			assert_eq!(outbox.next("http://").unwrap(), None);
+
+			let a1 = outbox.retry_later(&a1, 100, 600).unwrap();
+			let a1 = outbox.retry_later(&a1, 100, 600).unwrap();
+			assert_eq!(a1.next_attempt, 102);
+			assert_eq!(outbox.next("http://a").unwrap(), Some(a1.clone()));
+			outbox.remove(&a1).unwrap();
+		}
+
+		// still there after a restart, with the sequence going on
+		let outbox = WebhookOutbox::open(db_root).unwrap();
+		assert_eq!(outbox.urls().unwrap(), vec!["http://a", "http://b"]);
+		assert_eq!(outbox.next("http://a").unwrap().unwrap().sequence, 2);
+		let a3 = outbox.push("http://a", json!({}), 200, 0).unwrap();
+		assert_eq!(a3.sequence, 3);
+
+		// past the limit the oldest deliveries of the url are dropped
+		for _ in 0..3 {
+			outbox.push("http://a", json!({}), 200, 2).unwrap();
+		}
+		assert_eq!(outbox.next("http://a").unwrap().unwrap().sequence, 5);
+		assert_eq!(outbox.next("http://b").unwrap().unwrap().sequence, 1);
+
+		let _ = std::fs::remove_dir_all(db_root);
+	}
+
+	#[test]
+	fn backoff_and_signature() {
+		let intervals: Vec<u64> = (1..=6).map(|a| backoff(a, 10)).collect();
+		assert_eq!(intervals, vec![1, 2, 4, 8, 10, 10]);
+		assert_eq!(backoff(100, 600), 600);
+
+		// RFC 4231, test case 2
+		assert_eq!(
+			signature(b"Jefe", b"what do ya want for nothing?"),
+			"5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+		);
+	}
+}
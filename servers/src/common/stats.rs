@@ -76,6 +76,8 @@ pub struct WorkerStats {
 	pub initial_block_height: u64,
 	/// pow difficulty this worker is using
 	pub pow_difficulty: u64,
+	/// shares per minute submitted during the last vardiff window
+	pub share_rate: f64,
 	/// number of valid shares submitted
 	pub num_accepted: u64,
 	/// number of invalid shares submitted
@@ -162,6 +164,8 @@ pub struct PeerStats {
 	pub sent_bytes_per_sec: u64,
 	/// Number of bytes we've received from the peer.
 	pub received_bytes_per_sec: u64,
+	/// Misbehavior score of the peer.
+	pub misbehavior: u32,
 }
 
 impl StratumStats {
@@ -199,6 +203,7 @@ impl PeerStats {
 			last_seen: peer.info.last_seen(),
 			sent_bytes_per_sec: peer.last_min_sent_bytes().unwrap_or(0) / 60,
 			received_bytes_per_sec: peer.last_min_received_bytes().unwrap_or(0) / 60,
+			misbehavior: peer.info.misbehavior(),
 		}
 	}
 }
@@ -211,6 +216,7 @@ impl Default for WorkerStats {
 			last_seen: SystemTime::now(),
 			initial_block_height: 0,
 			pow_difficulty: 0,
+			share_rate: 0.0,
 			num_accepted: 0,
 			num_rejected: 0,
 			num_stale: 0,
@@ -0,0 +1,405 @@
+// Copyright 2018 The BitGrin Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Event subscriptions: API clients keep a request open and get the chain,
+//! network and pool events matching their filters streamed back to them as
+//! server-sent events, without having to be reachable like webhooks.
+
+use std::collections::HashSet;
+use std::io;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use futures::sync::mpsc;
+use futures::{future, Stream};
+use hyper::{Body, Chunk, Request, Response, StatusCode};
+use serde_json::{json, Value};
+
+use crate::api::{self, ErrorKind, Handler, HandlerObj, QueryParams, ResponseFuture};
+use crate::chain::BlockStatus;
+use crate::common::hooks::{add_reorg_fields, ChainEvents, NetEvents, PoolEvents};
+use crate::core::core;
+use crate::core::core::hash::Hashed;
+use crate::p2p::types::PeerAddr;
+use crate::util::secp::constants::PEDERSEN_COMMITMENT_SIZE;
+use crate::util::secp::pedersen::Commitment;
+use crate::util::{self, Mutex};
+
+/// Most subscriptions open at the same time.
+const MAX_SUBSCRIBERS: usize = 64;
+
+/// Events queued for a subscriber reading too slowly, past which it misses
+/// some. Event ids tell it which ones.
+const SUBSCRIBER_BUFFER: usize = 256;
+
+/// Kinds of events streamed to subscribers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EventType {
+	/// A block was accepted by our chain, might be a reorg or a fork
+	BlockAccepted,
+	/// A block arrived from a peer
+	BlockReceived,
+	/// A block header arrived from a peer
+	HeaderReceived,
+	/// A transaction arrived from a peer
+	TxReceived,
+	/// A transaction was accepted in the transaction pool
+	PoolAdd,
+	/// A transaction left the transaction pool without being mined
+	PoolEvict,
+}
+
+impl EventType {
+	/// Name of the events of this type in the stream and in filters.
+	pub fn name(&self) -> &'static str {
+		match self {
+			EventType::BlockAccepted => "block_accepted",
+			EventType::BlockReceived => "block_received",
+			EventType::HeaderReceived => "header_received",
+			EventType::TxReceived => "tx_received",
+			EventType::PoolAdd => "pool_add",
+			EventType::PoolEvict => "pool_evict",
+		}
+	}
+
+	/// Event type of the given name.
+	pub fn from_name(name: &str) -> Option<EventType> {
+		match name {
+			"block_accepted" => Some(EventType::BlockAccepted),
+			"block_received" => Some(EventType::BlockReceived),
+			"header_received" => Some(EventType::HeaderReceived),
+			"tx_received" => Some(EventType::TxReceived),
+			"pool_add" => Some(EventType::PoolAdd),
+			"pool_evict" => Some(EventType::PoolEvict),
+			_ => None,
+		}
+	}
+}
+
+/// Events a subscriber wants.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct EventFilter {
+	/// Types of the events, all of them when empty
+	pub types: HashSet<EventType>,
+	/// Output commitments the events must create or spend, any when empty.
+	/// Header events never match.
+	pub commitments: HashSet<Commitment>,
+}
+
+impl EventFilter {
+	/// Filter from the `type` and `commitment` query parameters, each taking
+	/// comma separated values.
+	pub fn from_query(query: Option<&str>) -> Result<EventFilter, api::Error> {
+		let params = QueryParams::from(query);
+		let mut filter = EventFilter::default();
+		let mut err = None;
+		params.process_multival_param("type", |name| match EventType::from_name(name) {
+			Some(event_type) => {
+				filter.types.insert(event_type);
+			}
+			None => err = Some(format!("unknown event type {}", name)),
+		});
+		params.process_multival_param("commitment", |commit| {
+			match util::from_hex(commit.to_owned()) {
+				Ok(bytes) if bytes.len() == PEDERSEN_COMMITMENT_SIZE => {
+					filter.commitments.insert(Commitment::from_vec(bytes));
+				}
+				_ => err = Some(format!("invalid commitment {}", commit)),
+			}
+		});
+		match err {
+			Some(msg) => Err(ErrorKind::Argument(msg).into()),
+			None => Ok(filter),
+		}
+	}
+
+	fn matches(&self, event_type: EventType, commitments: &[Commitment]) -> bool {
+		(self.types.is_empty() || self.types.contains(&event_type))
+			&& (self.commitments.is_empty()
+				|| commitments.iter().any(|c| self.commitments.contains(c)))
+	}
+}
+
+struct Subscriber {
+	filter: EventFilter,
+	sender: mpsc::Sender<Chunk>,
+}
+
+/// Hands the events to the subscribers whose filter they match.
+#[derive(Clone, Default)]
+pub struct EventHub {
+	subscribers: Arc<Mutex<Vec<Subscriber>>>,
+	next_event_id: Arc<AtomicU64>,
+}
+
+impl EventHub {
+	/// Hub without any subscriber yet.
+	pub fn new() -> EventHub {
+		EventHub::default()
+	}
+
+	/// Stream of the events matching the filter, formatted as server-sent
+	/// events. None when there are too many subscribers already.
+	pub fn subscribe(&self, filter: EventFilter) -> Option<mpsc::Receiver<Chunk>> {
+		let mut subscribers = self.subscribers.lock();
+		subscribers.retain(|s| !s.sender.is_closed());
+		if subscribers.len() >= MAX_SUBSCRIBERS {
+			return None;
+		}
+		let (mut sender, receiver) = mpsc::channel(SUBSCRIBER_BUFFER);
+		// a comment, so that clients see the stream open right away
+		let _ = sender.try_send(Chunk::from(": subscribed\n\n"));
+		subscribers.push(Subscriber { filter, sender });
+		Some(receiver)
+	}
+
+	/// Number of open subscriptions.
+	pub fn subscriber_count(&self) -> usize {
+		self.subscribers.lock().len()
+	}
+
+	/// Sends an event to the interested subscribers, building its data only
+	/// if there is one. Subscribers that went away are dropped, whether the
+	/// event is for them or not.
+	fn publish<F>(&self, event_type: EventType, commitments: &[Commitment], data: F)
+	where
+		F: FnOnce() -> Value,
+	{
+		let mut subscribers = self.subscribers.lock();
+		subscribers.retain(|s| !s.sender.is_closed());
+		if !subscribers
+			.iter()
+			.any(|s| s.filter.matches(event_type, commitments))
+		{
+			return;
+		}
+		let id = self.next_event_id.fetch_add(1, Ordering::Relaxed);
+		let event = format!(
+			"id: {}\nevent: {}\ndata: {}\n\n",
+			id,
+			event_type.name(),
+			data()
+		);
+		let mut live = Vec::with_capacity(subscribers.len());
+		for mut subscriber in subscribers.drain(..) {
+			if subscriber.filter.matches(event_type, commitments) {
+				if let Err(e) = subscriber.sender.try_send(Chunk::from(event.clone())) {
+					if e.is_disconnected() {
+						continue;
+					}
+				}
+			}
+			live.push(subscriber);
+		}
+		*subscribers = live;
+	}
+}
+
+fn block_commitments(block: &core::Block) -> Vec<Commitment> {
+	block
+		.inputs()
+		.iter()
+		.map(|i| i.commitment())
+		.chain(block.outputs().iter().map(|o| o.commitment()))
+		.collect()
+}
+
+fn tx_commitments(tx: &core::Transaction) -> Vec<Commitment> {
+	tx.inputs()
+		.iter()
+		.map(|i| i.commitment())
+		.chain(tx.outputs().iter().map(|o| o.commitment()))
+		.collect()
+}
+
+impl ChainEvents for EventHub {
+	fn on_block_accepted(&self, block: &core::Block, status: &BlockStatus) {
+		let commitments = block_commitments(block);
+		self.publish(EventType::BlockAccepted, &commitments, || {
+			let status_str = match status {
+				BlockStatus::Reorg(..) => "reorg",
+				BlockStatus::Fork => "fork",
+				BlockStatus::Next => "head",
+				BlockStatus::ChainIntegrityFailure => "integrityfailure",
+			};
+			let mut event = json!({
+				"hash": block.hash().to_hex(),
+				"height": block.header.height,
+				"status": status_str,
+				"data": block,
+			});
+			if let BlockStatus::Reorg(depth, reorg) = status {
+				add_reorg_fields(&mut event, *depth, reorg);
+			}
+			event
+		});
+	}
+}
+
+impl NetEvents for EventHub {
+	fn on_transaction_received(&self, tx: &core::Transaction) {
+		self.publish(EventType::TxReceived, &tx_commitments(tx), || {
+			json!({
+				"hash": tx.hash().to_hex(),
+				"data": tx,
+			})
+		});
+	}
+
+	fn on_block_received(&self, block: &core::Block, addr: &PeerAddr) {
+		self.publish(EventType::BlockReceived, &block_commitments(block), || {
+			json!({
+				"hash": block.hash().to_hex(),
+				"height": block.header.height,
+				"peer": addr,
+				"data": block,
+			})
+		});
+	}
+
+	fn on_header_received(&self, header: &core::BlockHeader, addr: &PeerAddr) {
+		self.publish(EventType::HeaderReceived, &[], || {
+			json!({
+				"hash": header.hash().to_hex(),
+				"height": header.height,
+				"peer": addr,
+				"data": header,
+			})
+		});
+	}
+}
+
+impl PoolEvents for EventHub {
+	fn on_transaction_accepted(&self, tx: &core::Transaction) {
+		self.publish(EventType::PoolAdd, &tx_commitments(tx), || {
+			json!({
+				"hash": tx.hash().to_hex(),
+				"data": tx,
+			})
+		});
+	}
+
+	fn on_transaction_evicted(&self, tx: &core::Transaction) {
+		self.publish(EventType::PoolEvict, &tx_commitments(tx), || {
+			json!({
+				"hash": tx.hash().to_hex(),
+				"data": tx,
+			})
+		});
+	}
+}
+
+/// Server-sent events stream of the chain, network and pool events.
+/// GET /v1/events?type=block_accepted,pool_add&commitment=08c1...
+pub struct EventsHandler {
+	/// Where the events come from
+	pub hub: EventHub,
+}
+
+impl Handler for EventsHandler {
+	fn get(&self, req: Request<Body>) -> ResponseFuture {
+		let filter = match EventFilter::from_query(req.uri().query()) {
+			Ok(filter) => filter,
+			Err(e) => return api::result_to_response::<()>(Err(e)),
+		};
+		let receiver = match self.hub.subscribe(filter) {
+			Some(receiver) => receiver,
+			None => return api::response(StatusCode::SERVICE_UNAVAILABLE, "too many subscribers"),
+		};
+		let stream =
+			receiver.map_err(|_| io::Error::new(io::ErrorKind::Other, "event stream closed"));
+		let res = Response::builder()
+			.status(StatusCode::OK)
+			.header("content-type", "text/event-stream")
+			.header("cache-control", "no-cache")
+			.body(Body::wrap_stream(stream));
+		match res {
+			Ok(res) => Box::new(future::ok(res)),
+			Err(e) => api::response(StatusCode::INTERNAL_SERVER_ERROR, format!("{}", e)),
+		}
+	}
+}
+
+/// Routes of the event subscription API, to be added to the node's.
+pub fn routes(hub: &EventHub) -> Vec<(&'static str, HandlerObj)> {
+	let events_handler: HandlerObj = Arc::new(EventsHandler { hub: hub.clone() });
+	vec![("/v1/events", events_handler)]
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use futures::{Async, Future};
+
+	// polls from within a task, as the receiver wants one to notify
+	fn next_event(receiver: &mut mpsc::Receiver<Chunk>) -> Option<String> {
+		let res = future::lazy(|| Ok::<_, ()>(receiver.poll())).wait();
+		match res {
+			Ok(Ok(Async::Ready(Some(chunk)))) => Some(String::from_utf8(chunk.to_vec()).unwrap()),
+			_ => None,
+		}
+	}
+
+	#[test]
+	fn event_filters() {
+		let hub = EventHub::new();
+		let commit = Commitment::from_vec(vec![8; 33]);
+		let other = Commitment::from_vec(vec![9; 33]);
+
+		let query = format!(
+			"type=pool_add,pool_evict&commitment={}",
+			util::to_hex(vec![8; 33])
+		);
+		let filter = EventFilter::from_query(Some(&query)).unwrap();
+		assert_eq!(filter.types.len(), 2);
+		assert!(filter.commitments.contains(&commit));
+		assert!(EventFilter::from_query(Some("type=block")).is_err());
+		assert!(EventFilter::from_query(Some("commitment=zz")).is_err());
+		let short = format!("commitment={}", util::to_hex(vec![8; 32]));
+		assert!(EventFilter::from_query(Some(&short)).is_err());
+
+		let mut pool = hub.subscribe(filter).unwrap();
+		let mut all = hub.subscribe(EventFilter::default()).unwrap();
+		assert_eq!(next_event(&mut pool).unwrap(), ": subscribed\n\n");
+		assert_eq!(next_event(&mut all).unwrap(), ": subscribed\n\n");
+
+		hub.publish(EventType::PoolAdd, &[other], || json!({"n": 0}));
+		hub.publish(EventType::HeaderReceived, &[], || json!({"n": 1}));
+		hub.publish(EventType::PoolEvict, &[other, commit], || json!({"n": 2}));
+		assert_eq!(
+			next_event(&mut pool).unwrap(),
+			"id: 2\nevent: pool_evict\ndata: {\"n\":2}\n\n"
+		);
+		assert_eq!(next_event(&mut pool), None);
+		for n in 0..3 {
+			assert!(next_event(&mut all)
+				.unwrap()
+				.contains(&format!("{{\"n\":{}}}", n)));
+		}
+
+		// gone subscribers are dropped with the next event, even one they
+		// wouldn't get
+		drop(all);
+		assert_eq!(hub.subscriber_count(), 2);
+		hub.publish(EventType::HeaderReceived, &[], || json!({}));
+		assert_eq!(hub.subscriber_count(), 1);
+
+		// and before a new subscription is counted against the limit
+		let rare = EventFilter::from_query(Some(&query)).unwrap();
+		for _ in 0..MAX_SUBSCRIBERS {
+			drop(hub.subscribe(rare.clone()).unwrap());
+		}
+		assert!(hub.subscribe(rare).is_some());
+	}
+}
@@ -165,6 +165,10 @@ pub struct ServerConfig {
 	/// Whether this node is a full archival node or a fast-sync, pruned node
 	pub archive_mode: Option<bool>,
 
+	/// Checkpoints enforced on top of the ones shipped for the chain type
+	#[serde(default)]
+	pub checkpoints: Vec<CheckpointConfig>,
+
 	/// Whether to skip the sync timeout on startup
 	/// (To assist testing on solo chains)
 	pub skip_sync_wait: Option<bool>,
@@ -197,6 +201,10 @@ pub struct ServerConfig {
 	/// Configuration for the webhooks that trigger on certain events
 	#[serde(default)]
 	pub webhook_config: WebHooksConfig,
+
+	/// Configuration for bootstrapping the chain from a hypersync snapshot
+	#[serde(default)]
+	pub hypersync_config: HypersyncConfig,
 }
 
 impl Default for ServerConfig {
@@ -212,6 +220,7 @@ impl Default for ServerConfig {
 			stratum_mining_config: Some(StratumServerConfig::default()),
 			chain_type: ChainTypes::default(),
 			archive_mode: Some(false),
+			checkpoints: vec![],
 			chain_validation_mode: ChainValidationMode::default(),
 			pool_config: pool::PoolConfig::default(),
 			skip_sync_wait: Some(false),
@@ -219,10 +228,21 @@ impl Default for ServerConfig {
 			run_test_miner: Some(false),
 			test_miner_wallet_url: None,
 			webhook_config: WebHooksConfig::default(),
+			hypersync_config: HypersyncConfig::default(),
 		}
 	}
 }
 
+/// A block hash the chain is required to have at a given height, supplied by
+/// the node operator
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CheckpointConfig {
+	/// Height of the checkpoint
+	pub height: u64,
+	/// Hex encoded hash of the block at that height
+	pub hash: String,
+}
+
 /// Stratum (Mining server) configuration
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct StratumServerConfig {
@@ -233,6 +253,17 @@ pub struct StratumServerConfig {
 	/// If enabled, the address and port to listen on
 	pub stratum_server_addr: Option<String>,
 
+	/// Address and port to also listen on with the encrypted binary protocol,
+	/// only JSON-RPC is served when not set
+	#[serde(default)]
+	pub binary_stratum_server_addr: Option<String>,
+
+	/// File holding the secret key the binary protocol server authenticates
+	/// with, generated when missing. Defaults to stratum_server.key in the
+	/// chain data directory.
+	#[serde(default)]
+	pub binary_stratum_key_file: Option<String>,
+
 	/// How long to wait before stopping the miner, recollecting transactions
 	/// and starting again
 	pub attempt_time_per_block: u32,
@@ -243,9 +274,28 @@ pub struct StratumServerConfig {
 	/// Base address to the HTTP wallet receiver
 	pub wallet_listener_url: String,
 
+	/// File listing the workers allowed to log in, with their passwords and
+	/// the wallet receiver paid for the blocks they find. Any login is
+	/// accepted when not set.
+	#[serde(default)]
+	pub worker_credentials_file: Option<String>,
+
 	/// Attributes the reward to a random private key instead of contacting the
 	/// wallet receiver. Mostly used for tests.
 	pub burn_reward: bool,
+
+	/// Number of network difficulties worth of last shares the reward of a
+	/// block found is split between (PPLNS)
+	#[serde(default = "default_pplns_window")]
+	pub pplns_window: u64,
+
+	/// Adjustment of each worker's share difficulty to its hashrate
+	#[serde(default)]
+	pub vardiff_config: VardiffConfig,
+
+	/// Transactions the blocks handed out to workers are filled with
+	#[serde(default)]
+	pub template_policy_config: TemplatePolicyConfig,
 }
 
 impl Default for StratumServerConfig {
@@ -257,10 +307,99 @@ impl Default for StratumServerConfig {
 			minimum_share_difficulty: 1,
 			enable_stratum_server: Some(false),
 			stratum_server_addr: Some("127.0.0.1:8516".to_string()),
+			binary_stratum_server_addr: None,
+			binary_stratum_key_file: None,
+			worker_credentials_file: None,
+			pplns_window: default_pplns_window(),
+			vardiff_config: VardiffConfig::default(),
+			template_policy_config: TemplatePolicyConfig::default(),
+		}
+	}
+}
+
+fn default_pplns_window() -> u64 {
+	2
+}
+
+/// Variable share difficulty configuration, for workers to submit shares at
+/// about the same pace whatever their hashrate
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct VardiffConfig {
+	/// Whether to adjust the share difficulty of each worker, all of them
+	/// mining at `minimum_share_difficulty` otherwise. Off by default.
+	#[serde(default)]
+	pub enable_vardiff: bool,
+	/// Number of seconds we'd like between two shares of a worker
+	#[serde(default = "default_target_share_time")]
+	pub target_share_time: u64,
+	/// Number of seconds the share rate of a worker is observed over before
+	/// adjusting its difficulty
+	#[serde(default = "default_retarget_time")]
+	pub retarget_time: u64,
+}
+
+impl Default for VardiffConfig {
+	fn default() -> VardiffConfig {
+		VardiffConfig {
+			enable_vardiff: false,
+			target_share_time: default_target_share_time(),
+			retarget_time: default_retarget_time(),
 		}
 	}
 }
 
+fn default_target_share_time() -> u64 {
+	10
+}
+
+fn default_retarget_time() -> u64 {
+	120
+}
+
+/// Policy picking the transactions of the blocks handed out to workers
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub enum TemplatePolicyType {
+	/// As many transactions as fit, best fees first
+	MaxFees,
+	/// No transaction for `empty_block_seconds` after a new tip, then as
+	/// many as fit
+	EmptyAfterTip,
+}
+
+/// Block template policy configuration
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct TemplatePolicyConfig {
+	/// How transactions are picked
+	#[serde(default = "default_template_policy")]
+	pub template_policy: TemplatePolicyType,
+	/// Number of seconds blocks stay empty after a new tip, with
+	/// `EmptyAfterTip`
+	#[serde(default = "default_empty_block_seconds")]
+	pub empty_block_seconds: u64,
+	/// Excesses of the kernels whose transactions are never included, hex
+	/// encoded
+	#[serde(default)]
+	pub excluded_kernels: Vec<String>,
+}
+
+impl Default for TemplatePolicyConfig {
+	fn default() -> TemplatePolicyConfig {
+		TemplatePolicyConfig {
+			template_policy: default_template_policy(),
+			empty_block_seconds: default_empty_block_seconds(),
+			excluded_kernels: vec![],
+		}
+	}
+}
+
+fn default_template_policy() -> TemplatePolicyType {
+	TemplatePolicyType::MaxFees
+}
+
+fn default_empty_block_seconds() -> u64 {
+	5
+}
+
 /// Web hooks configuration
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct WebHooksConfig {
@@ -278,6 +417,20 @@ pub struct WebHooksConfig {
 	/// timeout in seconds for the http request
 	#[serde(default = "default_timeout")]
 	pub timeout: u16,
+	/// secret the requests are signed with, in the X-BitGrin-Signature
+	/// header, when provided
+	#[serde(default)]
+	pub secret: Option<String>,
+	/// longest time in seconds between two attempts of a failed request
+	#[serde(default = "default_max_retry_interval")]
+	pub max_retry_interval: u64,
+	/// attempts after which a request is given up on, retried forever when 0
+	#[serde(default = "default_max_attempts")]
+	pub max_attempts: u32,
+	/// requests kept for each url while it doesn't respond, the oldest ones
+	/// being dropped past it, no limit when 0
+	#[serde(default = "default_max_pending")]
+	pub max_pending: u64,
 }
 
 fn default_timeout() -> u16 {
@@ -288,6 +441,28 @@ fn default_nthreads() -> u16 {
 	4
 }
 
+fn default_max_retry_interval() -> u64 {
+	600
+}
+
+fn default_max_attempts() -> u32 {
+	20
+}
+
+fn default_max_pending() -> u64 {
+	10_000
+}
+
+impl WebHooksConfig {
+	/// Whether any webhook url is configured.
+	pub fn is_enabled(&self) -> bool {
+		self.tx_received_url.is_some()
+			|| self.header_received_url.is_some()
+			|| self.block_received_url.is_some()
+			|| self.block_accepted_url.is_some()
+	}
+}
+
 impl Default for WebHooksConfig {
 	fn default() -> WebHooksConfig {
 		WebHooksConfig {
@@ -297,6 +472,10 @@ impl Default for WebHooksConfig {
 			block_accepted_url: None,
 			nthreads: default_nthreads(),
 			timeout: default_timeout(),
+			secret: None,
+			max_retry_interval: default_max_retry_interval(),
+			max_attempts: default_max_attempts(),
+			max_pending: default_max_pending(),
 		}
 	}
 }
@@ -556,3 +735,27 @@ impl DandelionEpoch {
 		self.relay_peer.clone()
 	}
 }
+
+/// Hypersync (chain snapshot bootstrap) configuration
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct HypersyncConfig {
+	/// Bootstrap an empty node from a snapshot before syncing with peers
+	pub enable_hypersync: bool,
+	/// Base urls the manifest and archive are downloaded from, tried in order
+	pub mirrors: Vec<String>,
+	/// Name of the snapshot manifest on the mirrors
+	pub manifest_name: String,
+	/// Expected blake2b digest of the manifest, if pinned
+	pub manifest_digest: Option<String>,
+}
+
+impl Default for HypersyncConfig {
+	fn default() -> HypersyncConfig {
+		HypersyncConfig {
+			enable_hypersync: true,
+			mirrors: vec!["https://d1joz5daoz8ntk.cloudfront.net".to_string()],
+			manifest_name: "bg_chain_data.manifest.json".to_string(),
+			manifest_digest: None,
+		}
+	}
+}
@@ -40,5 +40,11 @@ mod bitgrin;
 mod mining;
 
 pub use crate::common::stats::{DiffBlock, PeerStats, ServerStats, StratumStats, WorkerStats};
-pub use crate::common::types::{ServerConfig, StratumServerConfig};
+pub use crate::common::types::{
+	CheckpointConfig, HypersyncConfig, ServerConfig, StratumServerConfig, TemplatePolicyConfig,
+	TemplatePolicyType, VardiffConfig,
+};
+pub use crate::bitgrin::checkpoint::{header_consensus, HeaderConsensus};
+pub use crate::bitgrin::seed::dns_seeds;
 pub use crate::bitgrin::server::Server;
+pub use crate::mining::stratum_binary;
@@ -15,5 +15,11 @@
 //! Mining + Mining server
 
 mod mine_block;
+pub mod share_ledger;
+pub mod stratum_api;
+pub mod stratum_binary;
 pub mod stratumserver;
+pub mod template_policy;
 pub mod test_miner;
+mod vardiff;
+mod worker_credentials;
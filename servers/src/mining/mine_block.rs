@@ -32,6 +32,7 @@ use crate::core::libtx::secp_ser;
 use crate::core::libtx::ProofBuilder;
 use crate::core::{consensus, core, global};
 use crate::keychain::{ExtKeychain, Identifier, Keychain};
+use crate::mining::template_policy::{TemplateInfo, TemplatePolicy};
 use crate::pool;
 
 /// Fees in block to use for coinbase amount calculation
@@ -66,7 +67,8 @@ pub struct CbData {
 	pub key_id: Option<Identifier>,
 }
 
-// Ensure a block suitable for mining is built and returned
+// Ensure a block suitable for mining is built and returned, with
+// transactions picked by the provided policy given the age of the tip
 // If a wallet listener URL is not provided the reward will be "burnt"
 // Warning: This call does not return until/unless a new block can be built
 pub fn get_block(
@@ -75,7 +77,9 @@ pub fn get_block(
 	verifier_cache: Arc<RwLock<dyn VerifierCache>>,
 	key_id: Option<Identifier>,
 	wallet_listener_url: Option<String>,
-) -> (core::Block, BlockFees) {
+	policy: &dyn TemplatePolicy,
+	tip_age: u64,
+) -> (core::Block, BlockFees, TemplateInfo) {
 	let wallet_retry_interval = 5;
 	// get the latest chain state and build a block on top of it
 	let mut result = build_block(
@@ -84,6 +88,8 @@ pub fn get_block(
 		verifier_cache.clone(),
 		key_id.clone(),
 		wallet_listener_url.clone(),
+		policy,
+		tip_age,
 	);
 	while let Err(e) = result {
 		let mut new_key_id = key_id.to_owned();
@@ -124,20 +130,68 @@ pub fn get_block(
 			verifier_cache.clone(),
 			new_key_id,
 			wallet_listener_url.clone(),
+			policy,
+			tip_age,
 		);
 	}
 	return result.unwrap();
 }
 
+/// Same as `get_block`, giving up after the given number of attempts or as
+/// soon as the wallet listener can't be reached, rather than waiting for it.
+/// Uses the verifier cache of the pool.
+pub fn try_get_block(
+	chain: &Arc<chain::Chain>,
+	tx_pool: &Arc<RwLock<pool::TransactionPool>>,
+	key_id: Option<Identifier>,
+	wallet_listener_url: Option<String>,
+	policy: &dyn TemplatePolicy,
+	tip_age: u64,
+	attempts: usize,
+) -> Option<(core::Block, BlockFees, TemplateInfo)> {
+	let verifier_cache = tx_pool.read().verifier_cache.clone();
+	let mut key_id = key_id;
+	for _ in 0..attempts {
+		let result = build_block(
+			chain,
+			tx_pool,
+			verifier_cache.clone(),
+			key_id.clone(),
+			wallet_listener_url.clone(),
+			policy,
+			tip_age,
+		);
+		match result {
+			Ok(res) => return Some(res),
+			Err(self::Error::Chain(ref c)) => match c.kind() {
+				// use the next available key to generate a different coinbase commitment
+				chain::ErrorKind::DuplicateCommitment(_) => key_id = None,
+				_ => error!("Chain Error: {}", c),
+			},
+			Err(self::Error::WalletComm(_)) => {
+				error!(
+					"Error building new block: Can't connect to wallet listener at {:?}",
+					wallet_listener_url.as_ref().unwrap()
+				);
+				return None;
+			}
+			Err(e) => warn!("Error building new block: {:?}.", e),
+		}
+	}
+	None
+}
+
 /// Builds a new block with the chain head as previous and eligible
-/// transactions from the pool.
+/// transactions from the pool, picked by the provided policy.
 fn build_block(
 	chain: &Arc<chain::Chain>,
 	tx_pool: &Arc<RwLock<pool::TransactionPool>>,
 	verifier_cache: Arc<RwLock<dyn VerifierCache>>,
 	key_id: Option<Identifier>,
 	wallet_listener_url: Option<String>,
-) -> Result<(core::Block, BlockFees), Error> {
+	policy: &dyn TemplatePolicy,
+	tip_age: u64,
+) -> Result<(core::Block, BlockFees, TemplateInfo), Error> {
 	let head = chain.head_header()?;
 
 	// prepare the block header timestamp
@@ -155,15 +209,19 @@ fn build_block(
 	// If this fails for *any* reason then fallback to an empty vec of txs.
 	// This will allow us to mine an "empty" block if the txpool is in an
 	// invalid (and unexpected) state.
-	let txs = match tx_pool.read().prepare_mineable_transactions() {
-		Ok(txs) => txs,
+	let (txs, info) = match policy.select(&tx_pool.read(), tip_age) {
+		Ok(selection) => selection,
 		Err(e) => {
 			error!(
 				"build_block: Failed to prepare mineable txs from txpool: {:?}",
 				e
 			);
 			warn!("build_block: Falling back to mining empty block.");
-			vec![]
+			let info = TemplateInfo {
+				policy: "empty".to_owned(),
+				expires_in: None,
+			};
+			(vec![], info)
 		}
 	};
 
@@ -196,7 +254,7 @@ fn build_block(
 
 	// Now set txhashset roots and sizes on the header of the block being built.
 	match chain.set_txhashset_roots(&mut b) {
-		Ok(_) => Ok((b, block_fees)),
+		Ok(_) => Ok((b, block_fees, info)),
 		Err(e) => {
 			match e.kind() {
 				// If this is a duplicate commitment then likely trying to use
@@ -0,0 +1,486 @@
+// Copyright 2018 The BitGrin Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Ledger of the shares accepted by the stratum server and of the blocks
+//! they found. Once a block matures, its reward is split between the workers
+//! of the last shares before it (PPLNS, pay per last N shares).
+
+use std::collections::BTreeMap;
+
+use chrono::prelude::Utc;
+
+use crate::core::core::hash::Hash;
+use crate::core::global;
+use crate::core::ser::{self, read_string, Readable, Reader, Writeable, Writer};
+use crate::store::{self, option_to_not_found, to_key, u64_to_key, Error};
+
+const DB_NAME: &str = "stratum";
+const STORE_SUBPATH: &str = "stratum";
+
+const SHARE_PREFIX: u8 = 'S' as u8;
+const BLOCK_PREFIX: u8 = 'B' as u8;
+const BLOCK_HASH_PREFIX: u8 = 'H' as u8;
+const UNPAID_BLOCK_PREFIX: u8 = 'U' as u8;
+const NEXT_SHARE_ID_KEY: u8 = 'N' as u8;
+
+/// A share accepted from a worker.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Share {
+	/// Sequence number of the share in the ledger
+	pub id: u64,
+	/// Login of the worker, its connection id if it didn't log in
+	pub worker: String,
+	/// Share difficulty the worker was mining at
+	pub difficulty: u64,
+	/// Height of the block mined
+	pub height: u64,
+	/// When the share was accepted
+	pub timestamp: i64,
+}
+
+impl Writeable for Share {
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), ser::Error> {
+		writer.write_u64(self.id)?;
+		writer.write_bytes(&self.worker)?;
+		writer.write_u64(self.difficulty)?;
+		writer.write_u64(self.height)?;
+		writer.write_i64(self.timestamp)
+	}
+}
+
+impl Readable for Share {
+	fn read(reader: &mut dyn Reader) -> Result<Share, ser::Error> {
+		let id = reader.read_u64()?;
+		let worker = read_string(reader)?;
+		let difficulty = reader.read_u64()?;
+		let height = reader.read_u64()?;
+		let timestamp = reader.read_i64()?;
+		Ok(Share {
+			id,
+			worker,
+			difficulty,
+			height,
+			timestamp,
+		})
+	}
+}
+
+/// Whether the reward of a pool block can be paid yet.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum PoolBlockState {
+	/// The coinbase can't be spent yet
+	Immature = 0,
+	/// The coinbase is spendable, payouts are known
+	Matured = 1,
+	/// The block was reorganized out of the chain
+	Orphaned = 2,
+}
+
+impl PoolBlockState {
+	fn from_u8(state: u8) -> Option<PoolBlockState> {
+		match state {
+			0 => Some(PoolBlockState::Immature),
+			1 => Some(PoolBlockState::Matured),
+			2 => Some(PoolBlockState::Orphaned),
+			_ => None,
+		}
+	}
+}
+
+/// Amount of a block reward owed to a worker.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WorkerPayout {
+	/// Login of the worker
+	pub worker: String,
+	/// Total difficulty of the worker's shares in the PPLNS window
+	pub difficulty: u64,
+	/// Part of the reward owed to the worker
+	pub amount: u64,
+}
+
+/// A block found by one of our workers.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PoolBlock {
+	/// Height of the block
+	pub height: u64,
+	/// Hash of the block
+	pub hash: Hash,
+	/// Id of the share that found the block
+	pub share_id: u64,
+	/// Worker that found the block
+	pub worker: String,
+	/// Network difficulty of the block
+	pub network_difficulty: u64,
+	/// Block reward, fees included
+	pub reward: u64,
+	/// When the block was found
+	pub timestamp: i64,
+	/// Whether the reward can be paid yet
+	pub state: PoolBlockState,
+	/// Reward split between the workers, once matured
+	pub payouts: Vec<WorkerPayout>,
+}
+
+impl Writeable for PoolBlock {
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), ser::Error> {
+		writer.write_u64(self.height)?;
+		self.hash.write(writer)?;
+		writer.write_u64(self.share_id)?;
+		writer.write_bytes(&self.worker)?;
+		writer.write_u64(self.network_difficulty)?;
+		writer.write_u64(self.reward)?;
+		writer.write_i64(self.timestamp)?;
+		writer.write_u8(self.state as u8)?;
+		writer.write_u64(self.payouts.len() as u64)?;
+		for payout in &self.payouts {
+			writer.write_bytes(&payout.worker)?;
+			writer.write_u64(payout.difficulty)?;
+			writer.write_u64(payout.amount)?;
+		}
+		Ok(())
+	}
+}
+
+impl Readable for PoolBlock {
+	fn read(reader: &mut dyn Reader) -> Result<PoolBlock, ser::Error> {
+		let height = reader.read_u64()?;
+		let hash = Hash::read(reader)?;
+		let share_id = reader.read_u64()?;
+		let worker = read_string(reader)?;
+		let network_difficulty = reader.read_u64()?;
+		let reward = reader.read_u64()?;
+		let timestamp = reader.read_i64()?;
+		let state = PoolBlockState::from_u8(reader.read_u8()?).ok_or(ser::Error::CorruptedData)?;
+		let count = reader.read_u64()?;
+		let mut payouts = vec![];
+		for _ in 0..count {
+			let worker = read_string(reader)?;
+			let difficulty = reader.read_u64()?;
+			let amount = reader.read_u64()?;
+			payouts.push(WorkerPayout {
+				worker,
+				difficulty,
+				amount,
+			});
+		}
+		Ok(PoolBlock {
+			height,
+			hash,
+			share_id,
+			worker,
+			network_difficulty,
+			reward,
+			timestamp,
+			state,
+			payouts,
+		})
+	}
+}
+
+/// Splits `reward` between the workers of the shares in the PPLNS window.
+/// `shares` go backwards from the share that found the block, each of them
+/// weighing its difficulty, until they add up to `window`. The last share
+/// only counts for what fits in the window, and rounding leftovers go to
+/// the worker that found the block.
+pub fn pplns<I>(shares: I, window: u64, reward: u64) -> Vec<WorkerPayout>
+where
+	I: IntoIterator<Item = Share>,
+{
+	let mut by_worker: BTreeMap<String, u64> = BTreeMap::new();
+	let mut finder = None;
+	let mut total = 0;
+	for share in shares {
+		if total >= window {
+			break;
+		}
+		let difficulty = share.difficulty.min(window - total);
+		total += difficulty;
+		if finder.is_none() {
+			finder = Some(share.worker.clone());
+		}
+		*by_worker.entry(share.worker).or_insert(0) += difficulty;
+	}
+	if total == 0 {
+		return vec![];
+	}
+
+	let mut payouts: Vec<WorkerPayout> = by_worker
+		.into_iter()
+		.map(|(worker, difficulty)| WorkerPayout {
+			amount: (reward as u128 * difficulty as u128 / total as u128) as u64,
+			worker,
+			difficulty,
+		})
+		.collect();
+	let paid: u64 = payouts.iter().map(|p| p.amount).sum();
+	if let Some(finder) = payouts
+		.iter_mut()
+		.find(|p| Some(&p.worker) == finder.as_ref())
+	{
+		finder.amount += reward - paid;
+	}
+	payouts
+}
+
+/// Storage of the stratum shares and pool blocks.
+pub struct ShareLedger {
+	db: store::Store,
+	window_factor: u64,
+}
+
+impl ShareLedger {
+	/// Opens the ledger under the provided root path. Blocks are paid to
+	/// the shares of the last `window_factor` times their difficulty.
+	pub fn new(db_root: &str, window_factor: u64) -> Result<ShareLedger, Error> {
+		let db = store::Store::new(db_root, Some(DB_NAME), Some(STORE_SUBPATH), None)?;
+		Ok(ShareLedger { db, window_factor })
+	}
+
+	/// Records a share accepted from a worker, returning it.
+	pub fn add_share(&self, worker: &str, difficulty: u64, height: u64) -> Result<Share, Error> {
+		let batch = self.db.batch()?;
+		let key = to_key(NEXT_SHARE_ID_KEY, &mut vec![]);
+		let id = batch.get_ser::<u64>(&key)?.unwrap_or(0);
+		let share = Share {
+			id,
+			worker: worker.to_owned(),
+			difficulty,
+			height,
+			timestamp: Utc::now().timestamp(),
+		};
+		batch.put_ser(&u64_to_key(SHARE_PREFIX, id), &share)?;
+		batch.put_ser(&key, &(id + 1))?;
+		batch.commit()?;
+		Ok(share)
+	}
+
+	/// Share of the given id.
+	pub fn get_share(&self, id: u64) -> Result<Share, Error> {
+		option_to_not_found(
+			self.db.get_ser(&u64_to_key(SHARE_PREFIX, id)),
+			&format!("Share {}", id),
+		)
+	}
+
+	/// Records a block found by the share of the given id.
+	pub fn add_block(
+		&self,
+		share: &Share,
+		hash: Hash,
+		network_difficulty: u64,
+		reward: u64,
+	) -> Result<(), Error> {
+		let block = PoolBlock {
+			height: share.height,
+			hash,
+			share_id: share.id,
+			worker: share.worker.clone(),
+			network_difficulty,
+			reward,
+			timestamp: share.timestamp,
+			state: PoolBlockState::Immature,
+			payouts: vec![],
+		};
+		// the blocks not paid yet are also kept apart, by height, for the
+		// payouts not to go through all the blocks ever found
+		let batch = self.db.batch()?;
+		batch.put_ser(&block_key(BLOCK_PREFIX, block.height, &block.hash), &block)?;
+		batch.put_ser(
+			&block_key(UNPAID_BLOCK_PREFIX, block.height, &block.hash),
+			&block,
+		)?;
+		batch.put_ser(
+			&to_key(BLOCK_HASH_PREFIX, &mut block.hash.to_vec()),
+			&block.height,
+		)?;
+		batch.commit()
+	}
+
+	/// Block found with the given hash.
+	pub fn get_block(&self, hash: &Hash) -> Result<PoolBlock, Error> {
+		let not_found = format!("Pool block {}", hash);
+		let height: u64 = option_to_not_found(
+			self.db
+				.get_ser(&to_key(BLOCK_HASH_PREFIX, &mut hash.to_vec())),
+			&not_found,
+		)?;
+		option_to_not_found(
+			self.db.get_ser(&block_key(BLOCK_PREFIX, height, hash)),
+			&not_found,
+		)
+	}
+
+	/// All the blocks found, by height.
+	pub fn blocks(&self) -> Result<Vec<PoolBlock>, Error> {
+		let key = to_key(BLOCK_PREFIX, &mut vec![]);
+		Ok(self.db.iter::<PoolBlock>(&key)?.map(|(_, b)| b).collect())
+	}
+
+	/// Computes the payouts of the blocks whose coinbase is spendable at
+	/// `head_height`. `chain_hash` gives the hash of the block at a height
+	/// on our chain, to tell orphaned blocks apart.
+	pub fn pay_matured<F>(&self, head_height: u64, chain_hash: F) -> Result<Vec<PoolBlock>, Error>
+	where
+		F: Fn(u64) -> Option<Hash>,
+	{
+		let maturity = global::coinbase_maturity();
+		let key = to_key(UNPAID_BLOCK_PREFIX, &mut vec![]);
+		let matured: Vec<PoolBlock> = self
+			.db
+			.iter::<PoolBlock>(&key)?
+			.map(|(_, b)| b)
+			.take_while(|b| b.height + maturity <= head_height)
+			.collect();
+		if matured.is_empty() {
+			return Ok(vec![]);
+		}
+
+		let batch = self.db.batch()?;
+		let mut paid = vec![];
+		for mut block in matured {
+			if chain_hash(block.height) == Some(block.hash) {
+				let shares = (0..=block.share_id)
+					.rev()
+					.map(|id| batch.get_ser::<Share>(&u64_to_key(SHARE_PREFIX, id)))
+					.take_while(|share| match share {
+						Ok(Some(_)) => true,
+						_ => false,
+					})
+					.filter_map(|share| share.ok().and_then(|s| s));
+				let window = block.network_difficulty.saturating_mul(self.window_factor);
+				block.payouts = pplns(shares, window, block.reward);
+				block.state = PoolBlockState::Matured;
+			} else {
+				block.state = PoolBlockState::Orphaned;
+			}
+			batch.put_ser(&block_key(BLOCK_PREFIX, block.height, &block.hash), &block)?;
+			batch.delete(&block_key(UNPAID_BLOCK_PREFIX, block.height, &block.hash))?;
+			paid.push(block);
+		}
+		batch.commit()?;
+		Ok(paid)
+	}
+}
+
+fn block_key(prefix: u8, height: u64, hash: &Hash) -> Vec<u8> {
+	let mut key = u64_to_key(prefix, height);
+	key.extend_from_slice(hash.as_bytes());
+	key
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	fn share(id: u64, worker: &str, difficulty: u64) -> Share {
+		Share {
+			id,
+			worker: worker.to_owned(),
+			difficulty,
+			height: 10,
+			timestamp: 0,
+		}
+	}
+
+	#[test]
+	fn pplns_payouts() {
+		// newest first, bob found the block
+		let shares = vec![
+			share(5, "bob", 10),
+			share(4, "alice", 20),
+			share(3, "bob", 10),
+			share(2, "carol", 40),
+			share(1, "carol", 100),
+		];
+
+		// carol's last share only counts for 20
+		let payouts = pplns(shares.clone(), 60, 1_000);
+		assert_eq!(
+			payouts,
+			vec![
+				WorkerPayout {
+					worker: "alice".to_owned(),
+					difficulty: 20,
+					amount: 333,
+				},
+				WorkerPayout {
+					worker: "bob".to_owned(),
+					difficulty: 20,
+					amount: 334,
+				},
+				WorkerPayout {
+					worker: "carol".to_owned(),
+					difficulty: 20,
+					amount: 333,
+				},
+			]
+		);
+
+		// not enough shares to fill the window
+		let payouts = pplns(shares.clone(), 1_000, 180);
+		let amounts: Vec<u64> = payouts.iter().map(|p| p.amount).collect();
+		assert_eq!(amounts, vec![20, 20, 140]);
+
+		assert!(pplns(vec![], 100, 1_000).is_empty());
+		assert!(pplns(shares, 0, 1_000).is_empty());
+	}
+
+	#[test]
+	fn pay_matured_blocks() {
+		global::set_mining_mode(global::ChainTypes::AutomatedTesting);
+		let db_root = "test_output/share_ledger";
+		let _ = std::fs::remove_dir_all(db_root);
+		let ledger = ShareLedger::new(db_root, 2).unwrap();
+
+		ledger.add_share("alice", 10, 10).unwrap();
+		ledger.add_share("bob", 10, 10).unwrap();
+		let found = ledger.add_share("alice", 10, 10).unwrap();
+		assert_eq!(ledger.get_share(found.id).unwrap(), found);
+		let orphan = ledger.add_share("bob", 10, 11).unwrap();
+
+		let hash = Hash::from_vec(&[1; 32]);
+		let orphan_hash = Hash::from_vec(&[2; 32]);
+		ledger.add_block(&found, hash, 15, 900).unwrap();
+		ledger.add_block(&orphan, orphan_hash, 10, 900).unwrap();
+		let chain_hash = |height| if height == 10 { Some(hash) } else { None };
+
+		// not matured yet
+		assert!(ledger.pay_matured(12, chain_hash).unwrap().is_empty());
+		assert_eq!(ledger.blocks().unwrap().len(), 2);
+
+		let paid = ledger.pay_matured(13, chain_hash).unwrap();
+		assert_eq!(paid.len(), 1);
+		let block = ledger.get_block(&hash).unwrap();
+		assert_eq!(block.state, PoolBlockState::Matured);
+		assert!(ledger.get_block(&Hash::from_vec(&[3; 32])).is_err());
+		let amounts: Vec<(String, u64)> = block
+			.payouts
+			.iter()
+			.map(|p| (p.worker.clone(), p.amount))
+			.collect();
+		assert_eq!(
+			amounts,
+			vec![("alice".to_owned(), 600), ("bob".to_owned(), 300)]
+		);
+
+		// paid only once, the other one isn't on our chain
+		let paid = ledger.pay_matured(20, chain_hash).unwrap();
+		assert_eq!(paid.len(), 1);
+		assert_eq!(paid[0].state, PoolBlockState::Orphaned);
+		assert!(paid[0].payouts.is_empty());
+		assert!(ledger.pay_matured(20, chain_hash).unwrap().is_empty());
+
+		let _ = std::fs::remove_dir_all(db_root);
+	}
+}
@@ -0,0 +1,78 @@
+// Copyright 2018 The BitGrin Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! API endpoints of the stratum server, served along the node's own.
+
+use std::sync::{Arc, Weak};
+
+use hyper::{Body, Request};
+
+use crate::api::{self, ErrorKind, Handler, HandlerObj, ResponseFuture};
+use crate::core::core::hash::Hash;
+use crate::mining::share_ledger::{PoolBlock, ShareLedger};
+use crate::store;
+
+/// Blocks found by the stratum workers, with the payouts of the matured
+/// ones.
+/// GET /v1/stratum/blocks
+/// GET /v1/stratum/blocks/<hash>
+pub struct PoolBlocksHandler {
+	pub ledger: Weak<ShareLedger>,
+}
+
+impl PoolBlocksHandler {
+	fn ledger(&self) -> Result<Arc<ShareLedger>, api::Error> {
+		self.ledger
+			.upgrade()
+			.ok_or_else(|| ErrorKind::Internal("failed to get share ledger".to_owned()).into())
+	}
+
+	fn get_blocks(&self) -> Result<Vec<PoolBlock>, api::Error> {
+		self.ledger()?.blocks().map_err(store_error)
+	}
+
+	fn get_block(&self, hash: &str) -> Result<PoolBlock, api::Error> {
+		let hash = Hash::from_hex(hash)
+			.map_err(|e| ErrorKind::Argument(format!("invalid block hash {}: {}", hash, e)))?;
+		self.ledger()?.get_block(&hash).map_err(store_error)
+	}
+}
+
+fn store_error(e: store::Error) -> api::Error {
+	match e {
+		store::Error::NotFoundErr(_) => ErrorKind::NotFound.into(),
+		e => ErrorKind::Internal(format!("share ledger error: {}", e)).into(),
+	}
+}
+
+impl Handler for PoolBlocksHandler {
+	fn get(&self, req: Request<Body>) -> ResponseFuture {
+		let path = req.uri().path().trim_end_matches('/');
+		match path.rsplit('/').next() {
+			Some("blocks") | None => api::result_to_response(self.get_blocks()),
+			Some(hash) => api::result_to_response(self.get_block(hash)),
+		}
+	}
+}
+
+/// Routes of the stratum server API, to be added to the node's.
+pub fn routes(ledger: &Arc<ShareLedger>) -> Vec<(&'static str, HandlerObj)> {
+	let blocks_handler: HandlerObj = Arc::new(PoolBlocksHandler {
+		ledger: Arc::downgrade(ledger),
+	});
+	vec![
+		("/v1/stratum/blocks", blocks_handler.clone()),
+		("/v1/stratum/blocks/*", blocks_handler),
+	]
+}
@@ -0,0 +1,749 @@
+// Copyright 2018 The BitGrin Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Binary stratum protocol, for miners on networks they don't trust. It
+//! carries the same requests as the JSON-RPC one, serialized with `core::ser`,
+//! in frames encrypted after a Noise handshake authenticating the server:
+//!
+//! ```text
+//! Noise_NX_secp256k1_ChaChaPoly_SHA256
+//!   -> e
+//!   <- e, ee, s, es
+//! ```
+//!
+//! Every frame, handshake ones included, is a 2 bytes big endian length
+//! followed by that many bytes. Miners should pin the server's public key,
+//! logged when the server starts, so nobody in between can impersonate it.
+
+use std::fs::{self, OpenOptions};
+use std::io::{self, Read, Write};
+#[cfg(unix)]
+use std::os::unix::fs::OpenOptionsExt;
+
+use ring::aead;
+use ring::digest;
+use ring::hmac;
+use ring::rand::{SecureRandom, SystemRandom};
+
+use crate::core::ser::{self, read_string, Readable, Reader, Writeable, Writer};
+use crate::util::secp::ecdh::SharedSecret;
+use crate::util::secp::key::{PublicKey, SecretKey};
+use crate::util::{self, static_secp_instance};
+
+const PROTOCOL_NAME: &[u8] = b"Noise_NX_secp256k1_ChaChaPoly_SHA256";
+const PROLOGUE: &[u8] = b"bitgrin-stratum-1";
+
+const HASH_LEN: usize = 32;
+const TAG_LEN: usize = 16;
+const PUBLIC_KEY_LEN: usize = 33;
+const MAX_FRAME_LEN: usize = 65535;
+
+/// Most nonces a submitted proof of work can have.
+const MAX_POW_NONCES: u64 = 64;
+
+/// A secp256k1 key pair, the server's static one or an ephemeral one.
+pub struct Keypair {
+	secret: SecretKey,
+	public: PublicKey,
+}
+
+impl Keypair {
+	/// Generates a new random key pair.
+	pub fn generate() -> io::Result<Keypair> {
+		let rng = SystemRandom::new();
+		loop {
+			let mut secret = [0; 32];
+			rng.fill(&mut secret)
+				.map_err(|_| io::Error::new(io::ErrorKind::Other, "no randomness"))?;
+			// almost all 32 bytes values are valid keys
+			if let Ok(keypair) = Keypair::from_secret(&secret) {
+				return Ok(keypair);
+			}
+		}
+	}
+
+	/// Key pair of the provided secret key.
+	pub fn from_secret(secret: &[u8]) -> io::Result<Keypair> {
+		let secp = static_secp_instance();
+		let secp = secp.lock();
+		let secret = SecretKey::from_slice(&secp, secret).map_err(invalid_data)?;
+		let public = PublicKey::from_secret_key(&secp, &secret).map_err(invalid_data)?;
+		Ok(Keypair { secret, public })
+	}
+
+	/// Reads the hex encoded secret key in the provided file, generating and
+	/// saving a new one first if the file doesn't exist. The file is only
+	/// readable by its owner.
+	pub fn load_or_generate(path: &str) -> io::Result<Keypair> {
+		let mut options = OpenOptions::new();
+		options.write(true).create_new(true);
+		#[cfg(unix)]
+		options.mode(0o600);
+		match options.open(path) {
+			Ok(mut file) => {
+				let keypair = Keypair::generate()?;
+				file.write_all(util::to_hex(keypair.secret.0.to_vec()).as_bytes())?;
+				file.sync_all()?;
+				return Ok(keypair);
+			}
+			Err(ref e) if e.kind() == io::ErrorKind::AlreadyExists => {}
+			Err(e) => return Err(e),
+		}
+		let content = fs::read_to_string(path)?;
+		let secret = util::from_hex(content.trim().to_owned()).map_err(invalid_data)?;
+		Keypair::from_secret(&secret)
+	}
+
+	/// Public key of the pair.
+	pub fn public_key(&self) -> &PublicKey {
+		&self.public
+	}
+
+	fn dh(&self, public: &PublicKey) -> [u8; HASH_LEN] {
+		let secp = static_secp_instance();
+		let secp = secp.lock();
+		let shared = SharedSecret::new(&secp, public, &self.secret);
+		let mut out = [0; HASH_LEN];
+		out.copy_from_slice(&shared[..]);
+		out
+	}
+}
+
+/// Compressed serialization of a public key.
+pub fn public_key_to_bytes(public: &PublicKey) -> Vec<u8> {
+	let secp = static_secp_instance();
+	let secp = secp.lock();
+	public.serialize_vec(&secp, true).to_vec()
+}
+
+/// Public key from its compressed serialization.
+pub fn public_key_from_bytes(bytes: &[u8]) -> io::Result<PublicKey> {
+	let secp = static_secp_instance();
+	let secp = secp.lock();
+	PublicKey::from_slice(&secp, bytes).map_err(invalid_data)
+}
+
+fn invalid_data<E: std::fmt::Debug>(e: E) -> io::Error {
+	io::Error::new(io::ErrorKind::InvalidData, format!("{:?}", e))
+}
+
+// ----------------------------------------
+// Noise
+
+/// ChaCha20-Poly1305 key with its message counter.
+struct CipherState {
+	key: Option<[u8; HASH_LEN]>,
+	nonce: u64,
+}
+
+impl CipherState {
+	fn new(key: Option<[u8; HASH_LEN]>) -> CipherState {
+		CipherState { key, nonce: 0 }
+	}
+
+	// 32 bits of zeros followed by the little endian counter
+	fn next_nonce(&mut self) -> io::Result<[u8; 12]> {
+		if self.nonce == u64::MAX {
+			return Err(io::Error::new(io::ErrorKind::Other, "nonces exhausted"));
+		}
+		let mut nonce = [0; 12];
+		nonce[4..].copy_from_slice(&self.nonce.to_le_bytes());
+		self.nonce += 1;
+		Ok(nonce)
+	}
+
+	fn encrypt(&mut self, ad: &[u8], plaintext: &[u8]) -> io::Result<Vec<u8>> {
+		let key = match self.key {
+			Some(key) => key,
+			None => return Ok(plaintext.to_vec()),
+		};
+		let nonce = self.next_nonce()?;
+		let key = aead::SealingKey::new(&aead::CHACHA20_POLY1305, &key).map_err(invalid_data)?;
+		let mut in_out = plaintext.to_vec();
+		in_out.extend_from_slice(&[0; TAG_LEN]);
+		let len =
+			aead::seal_in_place(&key, &nonce, ad, &mut in_out, TAG_LEN).map_err(invalid_data)?;
+		in_out.truncate(len);
+		Ok(in_out)
+	}
+
+	fn decrypt(&mut self, ad: &[u8], ciphertext: &[u8]) -> io::Result<Vec<u8>> {
+		let key = match self.key {
+			Some(key) => key,
+			None => return Ok(ciphertext.to_vec()),
+		};
+		let nonce = self.next_nonce()?;
+		let key = aead::OpeningKey::new(&aead::CHACHA20_POLY1305, &key).map_err(invalid_data)?;
+		let mut in_out = ciphertext.to_vec();
+		let len = aead::open_in_place(&key, &nonce, ad, 0, &mut in_out)
+			.map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "decryption failed"))?
+			.len();
+		in_out.truncate(len);
+		Ok(in_out)
+	}
+}
+
+/// Chaining key and handshake hash, mixing in everything exchanged.
+struct SymmetricState {
+	ck: [u8; HASH_LEN],
+	h: [u8; HASH_LEN],
+	cipher: CipherState,
+}
+
+impl SymmetricState {
+	fn new() -> SymmetricState {
+		let mut h = [0; HASH_LEN];
+		h.copy_from_slice(digest::digest(&digest::SHA256, PROTOCOL_NAME).as_ref());
+		let mut state = SymmetricState {
+			ck: h,
+			h,
+			cipher: CipherState::new(None),
+		};
+		state.mix_hash(PROLOGUE);
+		state
+	}
+
+	fn mix_hash(&mut self, data: &[u8]) {
+		let mut ctx = digest::Context::new(&digest::SHA256);
+		ctx.update(&self.h);
+		ctx.update(data);
+		self.h.copy_from_slice(ctx.finish().as_ref());
+	}
+
+	fn mix_key(&mut self, ikm: &[u8]) {
+		let (ck, key) = hkdf(&self.ck, ikm);
+		self.ck = ck;
+		self.cipher = CipherState::new(Some(key));
+	}
+
+	fn encrypt_and_hash(&mut self, plaintext: &[u8]) -> io::Result<Vec<u8>> {
+		let ciphertext = self.cipher.encrypt(&self.h, plaintext)?;
+		self.mix_hash(&ciphertext);
+		Ok(ciphertext)
+	}
+
+	fn decrypt_and_hash(&mut self, ciphertext: &[u8]) -> io::Result<Vec<u8>> {
+		let plaintext = self.cipher.decrypt(&self.h, ciphertext)?;
+		self.mix_hash(ciphertext);
+		Ok(plaintext)
+	}
+
+	// ciphers of the initiator to responder and responder to initiator
+	// directions
+	fn split(&self) -> (CipherState, CipherState) {
+		let (k1, k2) = hkdf(&self.ck, &[]);
+		(CipherState::new(Some(k1)), CipherState::new(Some(k2)))
+	}
+}
+
+fn hkdf(ck: &[u8], ikm: &[u8]) -> ([u8; HASH_LEN], [u8; HASH_LEN]) {
+	let temp_key = hmac::SigningKey::new(&digest::SHA256, ck);
+	let temp_key = hmac::sign(&temp_key, ikm);
+	let temp_key = hmac::SigningKey::new(&digest::SHA256, temp_key.as_ref());
+	let out1 = hmac::sign(&temp_key, &[1]);
+	let mut ctx = hmac::SigningContext::with_key(&temp_key);
+	ctx.update(out1.as_ref());
+	ctx.update(&[2]);
+	let out2 = ctx.sign();
+
+	let mut res = ([0; HASH_LEN], [0; HASH_LEN]);
+	res.0.copy_from_slice(out1.as_ref());
+	res.1.copy_from_slice(out2.as_ref());
+	res
+}
+
+fn read_frame(stream: &mut dyn Read) -> io::Result<Vec<u8>> {
+	let mut len = [0; 2];
+	stream.read_exact(&mut len)?;
+	let mut frame = vec![0; u16::from_be_bytes(len) as usize];
+	stream.read_exact(&mut frame)?;
+	Ok(frame)
+}
+
+fn write_frame(stream: &mut dyn Write, frame: &[u8]) -> io::Result<()> {
+	if frame.len() > MAX_FRAME_LEN {
+		return Err(io::Error::new(
+			io::ErrorKind::InvalidInput,
+			"frame too large",
+		));
+	}
+	let mut buf = (frame.len() as u16).to_be_bytes().to_vec();
+	buf.extend_from_slice(frame);
+	stream.write_all(&buf)?;
+	stream.flush()
+}
+
+/// Reading half of an established session.
+pub struct FrameReader {
+	cipher: CipherState,
+}
+
+impl FrameReader {
+	/// Reads, decrypts and deserializes the next message.
+	pub fn read_msg<T: Readable>(&mut self, stream: &mut dyn Read) -> io::Result<T> {
+		let frame = read_frame(stream)?;
+		let plaintext = self.cipher.decrypt(&[], &frame)?;
+		ser::deserialize(&mut &plaintext[..]).map_err(invalid_data)
+	}
+}
+
+/// Writing half of an established session.
+pub struct FrameWriter {
+	cipher: CipherState,
+}
+
+impl FrameWriter {
+	/// Serializes, encrypts and writes a message.
+	pub fn write_msg<T: Writeable>(&mut self, stream: &mut dyn Write, msg: &T) -> io::Result<()> {
+		let plaintext = ser::ser_vec(msg).map_err(invalid_data)?;
+		if plaintext.len() > MAX_FRAME_LEN - TAG_LEN {
+			return Err(io::Error::new(
+				io::ErrorKind::InvalidInput,
+				"message too large",
+			));
+		}
+		let frame = self.cipher.encrypt(&[], &plaintext)?;
+		write_frame(stream, &frame)
+	}
+}
+
+/// Server side of the handshake, authenticating with the static key.
+pub fn server_handshake<S: Read + Write>(
+	stream: &mut S,
+	static_key: &Keypair,
+) -> io::Result<(FrameReader, FrameWriter)> {
+	let mut state = SymmetricState::new();
+
+	// -> e
+	let msg = read_frame(stream)?;
+	if msg.len() != PUBLIC_KEY_LEN {
+		return Err(invalid_data("bad handshake message length"));
+	}
+	let re = public_key_from_bytes(&msg)?;
+	state.mix_hash(&msg);
+	state.decrypt_and_hash(&[])?;
+
+	// <- e, ee, s, es
+	let e = Keypair::generate()?;
+	let mut msg = public_key_to_bytes(&e.public);
+	state.mix_hash(&msg);
+	state.mix_key(&e.dh(&re));
+	let s = state.encrypt_and_hash(&public_key_to_bytes(&static_key.public))?;
+	msg.extend_from_slice(&s);
+	state.mix_key(&static_key.dh(&re));
+	msg.extend_from_slice(&state.encrypt_and_hash(&[])?);
+	write_frame(stream, &msg)?;
+
+	let (c1, c2) = state.split();
+	Ok((FrameReader { cipher: c1 }, FrameWriter { cipher: c2 }))
+}
+
+/// Miner side of the handshake. Fails if the server doesn't authenticate with
+/// `server_key`, when provided. Returns the server's static key with the
+/// session.
+pub fn client_handshake<S: Read + Write>(
+	stream: &mut S,
+	server_key: Option<&PublicKey>,
+) -> io::Result<(FrameReader, FrameWriter, PublicKey)> {
+	let mut state = SymmetricState::new();
+
+	// -> e
+	let e = Keypair::generate()?;
+	let msg = public_key_to_bytes(&e.public);
+	state.mix_hash(&msg);
+	state.encrypt_and_hash(&[])?;
+	write_frame(stream, &msg)?;
+
+	// <- e, ee, s, es
+	let msg = read_frame(stream)?;
+	let s_end = 2 * PUBLIC_KEY_LEN + TAG_LEN;
+	if msg.len() != s_end + TAG_LEN {
+		return Err(invalid_data("bad handshake message length"));
+	}
+	let re = public_key_from_bytes(&msg[..PUBLIC_KEY_LEN])?;
+	state.mix_hash(&msg[..PUBLIC_KEY_LEN]);
+	state.mix_key(&e.dh(&re));
+	let rs = state.decrypt_and_hash(&msg[PUBLIC_KEY_LEN..s_end])?;
+	let rs = public_key_from_bytes(&rs)?;
+	if let Some(server_key) = server_key {
+		if *server_key != rs {
+			return Err(io::Error::new(
+				io::ErrorKind::PermissionDenied,
+				"unexpected server key",
+			));
+		}
+	}
+	state.mix_key(&e.dh(&rs));
+	state.decrypt_and_hash(&msg[s_end..])?;
+
+	let (c1, c2) = state.split();
+	Ok((FrameReader { cipher: c2 }, FrameWriter { cipher: c1 }, rs))
+}
+
+// ----------------------------------------
+// Messages
+
+/// Messages of the binary protocol, one for each JSON-RPC request and
+/// result. Requests carry an id their response repeats, jobs pushed by the
+/// server have the id 0.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Message {
+	/// Miner logging in
+	Login {
+		id: u32,
+		login: String,
+		pass: String,
+		agent: String,
+	},
+	/// Miner asking for the current job
+	GetJobTemplate { id: u32 },
+	/// Miner submitting a share
+	Submit {
+		id: u32,
+		height: u64,
+		job_id: u64,
+		nonce: u64,
+		edge_bits: u8,
+		pow: Vec<u64>,
+	},
+	/// Miner making sure the connection stays up
+	KeepAlive { id: u32 },
+	/// Miner asking for its stats
+	Status { id: u32 },
+	/// Job to mine, the block header before its proof of work
+	Job {
+		id: u32,
+		height: u64,
+		job_id: u64,
+		difficulty: u64,
+		pre_pow: Vec<u8>,
+	},
+	/// Request succeeded, "ok" or "blockfound - <hash>" for shares
+	Ok { id: u32, result: String },
+	/// Stats of the miner
+	WorkerStatus {
+		id: u32,
+		worker: String,
+		height: u64,
+		difficulty: u64,
+		accepted: u64,
+		rejected: u64,
+		stale: u64,
+		policy: String,
+	},
+	/// Request failed, with the JSON-RPC error code
+	Error { id: u32, code: i32, message: String },
+}
+
+impl Writeable for Message {
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), ser::Error> {
+		match self {
+			Message::Login {
+				id,
+				login,
+				pass,
+				agent,
+			} => {
+				writer.write_u8(1)?;
+				writer.write_u32(*id)?;
+				writer.write_bytes(login)?;
+				writer.write_bytes(pass)?;
+				writer.write_bytes(agent)
+			}
+			Message::GetJobTemplate { id } => {
+				writer.write_u8(2)?;
+				writer.write_u32(*id)
+			}
+			Message::Submit {
+				id,
+				height,
+				job_id,
+				nonce,
+				edge_bits,
+				pow,
+			} => {
+				writer.write_u8(3)?;
+				writer.write_u32(*id)?;
+				writer.write_u64(*height)?;
+				writer.write_u64(*job_id)?;
+				writer.write_u64(*nonce)?;
+				writer.write_u8(*edge_bits)?;
+				writer.write_u64(pow.len() as u64)?;
+				for n in pow {
+					writer.write_u64(*n)?;
+				}
+				Ok(())
+			}
+			Message::KeepAlive { id } => {
+				writer.write_u8(4)?;
+				writer.write_u32(*id)
+			}
+			Message::Status { id } => {
+				writer.write_u8(5)?;
+				writer.write_u32(*id)
+			}
+			Message::Job {
+				id,
+				height,
+				job_id,
+				difficulty,
+				pre_pow,
+			} => {
+				writer.write_u8(6)?;
+				writer.write_u32(*id)?;
+				writer.write_u64(*height)?;
+				writer.write_u64(*job_id)?;
+				writer.write_u64(*difficulty)?;
+				writer.write_bytes(pre_pow)
+			}
+			Message::Ok { id, result } => {
+				writer.write_u8(7)?;
+				writer.write_u32(*id)?;
+				writer.write_bytes(result)
+			}
+			Message::WorkerStatus {
+				id,
+				worker,
+				height,
+				difficulty,
+				accepted,
+				rejected,
+				stale,
+				policy,
+			} => {
+				writer.write_u8(8)?;
+				writer.write_u32(*id)?;
+				writer.write_bytes(worker)?;
+				writer.write_u64(*height)?;
+				writer.write_u64(*difficulty)?;
+				writer.write_u64(*accepted)?;
+				writer.write_u64(*rejected)?;
+				writer.write_u64(*stale)?;
+				writer.write_bytes(policy)
+			}
+			Message::Error { id, code, message } => {
+				writer.write_u8(9)?;
+				writer.write_u32(*id)?;
+				writer.write_i32(*code)?;
+				writer.write_bytes(message)
+			}
+		}
+	}
+}
+
+impl Readable for Message {
+	fn read(reader: &mut dyn Reader) -> Result<Message, ser::Error> {
+		let msg_type = reader.read_u8()?;
+		let id = reader.read_u32()?;
+		let msg = match msg_type {
+			1 => Message::Login {
+				id,
+				login: read_string(reader)?,
+				pass: read_string(reader)?,
+				agent: read_string(reader)?,
+			},
+			2 => Message::GetJobTemplate { id },
+			3 => {
+				let height = reader.read_u64()?;
+				let job_id = reader.read_u64()?;
+				let nonce = reader.read_u64()?;
+				let edge_bits = reader.read_u8()?;
+				let count = reader.read_u64()?;
+				if count > MAX_POW_NONCES {
+					return Err(ser::Error::TooLargeReadErr);
+				}
+				let mut pow = Vec::with_capacity(count as usize);
+				for _ in 0..count {
+					pow.push(reader.read_u64()?);
+				}
+				Message::Submit {
+					id,
+					height,
+					job_id,
+					nonce,
+					edge_bits,
+					pow,
+				}
+			}
+			4 => Message::KeepAlive { id },
+			5 => Message::Status { id },
+			6 => Message::Job {
+				id,
+				height: reader.read_u64()?,
+				job_id: reader.read_u64()?,
+				difficulty: reader.read_u64()?,
+				pre_pow: reader.read_bytes_len_prefix()?,
+			},
+			7 => Message::Ok {
+				id,
+				result: read_string(reader)?,
+			},
+			8 => Message::WorkerStatus {
+				id,
+				worker: read_string(reader)?,
+				height: reader.read_u64()?,
+				difficulty: reader.read_u64()?,
+				accepted: reader.read_u64()?,
+				rejected: reader.read_u64()?,
+				stale: reader.read_u64()?,
+				policy: read_string(reader)?,
+			},
+			9 => Message::Error {
+				id,
+				code: reader.read_i32()?,
+				message: read_string(reader)?,
+			},
+			_ => return Err(ser::Error::CorruptedData),
+		};
+		Ok(msg)
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use std::net::{TcpListener, TcpStream};
+	use std::thread;
+
+	fn connect(
+		server_key: Keypair,
+		pinned: Option<PublicKey>,
+	) -> (
+		io::Result<(FrameReader, FrameWriter, PublicKey)>,
+		TcpStream,
+		thread::JoinHandle<io::Result<()>>,
+	) {
+		let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+		let addr = listener.local_addr().unwrap();
+		let server = thread::spawn(move || {
+			let mut stream = listener.accept()?.0;
+			let (mut reader, mut writer) = server_handshake(&mut stream, &server_key)?;
+			// echoes back a single message
+			let msg: Message = reader.read_msg(&mut stream)?;
+			writer.write_msg(&mut stream, &msg)
+		});
+		let mut stream = TcpStream::connect(addr).unwrap();
+		let session = client_handshake(&mut stream, pinned.as_ref());
+		(session, stream, server)
+	}
+
+	#[test]
+	fn handshake_and_messages() {
+		let server_key = Keypair::generate().unwrap();
+		let server_public = server_key.public_key().clone();
+		let (session, mut stream, server) = connect(server_key, Some(server_public.clone()));
+		let (mut reader, mut writer, key) = session.unwrap();
+		assert_eq!(key, server_public);
+
+		let submit = Message::Submit {
+			id: 3,
+			height: 1000,
+			job_id: 2,
+			nonce: 12345,
+			edge_bits: 29,
+			pow: (0..42).collect(),
+		};
+		writer.write_msg(&mut stream, &submit).unwrap();
+		let echo: Message = reader.read_msg(&mut stream).unwrap();
+		assert_eq!(echo, submit);
+		server.join().unwrap().unwrap();
+	}
+
+	#[test]
+	fn wrong_server_key() {
+		let server_key = Keypair::generate().unwrap();
+		let other = Keypair::generate().unwrap();
+		let (session, stream, server) = connect(server_key, Some(other.public_key().clone()));
+		match session {
+			Err(e) => assert_eq!(e.kind(), io::ErrorKind::PermissionDenied),
+			Ok(_) => panic!("handshake with an impersonated server succeeded"),
+		}
+		drop(stream);
+		assert!(server.join().unwrap().is_err());
+	}
+
+	#[test]
+	fn tampered_frame() {
+		let (session, mut stream, server) = connect(Keypair::generate().unwrap(), None);
+		let (_, mut writer, _) = session.unwrap();
+
+		let mut frame = vec![];
+		writer
+			.write_msg(&mut frame, &Message::KeepAlive { id: 1 })
+			.unwrap();
+		let last = frame.len() - 1;
+		frame[last] ^= 1;
+		stream.write_all(&frame).unwrap();
+		let err = server.join().unwrap().unwrap_err();
+		assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+	}
+
+	#[test]
+	fn key_file() {
+		let path = std::env::temp_dir().join(format!(
+			"stratum_binary_key_{}",
+			util::to_hex(Keypair::generate().unwrap().secret.0[..8].to_vec())
+		));
+		let path = path.to_str().unwrap();
+		let key = Keypair::load_or_generate(path).unwrap();
+		let loaded = Keypair::load_or_generate(path).unwrap();
+		assert_eq!(loaded.public_key(), key.public_key());
+		#[cfg(unix)]
+		{
+			use std::os::unix::fs::PermissionsExt;
+			let mode = fs::metadata(path).unwrap().permissions().mode();
+			assert_eq!(mode & 0o777, 0o600);
+		}
+		fs::remove_file(path).unwrap();
+	}
+
+	#[test]
+	fn message_ser() {
+		let msgs = vec![
+			Message::Login {
+				id: 1,
+				login: "alice.rig1".to_owned(),
+				pass: "secret".to_owned(),
+				agent: "miner/1.0".to_owned(),
+			},
+			Message::Job {
+				id: 0,
+				height: 10,
+				job_id: 1,
+				difficulty: 4,
+				pre_pow: vec![1, 2, 3],
+			},
+			Message::WorkerStatus {
+				id: 5,
+				worker: "alice.rig1".to_owned(),
+				height: 10,
+				difficulty: 4,
+				accepted: 7,
+				rejected: 1,
+				stale: 0,
+				policy: "empty+exclude_kernels".to_owned(),
+			},
+			Message::Error {
+				id: 2,
+				code: -32501,
+				message: "Share rejected due to low difficulty".to_owned(),
+			},
+		];
+		for msg in msgs {
+			let bytes = ser::ser_vec(&msg).unwrap();
+			let res: Message = ser::deserialize(&mut &bytes[..]).unwrap();
+			assert_eq!(res, msg);
+		}
+	}
+}
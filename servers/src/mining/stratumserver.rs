@@ -25,22 +25,27 @@ use chrono::prelude::Utc;
 use serde;
 use serde_json;
 use serde_json::Value;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::io::BufReader;
-use std::net::SocketAddr;
+use std::net::{self, Shutdown, SocketAddr};
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
-use std::time::{Duration, SystemTime};
+use std::time::{Duration, Instant, SystemTime};
 use std::{cmp, thread};
 
 use crate::chain;
 use crate::common::stats::{StratumStats, WorkerStats};
-use crate::common::types::{StratumServerConfig, SyncState};
+use crate::common::types::{StratumServerConfig, SyncState, VardiffConfig};
 use crate::core::core::hash::Hashed;
-use crate::core::core::verifier_cache::VerifierCache;
 use crate::core::core::Block;
-use crate::core::{pow, ser};
+use crate::core::{consensus, pow, ser};
 use crate::keychain;
 use crate::mining::mine_block;
+use crate::mining::share_ledger::ShareLedger;
+use crate::mining::stratum_binary::{self, Keypair, Message};
+use crate::mining::template_policy::{self, TemplatePolicy};
+use crate::mining::vardiff::WorkerDifficulty;
+use crate::mining::worker_credentials::WorkerCredentials;
 use crate::pool;
 use crate::util;
 
@@ -48,6 +53,15 @@ use futures::sync::mpsc;
 
 type Tx = mpsc::UnboundedSender<String>;
 
+/// Wallet receiver a block reward is paid to, none to burn it.
+type Payout = Option<String>;
+
+/// Attempts at building the block of a payout before leaving it out
+const BLOCK_BUILD_ATTEMPTS: usize = 3;
+
+/// Seconds a payout whose block couldn't be built is left out for
+const FAILED_PAYOUT_RETRY_SECS: i64 = 30;
+
 // ----------------------------------------
 // http://www.jsonrpc.org/specification
 // RPC Methods
@@ -118,6 +132,24 @@ impl RpcError {
 			message: "Invalid Request".to_string(),
 		}
 	}
+	pub fn login_required() -> Self {
+		RpcError {
+			code: -32500,
+			message: "Unauthorized - Please login".to_string(),
+		}
+	}
+	pub fn login_failed() -> Self {
+		RpcError {
+			code: -32504,
+			message: "Login failed - Invalid login or password".to_string(),
+		}
+	}
+	pub fn no_job() -> Self {
+		RpcError {
+			code: -32001,
+			message: "No job available yet - Please wait".to_string(),
+		}
+	}
 }
 
 impl From<RpcError> for Value {
@@ -168,25 +200,35 @@ pub struct WorkerStatus {
 	accepted: u64,
 	rejected: u64,
 	stale: u64,
+	// block template policy that built the current job
+	policy: String,
 }
 
 struct State {
-	current_block_versions: Vec<Block>,
+	// versions of the block being mined, one list for each payout of the
+	// connected workers as the coinbase differs, a job id indexing into the
+	// list of the worker's payout
+	current_block_versions: HashMap<Payout, Vec<Block>>,
 	// to prevent the wallet from generating a new HD key derivation for each
 	// iteration, we keep the returned derivation to provide it back when
 	// nothing has changed. We only want to create a key_id for each new block,
 	// and reuse it when we rebuild the current block to add new tx.
-	current_key_id: Option<keychain::Identifier>,
+	current_key_ids: HashMap<Payout, keychain::Identifier>,
+	// name of the policy the transactions of the current block of each
+	// payout were picked by
+	current_policies: HashMap<Payout, String>,
+	current_height: u64,
 	current_difficulty: u64,
 	minimum_share_difficulty: u64,
 }
 
 impl State {
 	pub fn new(minimum_share_difficulty: u64) -> Self {
-		let blocks = vec![Block::default()];
 		State {
-			current_block_versions: blocks,
-			current_key_id: None,
+			current_block_versions: HashMap::new(),
+			current_key_ids: HashMap::new(),
+			current_policies: HashMap::new(),
+			current_height: 0,
 			current_difficulty: <u64>::max_value(),
 			minimum_share_difficulty: minimum_share_difficulty,
 		}
@@ -199,6 +241,12 @@ struct Handler {
 	sync_state: Arc<SyncState>,
 	chain: Arc<chain::Chain>,
 	current_state: Arc<RwLock<State>>,
+	// workers allowed to log in, anyone can mine when none
+	credentials: Option<WorkerCredentials>,
+	// payout of the workers without their own wallet receiver
+	default_payout: Payout,
+	// where accepted shares and blocks found are recorded
+	ledger: Option<Arc<ShareLedger>>,
 }
 
 impl Handler {
@@ -207,25 +255,67 @@ impl Handler {
 		stratum_stats: Arc<RwLock<StratumStats>>,
 		sync_state: Arc<SyncState>,
 		minimum_share_difficulty: u64,
+		vardiff_config: VardiffConfig,
 		chain: Arc<chain::Chain>,
+		credentials: Option<WorkerCredentials>,
+		default_payout: Payout,
+		ledger: Option<Arc<ShareLedger>>,
 	) -> Self {
 		Handler {
 			id: id,
-			workers: Arc::new(WorkersList::new(stratum_stats.clone())),
+			workers: Arc::new(WorkersList::new(
+				stratum_stats.clone(),
+				vardiff_config,
+				minimum_share_difficulty,
+			)),
 			sync_state: sync_state,
 			chain: chain,
 			current_state: Arc::new(RwLock::new(State::new(minimum_share_difficulty))),
+			credentials,
+			default_payout,
+			ledger,
 		}
 	}
-	pub fn from_stratum(stratum: &StratumServer) -> Self {
+	pub fn from_stratum(stratum: &StratumServer, credentials: Option<WorkerCredentials>) -> Self {
+		let default_payout = if stratum.config.burn_reward {
+			None
+		} else {
+			Some(stratum.config.wallet_listener_url.clone())
+		};
 		Handler::new(
 			stratum.id.clone(),
 			stratum.stratum_stats.clone(),
 			stratum.sync_state.clone(),
 			stratum.config.minimum_share_difficulty,
+			stratum.config.vardiff_config.clone(),
 			stratum.chain.clone(),
+			credentials,
+			default_payout,
+			stratum.share_ledger.clone(),
+		)
+	}
+	// Payout of the blocks found by the worker, none if it isn't allowed to
+	// mine (yet)
+	fn worker_payout(&self, worker: &Worker) -> Option<Payout> {
+		if self.credentials.is_some() && !worker.authenticated {
+			return None;
+		}
+		// burning the reward, whoever finds the block
+		if self.default_payout.is_none() {
+			return Some(None);
+		}
+		Some(
+			worker
+				.wallet_listener_url
+				.clone()
+				.or_else(|| self.default_payout.clone()),
 		)
 	}
+	fn payout_of(&self, worker_id: usize) -> Result<Payout, RpcError> {
+		let worker = self.workers.get_worker(worker_id)?;
+		self.worker_payout(&worker)
+			.ok_or_else(RpcError::login_required)
+	}
 	fn handle_rpc_requests(&self, request: RpcRequest, worker_id: usize) -> String {
 		self.workers.last_seen(worker_id);
 
@@ -235,8 +325,8 @@ impl Handler {
 			"submit" => {
 				let res = self.handle_submit(request.params, worker_id);
 				// this key_id has been used now, reset
-				if let Ok((_, true)) = res {
-					self.current_state.write().current_key_id = None;
+				if let Ok((_, Some(ref payout))) = res {
+					self.current_state.write().current_key_ids.remove(payout);
 				}
 				res.map(|(v, _)| v)
 			}
@@ -245,7 +335,7 @@ impl Handler {
 				if self.sync_state.is_syncing() {
 					Err(RpcError::node_is_syncing())
 				} else {
-					self.handle_getjobtemplate()
+					self.handle_getjobtemplate(worker_id)
 				}
 			}
 			"status" => self.handle_status(worker_id),
@@ -276,7 +366,21 @@ impl Handler {
 	}
 	fn handle_login(&self, params: Option<Value>, worker_id: usize) -> Result<Value, RpcError> {
 		let params: LoginParams = parse_params(params)?;
-		self.workers.login(worker_id, params.login, params.agent)?;
+		let wallet_listener_url = match self.credentials {
+			Some(ref credentials) => match credentials.authenticate(&params.login, &params.pass) {
+				Some(entry) => entry.wallet_listener_url.clone(),
+				None => {
+					warn!(
+						"(Server ID: {}) Worker {} failed to login as {}",
+						self.id, worker_id, params.login
+					);
+					return Err(RpcError::login_failed());
+				}
+			},
+			None => None,
+		};
+		self.workers
+			.login(worker_id, params.login, params.agent, wallet_listener_url)?;
 		return Ok("ok".into());
 	}
 
@@ -288,28 +392,31 @@ impl Handler {
 	fn handle_status(&self, worker_id: usize) -> Result<Value, RpcError> {
 		// Return worker status in json for use by a dashboard or healthcheck.
 		let stats = self.workers.get_stats(worker_id)?;
+		let payout = self.payout_of(worker_id).ok();
+		let state = self.current_state.read();
+		let policy = payout
+			.and_then(|payout| state.current_policies.get(&payout).cloned())
+			.unwrap_or_default();
 		let status = WorkerStatus {
 			id: stats.id.clone(),
-			height: self
-				.current_state
-				.read()
-				.current_block_versions
-				.last()
-				.unwrap()
-				.header
-				.height,
+			height: state.current_height,
 			difficulty: stats.pow_difficulty,
 			accepted: stats.num_accepted,
 			rejected: stats.num_rejected,
 			stale: stats.num_stale,
+			policy,
 		};
 		let response = serde_json::to_value(&status).unwrap();
 		return Ok(response);
 	}
 	// Handle GETJOBTEMPLATE message
-	fn handle_getjobtemplate(&self) -> Result<Value, RpcError> {
+	fn handle_getjobtemplate(&self, worker_id: usize) -> Result<Value, RpcError> {
 		// Build a JobTemplate from a BlockHeader and return JSON
-		let job_template = self.build_block_template();
+		let payout = self.payout_of(worker_id)?;
+		let mut job_template = self
+			.build_block_template(&payout)
+			.ok_or_else(RpcError::no_job)?;
+		job_template.difficulty = self.job_difficulty(worker_id, &job_template, false);
 		let response = serde_json::to_value(&job_template).unwrap();
 		debug!(
 			"(Server ID: {}) sending block {} with id {} to single worker",
@@ -318,16 +425,12 @@ impl Handler {
 		return Ok(response);
 	}
 
-	// Build and return a JobTemplate for mining the current block
-	fn build_block_template(&self) -> JobTemplate {
-		let bh = self
-			.current_state
-			.read()
-			.current_block_versions
-			.last()
-			.unwrap()
-			.header
-			.clone();
+	// Build and return a JobTemplate for mining the current block paying the
+	// given payout, if one was built already
+	fn build_block_template(&self, payout: &Payout) -> Option<JobTemplate> {
+		let state = self.current_state.read();
+		let block_versions = state.current_block_versions.get(payout)?;
+		let bh = block_versions.last()?.header.clone();
 		// Serialize the block header into pre and post nonce strings
 		let mut header_buf = vec![];
 		{
@@ -338,31 +441,49 @@ impl Handler {
 		let pre_pow = util::to_hex(header_buf);
 		let job_template = JobTemplate {
 			height: bh.height,
-			job_id: (self.current_state.read().current_block_versions.len() - 1) as u64,
-			difficulty: self.current_state.read().minimum_share_difficulty,
+			job_id: (block_versions.len() - 1) as u64,
+			difficulty: state.minimum_share_difficulty,
 			pre_pow,
 		};
-		return job_template;
+		return Some(job_template);
+	}
+
+	// Share difficulty of the job sent to the worker, adjusted to its share
+	// rate when it's a new job broadcast, the latest one's when the worker
+	// asks for the job again
+	fn job_difficulty(&self, worker_id: usize, job: &JobTemplate, new_job: bool) -> u64 {
+		let (min, max) = {
+			let state = self.current_state.read();
+			(state.minimum_share_difficulty, state.current_difficulty)
+		};
+		self.workers
+			.job_difficulty(worker_id, job, min, max, new_job)
+			.unwrap_or(min)
 	}
 	// Handle SUBMIT message
 	// params contains a solved block header
 	// We accept and log valid shares of all difficulty above configured minimum
 	// Accepted shares that are full solutions will also be submitted to the
-	// network
+	// network, returning the payout of the block found
 	fn handle_submit(
 		&self,
 		params: Option<Value>,
 		worker_id: usize,
-	) -> Result<(Value, bool), RpcError> {
+	) -> Result<(Value, Option<Payout>), RpcError> {
 		// Validate parameters
 		let params: SubmitParams = parse_params(params)?;
+		let worker = self.workers.get_worker(worker_id)?;
+		let payout = self
+			.worker_payout(&worker)
+			.ok_or_else(RpcError::login_required)?;
 
 		let state = self.current_state.read();
 		// Find the correct version of the block to match this header
-		let b: Option<&Block> = state.current_block_versions.get(params.job_id as usize);
-		if params.height != state.current_block_versions.last().unwrap().header.height
-			|| b.is_none()
-		{
+		let b: Option<&Block> = state
+			.current_block_versions
+			.get(&payout)
+			.and_then(|versions| versions.get(params.job_id as usize));
+		if params.height != state.current_height || b.is_none() {
 			// Return error status
 			error!(
 					"(Server ID: {}) Share at height {}, edge_bits {}, nonce {}, job_id {} submitted too late",
@@ -394,12 +515,17 @@ impl Handler {
 
 		// Get share difficulty
 		share_difficulty = b.header.pow.to_difficulty(b.header.height).to_num();
-		// If the difficulty is too low its an error
-		if share_difficulty < state.minimum_share_difficulty {
+		// If the difficulty is too low for the job its an error, the shares
+		// of jobs we don't remember have to meet either of the latest ones
+		let minimum_share_difficulty = worker
+			.difficulty
+			.job(params.height, params.job_id)
+			.unwrap_or_else(|| worker.difficulty.accepted());
+		if share_difficulty < minimum_share_difficulty {
 			// Return error status
 			error!(
 					"(Server ID: {}) Share at height {}, hash {}, edge_bits {}, nonce {}, job_id {} rejected due to low difficulty: {}/{}",
-					self.id, params.height, b.hash(), params.edge_bits, params.nonce, params.job_id, share_difficulty, state.minimum_share_difficulty,
+					self.id, params.height, b.hash(), params.edge_bits, params.nonce, params.job_id, share_difficulty, minimum_share_difficulty,
 				);
 			self.workers
 				.update_stats(worker_id, |worker_stats| worker_stats.num_rejected += 1);
@@ -461,7 +587,6 @@ impl Handler {
 			}
 		}
 		// Log this as a valid share
-		let worker = self.workers.get_worker(worker_id)?;
 		let submitted_by = match worker.login {
 			None => worker.id.to_string(),
 			Some(login) => login.clone(),
@@ -481,6 +606,33 @@ impl Handler {
 			);
 		self.workers
 			.update_stats(worker_id, |worker_stats| worker_stats.num_accepted += 1);
+		self.workers.add_share(
+			worker_id,
+			state.minimum_share_difficulty,
+			state.current_difficulty,
+		);
+		let network_difficulty = state.current_difficulty;
+		// the ledger is written to without holding up the jobs
+		drop(state);
+		if let Some(ref ledger) = self.ledger {
+			// credit the share with the difficulty of the job it was mined for
+			let res = ledger
+				.add_share(&submitted_by, minimum_share_difficulty, b.header.height)
+				.and_then(|share| {
+					if share_is_block {
+						let reward = consensus::reward(b.total_fees(), b.header.height).0;
+						ledger.add_block(&share, b.hash(), network_difficulty, reward)
+					} else {
+						Ok(())
+					}
+				});
+			if let Err(e) = res {
+				error!(
+					"(Server ID: {}) Failed to record share of {} in the ledger: {:?}",
+					self.id, submitted_by, e,
+				);
+			}
+		}
 		let submit_response;
 		if share_is_block {
 			submit_response = format!("blockfound - {}", b.hash().to_hex());
@@ -489,14 +641,29 @@ impl Handler {
 		}
 		return Ok((
 			serde_json::to_value(submit_response).unwrap(),
-			share_is_block,
+			if share_is_block { Some(payout) } else { None },
 		));
 	} // handle submit a solution
 
 	fn broadcast_job(&self) {
 		debug!("broadcast job");
-		// Package new block into RpcRequest
-		let job_template = self.build_block_template();
+		// Each worker mines the block paying its own payout, at its own share
+		// difficulty
+		for worker in self.workers.get_workers() {
+			let payout = match self.worker_payout(&worker) {
+				Some(payout) => payout,
+				None => continue,
+			};
+			if let Some(mut job_template) = self.build_block_template(&payout) {
+				job_template.difficulty = self.job_difficulty(worker.id, &job_template, true);
+				let job_request = self.build_job_request(&job_template);
+				self.workers.send_to(worker.id, job_request);
+			}
+		}
+	}
+
+	// Package a job template into a "job" RpcRequest
+	fn build_job_request(&self, job_template: &JobTemplate) -> String {
 		let job_template_json = serde_json::to_string(&job_template).unwrap();
 		// Issue #1159 - use a serde_json Value type to avoid extra quoting
 		let job_template_value: Value = serde_json::from_str(&job_template_json).unwrap();
@@ -508,57 +675,161 @@ impl Handler {
 		};
 		let job_request_json = serde_json::to_string(&job_request).unwrap();
 		debug!(
-			"(Server ID: {}) sending block {} with id {} to stratum clients",
-			self.id, job_template.height, job_template.job_id,
+			"(Server ID: {}) sending block {} with id {} and difficulty {} to stratum client",
+			self.id, job_template.height, job_template.job_id, job_template.difficulty,
 		);
-		self.workers.broadcast(job_request_json.clone());
+		job_request_json
+	}
+
+	fn pay_matured_blocks(&self, head_height: u64) {
+		let ledger = match self.ledger {
+			Some(ref ledger) => ledger,
+			None => return,
+		};
+		let chain = &self.chain;
+		let res = ledger.pay_matured(head_height, |height| {
+			chain.get_header_by_height(height).map(|h| h.hash()).ok()
+		});
+		match res {
+			Ok(blocks) => {
+				for block in blocks {
+					warn!(
+						"(Server ID: {}) Pool block {} at height {} {:?}, payouts: {:?}",
+						self.id, block.hash, block.height, block.state, block.payouts,
+					);
+				}
+			}
+			Err(e) => error!(
+				"(Server ID: {}) Failed to pay matured pool blocks: {:?}",
+				self.id, e
+			),
+		}
 	}
 
 	pub fn run(
 		&self,
 		config: &StratumServerConfig,
 		tx_pool: &Arc<RwLock<pool::TransactionPool>>,
-		verifier_cache: Arc<RwLock<dyn VerifierCache>>,
+		policy: &dyn TemplatePolicy,
 	) {
 		debug!("Run main loop");
 		let mut deadline: i64 = 0;
 		let mut head = self.chain.head().unwrap();
 		let mut current_hash = head.prev_block_h;
+		let mut tip_since = Utc::now().timestamp();
+		let mut paid_height = 0;
+		// payouts whose block couldn't be built, until when they are left out
+		let mut failed_payouts: HashMap<Payout, i64> = HashMap::new();
 		loop {
 			// get the latest chain state
 			head = self.chain.head().unwrap();
 			let latest_hash = head.last_block_h;
+			if current_hash != latest_hash {
+				tip_since = Utc::now().timestamp();
+			}
 
-			// Build a new block if:
+			// payouts of the workers allowed to mine, and those we don't
+			// have a block for yet
+			let now = Utc::now().timestamp();
+			failed_payouts.retain(|_, retry_at| *retry_at > now);
+			let payouts: HashSet<Payout> = self
+				.workers
+				.get_workers()
+				.iter()
+				.filter_map(|worker| self.worker_payout(worker))
+				.collect();
+			let missing_payouts: Vec<Payout> = {
+				let state = self.current_state.read();
+				payouts
+					.iter()
+					.filter(|payout| !state.current_block_versions.contains_key(*payout))
+					.filter(|payout| !failed_payouts.contains_key(*payout))
+					.cloned()
+					.collect()
+			};
+
+			// Build new blocks if:
 			//    There is a new block on the chain
-			// or We are rebuilding the current one to include new transactions
-			// and there is at least one worker connected
-			if (current_hash != latest_hash || Utc::now().timestamp() >= deadline)
-				&& self.workers.count() > 0
-			{
-				{
-					debug!("resend updated block");
-					let mut state = self.current_state.write();
-					let mut wallet_listener_url: Option<String> = None;
-					if !config.burn_reward {
-						wallet_listener_url = Some(config.wallet_listener_url.clone());
-					}
-					// If this is a new block, clear the current_block version history
-					let clear_blocks = current_hash != latest_hash;
-
-					// Build the new block (version)
-					let (new_block, block_fees) = mine_block::get_block(
+			// or We are rebuilding the current ones to include new transactions
+			// or A worker logged in with a payout we don't have a block for
+			// and there is at least one worker allowed to mine
+			let rebuild = current_hash != latest_hash || now >= deadline;
+			if (rebuild || !missing_payouts.is_empty()) && !payouts.is_empty() {
+				debug!("resend updated block");
+				let tip_age = (now - tip_since).max(0) as u64;
+				let build_payouts: Vec<Payout> = if rebuild {
+					payouts
+						.iter()
+						.filter(|payout| !failed_payouts.contains_key(*payout))
+						.cloned()
+						.collect()
+				} else {
+					missing_payouts
+				};
+
+				// Build the new blocks (versions) without holding the state, a
+				// payout whose block can't be built, like one with an
+				// unreachable wallet, is left out for a while
+				let key_ids = self.current_state.read().current_key_ids.clone();
+				let mut new_blocks = vec![];
+				for payout in build_payouts {
+					let block = mine_block::try_get_block(
 						&self.chain,
 						tx_pool,
-						verifier_cache.clone(),
-						state.current_key_id.clone(),
-						wallet_listener_url,
+						key_ids.get(&payout).cloned(),
+						payout.clone(),
+						policy,
+						tip_age,
+						BLOCK_BUILD_ATTEMPTS,
 					);
+					match block {
+						Some(block) => new_blocks.push((payout, block)),
+						None => {
+							warn!(
+								"Can't build a block paying to {:?}, retrying in {}s",
+								payout, FAILED_PAYOUT_RETRY_SECS
+							);
+							failed_payouts.insert(payout, now + FAILED_PAYOUT_RETRY_SECS);
+						}
+					}
+				}
 
-					state.current_difficulty =
-						(new_block.header.total_difficulty() - head.total_difficulty).to_num();
-
-					state.current_key_id = block_fees.key_id();
+				{
+					let mut state = self.current_state.write();
+					// If this is a new block, clear the current_block version history
+					if current_hash != latest_hash {
+						state.current_block_versions.clear();
+					}
+					let mut expires_in = None;
+					// forget about the payouts no worker mines for anymore
+					state
+						.current_block_versions
+						.retain(|payout, _| payouts.contains(payout));
+					state
+						.current_policies
+						.retain(|payout, _| payouts.contains(payout));
+
+					for (payout, (new_block, block_fees, info)) in new_blocks {
+						expires_in = match (expires_in, info.expires_in) {
+							(Some(a), Some(b)) => Some(cmp::min(a, b)),
+							(a, b) => a.or(b),
+						};
+
+						state.current_difficulty =
+							(new_block.header.total_difficulty() - head.total_difficulty).to_num();
+						state.current_height = new_block.header.height;
+
+						match block_fees.key_id() {
+							Some(key_id) => state.current_key_ids.insert(payout.clone(), key_id),
+							None => state.current_key_ids.remove(&payout),
+						};
+						state.current_policies.insert(payout.clone(), info.policy);
+						state
+							.current_block_versions
+							.entry(payout)
+							.or_insert_with(Vec::new)
+							.push(new_block);
+					}
 
 					current_hash = latest_hash;
 					// set the minimum acceptable share difficulty for this block
@@ -566,21 +837,35 @@ impl Handler {
 						cmp::min(config.minimum_share_difficulty, state.current_difficulty);
 
 					// set a new deadline for rebuilding with fresh transactions
-					deadline = Utc::now().timestamp() + config.attempt_time_per_block as i64;
+					if rebuild {
+						deadline = Utc::now().timestamp() + config.attempt_time_per_block as i64;
+					}
+					// or with the transactions the policy held back for now
+					if let Some(expires_in) = expires_in {
+						deadline = cmp::min(deadline, Utc::now().timestamp() + expires_in as i64);
+					}
 
-					self.workers.update_block_height(new_block.header.height);
+					self.workers.update_block_height(state.current_height);
 					self.workers
 						.update_network_difficulty(state.current_difficulty);
-
-					if clear_blocks {
-						state.current_block_versions.clear();
-					}
-					state.current_block_versions.push(new_block);
-					// Send this job to all connected workers
 				}
+				// Adjust the share difficulty of the workers not submitting
+				// shares often enough to do so when they did
+				{
+					let state = self.current_state.read();
+					self.workers
+						.retarget(state.minimum_share_difficulty, state.current_difficulty);
+				}
+				// Send the jobs to all connected workers
 				self.broadcast_job();
 			}
 
+			// Pay the blocks found that just matured
+			if paid_height != head.height {
+				paid_height = head.height;
+				self.pay_matured_blocks(head.height);
+			}
+
 			// sleep before restarting loop
 			thread::sleep(Duration::from_millis(5));
 		} // Main Loop
@@ -640,6 +925,217 @@ fn accept_connections(listen_addr: SocketAddr, handler: Arc<Handler>) {
 	tokio::run(server.map(|_| ()).map_err(|_| ()));
 }
 
+// ----------------------------------------
+// Binary protocol listener, a thread for each connection
+
+const BINARY_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Longest a miner connected with the binary protocol can stay silent, miners
+/// keep their connection up with keepalive requests
+const BINARY_IDLE_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// Most connections the binary listener serves at once, each one taking a
+/// thread
+const MAX_BINARY_CONNECTIONS: usize = 256;
+
+fn accept_binary_connections(listen_addr: SocketAddr, key: Arc<Keypair>, handler: Arc<Handler>) {
+	info!("Start binary stratum server");
+	let listener = net::TcpListener::bind(&listen_addr).expect(&format!(
+		"Stratum: Failed to bind to listen address {}",
+		listen_addr
+	));
+	let connections = Arc::new(AtomicUsize::new(0));
+	for stream in listener.incoming() {
+		let stream = match stream {
+			Ok(stream) => stream,
+			Err(e) => {
+				error!("accept error = {:?}", e);
+				continue;
+			}
+		};
+		if connections.fetch_add(1, Ordering::SeqCst) >= MAX_BINARY_CONNECTIONS {
+			connections.fetch_sub(1, Ordering::SeqCst);
+			debug!(
+				"Binary stratum connection {:?} refused, {} connections already",
+				stream.peer_addr(),
+				MAX_BINARY_CONNECTIONS
+			);
+			let _ = stream.shutdown(Shutdown::Both);
+			continue;
+		}
+		let key = key.clone();
+		let handler = handler.clone();
+		let conns = connections.clone();
+		let spawned = thread::Builder::new()
+			.name("stratum_binary".to_string())
+			.spawn(move || {
+				let peer_addr = stream.peer_addr();
+				if let Err(e) = handle_binary_connection(stream, &key, handler) {
+					debug!("Binary stratum connection {:?} closed: {}", peer_addr, e);
+				}
+				conns.fetch_sub(1, Ordering::SeqCst);
+			});
+		if let Err(e) = spawned {
+			error!(
+				"failed to spawn a binary stratum connection thread: {:?}",
+				e
+			);
+			connections.fetch_sub(1, Ordering::SeqCst);
+		}
+	}
+}
+
+fn handle_binary_connection(
+	mut stream: net::TcpStream,
+	key: &Keypair,
+	handler: Arc<Handler>,
+) -> std::io::Result<()> {
+	stream.set_read_timeout(Some(BINARY_HANDSHAKE_TIMEOUT))?;
+	let (mut reader, mut writer) = stratum_binary::server_handshake(&mut stream, key)?;
+	stream.set_read_timeout(Some(BINARY_IDLE_TIMEOUT))?;
+
+	let (tx, rx) = mpsc::unbounded();
+	let worker_id = handler.workers.add_worker(tx);
+	info!("Worker {} connected with the binary protocol", worker_id);
+
+	// responses and jobs go through the same queue as JSON-RPC ones, only
+	// converted before they're sent
+	let mut output = stream.try_clone()?;
+	let _ = thread::Builder::new()
+		.name("stratum_binary_out".to_string())
+		.spawn(move || {
+			for json in rx.wait() {
+				let msg = match json.ok().and_then(|json| rpc_to_binary(&json)) {
+					Some(msg) => msg,
+					None => continue,
+				};
+				if let Err(e) = writer.write_msg(&mut output, &msg) {
+					error!("cannot send {}", e);
+					break;
+				}
+			}
+			let _ = output.shutdown(Shutdown::Both);
+		});
+
+	let res = loop {
+		let msg: Message = match reader.read_msg(&mut stream) {
+			Ok(msg) => msg,
+			Err(e) => break Err(e),
+		};
+		let request = match binary_to_rpc(msg) {
+			Some(request) => request,
+			None => {
+				warn!("Worker {} sent a server message, disconnecting", worker_id);
+				break Ok(());
+			}
+		};
+		let resp = handler.handle_rpc_requests(request, worker_id);
+		handler.workers.send_to(worker_id, resp);
+	};
+
+	// closes the output queue too
+	handler.workers.remove_worker(worker_id);
+	let _ = stream.shutdown(Shutdown::Both);
+	info!("Worker {} disconnected", worker_id);
+	res
+}
+
+// The JSON-RPC request a binary message stands for, none for the messages
+// only the server sends
+fn binary_to_rpc(msg: Message) -> Option<RpcRequest> {
+	let (id, method, params) = match msg {
+		Message::Login {
+			id,
+			login,
+			pass,
+			agent,
+		} => {
+			let params = LoginParams { login, pass, agent };
+			(id, "login", Some(serde_json::to_value(params).unwrap()))
+		}
+		Message::GetJobTemplate { id } => (id, "getjobtemplate", None),
+		Message::Submit {
+			id,
+			height,
+			job_id,
+			nonce,
+			edge_bits,
+			pow,
+		} => {
+			let params = SubmitParams {
+				height,
+				job_id,
+				nonce,
+				edge_bits: edge_bits as u32,
+				pow,
+			};
+			(id, "submit", Some(serde_json::to_value(params).unwrap()))
+		}
+		Message::KeepAlive { id } => (id, "keepalive", None),
+		Message::Status { id } => (id, "status", None),
+		_ => return None,
+	};
+	Some(RpcRequest {
+		id: id.to_string(),
+		jsonrpc: String::from("2.0"),
+		method: method.to_string(),
+		params,
+	})
+}
+
+// The binary message for a JSON-RPC response, or job pushed to the worker
+fn rpc_to_binary(json: &str) -> Option<Message> {
+	let resp: RpcResponse = serde_json::from_str(json).ok()?;
+	// pushed jobs have no numeric id
+	let id = resp.id.parse().unwrap_or(0);
+	if let Some(error) = resp.error {
+		let error: RpcError = serde_json::from_value(error).ok()?;
+		return Some(Message::Error {
+			id,
+			code: error.code,
+			message: error.message,
+		});
+	}
+	match resp.method.as_str() {
+		"job" => {
+			let request: RpcRequest = serde_json::from_str(json).ok()?;
+			job_message(id, request.params?)
+		}
+		"getjobtemplate" => job_message(id, resp.result?),
+		"status" => {
+			let status: WorkerStatus = serde_json::from_value(resp.result?).ok()?;
+			Some(Message::WorkerStatus {
+				id,
+				worker: status.id,
+				height: status.height,
+				difficulty: status.difficulty,
+				accepted: status.accepted,
+				rejected: status.rejected,
+				stale: status.stale,
+				policy: status.policy,
+			})
+		}
+		_ => Some(Message::Ok {
+			id,
+			result: resp
+				.result
+				.and_then(|r| r.as_str().map(|r| r.to_owned()))
+				.unwrap_or_default(),
+		}),
+	}
+}
+
+fn job_message(id: u32, job: Value) -> Option<Message> {
+	let job: JobTemplate = serde_json::from_value(job).ok()?;
+	Some(Message::Job {
+		id,
+		height: job.height,
+		job_id: job.job_id,
+		difficulty: job.difficulty,
+		pre_pow: util::from_hex(job.pre_pow).ok()?,
+	})
+}
+
 // ----------------------------------------
 // Worker Object - a connected stratum client - a miner, pool, proxy, etc...
 
@@ -649,17 +1145,21 @@ pub struct Worker {
 	agent: String,
 	login: Option<String>,
 	authenticated: bool,
+	wallet_listener_url: Option<String>,
+	difficulty: WorkerDifficulty,
 	tx: Tx,
 }
 
 impl Worker {
 	/// Creates a new Stratum Worker.
-	pub fn new(id: usize, tx: Tx) -> Worker {
+	pub fn new(id: usize, tx: Tx, difficulty: u64) -> Worker {
 		Worker {
 			id: id,
 			agent: String::from(""),
 			login: None,
 			authenticated: false,
+			wallet_listener_url: None,
+			difficulty: WorkerDifficulty::new(difficulty, Instant::now()),
 			tx: tx,
 		}
 	}
@@ -668,27 +1168,35 @@ impl Worker {
 struct WorkersList {
 	workers_list: Arc<RwLock<HashMap<usize, Worker>>>,
 	stratum_stats: Arc<RwLock<StratumStats>>,
+	vardiff_config: VardiffConfig,
+	initial_difficulty: u64,
 }
 
 impl WorkersList {
-	pub fn new(stratum_stats: Arc<RwLock<StratumStats>>) -> Self {
+	pub fn new(
+		stratum_stats: Arc<RwLock<StratumStats>>,
+		vardiff_config: VardiffConfig,
+		initial_difficulty: u64,
+	) -> Self {
 		WorkersList {
 			workers_list: Arc::new(RwLock::new(HashMap::new())),
 			stratum_stats: stratum_stats,
+			vardiff_config,
+			initial_difficulty,
 		}
 	}
 
 	pub fn add_worker(&self, tx: Tx) -> usize {
 		let mut stratum_stats = self.stratum_stats.write();
 		let worker_id = stratum_stats.worker_stats.len();
-		let worker = Worker::new(worker_id, tx);
+		let worker = Worker::new(worker_id, tx, self.initial_difficulty);
 		let mut workers_list = self.workers_list.write();
 		workers_list.insert(worker_id, worker);
 
 		let mut worker_stats = WorkerStats::default();
 		worker_stats.is_connected = true;
 		worker_stats.id = worker_id.to_string();
-		worker_stats.pow_difficulty = 1; // XXX TODO
+		worker_stats.pow_difficulty = self.initial_difficulty;
 		stratum_stats.worker_stats.push(worker_stats);
 		stratum_stats.num_workers = workers_list.len();
 		worker_id
@@ -702,13 +1210,19 @@ impl WorkersList {
 		self.stratum_stats.write().num_workers = self.workers_list.read().len();
 	}
 
-	pub fn login(&self, worker_id: usize, login: String, agent: String) -> Result<(), RpcError> {
+	pub fn login(
+		&self,
+		worker_id: usize,
+		login: String,
+		agent: String,
+		wallet_listener_url: Option<String>,
+	) -> Result<(), RpcError> {
 		let mut wl = self.workers_list.write();
 		let mut worker = wl.get_mut(&worker_id).ok_or(RpcError::internal_error())?;
 		worker.login = Some(login);
-		// XXX TODO Future - Validate password?
 		worker.agent = agent;
 		worker.authenticated = true;
+		worker.wallet_listener_url = wallet_listener_url;
 		Ok(())
 	}
 
@@ -742,24 +1256,81 @@ impl WorkersList {
 		f(&mut stratum_stats.worker_stats[worker_id]);
 	}
 
-	pub fn send_to(&self, worker_id: usize, msg: String) {
-		let _ = self
-			.workers_list
-			.read()
-			.get(&worker_id)
-			.unwrap()
-			.tx
-			.unbounded_send(msg);
+	// Counts an accepted share of the worker, possibly adjusting the
+	// difficulty of its next job
+	pub fn add_share(&self, worker_id: usize, min: u64, max: u64) {
+		let share_rate = match self.workers_list.write().get_mut(&worker_id) {
+			Some(worker) => {
+				worker.difficulty.add_share();
+				worker
+					.difficulty
+					.retarget(&self.vardiff_config, min, max, Instant::now())
+			}
+			None => None,
+		};
+		if let Some(share_rate) = share_rate {
+			self.update_stats(worker_id, |ws| ws.share_rate = share_rate);
+		}
 	}
 
-	pub fn broadcast(&self, msg: String) {
-		for worker in self.workers_list.read().values() {
-			let _ = worker.tx.unbounded_send(msg.clone());
+	// Adjusts the difficulty of the next job of the workers whose retarget
+	// window is over
+	pub fn retarget(&self, min: u64, max: u64) {
+		let now = Instant::now();
+		let share_rates: Vec<(usize, f64)> = self
+			.workers_list
+			.write()
+			.values_mut()
+			.filter_map(|worker| {
+				worker
+					.difficulty
+					.retarget(&self.vardiff_config, min, max, now)
+					.map(|share_rate| (worker.id, share_rate))
+			})
+			.collect();
+		for (worker_id, share_rate) in share_rates {
+			self.update_stats(worker_id, |ws| ws.share_rate = share_rate);
 		}
 	}
 
-	pub fn count(&self) -> usize {
-		self.workers_list.read().len()
+	// Share difficulty to send a job of the worker with, only moving to the
+	// next difficulty for a new job
+	pub fn job_difficulty(
+		&self,
+		worker_id: usize,
+		job: &JobTemplate,
+		min: u64,
+		max: u64,
+		new_job: bool,
+	) -> Option<u64> {
+		let difficulty = self
+			.workers_list
+			.write()
+			.get_mut(&worker_id)
+			.map(|worker| {
+				let difficulty = if new_job {
+					worker.difficulty.next_job(min, max)
+				} else {
+					worker.difficulty.current_job(min, max)
+				};
+				worker
+					.difficulty
+					.sent_job(job.height, job.job_id, difficulty);
+				difficulty
+			})?;
+		self.update_stats(worker_id, |ws| ws.pow_difficulty = difficulty);
+		Some(difficulty)
+	}
+
+	pub fn get_workers(&self) -> Vec<Worker> {
+		self.workers_list.read().values().cloned().collect()
+	}
+
+	pub fn send_to(&self, worker_id: usize, msg: String) {
+		// the worker may have disconnected in the meantime
+		if let Some(worker) = self.workers_list.read().get(&worker_id) {
+			let _ = worker.tx.unbounded_send(msg);
+		}
 	}
 
 	pub fn update_block_height(&self, height: u64) {
@@ -781,9 +1352,9 @@ pub struct StratumServer {
 	config: StratumServerConfig,
 	chain: Arc<chain::Chain>,
 	tx_pool: Arc<RwLock<pool::TransactionPool>>,
-	verifier_cache: Arc<RwLock<dyn VerifierCache>>,
 	sync_state: Arc<SyncState>,
 	stratum_stats: Arc<RwLock<StratumStats>>,
+	share_ledger: Option<Arc<ShareLedger>>,
 }
 
 impl StratumServer {
@@ -792,17 +1363,17 @@ impl StratumServer {
 		config: StratumServerConfig,
 		chain: Arc<chain::Chain>,
 		tx_pool: Arc<RwLock<pool::TransactionPool>>,
-		verifier_cache: Arc<RwLock<dyn VerifierCache>>,
 		stratum_stats: Arc<RwLock<StratumStats>>,
+		share_ledger: Option<Arc<ShareLedger>>,
 	) -> StratumServer {
 		StratumServer {
 			id: String::from("0"),
 			config,
 			chain,
 			tx_pool,
-			verifier_cache,
 			sync_state: Arc::new(SyncState::new()),
 			stratum_stats: stratum_stats,
+			share_ledger,
 		}
 	}
 
@@ -827,13 +1398,75 @@ impl StratumServer {
 			.parse()
 			.expect("Stratum: Incorrect address ");
 
-		let handler = Arc::new(Handler::from_stratum(&self));
+		let credentials = match self.config.worker_credentials_file {
+			Some(ref path) => match WorkerCredentials::from_file(path) {
+				Ok(credentials) => Some(credentials),
+				Err(e) => {
+					error!(
+						"(Server ID: {}) Failed to load stratum worker credentials from {}: {:?}",
+						self.id, path, e
+					);
+					return;
+				}
+			},
+			None => None,
+		};
+
+		let policy = match template_policy::from_config(&self.config.template_policy_config) {
+			Ok(policy) => policy,
+			Err(e) => {
+				error!(
+					"(Server ID: {}) Invalid block template policy: {:?}",
+					self.id, e
+				);
+				return;
+			}
+		};
+
+		// the binary listener's key is loaded before anything listens, so a
+		// bad key file doesn't leave miners connected without jobs
+		let binary_listener = match self.config.binary_stratum_server_addr {
+			Some(ref addr) => {
+				let binary_addr: SocketAddr =
+					addr.parse().expect("Stratum: Incorrect binary address ");
+				let key_file = self
+					.config
+					.binary_stratum_key_file
+					.clone()
+					.unwrap_or_else(|| "stratum_server.key".to_owned());
+				match Keypair::load_or_generate(&key_file) {
+					Ok(key) => Some((binary_addr, Arc::new(key))),
+					Err(e) => {
+						error!(
+							"(Server ID: {}) Failed to load binary stratum key from {}: {:?}",
+							self.id, key_file, e
+						);
+						return;
+					}
+				}
+			}
+			None => None,
+		};
+
+		let handler = Arc::new(Handler::from_stratum(&self, credentials));
 		let h = handler.clone();
 
 		let _listener_th = thread::spawn(move || {
 			accept_connections(listen_addr, h);
 		});
 
+		if let Some((binary_addr, key)) = binary_listener {
+			warn!(
+				"Binary stratum server started on {}, public key {}",
+				binary_addr,
+				util::to_hex(stratum_binary::public_key_to_bytes(key.public_key())),
+			);
+			let h = handler.clone();
+			let _binary_listener_th = thread::spawn(move || {
+				accept_binary_connections(binary_addr, key, h);
+			});
+		}
+
 		// We have started
 		{
 			let mut stratum_stats = self.stratum_stats.write();
@@ -851,7 +1484,7 @@ impl StratumServer {
 			thread::sleep(Duration::from_millis(50));
 		}
 
-		handler.run(&self.config, &self.tx_pool, self.verifier_cache.clone());
+		handler.run(&self.config, &self.tx_pool, policy.as_ref());
 	} // fn run_loop()
 } // StratumServer
 
@@ -865,3 +1498,81 @@ where
 		.and_then(|v| serde_json::from_value(v).ok())
 		.ok_or(RpcError::invalid_request())
 }
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn binary_rpc_conversion() {
+		let request = binary_to_rpc(Message::Submit {
+			id: 7,
+			height: 10,
+			job_id: 2,
+			nonce: 99,
+			edge_bits: 29,
+			pow: vec![1, 2, 3],
+		})
+		.unwrap();
+		assert_eq!(request.id, "7");
+		assert_eq!(request.method, "submit");
+		let params: SubmitParams = parse_params(request.params).unwrap();
+		assert_eq!((params.edge_bits, params.pow), (29, vec![1, 2, 3]));
+		assert!(binary_to_rpc(Message::Ok {
+			id: 1,
+			result: "ok".to_owned(),
+		})
+		.is_none());
+
+		// pushed job
+		let job = JobTemplate {
+			height: 10,
+			job_id: 1,
+			difficulty: 4,
+			pre_pow: "0a0b".to_owned(),
+		};
+		let push = RpcRequest {
+			id: String::from("Stratum"),
+			jsonrpc: String::from("2.0"),
+			method: String::from("job"),
+			params: Some(serde_json::to_value(&job).unwrap()),
+		};
+		assert_eq!(
+			rpc_to_binary(&serde_json::to_string(&push).unwrap()),
+			Some(Message::Job {
+				id: 0,
+				height: 10,
+				job_id: 1,
+				difficulty: 4,
+				pre_pow: vec![10, 11],
+			})
+		);
+
+		// results and errors
+		let resp = |result: Option<Value>, error: Option<Value>| {
+			serde_json::to_string(&RpcResponse {
+				id: String::from("3"),
+				jsonrpc: String::from("2.0"),
+				method: String::from("submit"),
+				result,
+				error,
+			})
+			.unwrap()
+		};
+		assert_eq!(
+			rpc_to_binary(&resp(Some("ok".into()), None)),
+			Some(Message::Ok {
+				id: 3,
+				result: "ok".to_owned(),
+			})
+		);
+		assert_eq!(
+			rpc_to_binary(&resp(None, Some(RpcError::too_late().into()))),
+			Some(Message::Error {
+				id: 3,
+				code: -32503,
+				message: "Solution submitted too late".to_owned(),
+			})
+		);
+	}
+}
@@ -0,0 +1,210 @@
+// Copyright 2018 The BitGrin Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Policies picking the transactions of the blocks handed out to stratum
+//! workers.
+
+use std::collections::HashSet;
+
+use crate::common::types::{Error, TemplatePolicyConfig, TemplatePolicyType};
+use crate::core::core::Transaction;
+use crate::pool::{PoolError, TransactionPool};
+use crate::util;
+use crate::util::secp::constants::PEDERSEN_COMMITMENT_SIZE;
+use crate::util::secp::pedersen::Commitment;
+
+/// How the transactions of a block were picked.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TemplateInfo {
+	/// Name of the policy that picked them
+	pub policy: String,
+	/// Seconds after which the block should be rebuilt, whatever the
+	/// transactions in the pool
+	pub expires_in: Option<u64>,
+}
+
+impl TemplateInfo {
+	fn new(policy: &str, expires_in: Option<u64>) -> TemplateInfo {
+		TemplateInfo {
+			policy: policy.to_owned(),
+			expires_in,
+		}
+	}
+}
+
+/// Transactions picked for a block.
+pub type Selection = (Vec<Transaction>, TemplateInfo);
+
+/// Picks the transactions of a block to mine.
+pub trait TemplatePolicy: Send + Sync {
+	/// Transactions for a block on top of a tip we've known for `tip_age`
+	/// seconds.
+	fn select(&self, tx_pool: &TransactionPool, tip_age: u64) -> Result<Selection, PoolError>;
+}
+
+/// As many transactions as fit in the block, best fees first.
+pub struct MaxFees;
+
+impl TemplatePolicy for MaxFees {
+	fn select(&self, tx_pool: &TransactionPool, _tip_age: u64) -> Result<Selection, PoolError> {
+		let txs = tx_pool.prepare_mineable_transactions()?;
+		Ok((txs, TemplateInfo::new("max_fees", None)))
+	}
+}
+
+/// No transaction for the first seconds after a new tip, so workers switch
+/// to it without waiting for the block to be validated, then as many as fit.
+pub struct EmptyAfterTip {
+	/// How long the block stays empty
+	pub seconds: u64,
+}
+
+impl TemplatePolicy for EmptyAfterTip {
+	fn select(&self, tx_pool: &TransactionPool, tip_age: u64) -> Result<Selection, PoolError> {
+		if tip_age >= self.seconds {
+			return MaxFees.select(tx_pool, tip_age);
+		}
+		let expires_in = self.seconds - tip_age;
+		Ok((vec![], TemplateInfo::new("empty", Some(expires_in))))
+	}
+}
+
+/// Leaves out the transactions with any of the provided kernels, along with
+/// those spending their outputs, from what another policy picks.
+pub struct ExcludeKernels {
+	/// Excesses of the kernels to leave out
+	pub kernels: HashSet<Commitment>,
+	/// Policy picking the transactions to filter
+	pub inner: Box<dyn TemplatePolicy>,
+}
+
+impl TemplatePolicy for ExcludeKernels {
+	fn select(&self, tx_pool: &TransactionPool, tip_age: u64) -> Result<Selection, PoolError> {
+		let (txs, info) = self.inner.select(tx_pool, tip_age)?;
+		let info = TemplateInfo {
+			policy: format!("{}+exclude_kernels", info.policy),
+			..info
+		};
+		Ok((without_kernels(txs, &self.kernels), info))
+	}
+}
+
+// Transactions are in dependency order, spending ones after those they
+// spend from.
+fn without_kernels(txs: Vec<Transaction>, kernels: &HashSet<Commitment>) -> Vec<Transaction> {
+	let mut removed_outputs = HashSet::new();
+	txs.into_iter()
+		.filter(|tx| {
+			let keep = !tx.kernels().iter().any(|k| kernels.contains(&k.excess))
+				&& !tx
+					.inputs()
+					.iter()
+					.any(|i| removed_outputs.contains(&i.commitment()));
+			if !keep {
+				removed_outputs.extend(tx.outputs().iter().map(|o| o.commitment()));
+			}
+			keep
+		})
+		.collect()
+}
+
+/// Builds the policy described by the stratum server configuration.
+pub fn from_config(config: &TemplatePolicyConfig) -> Result<Box<dyn TemplatePolicy>, Error> {
+	let policy: Box<dyn TemplatePolicy> = match config.template_policy {
+		TemplatePolicyType::MaxFees => Box::new(MaxFees),
+		TemplatePolicyType::EmptyAfterTip => Box::new(EmptyAfterTip {
+			seconds: config.empty_block_seconds,
+		}),
+	};
+	if config.excluded_kernels.is_empty() {
+		return Ok(policy);
+	}
+	let mut kernels = HashSet::new();
+	for excess in &config.excluded_kernels {
+		let invalid = || Error::Configuration(format!("invalid excluded kernel {}", excess));
+		let bytes = util::from_hex(excess.clone()).map_err(|_| invalid())?;
+		if bytes.len() != PEDERSEN_COMMITMENT_SIZE {
+			return Err(invalid());
+		}
+		kernels.insert(Commitment::from_vec(bytes));
+	}
+	Ok(Box::new(ExcludeKernels {
+		kernels,
+		inner: policy,
+	}))
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::core::libtx::{self, build};
+	use crate::keychain::{ExtKeychain, Keychain};
+
+	fn tx(keychain: &ExtKeychain, input: u64, output: u64) -> Transaction {
+		build::transaction(
+			vec![
+				build::input(input, ExtKeychain::derive_key_id(1, input as u32, 0, 0, 0)),
+				build::output(
+					output,
+					ExtKeychain::derive_key_id(1, output as u32, 0, 0, 0),
+				),
+				build::with_fee(input - output),
+			],
+			keychain,
+			&libtx::ProofBuilder::new(keychain),
+		)
+		.unwrap()
+	}
+
+	#[test]
+	fn exclude_kernels() {
+		let keychain = ExtKeychain::from_random_seed(false).unwrap();
+		// the second one spends the output of the first one
+		let txs = vec![
+			tx(&keychain, 100, 90),
+			tx(&keychain, 90, 80),
+			tx(&keychain, 200, 190),
+		];
+
+		let excluded: HashSet<Commitment> = vec![txs[0].kernels()[0].excess].into_iter().collect();
+		let res = without_kernels(txs.clone(), &excluded);
+		assert_eq!(res, vec![txs[2].clone()]);
+
+		let excluded: HashSet<Commitment> = vec![txs[1].kernels()[0].excess].into_iter().collect();
+		let res = without_kernels(txs.clone(), &excluded);
+		assert_eq!(res, vec![txs[0].clone(), txs[2].clone()]);
+
+		assert_eq!(without_kernels(txs.clone(), &HashSet::new()), txs);
+	}
+
+	#[test]
+	fn policy_config() {
+		let config = TemplatePolicyConfig {
+			excluded_kernels: vec!["zz".to_owned()],
+			..TemplatePolicyConfig::default()
+		};
+		assert!(from_config(&config).is_err());
+		let config = TemplatePolicyConfig {
+			excluded_kernels: vec![util::to_hex(vec![8; 32])],
+			..TemplatePolicyConfig::default()
+		};
+		assert!(from_config(&config).is_err());
+		let config = TemplatePolicyConfig {
+			excluded_kernels: vec![util::to_hex(vec![8; 33])],
+			..TemplatePolicyConfig::default()
+		};
+		assert!(from_config(&config).is_ok());
+		assert!(from_config(&TemplatePolicyConfig::default()).is_ok());
+	}
+}
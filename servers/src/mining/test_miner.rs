@@ -28,6 +28,7 @@ use crate::core::core::verifier_cache::VerifierCache;
 use crate::core::core::{Block, BlockHeader};
 use crate::core::global;
 use crate::mining::mine_block;
+use crate::mining::template_policy::MaxFees;
 use crate::pool;
 use crate::util::StopState;
 
@@ -146,12 +147,14 @@ impl Miner {
 			let head = self.chain.head_header().unwrap();
 			let mut latest_hash = self.chain.head().unwrap().last_block_h;
 
-			let (mut b, block_fees) = mine_block::get_block(
+			let (mut b, block_fees, _) = mine_block::get_block(
 				&self.chain,
 				&self.tx_pool,
 				self.verifier_cache.clone(),
 				key_id.clone(),
 				wallet_listener_url.clone(),
+				&MaxFees,
+				0,
 			);
 
 			let sol = self.inner_mining_loop(
@@ -0,0 +1,242 @@
+// Copyright 2018 The BitGrin Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Variable share difficulty: each worker's share difficulty follows its
+//! observed share rate so that all of them submit shares at about the same
+//! pace, whatever their hashrate.
+
+use std::cmp;
+use std::collections::VecDeque;
+use std::time::Instant;
+
+use crate::common::types::VardiffConfig;
+
+/// Most a difficulty is multiplied or divided by in a single retarget.
+const MAX_ADJUSTMENT: f64 = 4.0;
+
+/// Share rates this close to the target don't warrant a change.
+const TOLERANCE: f64 = 0.25;
+
+/// Jobs sent to a worker whose difficulty is remembered, for the shares
+/// still coming in for them.
+const MAX_SENT_JOBS: usize = 16;
+
+/// Share difficulty of a single worker.
+#[derive(Debug, Clone)]
+pub struct WorkerDifficulty {
+	/// Difficulty of the latest job sent to the worker
+	pub current: u64,
+	/// Difficulty of the job sent before, shares for it may still come in
+	pub previous: u64,
+	/// Difficulty the next job will be sent with
+	pub next: u64,
+	window_start: Instant,
+	window_shares: u64,
+	// height, job id and difficulty of the latest jobs sent
+	sent_jobs: VecDeque<(u64, u64, u64)>,
+}
+
+impl WorkerDifficulty {
+	/// Worker mining at the given difficulty from now on.
+	pub fn new(difficulty: u64, now: Instant) -> WorkerDifficulty {
+		WorkerDifficulty {
+			current: difficulty,
+			previous: difficulty,
+			next: difficulty,
+			window_start: now,
+			window_shares: 0,
+			sent_jobs: VecDeque::new(),
+		}
+	}
+
+	/// Lowest difficulty a share of the worker can have.
+	pub fn accepted(&self) -> u64 {
+		cmp::min(self.current, self.previous)
+	}
+
+	/// Remembers the difficulty a job was sent to the worker with.
+	pub fn sent_job(&mut self, height: u64, job_id: u64, difficulty: u64) {
+		if self.sent_jobs.len() >= MAX_SENT_JOBS {
+			self.sent_jobs.pop_front();
+		}
+		self.sent_jobs.push_back((height, job_id, difficulty));
+	}
+
+	/// Difficulty the job of the given height and id was sent to the worker
+	/// with, the lowest one if it was sent a few times. None if it wasn't
+	/// sent lately.
+	pub fn job(&self, height: u64, job_id: u64) -> Option<u64> {
+		self.sent_jobs
+			.iter()
+			.filter(|(h, id, _)| *h == height && *id == job_id)
+			.map(|(_, _, difficulty)| *difficulty)
+			.min()
+	}
+
+	/// Counts an accepted share.
+	pub fn add_share(&mut self) {
+		self.window_shares += 1;
+	}
+
+	/// Picks the difficulty of the next job from the share rate observed
+	/// since the last retarget, bounded by `min` and `max`. Happens once per
+	/// retarget window, or earlier if the worker floods us with shares.
+	/// Returns the observed share rate, in shares per minute, when done.
+	pub fn retarget(
+		&mut self,
+		config: &VardiffConfig,
+		min: u64,
+		max: u64,
+		now: Instant,
+	) -> Option<f64> {
+		if !config.enable_vardiff {
+			return None;
+		}
+		let elapsed = now.duration_since(self.window_start);
+		let elapsed = elapsed.as_secs() as f64 + elapsed.subsec_millis() as f64 / 1000.0;
+		let target = cmp::max(config.target_share_time, 1) as f64;
+		let window = cmp::max(config.retarget_time, config.target_share_time) as f64;
+		let flooding = self.window_shares as f64 >= 2.0 * window / target;
+		if elapsed <= 0.0 || (elapsed < window && !flooding) {
+			return None;
+		}
+
+		// share rates go up linearly as difficulty goes down
+		let ratio = self.window_shares as f64 * target / elapsed;
+		if (ratio - 1.0).abs() > TOLERANCE {
+			let ratio = ratio.max(1.0 / MAX_ADJUSTMENT).min(MAX_ADJUSTMENT);
+			let next = (self.current as f64 * ratio).round() as u64;
+			self.next = cmp::max(cmp::min(next, max), min);
+		}
+
+		let share_rate = self.window_shares as f64 * 60.0 / elapsed;
+		self.window_start = now;
+		self.window_shares = 0;
+		Some(share_rate)
+	}
+
+	/// Difficulty to send a new job with, bounded by `min` and `max`. Shares
+	/// of the job before are still accepted at its difficulty.
+	pub fn next_job(&mut self, min: u64, max: u64) -> u64 {
+		self.next = cmp::max(cmp::min(self.next, max), min);
+		self.previous = self.current;
+		self.current = self.next;
+		self.current
+	}
+
+	/// Difficulty to send the latest job again with, when the worker asks
+	/// for it, bounded by `min` and `max`.
+	pub fn current_job(&mut self, min: u64, max: u64) -> u64 {
+		self.current = cmp::max(cmp::min(self.current, max), min);
+		self.current
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use std::time::Duration;
+
+	#[test]
+	fn worker_difficulty() {
+		let config = VardiffConfig {
+			enable_vardiff: true,
+			target_share_time: 10,
+			retarget_time: 60,
+		};
+		let start = Instant::now();
+		let mut diff = WorkerDifficulty::new(100, start);
+
+		// not enough time nor shares to tell
+		for _ in 0..5 {
+			diff.add_share();
+		}
+		assert_eq!(diff.retarget(&config, 1, 10_000, start + secs(30)), None);
+
+		// 7 shares in a minute, close enough to the target
+		for _ in 0..2 {
+			diff.add_share();
+		}
+		assert_eq!(
+			diff.retarget(&config, 1, 10_000, start + secs(60)),
+			Some(7.0)
+		);
+		assert_eq!(diff.next, 100);
+
+		// 12 shares in 10s, retargeting right away, at most 4 times up
+		let now = start + secs(60);
+		for _ in 0..12 {
+			diff.add_share();
+		}
+		assert_eq!(
+			diff.retarget(&config, 1, 10_000, now + secs(10)),
+			Some(72.0)
+		);
+		assert_eq!(diff.next, 400);
+		assert_eq!(diff.current, 100);
+		assert_eq!(diff.next_job(1, 10_000), 400);
+		assert_eq!(diff.accepted(), 100);
+		// asking for the job again doesn't drop the one before
+		assert_eq!(diff.current_job(1, 10_000), 400);
+		assert_eq!(diff.current_job(1, 10_000), 400);
+		assert_eq!(diff.accepted(), 100);
+		assert_eq!(diff.next_job(1, 10_000), 400);
+		assert_eq!(diff.accepted(), 400);
+
+		// 3 shares in 60s, halving
+		let now = now + secs(10);
+		for _ in 0..3 {
+			diff.add_share();
+		}
+		assert_eq!(diff.retarget(&config, 1, 10_000, now + secs(60)), Some(3.0));
+		assert_eq!(diff.next, 200);
+
+		// no share at all, bounded by the minimum
+		let now = now + secs(60);
+		assert_eq!(
+			diff.retarget(&config, 150, 10_000, now + secs(60)),
+			Some(0.0)
+		);
+		assert_eq!(diff.next, 150);
+
+		// bounds changing with the blocks
+		assert_eq!(diff.next_job(1, 120), 120);
+
+		// the difficulty of the jobs sent is remembered
+		diff.sent_job(10, 0, 100);
+		diff.sent_job(10, 1, 400);
+		diff.sent_job(10, 1, 200);
+		assert_eq!(diff.job(10, 0), Some(100));
+		assert_eq!(diff.job(10, 1), Some(200));
+		assert_eq!(diff.job(11, 0), None);
+		for job_id in 2..2 + MAX_SENT_JOBS as u64 {
+			diff.sent_job(10, job_id, 100);
+		}
+		assert_eq!(diff.job(10, 1), None);
+
+		let config = VardiffConfig {
+			enable_vardiff: false,
+			..config
+		};
+		for _ in 0..100 {
+			diff.add_share();
+		}
+		assert_eq!(diff.retarget(&config, 1, 10_000, now + secs(120)), None);
+		assert_eq!(diff.next_job(1, 10_000), 120);
+	}
+
+	fn secs(secs: u64) -> Duration {
+		Duration::from_secs(secs)
+	}
+}
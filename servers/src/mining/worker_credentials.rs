@@ -0,0 +1,139 @@
+// Copyright 2018 The BitGrin Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Credentials stratum workers log in with, and the wallet each of them gets
+//! their block rewards paid to. Read from a TOML file such as:
+//!
+//! ```toml
+//! # a single worker, logging in as "bob"
+//! [workers.bob]
+//! password = "bob's secret"
+//! wallet_listener_url = "http://10.0.0.2:8515"
+//!
+//! # any worker logging in as "alice.<rig name>"
+//! [groups.alice]
+//! password = "alice's secret"
+//! wallet_listener_url = "http://10.0.0.3:8515"
+//! ```
+//!
+//! Entries without a `wallet_listener_url` pay the stratum server's own.
+
+use std::collections::HashMap;
+use std::fs;
+
+use ring::constant_time::verify_slices_are_equal;
+
+use crate::common::types::Error;
+
+/// Password and payout destination of a worker or worker group.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WorkerEntry {
+	/// Password the worker has to log in with
+	pub password: String,
+	/// Wallet receiver the worker's blocks pay
+	pub wallet_listener_url: Option<String>,
+}
+
+/// All the workers allowed to log into the stratum server.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct WorkerCredentials {
+	/// Workers by exact login
+	#[serde(default)]
+	pub workers: HashMap<String, WorkerEntry>,
+	/// Worker groups, matching any "<group>.<rig name>" login
+	#[serde(default)]
+	pub groups: HashMap<String, WorkerEntry>,
+}
+
+impl WorkerCredentials {
+	/// Reads the credentials from the provided TOML file.
+	pub fn from_file(path: &str) -> Result<WorkerCredentials, Error> {
+		let content = fs::read_to_string(path)?;
+		toml::from_str(&content).map_err(|e| {
+			Error::Configuration(format!("invalid worker credentials file {}: {}", path, e))
+		})
+	}
+
+	/// Entry of the worker with the given login, looking at its group when
+	/// it isn't listed itself.
+	pub fn find(&self, login: &str) -> Option<&WorkerEntry> {
+		self.workers.get(login).or_else(|| {
+			let mut parts = login.splitn(2, '.');
+			match (parts.next(), parts.next()) {
+				(Some(group), Some(rig)) if !rig.is_empty() => self.groups.get(group),
+				_ => None,
+			}
+		})
+	}
+
+	/// Entry of the worker if the login and password match.
+	pub fn authenticate(&self, login: &str, password: &str) -> Option<&WorkerEntry> {
+		self.find(login).filter(|entry| {
+			verify_slices_are_equal(entry.password.as_bytes(), password.as_bytes()).is_ok()
+		})
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn worker_credentials() {
+		let credentials: WorkerCredentials = toml::from_str(
+			"
+[workers.bob]
+password = \"bob-pass\"
+wallet_listener_url = \"http://10.0.0.2:8515\"
+
+[workers.\"alice.special\"]
+password = \"special-pass\"
+
+[groups.alice]
+password = \"alice-pass\"
+wallet_listener_url = \"http://10.0.0.3:8515\"
+",
+		)
+		.unwrap();
+
+		let bob = credentials.authenticate("bob", "bob-pass").unwrap();
+		assert_eq!(
+			bob.wallet_listener_url,
+			Some("http://10.0.0.2:8515".to_owned())
+		);
+		assert!(credentials.authenticate("bob", "alice-pass").is_none());
+		assert!(credentials.authenticate("bob.rig1", "bob-pass").is_none());
+
+		let alice = credentials
+			.authenticate("alice.rig1", "alice-pass")
+			.unwrap();
+		assert_eq!(
+			alice.wallet_listener_url,
+			Some("http://10.0.0.3:8515".to_owned())
+		);
+		assert!(credentials.authenticate("alice", "alice-pass").is_none());
+		assert!(credentials.authenticate("alice.", "alice-pass").is_none());
+
+		// listed workers don't fall back to their group
+		let special = credentials
+			.authenticate("alice.special", "special-pass")
+			.unwrap();
+		assert_eq!(special.wallet_listener_url, None);
+		assert!(credentials
+			.authenticate("alice.special", "alice-pass")
+			.is_none());
+
+		assert!(credentials.authenticate("carol", "").is_none());
+	}
+}
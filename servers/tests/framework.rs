@@ -666,9 +666,15 @@ pub fn stratum_config() -> servers::common::types::StratumServerConfig {
 	servers::common::types::StratumServerConfig {
 		enable_stratum_server: Some(true),
 		stratum_server_addr: Some(String::from("127.0.0.1:18516")),
+		binary_stratum_server_addr: None,
+		binary_stratum_key_file: None,
 		attempt_time_per_block: 60,
 		minimum_share_difficulty: 1,
 		wallet_listener_url: String::from("http://127.0.0.1:18515"),
 		burn_reward: false,
+		worker_credentials_file: None,
+		pplns_window: 2,
+		vardiff_config: servers::VardiffConfig::default(),
+		template_policy_config: servers::TemplatePolicyConfig::default(),
 	}
 }
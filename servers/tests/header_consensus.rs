@@ -0,0 +1,248 @@
+// Copyright 2018 The BitGrin Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use bitgrin_chain as chain;
+use bitgrin_core as core;
+use bitgrin_p2p as p2p;
+use bitgrin_servers as servers;
+use bitgrin_util as util;
+
+use chrono::prelude::{DateTime, Utc};
+use chrono::Duration;
+use std::fs::{self, File};
+use std::io::Read;
+use std::net::{SocketAddr, TcpListener};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::{thread, time};
+
+use crate::core::core::hash::{Hash, Hashed};
+use crate::core::core::{Block, BlockHeader, CompactBlock, Transaction};
+use crate::core::global::{self, ChainTypes};
+use crate::core::pow::{Difficulty, Proof};
+use crate::p2p::types::{Misbehavior, NetAdapter, PeerAddr, PeerInfo, TxHashSetRead};
+use crate::p2p::{Capabilities, ChainAdapter, P2PConfig};
+use crate::util::StopState;
+
+/// A peer only serving a fixed header chain.
+struct HeaderChain {
+	headers: Vec<BlockHeader>,
+}
+
+/// Extends the provided header chain with new headers up to the given
+/// height.
+fn extend(headers: &[BlockHeader], height: u64) -> Vec<BlockHeader> {
+	let mut headers = headers.to_vec();
+	while (headers.len() as u64) <= height {
+		let mut header = BlockHeader::default();
+		if let Some(prev) = headers.last() {
+			header.height = prev.height + 1;
+			header.prev_hash = prev.hash();
+			header.timestamp = prev.timestamp + Duration::seconds(60);
+		}
+		header.pow.proof = Proof::random(global::proofsize());
+		headers.push(header);
+	}
+	headers
+}
+
+impl ChainAdapter for HeaderChain {
+	fn total_difficulty(&self) -> Result<Difficulty, chain::Error> {
+		Ok(Difficulty::from_num(self.headers.len() as u64))
+	}
+	fn total_height(&self) -> Result<u64, chain::Error> {
+		Ok(self.headers.len() as u64 - 1)
+	}
+	fn get_transaction(&self, _h: Hash) -> Option<Transaction> {
+		None
+	}
+	fn tx_kernel_received(&self, _h: Hash, _peer_info: &PeerInfo) -> Result<bool, chain::Error> {
+		Ok(true)
+	}
+	fn transaction_received(&self, _tx: Transaction, _stem: bool) -> Result<bool, chain::Error> {
+		Ok(true)
+	}
+	fn compact_block_received(
+		&self,
+		_cb: CompactBlock,
+		_peer_info: &PeerInfo,
+	) -> Result<bool, chain::Error> {
+		Ok(true)
+	}
+	fn header_received(
+		&self,
+		_bh: BlockHeader,
+		_peer_info: &PeerInfo,
+	) -> Result<bool, chain::Error> {
+		Ok(true)
+	}
+	fn block_received(
+		&self,
+		_b: Block,
+		_peer_info: &PeerInfo,
+		_was_requested: bool,
+	) -> Result<bool, chain::Error> {
+		Ok(true)
+	}
+	fn headers_received(
+		&self,
+		_bh: &[BlockHeader],
+		_peer_info: &PeerInfo,
+	) -> Result<bool, chain::Error> {
+		Ok(true)
+	}
+	fn locate_headers(&self, locator: &[Hash]) -> Result<Vec<BlockHeader>, chain::Error> {
+		let start = locator
+			.iter()
+			.filter_map(|h| self.headers.iter().position(|header| header.hash() == *h))
+			.next();
+		Ok(match start {
+			Some(start) => self
+				.headers
+				.iter()
+				.skip(start + 1)
+				.take(p2p::MAX_BLOCK_HEADERS as usize)
+				.cloned()
+				.collect(),
+			None => vec![],
+		})
+	}
+	fn get_block(&self, _h: Hash) -> Option<Block> {
+		None
+	}
+	fn kernel_data_read(&self) -> Result<File, chain::Error> {
+		unimplemented!()
+	}
+	fn kernel_data_write(&self, _reader: &mut dyn Read) -> Result<bool, chain::Error> {
+		unimplemented!()
+	}
+	fn txhashset_read(&self, _h: Hash) -> Option<TxHashSetRead> {
+		None
+	}
+	fn txhashset_receive_ready(&self) -> bool {
+		false
+	}
+	fn txhashset_write(
+		&self,
+		_h: Hash,
+		_txhashset_data: File,
+		_peer_info: &PeerInfo,
+	) -> Result<bool, chain::Error> {
+		Ok(false)
+	}
+	fn txhashset_download_update(
+		&self,
+		_start_time: DateTime<Utc>,
+		_downloaded_size: u64,
+		_total_size: u64,
+	) -> bool {
+		false
+	}
+	fn get_tmp_dir(&self) -> PathBuf {
+		unimplemented!()
+	}
+	fn get_tmpfile_pathname(&self, _tmpfile_name: String) -> PathBuf {
+		unimplemented!()
+	}
+}
+
+impl NetAdapter for HeaderChain {
+	fn find_peer_addrs(&self, _capab: Capabilities) -> Vec<PeerAddr> {
+		vec![]
+	}
+	fn peer_addrs_received(&self, _addrs: Vec<PeerAddr>) {}
+	fn peer_difficulty(&self, _addr: PeerAddr, _diff: Difficulty, _height: u64) {}
+	fn is_banned(&self, _addr: PeerAddr) -> bool {
+		false
+	}
+	fn peer_misbehaved(&self, _addr: PeerAddr, _misbehavior: Misbehavior) {}
+}
+
+fn open_port() -> u16 {
+	let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+	listener.local_addr().unwrap().port()
+}
+
+fn p2p_config(port: u16) -> P2PConfig {
+	P2PConfig {
+		host: "127.0.0.1".parse().unwrap(),
+		port,
+		..P2PConfig::default()
+	}
+}
+
+/// Starts a peer serving the provided header chain.
+fn start_peer(dir: &str, name: &str, headers: Vec<BlockHeader>) -> PeerAddr {
+	let config = p2p_config(open_port());
+	let genesis = headers[0].hash();
+	let server = Arc::new(
+		p2p::Server::new(
+			&format!("{}/{}", dir, name),
+			Capabilities::HEADER_HIST,
+			config.clone(),
+			Arc::new(HeaderChain { headers }),
+			genesis,
+			Arc::new(StopState::new()),
+		)
+		.unwrap(),
+	);
+	thread::spawn(move || server.listen());
+	PeerAddr(SocketAddr::new(config.host, config.port))
+}
+
+#[test]
+fn header_consensus_with_disagreeing_peers() {
+	util::init_test_logger();
+	global::set_mining_mode(ChainTypes::AutomatedTesting);
+	let dir = ".bitgrin_header_consensus";
+	let _ = fs::remove_dir_all(dir);
+
+	let main = extend(&[], 20);
+	let genesis = main[0].hash();
+	let fork = extend(&main[..=5], 20);
+
+	// the forked peer comes first so its candidate gets rejected
+	let forked = start_peer(dir, "forked", fork.clone());
+	let honest: Vec<PeerAddr> = (0..3)
+		.map(|i| start_peer(dir, &format!("honest{}", i), main.clone()))
+		.collect();
+	let behind = start_peer(dir, "behind", main[..=8].to_vec());
+	let offline = PeerAddr(SocketAddr::new("127.0.0.1".parse().unwrap(), open_port()));
+	thread::sleep(time::Duration::from_secs(1));
+
+	let mut addrs = vec![forked];
+	addrs.extend(honest.iter().cloned());
+	addrs.push(behind);
+	addrs.push(offline);
+
+	let config = p2p_config(open_port());
+	let consensus = servers::header_consensus(&addrs, 10, 3, genesis, config.clone()).unwrap();
+	assert!(consensus.has_quorum(3));
+	assert_eq!(consensus.height, 10);
+	assert_eq!(consensus.hash, main[10].hash());
+	assert_eq!(consensus.agreeing.len(), 3);
+	for addr in &honest {
+		assert!(consensus.agreeing.contains(addr));
+	}
+	assert_eq!(consensus.disagreeing, vec![(forked, Some(fork[10].hash()))]);
+	assert!(consensus.unavailable.contains(&behind));
+	assert!(consensus.unavailable.contains(&offline));
+
+	// without enough honest peers there's no quorum
+	let consensus =
+		servers::header_consensus(&[forked, honest[0]], 10, 2, genesis, config).unwrap();
+	assert!(!consensus.has_quorum(2));
+
+	let _ = fs::remove_dir_all(dir);
+}
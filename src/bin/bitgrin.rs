@@ -21,8 +21,6 @@ extern crate clap;
 
 #[macro_use]
 extern crate log;
-#[macro_use]
-extern crate guard;
 use crate::config::config::SERVER_CONFIG_FILE_NAME;
 use crate::core::global;
 use crate::util::init_logger;
@@ -167,7 +165,11 @@ fn real_main() -> i32 {
 	match args.subcommand() {
 		// server commands and options
 		("server", Some(server_args)) => {
-			cmd::try_hypersync();
+			// snapshots are taken from an already running server
+			if server_args.subcommand_name() != Some("snapshot") {
+				let server_config = &node_config.as_ref().unwrap().members.as_ref().unwrap().server;
+				cmd::try_hypersync(server_config);
+			}
 			cmd::server_command(Some(server_args), node_config.unwrap())
 		}
 
@@ -196,7 +198,8 @@ fn real_main() -> i32 {
 		// with most command line options being phased out
 		_ => {
 				println!("Checking need for hypersync...");
-				cmd::try_hypersync();
+				let server_config = &node_config.as_ref().unwrap().members.as_ref().unwrap().server;
+				cmd::try_hypersync(server_config);
 				println!("Done with hypersync check. Running server...");
 				cmd::server_command(None, node_config.unwrap())
 			},
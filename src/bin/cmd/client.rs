@@ -19,6 +19,7 @@ use clap::ArgMatches;
 
 use crate::api;
 use crate::config::GlobalConfig;
+use crate::core::core::amount_to_hr_string;
 use crate::p2p;
 use crate::servers::ServerConfig;
 use crate::util::file::get_first_line;
@@ -36,6 +37,9 @@ pub fn client_command(client_args: &ArgMatches<'_>, global_config: GlobalConfig)
 		("listconnectedpeers", Some(_)) => {
 			list_connected_peers(&server_config, api_secret);
 		}
+		("devfee", Some(_)) => {
+			list_dev_fee_outputs(&server_config, api_secret);
+		}
 		("ban", Some(peer_args)) => {
 			let peer = peer_args.value_of("peer").unwrap();
 
@@ -154,6 +158,51 @@ pub fn list_connected_peers(config: &ServerConfig, api_secret: Option<String>) {
 	e.reset().unwrap();
 }
 
+pub fn list_dev_fee_outputs(config: &ServerConfig, api_secret: Option<String>) {
+	let mut e = term::stdout().unwrap();
+	let url = format!("http://{}/v1/chain/devfee", config.api_http_addr);
+	let status = get_status_from_node(config, api_secret.clone());
+	let outputs = api::client::get::<Vec<api::DevFeeOutputPrintable>>(url.as_str(), api_secret);
+
+	match (status, outputs.map_err(Error::API)) {
+		(Ok(status), Ok(outputs)) => {
+			let height = status.tip.height;
+			for output in outputs {
+				writeln!(e, "Payout at height {}:", output.height).unwrap();
+				writeln!(e, "Amount: {}", amount_to_hr_string(output.amount, false)).unwrap();
+				if output.locked {
+					writeln!(
+						e,
+						"Unlocks at height: {} (in {} blocks)",
+						output.lock_height,
+						output.lock_height - height
+					)
+					.unwrap();
+				} else {
+					writeln!(e, "Unlocked at height: {}", output.lock_height).unwrap();
+				}
+				writeln!(e, "Unspent: {}", output.unspent).unwrap();
+				writeln!(
+					e,
+					"Commitment: {}",
+					output.commit.unwrap_or_else(|| "unknown".to_owned())
+				)
+				.unwrap();
+				writeln!(
+					e,
+					"Kernel excess: {}",
+					output.kernel_excess.unwrap_or_else(|| "unknown".to_owned())
+				)
+				.unwrap();
+				println!();
+			}
+		}
+		_ => writeln!(e, "Failed to get dev fee outputs").unwrap(),
+	};
+
+	e.reset().unwrap();
+}
+
 fn get_status_from_node(
 	config: &ServerConfig,
 	api_secret: Option<String>,
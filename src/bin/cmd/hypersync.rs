@@ -1,188 +1,129 @@
-// #[macro_use] extern crate guard;
-use std::io::{Read};
-use std::path::{Path};
-use std::fs::{self, File};
-
-use reqwest::{header, Client, Url};
-
-use bitgrin_config as config;
-use bitgrin_core::global;
-use bitgrin_servers as servers;
-use bitgrin_util::zip as bitgrin_zip;
-
-enum HyperSyncState {
-	NeedsExtract,
-	NeedsDownload,
-	NotNeeded,
+// Copyright 2018 The BitGrin Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+/// Bootstraps an empty node from a hypersync snapshot
+use std::fs;
+use std::path::Path;
+
+use bitgrin_chain as chain;
+use bitgrin_chain::hypersync;
+use bitgrin_core::core::Block;
+use bitgrin_core::{genesis, global};
+use bitgrin_servers::ServerConfig;
+
+/// Name of the working directory (next to the db root) holding the
+/// downloaded archive and the extracted, not yet verified, chain data.
+const HYPERSYNC_DIR: &str = "hypersync";
+
+pub fn genesis_block(chain_type: &global::ChainTypes) -> Block {
+	match chain_type {
+		global::ChainTypes::AutomatedTesting => genesis::genesis_dev(),
+		global::ChainTypes::UserTesting => genesis::genesis_dev(),
+		global::ChainTypes::Floonet => genesis::genesis_floo(),
+		global::ChainTypes::Mainnet => genesis::genesis_main(),
+	}
 }
 
-struct DownloadProgress<R> {
-    inner: R,
-    bytes_downloaded: u64,
-	total_size: u64,
-	out_per: f64,
+fn needs_hypersync(db_root: &Path) -> bool {
+	!db_root.join("header/header_head/pmmr_data.bin").is_file()
 }
 
-impl<R: Read> DownloadProgress<R> {
-	// Increment downloaded bytes
-	fn inc(&mut self, n: u64) {
-		self.bytes_downloaded += n;
-		let per: f64 = self.bytes_downloaded as f64 / self.total_size as f64 * 100.0;
-		if per - self.out_per > 10.0 {
-			println!("Hyper-sync downloading chain state {}%", per);
-			self.out_per = per;
+/// Moves every entry of the verified chain data into the db root, replacing
+/// whatever was there.
+fn install(staging: &Path, db_root: &Path) -> Result<(), chain::Error> {
+	fs::create_dir_all(db_root)?;
+	for entry in fs::read_dir(staging)? {
+		let entry = entry?;
+		let target = db_root.join(entry.file_name());
+		if target.is_dir() {
+			fs::remove_dir_all(&target)?;
+		} else if target.exists() {
+			fs::remove_file(&target)?;
 		}
+		fs::rename(entry.path(), &target)?;
 	}
+	Ok(())
 }
 
-impl<R: Read> Read for DownloadProgress<R> {
-    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
-        self.inner.read(buf).map(|n| {
-            self.inc(n as u64);
-            n
-        })
-    }
-}
-
-fn get_server_config() -> servers::common::types::ServerConfig {
-	let chain_type = global::ChainTypes::Mainnet;
-	let node_config = Some(
-		config::initial_setup_server(&chain_type).unwrap_or_else(|e| {
-			panic!("Hypersync - Error loading server configuration: {}", e);
-		}),
-	);
-	node_config.unwrap().members.as_ref().unwrap().server.clone()
-}
-
-fn expected_file(_: &Path) -> bool {
-	true
-}
-
-fn do_extract(zip_path: &Path, target_dir: &Path) {
-	guard!(let Ok(zip_file) = File::open(zip_path)
-		   else {
-			   println!("Hypersync - Could not open {:?} for extraction.", zip_path);
-			   return;
-		   });
-	match bitgrin_zip::decompress(zip_file, target_dir, expected_file) {
-		Ok(_) => { println!("Hypersync - Decompressed"); },
-		Err(_) => { println!("Hypersync - Error decompressing"); },
-	};
-}
-
-fn start_hyper_sync() {
-	println!("Starting hyper-sync...");
-	let uri = "https://d1joz5daoz8ntk.cloudfront.net/bg_chain_data02112020.zip";
-
-	guard!(let Ok(url) = Url::parse(uri)
-		   else { println!("Hypersync - Cannot parse URL"); return; });
-    let client = Client::new();
-
-    let total_size = {
-        let resp_try = client.head(url.as_str()).send();
-		match resp_try {
-			Ok(resp) => {
-				if resp.status().is_success() {
-					resp.headers()
-						.get(header::CONTENT_LENGTH)
-						.and_then(|ct_len| ct_len.to_str().ok())
-						.and_then(|ct_len| ct_len.parse().ok())
-						.unwrap_or(0)
-				} else {
-					println!("Hypersync - Couldn't download URL: {}. Error: {:?}", url, resp.status()); -1
-				}
-			},
-			Err(_) => { println!("Hypersync - Can't get size."); -1 }
-		}
-    };
-
-    let mut request = client.get(url.as_str());
-
-	let server_config =	get_server_config();
+fn hypersync(server_config: &ServerConfig) -> Result<(), chain::Error> {
+	let config = &server_config.hypersync_config;
 	let db_root = Path::new(&server_config.db_root);
-	
-	guard!(let Some(db_parent_path) = db_root.parent()
-		   else { println!("Hypersync - No db_root."); return; });
-	guard!(let zip_path_root = Path::new(&db_parent_path)
-	       else { println!("Hypersync - No db_parent_path"); return; });
-	guard!(let zip_path = zip_path_root.join("bg_chain_data2.zip")
-	       else { println!("Hypersync - No db_parent_path"); return; });
-
-    if zip_path.exists() {
-		println!("file_exists");
-		guard!(let Ok(zip_metadata) = zip_path.metadata()
-			   else { println!("Hypersync - Couldnt get zip metadata."); return; });
-        let size = zip_metadata.len() - 1;
-        request = request.header(header::RANGE, format!("bytes={}-", size));
-    }
-
-	guard!(let Ok(send_request) = request.send()
-	       else { println!("send() error"); return; });
-
-    let mut source = DownloadProgress {
-        inner: send_request,
-		bytes_downloaded: 0,
-		out_per: 0.0,
-		total_size: total_size as u64,
-    };
-
-	guard!(let Ok(mut dest) = fs::OpenOptions::new().create(true).append(true).open(&zip_path)
-		   else { println!("Hypersync - Err opening options"); return; });
-
-    let _ = std::io::copy(&mut source, &mut dest);
-	
-    println!(
-        "Hypersync - Download of '{}' has been completed.",
-        zip_path.to_str().unwrap()
-    );
-}
+	let work_dir = db_root
+		.parent()
+		.unwrap_or_else(|| Path::new("."))
+		.join(HYPERSYNC_DIR);
+	fs::create_dir_all(&work_dir)?;
+
+	let manifest = hypersync::fetch_manifest(
+		&config.mirrors,
+		&config.manifest_name,
+		config.manifest_digest.as_deref(),
+	)?;
+	println!(
+		"Hypersync - Snapshot at height {} ({})",
+		manifest.height, manifest.block_hash
+	);
 
-fn should_perform_hyper_sync(db_root: &Path, zip_path: &Path) -> HyperSyncState {
-	// Check if pmmr data exists
-	let pmmr_data_db_path = db_root.join("header/header_head/pmmr_data.bin");
-	if pmmr_data_db_path.exists() && pmmr_data_db_path.is_file() {
-		return HyperSyncState::NotNeeded;
+	// never trust the archive name to stay within our working directory
+	let archive_name = Path::new(&manifest.archive.path)
+		.file_name()
+		.ok_or_else(|| chain::ErrorKind::Snapshot("invalid archive name".to_owned()))?;
+	let archive = work_dir.join(archive_name);
+	hypersync::download_archive(&config.mirrors, &manifest, &archive)?;
+
+	println!("Hypersync - Verifying chain state, this may take a while...");
+	let staging = work_dir.join("chain_data");
+	if staging.exists() {
+		fs::remove_dir_all(&staging)?;
 	}
-	else {
-		// if zip exist, skip to extraction
-		let zip_file = File::open(zip_path.clone());
-		if let Ok(_) = zip_file {
-			return HyperSyncState::NeedsExtract;
-		}
-		else {
-			return HyperSyncState::NeedsDownload;
-		}
+	let verified = manifest.extract_archive(&archive, &staging).and_then(|_| {
+		manifest.verify_chain_data(&staging, genesis_block(&server_config.chain_type))
+	});
+	if let Err(e) = verified {
+		// a bad snapshot is not worth resuming, start from scratch next time
+		let _ = fs::remove_dir_all(&work_dir);
+		return Err(e);
 	}
+
+	install(&staging, db_root)?;
+	let _ = fs::remove_dir_all(&work_dir);
+	Ok(())
 }
 
-pub fn try_hypersync() {
-    // No hypersync ARM machine
-    if cfg!(target_arch = "arm") {
+/// Bootstraps the chain state from a verified snapshot when the node has no
+/// chain data yet. Any failure leaves the node to sync from its peers.
+pub fn try_hypersync(server_config: &ServerConfig) {
+	// No hypersync ARM machine
+	if cfg!(target_arch = "arm") {
 		println!("ARM arch - Skipping hypersync");
-        return;
-    }
-    // Retrieve common paths used for hyper-sync stages
-	let server_config =	get_server_config();
-	let db_root = Path::new(&server_config.db_root);
-	guard!(let Some(db_parent_path) = db_root.parent()
-		   else { println!("No db_parent_path."); return; });
-	guard!(let zip_path_root = Path::new(&db_parent_path)
-	       else { println!("No zip_path_root"); return; });
-	guard!(let zip_path = zip_path_root.join("bg_chain_data2.zip")
-	       else { println!("No zip_path"); return; });
+		return;
+	}
+	if !server_config.hypersync_config.enable_hypersync
+		|| !needs_hypersync(Path::new(&server_config.db_root))
+	{
+		return;
+	}
 
-    match should_perform_hyper_sync(db_root, &zip_path) {
-        HyperSyncState::NeedsDownload => {
-			println!("start_hyper_sync");
-			start_hyper_sync();
-			do_extract(&zip_path, db_root);
-		},
-        HyperSyncState::NeedsExtract => {
-			do_extract(&zip_path, db_root);
-		},
-        HyperSyncState::NotNeeded => {
-			println!("NotNeeded");
+	println!("Starting hyper-sync...");
+	match hypersync(server_config) {
+		Ok(_) => println!("Hypersync - Chain state installed"),
+		Err(e) => {
+			error!("Hypersync failed: {}", e);
+			println!(
+				"Hypersync - Failed ({}), syncing from peers instead",
+				e.kind()
+			);
 		}
-    };
-
+	}
 }
@@ -19,14 +19,20 @@ use std::sync::Arc;
 use std::thread;
 use std::time::Duration;
 
+use bitgrin_chain::hypersync;
+use bitgrin_chain::repair;
+use bitgrin_chain::ChainStore;
 use clap::ArgMatches;
 use ctrlc;
 
+use super::hypersync::genesis_block;
+use crate::api;
 use crate::config::GlobalConfig;
 use crate::core::global;
 use crate::p2p::{PeerAddr, Seeding};
 use crate::servers;
 use crate::tui::ui;
+use crate::util::file::get_first_line;
 
 /// wrap below to allow UI to clean up on stop
 pub fn start_server(config: servers::ServerConfig) {
@@ -131,6 +137,15 @@ pub fn server_command(
 			("run", _) => {
 				start_server(server_config);
 			}
+			("snapshot", Some(snapshot_args)) => {
+				return snapshot(&server_config, snapshot_args);
+			}
+			("reindex", _) => {
+				return reindex(&server_config);
+			}
+			("repair", _) => {
+				return repair(&server_config);
+			}
 			("", _) => {
 				println!("Subcommand required, use 'bitgrin help server' for details");
 			}
@@ -148,3 +163,122 @@ pub fn server_command(
 	}
 	0
 }
+
+/// Asks the running server to package its chain state as a hypersync
+/// snapshot and waits for it to be done.
+fn snapshot(config: &servers::ServerConfig, args: &ArgMatches<'_>) -> i32 {
+	let api_secret = get_first_line(config.api_secret_path.clone());
+	let url = format!("http://{}/v1/chain/snapshot", config.api_http_addr);
+	let mut params = format!(
+		"name={}",
+		args.value_of("name").unwrap_or(hypersync::SNAPSHOT_NAME)
+	);
+	if let Some(height) = args.value_of("height") {
+		params.push_str(&format!("&height={}", height));
+	}
+
+	let res = api::client::post::<_, api::SnapshotStatus>(
+		&format!("{}?{}", url, params),
+		api_secret.clone(),
+		&(),
+	);
+	match res {
+		Ok(api::SnapshotStatus::InProgress(height)) => {
+			println!("Packaging snapshot at height {}...", height)
+		}
+		Ok(status) => {
+			println!("Unexpected snapshot status {:?}", status);
+			return 1;
+		}
+		Err(e) => {
+			println!("Snapshot request failed: {}", e);
+			return 1;
+		}
+	}
+
+	loop {
+		thread::sleep(Duration::from_secs(5));
+		match api::client::get::<api::SnapshotStatus>(&url, api_secret.clone()) {
+			Ok(api::SnapshotStatus::InProgress(_)) => {}
+			Ok(api::SnapshotStatus::Done(manifest)) => {
+				println!(
+					"Snapshot at height {} ({}) written to the {} directory next to the server db_root",
+					manifest.height,
+					manifest.block_hash,
+					hypersync::SNAPSHOT_DIR
+				);
+				return 0;
+			}
+			Ok(api::SnapshotStatus::Failed(e)) => {
+				println!("Snapshot failed: {}", e);
+				return 1;
+			}
+			Ok(api::SnapshotStatus::Idle) | Err(_) => {
+				println!("Lost track of the snapshot, is the server still running?");
+				return 1;
+			}
+		}
+	}
+}
+
+/// Rebuilds the archive indexes of the chain store from its blocks, for
+/// archive nodes which ran without them. The server must be stopped.
+fn reindex(config: &servers::ServerConfig) -> i32 {
+	if !config.archive_mode.unwrap_or(false) {
+		println!("Archive indexes are only kept in archive mode, set archive_mode = true first");
+		return 1;
+	}
+	let store = match ChainStore::new(&config.db_root) {
+		Ok(store) => store,
+		Err(e) => {
+			println!("Can't open the chain store at {}: {}", config.db_root, e);
+			return 1;
+		}
+	};
+	println!("Rebuilding the archive indexes...");
+	match store.rebuild_archive_indexes() {
+		Ok(count) => {
+			println!("Indexed {} blocks", count);
+			0
+		}
+		Err(e) => {
+			println!("Reindex failed: {}", e);
+			1
+		}
+	}
+}
+
+/// Checks the chain data against the txhashset files and repairs it, so a
+/// node which stopped in an inconsistent state doesn't need a full resync.
+/// The server must be stopped.
+fn repair(config: &servers::ServerConfig) -> i32 {
+	println!("Checking the chain data at {}...", config.db_root);
+	let genesis = genesis_block(&config.chain_type);
+	let report = match repair::repair(config.db_root.clone(), &genesis) {
+		Ok(report) => report,
+		Err(e) => {
+			println!("Repair failed: {}", e);
+			return 1;
+		}
+	};
+	if report.is_clean() {
+		println!(
+			"Chain data is consistent, head {} at {}",
+			report.head.last_block_h, report.head.height
+		);
+		return 0;
+	}
+	for fix in &report.fixes {
+		println!("- {}", fix);
+	}
+	println!(
+		"Repaired: head {} at {} (was {}), {} blocks removed, {} output positions and {} block sums fixed",
+		report.head.last_block_h,
+		report.head.height,
+		report.old_head.height,
+		report.blocks_removed,
+		report.output_positions,
+		report.block_sums
+	);
+	0
+}
@@ -41,6 +41,7 @@ enum StratumWorkerColumn {
 	IsConnected,
 	LastSeen,
 	PowDifficulty,
+	ShareRate,
 	NumAccepted,
 	NumRejected,
 	NumStale,
@@ -54,6 +55,7 @@ impl StratumWorkerColumn {
 			StratumWorkerColumn::IsConnected => "Connected",
 			StratumWorkerColumn::LastSeen => "Last Seen",
 			StratumWorkerColumn::PowDifficulty => "PowDifficulty",
+			StratumWorkerColumn::ShareRate => "Shares/min",
 			StratumWorkerColumn::NumAccepted => "Num Accepted",
 			StratumWorkerColumn::NumRejected => "Num Rejected",
 			StratumWorkerColumn::NumStale => "Num Stale",
@@ -78,6 +80,7 @@ impl TableViewItem<StratumWorkerColumn> for WorkerStats {
 			StratumWorkerColumn::IsConnected => self.is_connected.to_string(),
 			StratumWorkerColumn::LastSeen => datetime.to_string(),
 			StratumWorkerColumn::PowDifficulty => self.pow_difficulty.to_string(),
+			StratumWorkerColumn::ShareRate => format!("{:.1}", self.share_rate),
 			StratumWorkerColumn::NumAccepted => self.num_accepted.to_string(),
 			StratumWorkerColumn::NumRejected => self.num_rejected.to_string(),
 			StratumWorkerColumn::NumStale => self.num_stale.to_string(),
@@ -94,6 +97,7 @@ impl TableViewItem<StratumWorkerColumn> for WorkerStats {
 			StratumWorkerColumn::IsConnected => Ordering::Equal,
 			StratumWorkerColumn::LastSeen => Ordering::Equal,
 			StratumWorkerColumn::PowDifficulty => Ordering::Equal,
+			StratumWorkerColumn::ShareRate => Ordering::Equal,
 			StratumWorkerColumn::NumAccepted => Ordering::Equal,
 			StratumWorkerColumn::NumRejected => Ordering::Equal,
 			StratumWorkerColumn::NumStale => Ordering::Equal,
@@ -195,6 +199,9 @@ impl TUIStatusListener for TUIMiningView {
 			.column(StratumWorkerColumn::PowDifficulty, "Pow Difficulty", |c| {
 				c.width_percent(12)
 			})
+			.column(StratumWorkerColumn::ShareRate, "Shares/min", |c| {
+				c.width_percent(10)
+			})
 			.column(StratumWorkerColumn::NumAccepted, "Num Accepted", |c| {
 				c.width_percent(10)
 			})
@@ -41,6 +41,7 @@ enum PeerColumn {
 	Direction,
 	Version,
 	UserAgent,
+	Misbehavior,
 }
 
 impl PeerColumn {
@@ -53,6 +54,7 @@ impl PeerColumn {
 			PeerColumn::TotalDifficulty => "Total Difficulty",
 			PeerColumn::Direction => "Direction",
 			PeerColumn::UserAgent => "User Agent",
+			PeerColumn::Misbehavior => "Misbehavior",
 		}
 	}
 }
@@ -83,6 +85,7 @@ impl TableViewItem<PeerColumn> for PeerStats {
 			PeerColumn::Direction => self.direction.clone(),
 			PeerColumn::Version => format!("{}", self.version),
 			PeerColumn::UserAgent => self.user_agent.clone(),
+			PeerColumn::Misbehavior => self.misbehavior.to_string(),
 		}
 	}
 
@@ -111,6 +114,7 @@ impl TableViewItem<PeerColumn> for PeerStats {
 			PeerColumn::Direction => self.direction.cmp(&other.direction),
 			PeerColumn::Version => self.version.cmp(&other.version),
 			PeerColumn::UserAgent => self.user_agent.cmp(&other.user_agent),
+			PeerColumn::Misbehavior => self.misbehavior.cmp(&other.misbehavior),
 		}
 	}
 }
@@ -130,7 +134,8 @@ impl TUIStatusListener for TUIPeerView {
 				c.width_percent(24)
 			})
 			.column(PeerColumn::Version, "Proto", |c| c.width_percent(6))
-			.column(PeerColumn::UserAgent, "User Agent", |c| c.width_percent(18));
+			.column(PeerColumn::Misbehavior, "Misb.", |c| c.width_percent(6))
+			.column(PeerColumn::UserAgent, "User Agent", |c| c.width_percent(12));
 		let peer_status_view = BoxView::with_full_screen(
 			LinearLayout::new(Orientation::Vertical)
 				.child(
@@ -1,57 +1,60 @@
-use api::{BlockPrintable, Error, ErrorKind};
-use bitgrin_api as api;
-use bitgrin_core;
-use bitgrin_core::core::hash::Hash;
-
-use reqwest;
-use std::time::Duration;
-
-fn try_get_seed_hash(url: &str) -> Result<Hash, Error> {
-	let client = reqwest::Client::builder()
-		.timeout(Duration::from_secs(4))
-		.build();
-	match client {
-		Ok(c) => {
-			let r = c.get(url).send();
-			match r {
-				Ok(mut res) => {
-					let parsed_result: Result<BlockPrintable, reqwest::Error> = res.json();
-					match parsed_result {
-						Ok(parsed) => {
-							println!("Parsed {}", &parsed.header.hash);
-							Hash::from_hex(&parsed.header.hash)
-								.map_err(|_| ErrorKind::NotFound.into())
-						}
-						Err(_) => {
-							println!("Error parsing json");
-							Err(ErrorKind::NotFound.into())
-						}
-					}
-				}
-				Err(e) => {
-					println!("Error making web request to seed hash: {}", e);
-					Err(ErrorKind::NotFound.into())
-				}
-			}
-		}
-		Err(e) => {
-			println!("Error creating reqwest client: {}", e);
-			Err(ErrorKind::NotFound.into())
+use bitgrin_core::core::hash::{Hash, Hashed};
+use bitgrin_core::genesis;
+use bitgrin_p2p::PeerAddr;
+
+/// Height of the header the seed bridge checks, and how many peers must
+/// agree on it.
+const SEED_BRIDGE_HEIGHT: u64 = 215742;
+const SEED_BRIDGE_QUORUM: usize = 3;
+
+/// Asks our configured seeds (or the DNS seeds) for the header at the given
+/// height, and only trusts it when a quorum of them agrees.
+pub fn seed_bridge(
+	server_config: &servers::ServerConfig,
+	height: u64,
+	quorum: usize,
+) -> Result<Hash, String> {
+	global::set_mining_mode(server_config.chain_type.clone());
+	let genesis = match server_config.chain_type {
+		global::ChainTypes::Mainnet => genesis::genesis_main(),
+		global::ChainTypes::Floonet => genesis::genesis_floo(),
+		_ => genesis::genesis_dev(),
+	};
+	let p2p_config = server_config.p2p_config.clone();
+	let addrs: Vec<PeerAddr> = match p2p_config.seeds {
+		Some(ref seeds) if !seeds.is_empty() => seeds.clone(),
+		_ => servers::dns_seeds()(),
+	};
+	println!("Asking {} peers for the header at {}...", addrs.len(), height);
+
+	let consensus =
+		servers::header_consensus(&addrs, height, quorum, genesis.hash(), p2p_config)
+			.map_err(|e| format!("{:?}", e))?;
+	for (addr, hash) in &consensus.disagreeing {
+		match hash {
+			Some(h) => println!("Peer {} disagrees, has {}", addr, h),
+			None => println!("Peer {} disagrees, follows another chain", addr),
 		}
 	}
-}
-
-pub fn seed_bridge() -> Result<Hash, Error> {
-	let block_num = 215742;
-	let url = format!("http://mainseed.bitgrin.io:8513/v1/blocks/{}", block_num);
-	match try_get_seed_hash(&url) {
-		Ok(res) => Ok(res),
-		Err(_) => {
-			println!("Could not get main seed hash from mainseed, trying mainseed2...");
-			let url2 = format!("http://mainseed2.bitgrin.io:8513/v1/blocks/{}", block_num);
-			try_get_seed_hash(&url2)
-		}
+	for addr in &consensus.unavailable {
+		println!("Peer {} unavailable", addr);
 	}
+	if !consensus.has_quorum(quorum) {
+		return Err(format!(
+			"no quorum: {} peers agree on {}, {} disagree, {} needed",
+			consensus.agreeing.len(),
+			consensus.hash,
+			consensus.disagreeing.len(),
+			quorum
+		));
+	}
+	println!(
+		"{} peers agree on {} at {}",
+		consensus.agreeing.len(),
+		consensus.hash,
+		height
+	);
+	Ok(consensus.hash)
 }
 
 /// Retrieve chain data for hyper-sync
@@ -272,6 +275,8 @@ pub fn print_usage() {
 	println!("  Checkpoint the current blockchain state and save as a zip file");
 	println!("hs / hypersync");
 	println!("  Test hyper-sync functionality");
+	println!("sb / seedbridge [height] [quorum]");
+	println!("  Check the header at a height with a quorum of peers");
 }
 
 pub fn main() {
@@ -301,17 +306,27 @@ pub fn main() {
 					HyperSyncState::NotNeeded => { println!("Skipping hyper-sync."); }
 				};
 			}
+			"seedbridge" | "sb" => {
+				let height = args
+					.get(2)
+					.and_then(|h| h.parse().ok())
+					.unwrap_or(SEED_BRIDGE_HEIGHT);
+				let quorum = args
+					.get(3)
+					.and_then(|q| q.parse().ok())
+					.unwrap_or(SEED_BRIDGE_QUORUM);
+				match seed_bridge(&server_config, height, quorum) {
+					Ok(hash) => println!("OK!: {}", hash),
+					Err(e) => {
+						println!("Err: {}", e);
+						std::process::exit(1);
+					}
+				}
+			}
 			_ => { println!("Unrecognized command."); print_usage(); }
 		}
 	}
 	else {
 		print_usage();
 	}
-
-	/*
-	// Seed bridge tests
-	match seed_bridge() {
-		Ok(x) => { println!("OK!: {}", x) },
-		Err(e) => { println!("Err: {:?}", e)},
-	}*/
 }